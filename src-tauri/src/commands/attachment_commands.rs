@@ -0,0 +1,208 @@
+use tauri::State;
+use crate::database::{connection::DbConnection, SettingsCache, schema::{OrderAttachmentRepository, OrderEventRepository, OrderRepository, Repository, SettingsRepository}};
+use crate::models::OrderAttachment;
+use crate::utils::clock::SharedClock;
+use std::path::{Path, PathBuf};
+
+// 单个附件的大小上限（字节）
+const MAX_ATTACHMENT_BYTES: u64 = 20 * 1024 * 1024;
+
+/// 按扩展名判断附件类型，不支持的类型返回 None
+fn attachment_kind(extension: &str) -> Option<&'static str> {
+    match extension {
+        "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" | "heic" => Some("image"),
+        "pdf" | "doc" | "docx" | "xls" | "xlsx" | "txt" => Some("document"),
+        _ => None,
+    }
+}
+
+/// 附件根目录：设置了数据目录时放在数据目录下，否则放在数据库文件旁边
+fn attachments_root(conn: &DbConnection, settings_cache: &SettingsCache) -> Result<PathBuf, String> {
+    let data_directory = settings_cache
+        .get(&SettingsRepository::new(conn.clone()))
+        .map_err(|e| e.to_string())?
+        .map(|s| s.data_directory)
+        .filter(|dir| !dir.trim().is_empty());
+
+    let base = match data_directory {
+        Some(dir) => PathBuf::from(dir),
+        None => OrderAttachmentRepository::new(conn.clone())
+            .database_dir()
+            .ok_or_else(|| "无法确定数据目录".to_string())?,
+    };
+    Ok(base.join("attachments"))
+}
+
+/// 删除订单的附件目录（在删除订单的事务提交后调用）。删除失败只记录日志，不影响订单删除
+pub fn remove_attachment_dirs(conn: &DbConnection, settings_cache: &SettingsCache, order_ids: &[String]) {
+    let root = match attachments_root(conn, settings_cache) {
+        Ok(root) => root,
+        Err(e) => {
+            log::warn!("无法确定附件目录，未清理已删除订单的附件: {}", e);
+            return;
+        }
+    };
+    for order_id in order_ids {
+        let dir = root.join(order_id);
+        if !dir.exists() {
+            continue;
+        }
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            log::warn!("删除订单 {} 的附件目录失败: {}", order_id, e);
+        }
+    }
+}
+
+/// 为订单添加附件（照片、签字单等）：校验类型与大小后复制到附件目录，原文件保持不变
+#[tauri::command]
+pub async fn add_order_attachment(
+    order_id: String,
+    source_path: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<OrderAttachment, String> {
+    OrderRepository::new(conn.inner().clone())
+        .get_by_id(&order_id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+            e => e.to_string(),
+        })?;
+
+    let source = Path::new(&source_path);
+    let metadata = std::fs::metadata(source).map_err(|_| format!("文件不存在: {}", source_path))?;
+    if !metadata.is_file() {
+        return Err(format!("不是文件: {}", source_path));
+    }
+    if metadata.len() == 0 {
+        return Err("文件为空".to_string());
+    }
+    if metadata.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!("文件过大（上限 {} MB）", MAX_ATTACHMENT_BYTES / 1024 / 1024));
+    }
+
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+    let kind = attachment_kind(&extension)
+        .ok_or_else(|| "不支持的文件类型，仅支持图片、PDF、Word、Excel 与文本文件".to_string())?;
+
+    // 每个订单一个子目录，文件以新 ID 命名避免重名覆盖
+    let id = uuid::Uuid::new_v4().to_string();
+    let target_dir = attachments_root(conn.inner(), settings_cache.inner())?.join(&order_id);
+    std::fs::create_dir_all(&target_dir).map_err(|e| format!("创建附件目录失败: {}", e))?;
+    let target = target_dir.join(format!("{}.{}", id, extension));
+    std::fs::copy(source, &target).map_err(|e| {
+        log::error!("复制订单 {} 的附件失败: {}", order_id, e);
+        format!("复制附件失败: {}", e)
+    })?;
+
+    let attachment = OrderAttachment {
+        id,
+        order_id: order_id.clone(),
+        file_path: target.to_string_lossy().to_string(),
+        file_name: source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        kind: kind.to_string(),
+        size_bytes: metadata.len() as i64,
+        created_at: clock.now_rfc3339(),
+    };
+
+    let repo = OrderAttachmentRepository::new(conn.inner().clone());
+    if let Err(e) = repo.insert(&attachment) {
+        log::error!("保存订单 {} 的附件记录失败: {}", order_id, e);
+        let _ = std::fs::remove_file(&target);
+        return Err(e.to_string());
+    }
+
+    OrderEventRepository::new(conn.inner().clone())
+        .record(&order_id, "attachment_added", Some(&attachment.file_name), &attachment.created_at)
+        .map_err(|e| {
+            log::error!("记录订单 {} 的附件事件失败: {}", order_id, e);
+            e.to_string()
+        })?;
+
+    log::info!("订单 {} 添加附件: {}", order_id, attachment.file_name);
+    Ok(attachment)
+}
+
+/// 获取订单的附件列表（订单删除时附件记录与文件一并删除，合并订单时转到主订单）
+#[tauri::command]
+pub async fn get_order_attachments(
+    order_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<OrderAttachment>, String> {
+    let repo = OrderAttachmentRepository::new(conn.inner().clone());
+    repo.get_by_order(&order_id).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::order_commands::{batch_delete_orders, delete_order};
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    #[test]
+    fn deleting_orders_removes_attachment_rows_and_files() {
+        let data_dir = std::env::temp_dir().join(format!("attachments_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let source = data_dir.join("签字单.pdf");
+        std::fs::write(&source, b"%PDF-1.4").unwrap();
+
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            c.execute("UPDATE app_settings SET data_directory = ?1", rusqlite::params![data_dir.to_string_lossy()])
+                .unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            for id in ["o1", "o2", "o3"] {
+                insert_order(&c, id, "c1", "2024-01-05", "completed");
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        for id in ["o1", "o2", "o3"] {
+            tauri::async_runtime::block_on(add_order_attachment(
+                id.to_string(),
+                source.to_string_lossy().to_string(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+        }
+        let attachment_dir = |id: &str| data_dir.join("attachments").join(id);
+        let attachment_rows = || {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM order_attachments", [], |row| row.get::<_, i64>(0))
+                .unwrap()
+        };
+        assert!(attachment_dir("o1").is_dir());
+        assert_eq!(attachment_rows(), 3);
+
+        tauri::async_runtime::block_on(delete_order("o1".to_string(), app.state(), app.state(), app.state(), app.state())).unwrap();
+        assert!(!attachment_dir("o1").exists());
+        assert_eq!(attachment_rows(), 2);
+
+        let deleted = tauri::async_runtime::block_on(batch_delete_orders(
+            vec!["o2".to_string(), "o3".to_string()],
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(deleted, 2);
+        assert!(!attachment_dir("o2").exists() && !attachment_dir("o3").exists());
+        assert_eq!(attachment_rows(), 0);
+        // 原文件保持不变
+        assert!(source.is_file());
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
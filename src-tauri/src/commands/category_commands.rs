@@ -1,6 +1,12 @@
 use tauri::State;
-use crate::database::{connection::DbConnection, schema::{CategoryRepository, Repository}};
-use crate::models::Category;
+use crate::database::{
+    connection::DbConnection,
+    SettingsCache,
+    schema::{CategoryRepository, RemarkPresetRepository, Repository, SettingsRepository, UnitPresetRepository},
+};
+use crate::models::{Category, CategoryCrumb, CategoryImportResult, CategorySubtreeDeletion};
+use crate::utils::clock::SharedClock;
+use crate::utils::csv;
 use rusqlite;
 
 #[tauri::command]
@@ -28,19 +34,56 @@ pub async fn get_category_tree(
     repo.get_tree().map_err(|e| e.to_string())
 }
 
+/// 获取分类面包屑（从根分类到当前分类的 id 与名称）
+#[tauri::command]
+pub async fn get_category_breadcrumb(
+    category_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<CategoryCrumb>, String> {
+    let repo = CategoryRepository::new(conn.inner().clone());
+    repo.get_breadcrumb(&category_id).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn save_category(
-    category: Category,
+    mut category: Category,
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = CategoryRepository::new(conn.inner().clone());
 
     let existing = repo.get_by_id(&category.id);
-    if existing.is_ok() {
-        repo.update(&category).map_err(|e| e.to_string())
+    let result = if existing.is_ok() {
+        repo.update(&category)
     } else {
-        repo.insert(&category).map_err(|e| e.to_string())
+        // 新分类未指定排序时排到同级最后
+        if category.sort_order == 0 {
+            category.sort_order = repo
+                .next_sort_order(category.parent_id.as_deref())
+                .map_err(|e| e.to_string())?;
+        }
+        repo.insert(&category)
+    };
+    result.map_err(|e| {
+        log::error!("保存分类 {} 失败: {}", category.id, e);
+        e.to_string()
+    })
+}
+
+/// 获取新建记录的下一个排序值：entity 为 remark_preset / unit_preset（全局）或 category（按上级分类）
+#[tauri::command]
+pub async fn get_next_sort_order(
+    entity: String,
+    parent_id: Option<String>,
+    conn: State<'_, DbConnection>,
+) -> Result<i32, String> {
+    let db = conn.inner().clone();
+    match entity.as_str() {
+        "remark_preset" => RemarkPresetRepository::new(db).next_sort_order(),
+        "unit_preset" => UnitPresetRepository::new(db).next_sort_order(),
+        "category" => CategoryRepository::new(db).next_sort_order(parent_id.as_deref()),
+        _ => return Err(format!("不支持的排序对象: {}", entity)),
     }
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -49,7 +92,54 @@ pub async fn save_categories_batch(
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = CategoryRepository::new(conn.inner().clone());
-    repo.save_batch(&categories).map_err(|e: rusqlite::Error| e.to_string())
+    repo.save_batch(&categories).map_err(|e: rusqlite::Error| {
+        log::error!("批量保存 {} 个分类失败: {}", categories.len(), e);
+        e.to_string()
+    })
+}
+
+/// 从 CSV（名称,上级名称,排序）导入分类树，子分类可以排在上级之前
+#[tauri::command]
+pub async fn import_categories_csv(
+    path: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CategoryImportResult, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+    let mut records = csv::parse(&content)?;
+
+    // 首行为表头时跳过
+    csv::strip_header(&mut records, &["name", "名称", "分类名称"]);
+
+    let mut warnings = Vec::new();
+    let mut rows = Vec::with_capacity(records.len());
+    for (index, record) in records.iter().enumerate() {
+        let name = record.first().map(|s| s.trim()).unwrap_or_default();
+        if name.is_empty() {
+            warnings.push(format!("第 {} 行缺少分类名称，已跳过", index + 1));
+            continue;
+        }
+        let parent_name = record.get(1).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let sort_order = match record.get(2).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            None => 0,
+            Some(value) => value.parse::<i32>().unwrap_or_else(|_| {
+                warnings.push(format!("分类「{}」的排序值「{}」无效，已按 0 处理", name, value));
+                0
+            }),
+        };
+        rows.push((name.to_string(), parent_name, sort_order));
+    }
+
+    let repo = CategoryRepository::new(conn.inner().clone());
+    let mut result = repo.import_batch(&rows, &clock.now_rfc3339()).map_err(|e| {
+        log::error!("分类导入失败: {}", e);
+        e.to_string()
+    })?;
+    warnings.append(&mut result.warnings);
+    result.warnings = warnings;
+
+    log::info!("分类导入完成: 新增 {} 个, {} 条提示", result.inserted, result.warnings.len());
+    Ok(result)
 }
 
 #[tauri::command]
@@ -58,5 +148,317 @@ pub async fn delete_category(
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = CategoryRepository::new(conn.inner().clone());
-    repo.delete(&id).map_err(|e| e.to_string())
+    repo.delete(&id).map_err(|e| {
+        log::error!("删除分类 {} 失败: {}", id, e);
+        e.to_string()
+    })
+}
+
+/// 删除分类及其全部子孙分类（同一事务），其中的商品改到 reassign_products_to 指定的分类，
+/// 未指定时使用设置中的默认分类（默认分类也不存在时商品不再属于任何分类）；目标分类不能位于被删除的子树中
+#[tauri::command]
+pub async fn delete_category_subtree(
+    id: String,
+    reassign_products_to: Option<String>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<CategorySubtreeDeletion, String> {
+    let repo = CategoryRepository::new(conn.inner().clone());
+    let subtree = repo.get_subtree_ids(&id).map_err(|e| e.to_string())?;
+    if subtree.is_empty() {
+        return Err(format!("分类不存在: {}", id));
+    }
+
+    let target = match reassign_products_to.filter(|t| !t.trim().is_empty()) {
+        Some(target) => {
+            if subtree.contains(&target) {
+                return Err("接收商品的分类位于要删除的分类之下，请选择其他分类".to_string());
+            }
+            repo.get_by_id(&target).map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => format!("分类不存在: {}", target),
+                e => e.to_string(),
+            })?;
+            Some(target)
+        }
+        None => {
+            let default_category_id = settings_cache
+                .get(&SettingsRepository::new(conn.inner().clone()))
+                .map_err(|e| e.to_string())?
+                .map(|s| s.default_category_id)
+                .filter(|d| !d.trim().is_empty());
+            match default_category_id {
+                Some(default) if subtree.contains(&default) => {
+                    return Err("默认分类位于要删除的分类之下，请指定接收商品的分类".to_string());
+                }
+                Some(default) if repo.get_by_id(&default).is_ok() => Some(default),
+                _ => None,
+            }
+        }
+    };
+
+    let (deleted_categories, reassigned_products) = repo
+        .delete_subtree(&id, target.as_deref(), &clock.now_rfc3339())
+        .map_err(|e| {
+            log::error!("删除分类 {} 及其子分类失败: {}", id, e);
+            e.to_string()
+        })?;
+    log::info!(
+        "已删除分类 {} 及其子分类共 {} 个，{} 个商品改到分类 {:?}",
+        id, deleted_categories, reassigned_products, target
+    );
+    Ok(CategorySubtreeDeletion {
+        deleted_categories,
+        reassigned_products,
+        reassigned_to: target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    #[test]
+    fn breadcrumb_runs_from_the_root_and_stops_at_a_missing_ancestor() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "c1", None, 1);
+            insert_category(&c, "c2", Some("c1"), 2);
+            insert_category(&c, "c3", Some("c2"), 3);
+            c.execute_batch(
+                "UPDATE categories SET name = '保养' WHERE id = 'c1';
+                 UPDATE categories SET name = '机油' WHERE id = 'c2';
+                 UPDATE categories SET name = '全合成' WHERE id = 'c3';",
+            )
+            .unwrap();
+            // 上级已被删除但未置空 parent_id 的旧数据
+            c.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+            insert_category(&c, "b2", Some("gone"), 2);
+            insert_category(&c, "b3", Some("b2"), 3);
+            c.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let breadcrumb = |id: &str| {
+            tauri::async_runtime::block_on(get_category_breadcrumb(id.to_string(), app.state()))
+                .unwrap()
+                .into_iter()
+                .map(|crumb| (crumb.id, crumb.name))
+                .collect::<Vec<_>>()
+        };
+        let crumbs = |pairs: &[(&str, &str)]| {
+            pairs.iter().map(|(id, name)| (id.to_string(), name.to_string())).collect::<Vec<_>>()
+        };
+
+        assert_eq!(breadcrumb("c3"), crumbs(&[("c1", "保养"), ("c2", "机油"), ("c3", "全合成")]));
+        assert_eq!(breadcrumb("c1"), crumbs(&[("c1", "保养")]));
+        assert_eq!(breadcrumb("b3"), crumbs(&[("b2", "b2"), ("b3", "b3")]));
+        assert!(breadcrumb("missing").is_empty());
+    }
+
+    #[test]
+    fn csv_import_links_children_listed_before_their_parents() {
+        let conn = memory_db();
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let path = std::env::temp_dir().join(format!("categories_{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "名称,上级名称,排序\n全合成,机油,2\n机油,发动机,1\n发动机,,0\n轮胎,底盘,x\n,发动机,1\n机油,发动机,3\n雨刮,保养,4\n甲,乙,0\n乙,甲,0\n",
+        )
+        .unwrap();
+
+        let result =
+            tauri::async_runtime::block_on(import_categories_csv(path.to_string_lossy().to_string(), app.state(), app.state()))
+                .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.inserted, 7);
+        assert_eq!(
+            result.warnings,
+            vec![
+                "分类「轮胎」的排序值「x」无效，已按 0 处理",
+                "第 5 行缺少分类名称，已跳过",
+                "分类「机油」已存在，已跳过",
+                "分类「轮胎」的上级「底盘」不存在，已作为顶级分类导入",
+                "分类「乙」的上级形成循环引用，已作为顶级分类导入",
+            ]
+        );
+
+        let categories: Vec<(String, String, Option<String>, i32, String, i32)> = {
+            let c = conn.lock().unwrap();
+            let mut stmt = c
+                .prepare("SELECT id, name, parent_id, level, path, sort_order FROM categories WHERE created_at = ?1")
+                .unwrap();
+            let rows = stmt
+                .query_map(["2024-03-10T04:00:00+00:00"], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+                })
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            rows
+        };
+        let find = |name: &str| categories.iter().find(|c| c.1 == name).expect(name);
+        let (root, oil, synthetic) = (find("发动机"), find("机油"), find("全合成"));
+        assert_eq!((root.2.as_deref(), root.3, root.4.as_str()), (None, 0, ""));
+        assert_eq!((oil.2.as_deref(), oil.3, oil.4.clone(), oil.5), (Some(root.0.as_str()), 1, format!("/{}", root.0), 1));
+        assert_eq!(
+            (synthetic.2.as_deref(), synthetic.3, synthetic.4.clone(), synthetic.5),
+            (Some(oil.0.as_str()), 2, format!("/{}/{}", root.0, oil.0), 2)
+        );
+        assert_eq!((find("轮胎").2.as_deref(), find("轮胎").3), (None, 0));
+        // 上级可以是数据库中已有的分类（默认分类「保养」）
+        let existing_parent = CategoryRepository::new(conn.clone())
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .find(|c| c.name == "保养")
+            .unwrap();
+        assert_eq!((find("雨刮").2.as_deref(), find("雨刮").3), (Some(existing_parent.id.as_str()), 1));
+        // 循环引用在链尾断开：甲 挂在 乙 下，乙 成为顶级分类
+        let (first, second) = (find("甲"), find("乙"));
+        assert_eq!((first.2.as_deref(), first.3), (Some(second.0.as_str()), 1));
+        assert_eq!((second.2.as_deref(), second.3), (None, 0));
+    }
+
+    #[test]
+    fn next_sort_order_is_global_for_presets_and_per_parent_for_categories() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            // 默认数据：顶级分类排序 0..=5，单位预设若干
+            c.execute("UPDATE unit_presets SET sort_order = 40 WHERE rowid = (SELECT MIN(rowid) FROM unit_presets)", [])
+                .unwrap();
+            insert_category(&c, "engine", None, 0);
+            insert_category(&c, "oil", Some("engine"), 1);
+            c.execute("UPDATE categories SET sort_order = 3 WHERE id = 'oil'", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let next = |entity: &str, parent_id: Option<&str>| {
+            tauri::async_runtime::block_on(get_next_sort_order(
+                entity.to_string(),
+                parent_id.map(str::to_string),
+                app.state(),
+            ))
+        };
+
+        assert_eq!(next("remark_preset", None), Ok(0));
+        conn.lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO remark_presets (id, content, type, sort_order, created_at, updated_at)
+                 VALUES ('r1', '加急', 'order', 7, 'now', 'now')",
+                [],
+            )
+            .unwrap();
+        assert_eq!(next("remark_preset", None), Ok(8));
+        // 预设不分上级，parent_id 被忽略
+        assert_eq!(next("unit_preset", Some("engine")), Ok(41));
+
+        assert_eq!(next("category", None), Ok(6));
+        assert_eq!(next("category", Some("engine")), Ok(4));
+        assert_eq!(next("category", Some("oil")), Ok(0));
+        assert_eq!(next("product", None).unwrap_err(), "不支持的排序对象: product");
+
+        // 新建分类未指定排序时使用同级的下一个排序值
+        let category = Category {
+            id: "synthetic".to_string(),
+            name: "全合成".to_string(),
+            parent_id: Some("engine".to_string()),
+            level: 1,
+            path: "/engine".to_string(),
+            sort_order: 0,
+            created_at: "now".to_string(),
+            updated_at: "now".to_string(),
+        };
+        tauri::async_runtime::block_on(save_category(category, app.state())).unwrap();
+        let repo = CategoryRepository::new(conn.clone());
+        assert_eq!(repo.get_by_id("synthetic").unwrap().sort_order, 4);
+        assert_eq!(next("category", Some("engine")), Ok(5));
+    }
+
+    #[test]
+    fn deleting_a_three_level_subtree_moves_its_products_to_the_chosen_category() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "a1", None, 1);
+            insert_category(&c, "a2", Some("a1"), 2);
+            insert_category(&c, "a3", Some("a2"), 3);
+            insert_category(&c, "x1", None, 1);
+            insert_category(&c, "x2", Some("x1"), 2);
+            insert_category(&c, "x3", Some("x2"), 3);
+            insert_category(&c, "b1", None, 1);
+            for (product, category) in [("p1", "a1"), ("p2", "a2"), ("p3", "a3"), ("p4", "x3"), ("p5", "b1")] {
+                insert_product(&c, product, 10.0, Some(category));
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let delete = |id: &str, reassign: Option<&str>| {
+            tauri::async_runtime::block_on(delete_category_subtree(
+                id.to_string(),
+                reassign.map(str::to_string),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let set_default_category = |id: &str| {
+            let repo = SettingsRepository::new(conn.clone());
+            let cache = app.state::<SettingsCache>();
+            let mut settings = cache.get(&repo).unwrap().unwrap();
+            settings.default_category_id = id.to_string();
+            cache.save(&repo, &settings).unwrap();
+        };
+        let category_of = |product: &str| {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT category_id FROM products WHERE id = ?1", [product], |row| {
+                    row.get::<_, Option<String>>(0)
+                })
+                .unwrap()
+        };
+        let exists = |id: &str| {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM categories WHERE id = ?1", [id], |row| row.get::<_, i64>(0))
+                .unwrap()
+                == 1
+        };
+
+        // 接收商品的分类在被删除的子树中：拒绝且不改动任何数据
+        assert!(delete("a1", Some("a3")).unwrap_err().contains("要删除的分类之下"));
+        assert!(delete("a1", Some("missing")).unwrap_err().contains("分类不存在"));
+        assert!(["a1", "a2", "a3"].iter().all(|id| exists(id)));
+        assert_eq!(category_of("p3").as_deref(), Some("a3"));
+
+        let result = delete("a1", Some("b1")).unwrap();
+        assert_eq!((result.deleted_categories, result.reassigned_products), (3, 3));
+        assert_eq!(result.reassigned_to.as_deref(), Some("b1"));
+        assert!(!["a1", "a2", "a3"].iter().any(|id| exists(id)));
+        for product in ["p1", "p2", "p3", "p5"] {
+            assert_eq!(category_of(product).as_deref(), Some("b1"));
+        }
+
+        // 未指定时使用设置中的默认分类，默认分类位于子树中则拒绝
+        set_default_category("x2");
+        assert!(delete("x1", None).unwrap_err().contains("默认分类"));
+        set_default_category("b1");
+        let result = delete("x1", None).unwrap();
+        assert_eq!((result.deleted_categories, result.reassigned_products), (3, 1));
+        assert_eq!(result.reassigned_to.as_deref(), Some("b1"));
+        assert_eq!(category_of("p4").as_deref(), Some("b1"));
+
+        // 没有可用的默认分类时商品不再属于任何分类
+        set_default_category("");
+        insert_category(&conn.lock().unwrap(), "y1", Some("b1"), 2);
+        conn.lock().unwrap().execute("UPDATE products SET category_id = 'y1' WHERE id = 'p5'", []).unwrap();
+        let result = delete("y1", None).unwrap();
+        assert_eq!((result.deleted_categories, result.reassigned_products), (1, 1));
+        assert_eq!(result.reassigned_to, None);
+        assert_eq!(category_of("p5"), None);
+        assert!(delete("y1", None).unwrap_err().contains("分类不存在"));
+    }
 }
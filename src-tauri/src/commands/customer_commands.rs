@@ -1,16 +1,27 @@
 use tauri::State;
-use crate::database::{connection::DbConnection, schema::{CustomerRepository, Repository}};
-use crate::models::Customer;
-use chrono::Utc;
+use crate::database::{connection::DbConnection, schema::{CustomerRepository, CustomerTransactionRepository, OrderEventRepository, OrderRepository, ProductRepository, Repository}};
+use crate::models::{
+    CsvImportReport, CsvImportRow, Customer, CustomerBalance, CustomerIdMapping, CustomerPage, CustomerResolution,
+    CustomerTransaction, CustomerTransferBundle, CustomerTransferEntry, CustomerTransferProduct, CustomerTransferReport, Order, OrderIdMapping, OrderItem,
+};
+use crate::utils::clock::SharedClock;
+use crate::utils::csv;
+use crate::utils::money::{from_cents, line_total_cents, to_cents};
 use rusqlite::params;
 
+// 客户分页的默认与最大每页条数
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+// 客户迁移文件的格式版本
+const TRANSFER_VERSION: u32 = 1;
+
 fn ensure_placeholder_customer_and_relink_orders(
-    conn: &DbConnection,
+    db: &rusqlite::Connection,
     original_customer_id: &str,
+    now: &str,
 ) -> Result<(), String> {
     let placeholder_id = format!("deleted_{}", original_customer_id);
-    let now = Utc::now().to_rfc3339();
-    let db = conn.lock().unwrap();
 
     let exists: i64 = db
         .query_row(
@@ -28,7 +39,7 @@ fn ensure_placeholder_customer_and_relink_orders(
                 &placeholder_id,
                 "已删除客户（历史保留）",
                 format!("原客户ID: {}", original_customer_id),
-                &now,
+                now,
             ],
         )
         .map_err(|e| e.to_string())?;
@@ -36,13 +47,88 @@ fn ensure_placeholder_customer_and_relink_orders(
 
     db.execute(
         "UPDATE orders SET customer_id = ?1, updated_at = ?2 WHERE customer_id = ?3",
-        params![&placeholder_id, &now, original_customer_id],
+        params![&placeholder_id, now, original_customer_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    // 归档订单没有外键，也要改指向占位客户，否则恢复时客户已不存在
+    db.execute(
+        "UPDATE orders_archive SET customer_id = ?1 WHERE customer_id = ?2",
+        params![&placeholder_id, original_customer_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    // 往来账随订单一起保留在占位客户下
+    db.execute(
+        "UPDATE customer_transactions SET customer_id = ?1 WHERE customer_id = ?2",
+        params![&placeholder_id, original_customer_id],
     )
     .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+fn add_customer_transaction(
+    conn: &DbConnection,
+    customer_id: &str,
+    amount: f64,
+    kind: &str,
+    order_id: Option<String>,
+    remark: Option<String>,
+    now: &str,
+) -> Result<CustomerTransaction, String> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err("金额必须大于 0".to_string());
+    }
+
+    let customer_repo = CustomerRepository::new(conn.clone());
+    customer_repo.get_by_id(customer_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("客户不存在: {}", customer_id),
+        e => e.to_string(),
+    })?;
+    if let Some(order_id) = &order_id {
+        OrderRepository::new(conn.clone()).get_by_id(order_id).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+            e => e.to_string(),
+        })?;
+    }
+
+    let transaction = CustomerTransaction {
+        id: uuid::Uuid::new_v4().to_string(),
+        customer_id: customer_id.to_string(),
+        amount,
+        kind: kind.to_string(),
+        order_id,
+        remark,
+        created_at: now.to_string(),
+    };
+
+    // 关联订单的往来账同时写入订单操作记录，两者在同一事务中完成
+    let mut db = conn.lock().unwrap();
+    let mut insert = || -> rusqlite::Result<()> {
+        let tx = db.transaction()?;
+        CustomerTransactionRepository::insert_on(&tx, &transaction)?;
+        if let Some(order_id) = &transaction.order_id {
+            let label = match kind {
+                "payment" => "收款",
+                "refund" => "退款",
+                _ => "挂账",
+            };
+            let detail = match &transaction.remark {
+                Some(remark) if !remark.trim().is_empty() => format!("{} {:.2}（{}）", label, amount, remark.trim()),
+                _ => format!("{} {:.2}", label, amount),
+            };
+            OrderEventRepository::record_on(&tx, order_id, kind, Some(&detail), now)?;
+        }
+        tx.commit()
+    };
+    insert().map_err(|e| {
+        log::error!("记录客户 {} 往来账失败: {}", customer_id, e);
+        e.to_string()
+    })?;
+    Ok(transaction)
+}
+
 #[tauri::command]
 pub async fn get_all_customers(
     conn: State<'_, DbConnection>,
@@ -69,10 +155,36 @@ pub async fn search_customers(
     repo.search(&query).map_err(|e| e.to_string())
 }
 
+/// 分页获取客户（关键字匹配姓名/车牌/电话），page 从 1 开始；
+/// sort_by 可选 name（默认）、last_purchase_at（最近购买在前）、created_at（最新创建在前）
+#[tauri::command]
+pub async fn get_customers_paged(
+    query: Option<String>,
+    sort_by: Option<String>,
+    page: Option<u32>,
+    page_size: Option<u32>,
+    conn: State<'_, DbConnection>,
+) -> Result<CustomerPage, String> {
+    let order_by = match sort_by.as_deref().unwrap_or("name") {
+        "name" => "name, id",
+        "last_purchase_at" => "last_purchase_at IS NULL, last_purchase_at DESC, name",
+        "created_at" => "created_at DESC, name",
+        other => return Err(format!("不支持的排序字段: {}", other)),
+    };
+
+    let page = page.unwrap_or(1).max(1);
+    let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let repo = CustomerRepository::new(conn.inner().clone());
+    repo.get_page(query.as_deref(), order_by, page_size, (page - 1) * page_size)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn save_customer(
     customer: Customer,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
     let repo = CustomerRepository::new(conn.inner().clone());
 
@@ -82,7 +194,7 @@ pub async fn save_customer(
         .map_err(|e| e.to_string())?;
 
     if let Some(existing) = matched {
-        let now = Utc::now().to_rfc3339();
+        let now = clock.now_rfc3339();
         let incoming_name = customer.name.trim().to_string();
         let incoming_phone = customer.phone.trim().to_string();
         let incoming_plate = customer.license_plate.trim().to_string();
@@ -99,15 +211,22 @@ pub async fn save_customer(
             updated_at: now,
         };
 
-        return repo.update(&merged).map_err(|e| e.to_string())
+        return repo.update(&merged).map_err(|e| {
+            log::error!("保存客户 {} 失败: {}", merged.id, e);
+            e.to_string()
+        });
     }
 
     let existing = repo.get_by_id(&customer.id);
-    if existing.is_ok() {
-        repo.update(&customer).map_err(|e| e.to_string())
+    let result = if existing.is_ok() {
+        repo.update(&customer)
     } else {
-        repo.insert(&customer).map_err(|e| e.to_string())
-    }
+        repo.insert(&customer)
+    };
+    result.map_err(|e| {
+        log::error!("保存客户 {} 失败: {}", customer.id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -115,6 +234,7 @@ pub async fn merge_customers(
     source_id: String,
     target_id: String,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
     if source_id == target_id {
         return Err("源客户和目标客户不能相同".to_string());
@@ -132,47 +252,1023 @@ pub async fn merge_customers(
         address: target.address.or(source.address),
         last_purchase_at: target.last_purchase_at.or(source.last_purchase_at),
         created_at: target.created_at,
-        updated_at: Utc::now().to_rfc3339(),
+        updated_at: clock.now_rfc3339(),
     };
 
-    // 先更新目标客户信息
-    repo.update(&merged).map_err(|e| e.to_string())?;
+    // 更新目标客户、转移订单与往来账、删除源客户在同一事务中完成
+    let mut db = conn.inner().lock().unwrap();
+    let mut merge = || -> rusqlite::Result<()> {
+        let tx = db.transaction()?;
+        tx.execute(
+            "UPDATE customers SET name = ?1, phone = ?2, license_plate = ?3,
+             address = ?4, last_purchase_at = ?5, updated_at = ?6 WHERE id = ?7",
+            params![
+                &merged.name,
+                &merged.phone,
+                &merged.license_plate,
+                &merged.address,
+                &merged.last_purchase_at,
+                &merged.updated_at,
+                &merged.id,
+            ],
+        )?;
 
-    // 再将历史订单指向目标客户，并删除源客户
-    let db = conn.inner().lock().unwrap();
-    db.execute(
-        "UPDATE orders SET customer_id = ?1, updated_at = ?2 WHERE customer_id = ?3",
-        params![target_id, Utc::now().to_rfc3339(), source_id],
-    )
-    .map_err(|e| e.to_string())?;
+        // 再将历史订单（含归档订单）指向目标客户，并删除源客户
+        tx.execute(
+            "UPDATE orders SET customer_id = ?1, updated_at = ?2 WHERE customer_id = ?3",
+            params![target_id, &merged.updated_at, source_id],
+        )?;
+        tx.execute(
+            "UPDATE orders_archive SET customer_id = ?1 WHERE customer_id = ?2",
+            params![target_id, source_id],
+        )?;
 
-    db.execute("DELETE FROM customers WHERE id = ?1", params![source_id])
-        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE customer_transactions SET customer_id = ?1 WHERE customer_id = ?2",
+            params![target_id, source_id],
+        )?;
 
-    Ok(())
+        tx.execute("DELETE FROM customers WHERE id = ?1", params![source_id])?;
+
+        // 记录合并关系，并把之前合并到源客户的记录改指向目标客户
+        tx.execute(
+            "UPDATE customer_merges SET target_id = ?1 WHERE target_id = ?2",
+            params![target_id, source_id],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO customer_merges (source_id, target_id, merged_at) VALUES (?1, ?2, ?3)",
+            params![source_id, target_id, &merged.updated_at],
+        )?;
+
+        tx.commit()
+    };
+    merge().map_err(|e| {
+        log::error!("合并客户 {} 到 {} 失败: {}", source_id, target_id, e);
+        e.to_string()
+    })
+}
+
+/// 将任意客户 ID（包括已合并客户与占位 ID）解析为当前有效的客户
+#[tauri::command]
+pub async fn resolve_customer(
+    id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<CustomerResolution, String> {
+    let repo = CustomerRepository::new(conn.inner().clone());
+    repo.resolve(&id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn delete_customer(
     id: String,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
-    ensure_placeholder_customer_and_relink_orders(conn.inner(), &id)?;
-    let repo = CustomerRepository::new(conn.inner().clone());
-    repo.delete(&id).map_err(|e| e.to_string())
+    let mut db = conn.inner().lock().unwrap();
+    let mut delete = || -> Result<(), String> {
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+        ensure_placeholder_customer_and_relink_orders(&tx, &id, &clock.now_rfc3339())?;
+        tx.execute("DELETE FROM customers WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())
+    };
+    delete().map_err(|e| {
+        log::error!("删除客户 {} 失败: {}", id, e);
+        e
+    })
 }
 
 #[tauri::command]
 pub async fn batch_delete_customers(
     ids: Vec<String>,
     conn: State<'_, DbConnection>,
-) -> Result<(), String> {
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    // 转移订单与删除客户在同一事务中完成，返回实际删除的客户数（不存在的 id 不计入）
+    let mut db = conn.inner().lock().unwrap();
+    let now = clock.now_rfc3339();
+    let mut delete = || -> Result<usize, String> {
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+        let mut deleted = 0;
+        for id in &ids {
+            ensure_placeholder_customer_and_relink_orders(&tx, id, &now)?;
+            deleted += tx
+                .execute("DELETE FROM customers WHERE id = ?1", params![id])
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(deleted)
+    };
+    delete().map_err(|e| {
+        log::error!("批量删除 {} 个客户失败: {}", ids.len(), e);
+        e
+    })
+}
+
+/// 记一笔客户挂账（赊账）
+#[tauri::command]
+pub async fn add_customer_charge(
+    customer_id: String,
+    amount: f64,
+    order_id: Option<String>,
+    remark: Option<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CustomerTransaction, String> {
+    add_customer_transaction(conn.inner(), &customer_id, amount, "charge", order_id, remark, &clock.now_rfc3339())
+}
+
+/// 记一笔客户还款，指定订单时同时记入该订单的操作记录
+#[tauri::command]
+pub async fn add_customer_payment(
+    customer_id: String,
+    amount: f64,
+    order_id: Option<String>,
+    remark: Option<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CustomerTransaction, String> {
+    add_customer_transaction(conn.inner(), &customer_id, amount, "payment", order_id, remark, &clock.now_rfc3339())
+}
+
+/// 记一笔退款给客户（冲减已还款），指定订单时同时记入该订单的操作记录
+#[tauri::command]
+pub async fn add_customer_refund(
+    customer_id: String,
+    amount: f64,
+    order_id: Option<String>,
+    remark: Option<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CustomerTransaction, String> {
+    add_customer_transaction(conn.inner(), &customer_id, amount, "refund", order_id, remark, &clock.now_rfc3339())
+}
+
+#[tauri::command]
+pub async fn get_customer_transactions(
+    customer_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<CustomerTransaction>, String> {
+    let repo = CustomerTransactionRepository::new(conn.inner().clone());
+    repo.get_by_customer(&customer_id).map_err(|e| e.to_string())
+}
+
+/// 客户当前余额（挂账合计 - 还款合计）
+#[tauri::command]
+pub async fn get_customer_balance(
+    customer_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<CustomerBalance, String> {
+    let customer_repo = CustomerRepository::new(conn.inner().clone());
+    let customer = customer_repo.get_by_id(&customer_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("客户不存在: {}", customer_id),
+        e => e.to_string(),
+    })?;
+
+    let repo = CustomerTransactionRepository::new(conn.inner().clone());
+    repo.get_balance(&customer.id, &customer.name).map_err(|e| e.to_string())
+}
+
+/// 所有仍有欠款的客户
+#[tauri::command]
+pub async fn get_outstanding_balances(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<CustomerBalance>, String> {
+    let repo = CustomerTransactionRepository::new(conn.inner().clone());
+    repo.get_outstanding().map_err(|e| e.to_string())
+}
+
+/// 按导出格式（姓名,电话,车牌号,地址,备注）逐行规划导入：有电话时按电话匹配已有客户，
+/// 否则按姓名+车牌匹配；文件内重复或内容无变化的行跳过。预览与实际导入共用此函数
+fn plan_customer_import(
+    records: &[Vec<String>],
+    first_line: usize,
+    existing: &[Customer],
+    now: &str,
+) -> Vec<(CsvImportRow, Option<Customer>)> {
+    let key_of = |name: &str, phone: &str, license_plate: &str| {
+        if phone.is_empty() {
+            format!("name:{}|{}", name, license_plate)
+        } else {
+            format!("phone:{}", phone)
+        }
+    };
+    let by_key: std::collections::HashMap<String, &Customer> = existing
+        .iter()
+        .map(|c| (key_of(c.name.trim(), c.phone.trim(), c.license_plate.trim()), c))
+        .collect();
+    let mut seen: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+    records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| {
+            let line = first_line + index;
+            let field = |i: usize| record.get(i).map(|v| v.trim()).unwrap_or_default();
+            let name = field(0).to_string();
+            let skip = |reason: String| {
+                (
+                    CsvImportRow { line, name: name.clone(), action: "skip".to_string(), reason: Some(reason) },
+                    None,
+                )
+            };
+
+            if name.is_empty() {
+                return skip("姓名为空".to_string());
+            }
+            let key = key_of(&name, field(1), field(2));
+            if let Some(first) = seen.get(&key) {
+                return skip(format!("与第 {} 行重复", first));
+            }
+            seen.insert(key.clone(), line);
+
+            let current = by_key.get(&key).copied();
+            let address = Some(field(3).to_string())
+                .filter(|a| !a.is_empty())
+                .or_else(|| current.and_then(|c| c.address.clone()));
+            let customer = Customer {
+                id: current.map(|c| c.id.clone()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                name: name.clone(),
+                phone: field(1).to_string(),
+                license_plate: field(2).to_string(),
+                address,
+                last_purchase_at: current.and_then(|c| c.last_purchase_at.clone()),
+                created_at: current.map(|c| c.created_at.clone()).unwrap_or_else(|| now.to_string()),
+                updated_at: now.to_string(),
+            };
+
+            match current {
+                None => (
+                    CsvImportRow { line, name, action: "insert".to_string(), reason: None },
+                    Some(customer),
+                ),
+                Some(old)
+                    if old.name == customer.name
+                        && old.phone == customer.phone
+                        && old.license_plate == customer.license_plate
+                        && old.address == customer.address =>
+                {
+                    skip("与现有客户一致，无需更新".to_string())
+                }
+                Some(_) => (
+                    CsvImportRow { line, name, action: "update".to_string(), reason: Some("匹配到已有客户，将更新资料".to_string()) },
+                    Some(customer),
+                ),
+            }
+        })
+        .collect()
+}
+
+/// 从 CSV 导入客户（格式同客户导出），全部写入在同一事务中完成，任一行失败时不导入任何客户。
+/// validate 为 true 时只返回逐行预览结果，不写入数据库
+#[tauri::command]
+pub async fn import_customers_csv(
+    path: String,
+    validate: Option<bool>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CsvImportReport, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+    let mut records = csv::parse(&content)?;
+    let first_line = if csv::strip_header(&mut records, &["name", "姓名"]) { 2 } else { 1 };
+
     let repo = CustomerRepository::new(conn.inner().clone());
+    let existing = repo.get_all().map_err(|e| e.to_string())?;
+
+    let planned = plan_customer_import(&records, first_line, &existing, &clock.now_rfc3339());
+    let dry_run = validate.unwrap_or(false);
 
-    for id in ids {
-        ensure_placeholder_customer_and_relink_orders(conn.inner(), &id)?;
-        repo.delete(&id).map_err(|e| e.to_string())?;
+    // 全部新增与更新在同一事务中写入，任一行失败时整批不导入
+    if !dry_run {
+        let mut db = conn.inner().lock().unwrap();
+        let mut import = || -> Result<(), String> {
+            let tx = db.transaction().map_err(|e| e.to_string())?;
+            for (row, customer) in &planned {
+                let Some(customer) = customer else { continue };
+                let result = if row.action == "update" {
+                    CustomerRepository::update_on(&tx, customer)
+                } else {
+                    CustomerRepository::insert_on(&tx, customer)
+                };
+                result.map_err(|e| format!("第 {} 行「{}」导入失败: {}", row.line, row.name, e))?;
+            }
+            tx.commit().map_err(|e| e.to_string())
+        };
+        import().map_err(|e| {
+            log::error!("客户导入失败: {}", e);
+            e
+        })?;
     }
 
-    Ok(())
+    let report = csv::import_report(planned.into_iter().map(|(row, _)| row).collect(), dry_run);
+    if !dry_run {
+        log::info!("客户导入完成: 新增 {} 个, 更新 {} 个, 跳过 {} 行", report.inserted, report.updated, report.skipped);
+    }
+    Ok(report)
+}
+
+/// 规范化电话：只保留数字（去掉空格、横线、括号等）
+fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// 规范化车牌：去掉空白、横线与间隔点，字母转大写
+fn normalize_plate(license_plate: &str) -> String {
+    license_plate
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '·' | '•' | '.'))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// 导出指定客户及其全部订单（含订单项）到 JSON 文件，用于迁移到另一台电脑，返回导出的客户数
+#[tauri::command]
+pub async fn export_customers_with_orders(
+    customer_ids: Vec<String>,
+    path: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    let customer_repo = CustomerRepository::new(conn.inner().clone());
+    let order_repo = OrderRepository::new(conn.inner().clone());
+
+    let mut customers = Vec::new();
+    for id in &customer_ids {
+        let customer = customer_repo.get_by_id(id).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("客户不存在: {}", id),
+            e => e.to_string(),
+        })?;
+        let orders = order_repo.get_by_customer(id).map_err(|e| e.to_string())?;
+        customers.push(CustomerTransferEntry { customer, orders });
+    }
+
+    // 附上订单项引用的商品条码与名称，导入时据此对应到目标电脑上的商品
+    let product_repo = ProductRepository::new(conn.inner().clone());
+    let mut products: Vec<CustomerTransferProduct> = Vec::new();
+    for item in customers.iter().flat_map(|entry| &entry.orders).flat_map(|order| &order.items) {
+        if item.id.is_empty() || products.iter().any(|product| product.id == item.id) {
+            continue;
+        }
+        if let Ok(product) = product_repo.get_by_id(&item.id) {
+            products.push(CustomerTransferProduct { id: product.id, name: product.name, barcode: product.barcode });
+        }
+    }
+
+    let count = customers.len();
+    let bundle = CustomerTransferBundle {
+        version: TRANSFER_VERSION,
+        exported_at: clock.now_rfc3339(),
+        customers,
+        products,
+    };
+    let content = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    std::fs::write(&path, content).map_err(|e| format!("写入数据文件失败: {}", e))?;
+
+    log::info!("已导出 {} 个客户及其订单到 {}", count, path);
+    Ok(count)
+}
+
+/// 导入 export_customers_with_orders 导出的客户及订单。客户按规范化后的电话（优先）或车牌匹配本机已有客户，
+/// 两者都没有的客户按名称匹配同样没有电话和车牌的客户，匹配不到时新建；订单关联到匹配/新建的客户，
+/// ID 或单号在本机已被占用时重新分配，返回新旧 ID 对照报告。订单项的商品按条码（优先）或名称对应到本机商品，
+/// 对应不到时不关联商品。客户在本机已有同单号（含追加后缀的单号）、同日期、同金额的订单视为已导入并跳过，
+/// 重复导入同一文件不会产生重复订单。整个导入在同一事务中完成，任一条失败时不写入任何数据
+#[tauri::command]
+pub async fn import_customers_with_orders(
+    path: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<CustomerTransferReport, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+    let bundle: CustomerTransferBundle =
+        serde_json::from_str(&content).map_err(|e| format!("数据文件格式错误: {}", e))?;
+    if bundle.version > TRANSFER_VERSION {
+        return Err(format!("数据文件版本 {} 高于当前支持的版本 {}，请先升级软件", bundle.version, TRANSFER_VERSION));
+    }
+
+    let mut db = conn.inner().lock().unwrap();
+    let mut import = || -> Result<CustomerTransferReport, String> {
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+        let report = import_transfer_bundle(&tx, &bundle, &clock.now_rfc3339()).map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(report)
+    };
+    let report = import().map_err(|e| {
+        log::error!("客户迁移导入失败: {}", e);
+        e
+    })?;
+
+    log::info!(
+        "客户迁移导入完成: 匹配 {} 个客户, 新建 {} 个客户, 导入 {} 个订单, 跳过 {} 个订单",
+        report.customers_matched,
+        report.customers_created,
+        report.orders_imported,
+        report.orders_skipped
+    );
+    Ok(report)
+}
+
+/// 在调用方的事务上导入迁移文件，见 import_customers_with_orders
+fn import_transfer_bundle(tx: &rusqlite::Connection, bundle: &CustomerTransferBundle, now: &str) -> Result<CustomerTransferReport, String> {
+    use std::collections::HashMap;
+
+    let exists = |sql: &str, id: &str| -> Result<bool, String> {
+        tx.query_row(sql, params![id], |_| Ok(()))
+            .map(|_| true)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(false),
+                e => Err(e.to_string()),
+            })
+    };
+
+    // 规范化身份 → 本机客户 ID（导入过程中新建的客户也加入，文件内同一身份的客户只建一次）；
+    // 没有电话和车牌的客户按去掉首尾空白的名称对应
+    let mut by_phone: HashMap<String, String> = HashMap::new();
+    let mut by_plate: HashMap<String, String> = HashMap::new();
+    let mut by_name: HashMap<String, String> = HashMap::new();
+    {
+        let mut stmt = tx
+            .prepare("SELECT id, name, phone, license_plate FROM customers ORDER BY created_at, id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, name, phone, plate) = row.map_err(|e| e.to_string())?;
+            let (phone, plate) = (normalize_phone(&phone), normalize_plate(&plate));
+            if !phone.is_empty() {
+                by_phone.entry(phone.clone()).or_insert_with(|| id.clone());
+            }
+            if !plate.is_empty() {
+                by_plate.entry(plate.clone()).or_insert_with(|| id.clone());
+            }
+            if phone.is_empty() && plate.is_empty() && !name.trim().is_empty() {
+                by_name.entry(name.trim().to_string()).or_insert(id);
+            }
+        }
+    }
+
+    // 文件中的商品 ID → 本机商品 ID：先按条码，再按唯一的同名商品
+    let mut local_by_barcode: HashMap<String, String> = HashMap::new();
+    let mut local_by_name: HashMap<String, Option<String>> = HashMap::new();
+    {
+        let mut stmt = tx.prepare("SELECT id, name, barcode FROM products").map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)))
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, name, barcode) = row.map_err(|e| e.to_string())?;
+            if let Some(barcode) = barcode.map(|b| b.trim().to_string()).filter(|b| !b.is_empty()) {
+                local_by_barcode.entry(barcode).or_insert_with(|| id.clone());
+            }
+            // 同名商品不止一个时无法确定，记为 None
+            local_by_name
+                .entry(name.trim().to_string())
+                .and_modify(|existing| *existing = None)
+                .or_insert(Some(id));
+        }
+    }
+    let source_products: HashMap<&str, &CustomerTransferProduct> =
+        bundle.products.iter().map(|product| (product.id.as_str(), product)).collect();
+    let map_product = |item: &OrderItem| -> String {
+        let source = source_products.get(item.id.as_str());
+        let by_barcode = source
+            .and_then(|product| product.barcode.as_deref())
+            .map(str::trim)
+            .filter(|barcode| !barcode.is_empty())
+            .and_then(|barcode| local_by_barcode.get(barcode));
+        let name = source.map(|product| product.name.as_str()).unwrap_or(&item.name).trim();
+        by_barcode
+            .cloned()
+            .or_else(|| local_by_name.get(name).cloned().flatten())
+            .unwrap_or_default()
+    };
+
+    let mut report = CustomerTransferReport {
+        customers: Vec::new(),
+        orders: Vec::new(),
+        customers_matched: 0,
+        customers_created: 0,
+        orders_imported: 0,
+        orders_skipped: 0,
+    };
+
+    for entry in &bundle.customers {
+        let source = &entry.customer;
+        let phone = normalize_phone(&source.phone);
+        let plate = normalize_plate(&source.license_plate);
+        let name = source.name.trim().to_string();
+        let matched = by_phone
+            .get(&phone)
+            .filter(|_| !phone.is_empty())
+            .or_else(|| by_plate.get(&plate).filter(|_| !plate.is_empty()))
+            .or_else(|| by_name.get(&name).filter(|_| phone.is_empty() && plate.is_empty() && !name.is_empty()))
+            .cloned();
+
+        let customer_id = match matched {
+            Some(id) => {
+                report.customers_matched += 1;
+                report.customers.push(CustomerIdMapping {
+                    source_id: source.id.clone(),
+                    target_id: id.clone(),
+                    name: source.name.clone(),
+                    action: "matched".to_string(),
+                });
+                id
+            }
+            None => {
+                let id_taken =
+                    source.id.trim().is_empty() || exists("SELECT 1 FROM customers WHERE id = ?1", &source.id)?;
+                let id = if id_taken { uuid::Uuid::new_v4().to_string() } else { source.id.clone() };
+                tx.execute(
+                    "INSERT INTO customers (id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        &id,
+                        &source.name,
+                        &source.phone,
+                        &source.license_plate,
+                        &source.address,
+                        &source.last_purchase_at,
+                        &source.created_at,
+                        now,
+                    ],
+                )
+                .map_err(|e| format!("客户「{}」导入失败: {}", source.name, e))?;
+                if !phone.is_empty() {
+                    by_phone.insert(phone.clone(), id.clone());
+                }
+                if !plate.is_empty() {
+                    by_plate.insert(plate.clone(), id.clone());
+                }
+                if phone.is_empty() && plate.is_empty() && !name.is_empty() {
+                    by_name.insert(name, id.clone());
+                }
+                report.customers_created += 1;
+                report.customers.push(CustomerIdMapping {
+                    source_id: source.id.clone(),
+                    target_id: id.clone(),
+                    name: source.name.clone(),
+                    action: "created".to_string(),
+                });
+                id
+            }
+        };
+
+        // 该客户在本机已有的订单，用于识别此前已导入过的订单（单号可能已追加后缀）
+        let existing_orders: Vec<(String, String, String, i64)> = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT id, order_number, date, COALESCE(total_amount_cents, CAST(ROUND(total_amount * 100) AS INTEGER))
+                     FROM orders WHERE customer_id = ?1",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![&customer_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            rows
+        };
+        for order in &entry.orders {
+            let suffixed = format!("{}-", order.order_number);
+            let existing = existing_orders.iter().find(|(_, number, date, total_cents)| {
+                *date == order.date
+                    && *total_cents == to_cents(order.total_amount)
+                    && (*number == order.order_number || number.starts_with(&suffixed))
+            });
+            if let Some((existing_id, existing_number, _, _)) = existing {
+                report.orders_skipped += 1;
+                report.orders.push(OrderIdMapping {
+                    source_id: order.id.clone(),
+                    target_id: existing_id.clone(),
+                    source_number: order.order_number.clone(),
+                    target_number: existing_number.clone(),
+                    customer_id: customer_id.clone(),
+                    action: "skipped".to_string(),
+                    reason: Some("本机该客户已有此订单".to_string()),
+                });
+                continue;
+            }
+
+            // 单号被其他订单占用时在原单号后追加后缀
+            let mut order_number = order.order_number.clone();
+            let mut suffix = 2;
+            while exists("SELECT 1 FROM orders WHERE order_number = ?1", &order_number)? {
+                order_number = format!("{}-{}", order.order_number, suffix);
+                suffix += 1;
+            }
+            let id_taken = order.id.trim().is_empty() || exists("SELECT 1 FROM orders WHERE id = ?1", &order.id)?;
+            let id = if id_taken { uuid::Uuid::new_v4().to_string() } else { order.id.clone() };
+            // 本机没有的模板不保留引用
+            let template_id = match order.template_id.clone() {
+                Some(template_id) if exists("SELECT 1 FROM templates WHERE id = ?1", &template_id)? => Some(template_id),
+                _ => None,
+            };
+
+            let mut imported = Order {
+                id: id.clone(),
+                order_number: order_number.clone(),
+                customer_id: customer_id.clone(),
+                template_id,
+                ..order.clone()
+            };
+            for item in &mut imported.items {
+                item.id = map_product(item);
+                // 行 ID 按新订单 ID 重新生成
+                item.line_id = None;
+                // 旧版本导出的订单项没有行金额
+                item.line_total = from_cents(line_total_cents(item.price, item.discount_price, item.quantity));
+            }
+            OrderRepository::insert_on(tx, &imported)
+                .map_err(|e| format!("订单 {} 导入失败: {}", order.order_number, e))?;
+            CustomerTransactionRepository::sync_order_charge_on(tx, &imported).map_err(|e| e.to_string())?;
+            OrderEventRepository::record_on(
+                tx,
+                &id,
+                "imported",
+                Some(&format!("迁移导入，原订单 {}（{}）", order.order_number, order.id)),
+                now,
+            )
+            .map_err(|e| e.to_string())?;
+
+            report.orders_imported += 1;
+            report.orders.push(OrderIdMapping {
+                source_id: order.id.clone(),
+                target_id: id,
+                source_number: order.order_number.clone(),
+                target_number: order_number,
+                customer_id: customer_id.clone(),
+                action: "imported".to_string(),
+                reason: None,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    fn archive_order(conn: &DbConnection, order_id: &str, customer_id: &str) {
+        {
+            let c = conn.lock().unwrap();
+            insert_order(&c, order_id, customer_id, "2023-06-01", "completed");
+            insert_item(&c, &format!("{}_p1", order_id), order_id, "p1", 10.0, 1.0, None);
+        }
+        OrderRepository::new(conn.clone()).archive_before("2024-01-01").unwrap();
+    }
+
+    fn archived_customer(conn: &DbConnection, order_id: &str) -> String {
+        conn.lock()
+            .unwrap()
+            .query_row("SELECT customer_id FROM orders_archive WHERE id = ?1", params![order_id], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn deleting_customers_relinks_archived_orders_so_they_can_be_restored() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_customer(&c, "c2", "李四", "13900000000", "B12345");
+        }
+        archive_order(&conn, "a1", "c1");
+        archive_order(&conn, "a2", "c2");
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        tauri::async_runtime::block_on(delete_customer("c1".to_string(), app.state(), app.state())).unwrap();
+        let deleted = tauri::async_runtime::block_on(batch_delete_customers(
+            vec!["c2".to_string(), "missing".to_string()],
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(archived_customer(&conn, "a1"), "deleted_c1");
+        assert_eq!(archived_customer(&conn, "a2"), "deleted_c2");
+
+        let repo = OrderRepository::new(conn.clone());
+        repo.restore_archived("a1").unwrap();
+        repo.restore_archived("a2").unwrap();
+        assert_eq!(repo.get_by_id("a1").unwrap().customer_id, "deleted_c1");
+        assert_eq!(repo.get_order_items("a2").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn merging_customers_moves_archived_orders_to_the_target() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_customer(&c, "source", "张三", "13800000000", "");
+            insert_customer(&c, "target", "张三", "", "A12345");
+        }
+        archive_order(&conn, "a1", "source");
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        tauri::async_runtime::block_on(merge_customers("source".to_string(), "target".to_string(), app.state(), app.state()))
+            .unwrap();
+        assert_eq!(archived_customer(&conn, "a1"), "target");
+        let target = CustomerRepository::new(conn.clone()).get_by_id("target").unwrap();
+        assert_eq!((target.phone.as_str(), target.license_plate.as_str()), ("13800000000", "A12345"));
+
+        OrderRepository::new(conn.clone()).restore_archived("a1").unwrap();
+        assert_eq!(OrderRepository::new(conn).get_by_id("a1").unwrap().customer_id, "target");
+    }
+
+    fn transfer_order(id: &str, customer: &Customer, items: &[(&str, &str, f64)]) -> Order {
+        let mut order = new_order(id, &customer.id, "completed", &[]);
+        order.order_number = id.to_string();
+        order.date = "2024-01-05".to_string();
+        order.customer = customer.clone();
+        order.items = items
+            .iter()
+            .enumerate()
+            .map(|(index, (product_id, name, price))| OrderItem {
+                id: product_id.to_string(),
+                name: name.to_string(),
+                unit: "个".to_string(),
+                price: *price,
+                quantity: 1.0,
+                category: String::new(),
+                discount_price: None,
+                remark: None,
+                sort_value: index as i64,
+                line_total: *price,
+                line_id: Some(format!("{}_{}", id, product_id)),
+            })
+            .collect();
+        order.total_amount = items.iter().map(|(_, _, price)| price).sum();
+        order
+    }
+
+    #[test]
+    fn importing_customers_with_orders_remaps_colliding_ids_and_products() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            // 本机已有同 ID 的其他客户、订单与商品
+            insert_customer(&c, "c1", "本机客户", "13800000000", "");
+            insert_customer(&c, "walkin", "散客", "", "");
+            insert_product(&c, "p1", 1.0, None);
+            insert_product(&c, "local_oil", 80.0, None);
+            c.execute("UPDATE products SET name = '机油', barcode = '690001' WHERE id = 'local_oil'", []).unwrap();
+            c.execute("UPDATE products SET name = '螺丝' WHERE id = 'p1'", []).unwrap();
+            insert_order(&c, "o1", "c1", "2024-01-01", "completed");
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let mut remote = new_order("", "c1", "", &[]).customer;
+        remote.name = "王五".to_string();
+        remote.phone = "139 0000 0000".to_string();
+        let mut walk_in = new_order("", "w9", "", &[]).customer;
+        walk_in.name = " 散客 ".to_string();
+        let bundle = CustomerTransferBundle {
+            version: TRANSFER_VERSION,
+            exported_at: "2024-03-01T00:00:00Z".to_string(),
+            customers: vec![
+                CustomerTransferEntry {
+                    customer: remote.clone(),
+                    orders: vec![transfer_order("o1", &remote, &[("p1", "机油", 90.0), ("p9", "滤芯", 30.0)])],
+                },
+                CustomerTransferEntry { customer: walk_in.clone(), orders: vec![transfer_order("o2", &walk_in, &[("p2", "螺丝", 2.0)])] },
+            ],
+            products: vec![CustomerTransferProduct { id: "p1".to_string(), name: "机油".to_string(), barcode: Some("690001".to_string()) }],
+        };
+        let path = std::env::temp_dir().join(format!("transfer_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, serde_json::to_string(&bundle).unwrap()).unwrap();
+        let import = || {
+            tauri::async_runtime::block_on(import_customers_with_orders(path.to_string_lossy().to_string(), app.state(), app.state()))
+                .unwrap()
+        };
+
+        let report = import();
+        assert_eq!((report.customers_created, report.customers_matched, report.orders_imported), (1, 1, 2));
+        let remote_id = &report.customers[0].target_id;
+        assert_ne!(remote_id, "c1");
+        assert_eq!(report.customers[1].target_id, "walkin");
+        let imported = &report.orders[0];
+        assert_ne!(imported.target_id, "o1");
+        assert_eq!(imported.target_number, "o1-2");
+
+        // 条码对应到本机商品，对应不到的不关联商品，同名的唯一商品按名称对应
+        let product_ids = |order_id: &str| -> Vec<Option<String>> {
+            let c = conn.lock().unwrap();
+            let mut stmt = c.prepare("SELECT product_id FROM order_items WHERE order_id = ?1 ORDER BY sort_value").unwrap();
+            let rows = stmt.query_map(params![order_id], |row| row.get(0)).unwrap();
+            rows.collect::<Result<Vec<_>, _>>().unwrap()
+        };
+        assert_eq!(product_ids(&imported.target_id), vec![Some("local_oil".to_string()), None]);
+        assert_eq!(product_ids(&report.orders[1].target_id), vec![Some("p1".to_string())]);
+        let items = OrderRepository::new(conn.clone()).get_order_items(&imported.target_id).unwrap();
+        assert_eq!(items.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec!["local_oil", ""]);
+
+        // 本机原有数据不受影响
+        let repo = OrderRepository::new(conn.clone());
+        assert_eq!(repo.get_by_id("o1").unwrap().customer_id, "c1");
+        assert_eq!(CustomerRepository::new(conn.clone()).get_by_id("c1").unwrap().name, "本机客户");
+
+        // 重复导入不产生重复的客户与订单
+        let again = import();
+        assert_eq!((again.customers_created, again.customers_matched, again.orders_skipped), (0, 2, 2));
+        assert_eq!(&again.customers[0].target_id, remote_id);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_import_leaves_no_partial_data() {
+        let conn = memory_db();
+        conn.lock().unwrap().execute_batch("CREATE TRIGGER reject_order BEFORE INSERT ON orders WHEN NEW.order_number = 'bad'
+             BEGIN SELECT RAISE(ABORT, '拒绝写入'); END;").unwrap();
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let mut customer = new_order("", "c1", "", &[]).customer;
+        customer.phone = "13800000000".to_string();
+        let bundle = CustomerTransferBundle {
+            version: TRANSFER_VERSION,
+            exported_at: "2024-03-01T00:00:00Z".to_string(),
+            customers: vec![CustomerTransferEntry {
+                customer: customer.clone(),
+                orders: vec![transfer_order("good", &customer, &[("p1", "机油", 90.0)]), transfer_order("bad", &customer, &[])],
+            }],
+            products: Vec::new(),
+        };
+        let path = std::env::temp_dir().join(format!("transfer_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, serde_json::to_string(&bundle).unwrap()).unwrap();
+
+        let result = tauri::async_runtime::block_on(import_customers_with_orders(path.to_string_lossy().to_string(), app.state(), app.state()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().contains("订单 bad 导入失败"));
+        let c = conn.lock().unwrap();
+        let count = |table: &str| c.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!((count("customers"), count("orders"), count("order_items"), count("order_events")), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn csv_import_writes_nothing_when_a_row_fails() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            c.execute_batch(
+                "CREATE TRIGGER reject_bad_row BEFORE INSERT ON customers WHEN NEW.name = '坏行'
+                 BEGIN SELECT RAISE(ABORT, '写入被拒绝'); END;",
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let path = std::env::temp_dir().join(format!("customers_{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "姓名,电话,车牌号,地址\n张三,13800000000,A12345,新地址\n李四,13900000000,,\n坏行,13700000000,,\n").unwrap();
+        let import = |validate: bool| {
+            tauri::async_runtime::block_on(import_customers_csv(
+                path.to_string_lossy().to_string(),
+                Some(validate),
+                app.state(),
+                app.state(),
+            ))
+        };
+
+        let preview = import(true).unwrap();
+        assert_eq!((preview.inserted, preview.updated, preview.skipped), (2, 1, 0));
+
+        let error = import(false).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("第 4 行「坏行」导入失败"), "{}", error);
+
+        // 之前的更新与新增随失败的行一起回滚
+        let customers = CustomerRepository::new(conn.clone()).get_all().unwrap();
+        assert_eq!(customers.len(), 1);
+        assert_eq!((customers[0].name.as_str(), customers[0].address.as_deref()), ("张三", None));
+    }
+
+    #[test]
+    fn order_payments_and_refunds_are_recorded_as_order_events() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-03-10", "completed");
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let pay = |kind: &str, amount: f64, remark: Option<&str>| {
+            clock.advance(chrono::Duration::seconds(1));
+            let remark = remark.map(str::to_string);
+            let result = if kind == "payment" {
+                tauri::async_runtime::block_on(add_customer_payment(
+                    "c1".to_string(), amount, Some("o1".to_string()), remark, app.state(), app.state(),
+                ))
+            } else {
+                tauri::async_runtime::block_on(add_customer_refund(
+                    "c1".to_string(), amount, Some("o1".to_string()), remark, app.state(), app.state(),
+                ))
+            };
+            result.unwrap()
+        };
+
+        pay("payment", 100.0, Some("微信"));
+        pay("refund", 30.0, None);
+
+        let events = OrderEventRepository::new(conn.clone()).get_by_order("o1").unwrap();
+        let events: Vec<_> = events.iter().map(|e| (e.event_type.as_str(), e.detail.as_deref())).collect();
+        assert_eq!(events, vec![("payment", Some("收款 100.00（微信）")), ("refund", Some("退款 30.00"))]);
+
+        // 退款冲减已还款
+        let balance = CustomerTransactionRepository::new(conn.clone()).get_balance("c1", "张三").unwrap();
+        assert_eq!((balance.paid, balance.balance), (70.0, -70.0));
+
+        // 订单不存在时不写入往来账
+        let error = tauri::async_runtime::block_on(add_customer_payment(
+            "c1".to_string(), 10.0, Some("missing".to_string()), None, app.state(), app.state(),
+        ))
+        .unwrap_err();
+        assert!(error.contains("订单不存在"), "{}", error);
+        assert_eq!(CustomerTransactionRepository::new(conn).get_by_customer("c1").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn customer_page_excludes_placeholders_from_items_and_total() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            for (id, name, plate, created_at, last_purchase_at) in [
+                ("c1", "张三", "A12345", "2024-01-01", Some("2024-03-05")),
+                ("c2", "张四", "B67890", "2024-01-02", None),
+                ("c3", "李五", "C11111", "2024-01-03", Some("2024-03-08")),
+                // 占位客户：临时客户、订单快照客户、删除后留下的占位记录
+                ("temp_1", "张临时", "A12345", "2024-01-04", Some("2024-03-09")),
+                ("order_customer_o1", "张三", "A12345", "2024-01-05", None),
+                ("deleted_c9", "张已删", "", "2024-01-06", None),
+            ] {
+                insert_customer(&c, id, name, "", plate);
+                c.execute(
+                    "UPDATE customers SET created_at = ?2, last_purchase_at = ?3 WHERE id = ?1",
+                    params![id, created_at, last_purchase_at],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let page = |query: Option<&str>, sort_by: Option<&str>, page: Option<u32>, page_size: Option<u32>| {
+            tauri::async_runtime::block_on(get_customers_paged(
+                query.map(str::to_string),
+                sort_by.map(str::to_string),
+                page,
+                page_size,
+                app.state(),
+            ))
+            .map(|page| (page.items.into_iter().map(|c| c.id).collect::<Vec<_>>(), page.total))
+        };
+        let ids = |ids: &[&str], total: i64| Ok((ids.iter().map(|id| id.to_string()).collect::<Vec<_>>(), total));
+
+        assert_eq!(page(None, None, None, None), ids(&["c1", "c2", "c3"], 3));
+        assert_eq!(page(Some("张"), None, None, None), ids(&["c1", "c2"], 2));
+        assert_eq!(page(Some("A123"), None, None, None), ids(&["c1"], 1));
+        assert_eq!(page(None, None, Some(2), Some(2)), ids(&["c3"], 3));
+        assert_eq!(page(None, Some("last_purchase_at"), None, None), ids(&["c3", "c1", "c2"], 3));
+        assert_eq!(page(None, Some("created_at"), None, None), ids(&["c3", "c2", "c1"], 3));
+        assert_eq!(page(None, Some("phone"), None, None).unwrap_err(), "不支持的排序字段: phone");
+    }
+
+    #[test]
+    fn resolve_customer_follows_merges_and_flags_placeholders() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000001", "A00001");
+            insert_customer(&c, "c2", "张三", "13800000002", "A00002");
+            insert_customer(&c, "c3", "张三", "13800000003", "A00003");
+            insert_customer(&c, "c4", "李四", "13900000000", "B00001");
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let merge = |source: &str, target: &str| {
+            tauri::async_runtime::block_on(merge_customers(source.to_string(), target.to_string(), app.state(), app.state()))
+                .unwrap()
+        };
+        merge("c1", "c2");
+        merge("c2", "c3");
+        tauri::async_runtime::block_on(delete_customer("c4".to_string(), app.state(), app.state())).unwrap();
+        let resolve = |id: &str| {
+            let resolution = tauri::async_runtime::block_on(resolve_customer(id.to_string(), app.state())).unwrap();
+            (resolution.customer.map(|c| c.id), resolution.is_placeholder, resolution.merged)
+        };
+
+        assert_eq!(resolve("c3"), (Some("c3".to_string()), false, false));
+        // 连续合并后旧 ID 指向最终的客户
+        assert_eq!(resolve("c1"), (Some("c3".to_string()), false, true));
+        assert_eq!(resolve("c2"), (Some("c3".to_string()), false, true));
+        // 已删除客户留下的占位 ID 不对应任何有效客户
+        assert_eq!(resolve("deleted_c4"), (None, true, false));
+        assert_eq!(resolve("temp_123"), (None, true, false));
+        assert_eq!(resolve("c4"), (None, false, false));
+        assert_eq!(resolve("nobody"), (None, false, false));
+    }
 }
@@ -0,0 +1,764 @@
+use tauri::State;
+use crate::database::{
+    connection::{Database, DbConnection},
+    migrations,
+    schema::SettingsRepository,
+    SettingsCache,
+};
+use crate::models::TableImportCount;
+use crate::utils::clock::SharedClock;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Local;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::DatabaseName;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// 数据包格式版本（2 起订单内嵌订单项与客户信息，并记录数据库结构版本）
+const BUNDLE_VERSION: i64 = 2;
+
+// 数据包包含的表，按外键依赖排列（被引用的表在前），导入时也按此顺序写入
+const BUNDLE_TABLES: &[&str] = &[
+    "categories",
+    "products",
+    "customers",
+    "customer_merges",
+    "templates",
+    "template_files",
+    "orders",
+    "orders_archive",
+    "order_items",
+    "customer_transactions",
+    "stock_movements",
+    "order_events",
+    "order_drafts",
+    "remark_presets",
+    "unit_presets",
+    "app_settings",
+];
+
+// 订单表与其订单项表：导出时订单项内嵌在订单的 items 中，不单独成表
+const ORDER_TABLES: &[(&str, &str)] = &[("orders", "order_items"), ("orders_archive", "order_items_archive")];
+
+// 没有声明外键、但按 ID 引用其他表的列：(表, 列, 被引用的表)
+const UNDECLARED_REFERENCES: &[(&str, &str, &str)] = &[
+    ("customer_merges", "target_id", "customers"),
+    ("orders_archive", "customer_id", "customers"),
+    ("order_items_archive", "order_id", "orders_archive"),
+    ("customer_transactions", "customer_id", "customers"),
+    ("customer_transactions", "order_id", "orders"),
+    ("stock_movements", "product_id", "products"),
+    ("stock_movements", "order_id", "orders"),
+    ("order_events", "order_id", "orders"),
+];
+
+// 单行的应用设置：导入时覆盖本机设置，其他表遇到冲突的记录一律跳过
+const OVERWRITE_TABLES: &[&str] = &["app_settings"];
+
+fn to_json_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(v) => json!(v),
+        ValueRef::Real(v) => json!(v),
+        ValueRef::Text(bytes) => json!(String::from_utf8_lossy(bytes)),
+        // 二进制列（模板文件）以 { "base64": "..." } 形式保存
+        ValueRef::Blob(bytes) => json!({ "base64": BASE64.encode(bytes) }),
+    }
+}
+
+fn to_sql_value(value: &Value) -> Result<SqlValue, String> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Object(obj) => match obj.get("base64").and_then(Value::as_str) {
+            Some(encoded) => SqlValue::Blob(
+                BASE64
+                    .decode(encoded)
+                    .map_err(|e| format!("二进制数据解码失败: {}", e))?,
+            ),
+            None => return Err("不支持的字段值".to_string()),
+        },
+        Value::Array(_) => return Err("不支持的字段值".to_string()),
+    })
+}
+
+/// 表的列名与主键列
+fn table_columns(db: &rusqlite::Connection, table: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut stmt = db
+        .prepare(&format!("PRAGMA table_info(\"{}\")", table))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, i64>(5)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let columns = rows.iter().map(|(name, _)| name.clone()).collect();
+    let primary_key = rows
+        .iter()
+        .filter(|(_, pk)| *pk > 0)
+        .map(|(name, _)| name.clone())
+        .collect();
+    Ok((columns, primary_key))
+}
+
+/// 表中引用其他表的列：(列, 被引用的表, 被引用记录删除时是否置空)，包括声明的外键与 UNDECLARED_REFERENCES
+fn table_references(db: &rusqlite::Connection, table: &str) -> Result<Vec<(String, String, bool)>, String> {
+    let mut stmt = db
+        .prepare(&format!("PRAGMA foreign_key_list(\"{}\")", table))
+        .map_err(|e| e.to_string())?;
+    let mut references = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(3)?, row.get::<_, String>(2)?, row.get::<_, String>(6)? == "SET NULL"))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    references.extend(
+        UNDECLARED_REFERENCES
+            .iter()
+            .filter(|(t, _, _)| *t == table)
+            .map(|(_, column, parent)| (column.to_string(), parent.to_string(), false)),
+    );
+    Ok(references)
+}
+
+/// 记录的主键值（用于报告跳过的记录）
+fn record_key(record: &Map<String, Value>, primary_key: &[String]) -> String {
+    primary_key
+        .iter()
+        .map(|c| match record.get(c) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 处理记录中指向本次导入被跳过记录的引用：可置空的引用（如商品分类、订单模板）改为 null，
+/// 其他引用（如订单的客户、订单项的订单）说明记录属于被跳过的数据，返回 None 表示整条跳过
+fn detach_skipped_references(
+    record: &Map<String, Value>,
+    references: &[(String, String, bool)],
+    skipped: &HashMap<String, HashSet<String>>,
+) -> Option<Map<String, Value>> {
+    let mut record = record.clone();
+    for (column, parent, nullable) in references {
+        let is_skipped = match (record.get(column).and_then(Value::as_str), skipped.get(parent)) {
+            (Some(id), Some(keys)) => keys.contains(id),
+            _ => false,
+        };
+        if !is_skipped {
+            continue;
+        }
+        if !nullable {
+            return None;
+        }
+        record.insert(column.clone(), Value::Null);
+    }
+    Some(record)
+}
+
+/// 执行查询，每行转为以列名为键的对象
+fn query_records<P: rusqlite::Params>(
+    db: &rusqlite::Connection,
+    sql: &str,
+    params: P,
+) -> Result<Vec<Map<String, Value>>, String> {
+    let mut stmt = db.prepare(sql).map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+    let mut records = Vec::new();
+    let mut result = stmt.query(params).map_err(|e| e.to_string())?;
+    while let Some(row) = result.next().map_err(|e| e.to_string())? {
+        let mut record = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            record.insert(column.clone(), to_json_value(value));
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// 按合并记录找到客户 ID 最终对应的客户（被合并的客户指向合并目标）
+fn resolve_merged_customer<'a>(merges: &'a HashMap<String, String>, mut customer_id: &'a str) -> &'a str {
+    // 限制跳转次数，避免合并记录成环时死循环
+    for _ in 0..merges.len() {
+        match merges.get(customer_id) {
+            Some(target) => customer_id = target,
+            None => break,
+        }
+    }
+    customer_id
+}
+
+/// 导出全部业务数据为一个 JSON 数据包并返回其内容（由前端保存，可用于迁移到其他电脑），
+/// 可由 import_all_json 导入。顶层 version 为数据包格式版本，schemaVersion 为导出时的数据库结构版本；
+/// 订单（含归档订单）内嵌订单项（items）与客户信息（customer），不依赖其他表即可读取。
+/// 全部表在同一个读事务中读取，导出内容是同一时刻的一致快照
+#[tauri::command]
+pub async fn export_all_json(
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<String, String> {
+    let (bundle, total_rows) = {
+        let mut db = conn.inner().lock().unwrap();
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+        let schema_version = migrations::current_version(&tx).map_err(|e| e.to_string())?;
+
+        let mut tables = Map::new();
+        let mut total_rows = 0;
+        let mut customers = HashMap::new();
+        let mut merges = HashMap::new();
+        for table in BUNDLE_TABLES {
+            // 订单项内嵌在订单中导出
+            if ORDER_TABLES.iter().any(|(_, items)| items == table) {
+                continue;
+            }
+            let mut rows = query_records(&tx, &format!("SELECT * FROM \"{}\"", table), [])?;
+            total_rows += rows.len();
+
+            match *table {
+                "customers" => {
+                    for row in &rows {
+                        if let Some(id) = row.get("id").and_then(Value::as_str) {
+                            customers.insert(id.to_string(), Value::Object(row.clone()));
+                        }
+                    }
+                }
+                "customer_merges" => {
+                    for row in &rows {
+                        if let (Some(source), Some(target)) =
+                            (row.get("source_id").and_then(Value::as_str), row.get("target_id").and_then(Value::as_str))
+                        {
+                            merges.insert(source.to_string(), target.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some((_, item_table)) = ORDER_TABLES.iter().find(|(orders, _)| orders == table) {
+                let mut items_by_order: HashMap<String, Vec<Value>> = HashMap::new();
+                for item in query_records(&tx, &format!("SELECT * FROM \"{}\" ORDER BY sort_value", item_table), [])? {
+                    let order_id = item.get("order_id").and_then(Value::as_str).unwrap_or_default().to_string();
+                    items_by_order.entry(order_id).or_default().push(Value::Object(item));
+                }
+                for row in &mut rows {
+                    let order_id = row.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+                    let items = items_by_order.remove(&order_id).unwrap_or_default();
+                    total_rows += items.len();
+                    // 订单不保存客户副本，内嵌的是导出时刻的客户记录；客户已被合并时取合并目标，已不存在时为 null
+                    let customer_id = row.get("customer_id").and_then(Value::as_str).unwrap_or_default().to_string();
+                    let customer = customers
+                        .get(resolve_merged_customer(&merges, &customer_id))
+                        .cloned()
+                        .unwrap_or(Value::Null);
+                    row.insert("items".to_string(), Value::Array(items));
+                    row.insert("customer".to_string(), customer);
+                }
+            }
+
+            tables.insert(table.to_string(), Value::Array(rows.into_iter().map(Value::Object).collect()));
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+
+        let bundle = json!({
+            "version": BUNDLE_VERSION,
+            "schemaVersion": schema_version,
+            "exportedAt": clock.now_rfc3339(),
+            "appVersion": env!("CARGO_PKG_VERSION"),
+            "tables": tables,
+        });
+        (bundle, total_rows)
+    };
+
+    log::info!("全部数据已导出为 JSON 数据包 ({} 行)", total_rows);
+    serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())
+}
+
+/// 把一条记录写入表，只写入当前表结构中存在的列（兼容不同版本导出的数据），返回是否写入。
+/// 主键或唯一约束与已有记录冲突时不写入（overwrite 时按主键覆盖已有记录）；记录中没有任何已知列时也不写入
+fn import_record(
+    tx: &rusqlite::Connection,
+    table: &str,
+    columns: &[String],
+    primary_key: &[String],
+    record: &Map<String, Value>,
+    overwrite: bool,
+) -> Result<bool, String> {
+    let present: Vec<&String> = columns.iter().filter(|c| record.contains_key(*c)).collect();
+    if present.is_empty() {
+        return Ok(false);
+    }
+    let values = present
+        .iter()
+        .map(|c| to_sql_value(&record[c.as_str()]))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", table, e))?;
+
+    let column_list = present.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", ");
+    let placeholders = (1..=present.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+    let updates: Vec<String> = present
+        .iter()
+        .filter(|c| !primary_key.contains(c))
+        .map(|c| format!("\"{0}\" = excluded.\"{0}\"", c))
+        .collect();
+    let conflict = if !overwrite {
+        "ON CONFLICT DO NOTHING".to_string()
+    } else if primary_key.is_empty() {
+        String::new()
+    } else if updates.is_empty() {
+        format!("ON CONFLICT({}) DO NOTHING", primary_key.join(", "))
+    } else {
+        format!("ON CONFLICT({}) DO UPDATE SET {}", primary_key.join(", "), updates.join(", "))
+    };
+
+    let changed = tx
+        .execute(
+            &format!("INSERT INTO \"{}\" ({}) VALUES ({}) {}", table, column_list, placeholders, conflict),
+            rusqlite::params_from_iter(values.iter()),
+        )
+        .map_err(|e| format!("导入 {} 失败: {}", table, e))?;
+    Ok(changed > 0)
+}
+
+/// 从 JSON 数据包导入数据，整个导入在同一事务中完成。
+/// tables 为空时导入数据包中的全部表，否则只导入指定的表（如只迁移模板或预设）；
+/// 导入订单时一并导入其内嵌的订单项（版本 1 的数据包中订单项为单独的 order_items 表）。
+/// 本机已有相同 ID（或相同订单号等唯一值）的记录不会被覆盖：该记录及属于它的记录（如订单项）都跳过，
+/// 主键列在返回结果的 skipped 中，可置空的引用改为空；只有应用设置会覆盖本机设置
+#[tauri::command]
+pub async fn import_all_json(
+    path: String,
+    tables: Option<Vec<String>>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<Vec<TableImportCount>, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取数据文件失败: {}", e))?;
+    let bundle: Value = serde_json::from_str(&content).map_err(|e| format!("数据文件格式错误: {}", e))?;
+    let version = bundle.get("version").and_then(Value::as_i64).unwrap_or(1);
+    if version > BUNDLE_VERSION {
+        return Err(format!("数据文件版本 {} 高于当前程序支持的版本 {}，请先升级程序", version, BUNDLE_VERSION));
+    }
+    let bundle_tables = bundle
+        .get("tables")
+        .and_then(Value::as_object)
+        .ok_or_else(|| "数据文件格式错误：缺少 tables".to_string())?;
+
+    // 校验要导入的表名，未指定时导入数据包中所有已知的表
+    let selected: Vec<&str> = match tables.filter(|t| !t.is_empty()) {
+        Some(names) => {
+            for name in &names {
+                if !BUNDLE_TABLES.contains(&name.as_str()) {
+                    return Err(format!("不支持导入的表: {}", name));
+                }
+                if !bundle_tables.contains_key(name) {
+                    return Err(format!("数据文件中没有表: {}", name));
+                }
+            }
+            BUNDLE_TABLES
+                .iter()
+                .copied()
+                .filter(|t| names.iter().any(|n| n == t))
+                .collect()
+        }
+        None => BUNDLE_TABLES
+            .iter()
+            .copied()
+            .filter(|t| bundle_tables.contains_key(*t))
+            .collect(),
+    };
+
+    let mut db = conn.inner().lock().unwrap();
+    let tx = db.transaction().map_err(|e| e.to_string())?;
+    // 外键在提交时统一检查，避免表内记录顺序影响导入
+    tx.execute_batch("PRAGMA defer_foreign_keys = ON")
+        .map_err(|e| e.to_string())?;
+
+    let mut counts = Vec::with_capacity(selected.len());
+    // 每个表中被跳过的记录主键，引用这些记录的数据随之跳过，避免挂到本机同 ID 的其他记录上
+    let mut skipped: HashMap<String, HashSet<String>> = HashMap::new();
+    for table in selected {
+        let rows = bundle_tables[table]
+            .as_array()
+            .ok_or_else(|| format!("数据文件格式错误：{} 不是数组", table))?;
+        let overwrite = OVERWRITE_TABLES.contains(&table);
+        let (columns, primary_key) = table_columns(&tx, table)?;
+        let references = table_references(&tx, table)?;
+        let item_table = match ORDER_TABLES.iter().find(|(orders, _)| *orders == table) {
+            Some((_, items)) => {
+                let (item_columns, item_key) = table_columns(&tx, items)?;
+                Some((*items, item_columns, item_key, table_references(&tx, items)?))
+            }
+            None => None,
+        };
+
+        let mut count = TableImportCount {
+            table: table.to_string(),
+            rows: 0,
+            skipped: Vec::new(),
+        };
+        let mut item_count = item_table.as_ref().map(|(items, ..)| TableImportCount {
+            table: items.to_string(),
+            rows: 0,
+            skipped: Vec::new(),
+        });
+        for row in rows {
+            let record = row
+                .as_object()
+                .ok_or_else(|| format!("数据文件格式错误：{} 中存在非对象记录", table))?;
+            let imported = match detach_skipped_references(record, &references, &skipped) {
+                Some(detached) => import_record(&tx, table, &columns, &primary_key, &detached, overwrite)?,
+                None => false,
+            };
+            if imported {
+                count.rows += 1;
+            } else {
+                let key = record_key(record, &primary_key);
+                skipped.entry(table.to_string()).or_default().insert(key.clone());
+                count.skipped.push(key);
+            }
+
+            if let (Some((items_table, item_columns, item_key, item_references)), Some(item_count)) =
+                (&item_table, &mut item_count)
+            {
+                for item in record.get("items").and_then(Value::as_array).into_iter().flatten() {
+                    let item = item
+                        .as_object()
+                        .ok_or_else(|| format!("数据文件格式错误：{} 中存在非对象订单项", table))?;
+                    let imported = match detach_skipped_references(item, item_references, &skipped) {
+                        Some(detached) => import_record(&tx, items_table, item_columns, item_key, &detached, false)?,
+                        None => false,
+                    };
+                    if imported {
+                        item_count.rows += 1;
+                    } else {
+                        let key = record_key(item, item_key);
+                        skipped.entry(items_table.to_string()).or_default().insert(key.clone());
+                        item_count.skipped.push(key);
+                    }
+                }
+            }
+        }
+
+        counts.push(count);
+        counts.extend(item_count.filter(|c| c.rows > 0 || !c.skipped.is_empty()));
+    }
+
+    // 旧版本导出的数据没有行金额，按单价与数量补齐
+    crate::database::backfill_line_totals(&tx).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| format!("导入失败: {}", e))?;
+    if counts.iter().any(|c| c.table == "app_settings") {
+        settings_cache.invalidate();
+    }
+
+    log::info!(
+        "数据导入完成: {}",
+        counts
+            .iter()
+            .map(|c| format!("{} {} 行（跳过 {} 行）", c.table, c.rows, c.skipped.len()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(counts)
+}
+
+/// 立即备份数据库到设置中的备份目录（未设置时为数据库所在目录下的 backups），
+/// 文件名为 quicksales_年月日_时分秒.db，返回备份文件路径。
+/// 使用 SQLite 在线备份接口，WAL 中尚未写回主文件的改动也会完整包含在备份中
+#[tauri::command]
+pub async fn backup_database(
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<String, String> {
+    let settings_repo = SettingsRepository::new(conn.inner().clone());
+    let configured = settings_cache
+        .get(&settings_repo)
+        .map_err(|e| e.to_string())?
+        .map(|s| s.backup_directory.trim().to_string())
+        .filter(|d| !d.is_empty());
+
+    let dir = match configured {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let db = conn.inner().lock().unwrap();
+            match db.path().filter(|p| !p.is_empty()) {
+                Some(db_path) => Path::new(db_path)
+                    .parent()
+                    .map(|parent| parent.join("backups"))
+                    .ok_or_else(|| "未设置备份目录".to_string())?,
+                None => return Err("未设置备份目录".to_string()),
+            }
+        }
+    };
+    std::fs::create_dir_all(&dir).map_err(|e| format!("无法创建备份目录 {:?}: {}", dir, e))?;
+
+    // 同一秒内多次备份时追加序号，不覆盖已有的备份
+    let stamp = clock.now().with_timezone(&Local).format("%Y%m%d_%H%M%S").to_string();
+    let mut path = dir.join(format!("quicksales_{}.db", stamp));
+    let mut suffix = 2;
+    while path.exists() {
+        path = dir.join(format!("quicksales_{}_{}.db", stamp, suffix));
+        suffix += 1;
+    }
+
+    let result = {
+        let db = conn.inner().lock().unwrap();
+        db.backup(DatabaseName::Main, &path, None)
+    };
+    if let Err(e) = result {
+        log::error!("备份数据库失败: {}", e);
+        let _ = std::fs::remove_file(&path);
+        return Err(format!("备份数据库失败: {}", e));
+    }
+
+    let path = path.to_string_lossy().to_string();
+    log::info!("数据库已备份: {}", path);
+    Ok(path)
+}
+
+/// 从备份文件恢复数据库（覆盖当前全部数据），恢复后按当前程序版本补齐表结构；
+/// 备份文件无效或缺少必需的表（商品、客户、订单、设置等）时拒绝恢复，当前数据保持不变。
+/// 附件文件不在数据库备份中，不会随之恢复
+#[tauri::command]
+pub async fn restore_database(
+    backup_path: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<(), String> {
+    let db = Database { conn: conn.inner().clone() };
+    db.restore_from(Path::new(&backup_path), &clock.now_rfc3339()).map_err(|e| {
+        log::error!("恢复数据库失败: {:#}", e);
+        format!("{:#}", e)
+    })?;
+    settings_cache.invalidate();
+
+    log::info!("数据库已从备份恢复: {}", backup_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::schema::OrderRepository;
+    use crate::test_support::{
+        insert_category, insert_customer, insert_item, insert_order, insert_product, memory_db, test_app,
+    };
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    #[test]
+    fn exported_bundle_embeds_order_details_and_round_trips() {
+        let source = memory_db();
+        {
+            let c = source.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 2.0, None);
+            insert_order(&c, "o2", "c1", "2023-01-05", "completed");
+            insert_item(&c, "i2", "o2", "p1", 10.0, 1.0, None);
+        }
+        OrderRepository::new(source.clone()).archive_before("2024-01-01").unwrap();
+        {
+            // 归档订单的客户已被合并：导出时应取合并目标
+            let c = source.lock().unwrap();
+            c.execute("UPDATE orders_archive SET customer_id = 'c0' WHERE id = 'o2'", []).unwrap();
+            c.execute("INSERT INTO customer_merges (source_id, target_id, merged_at) VALUES ('c0', 'c1', 'now')", [])
+                .unwrap();
+        }
+
+        let (app, _clock) = test_app(source.clone(), "2024-03-10T04:00:00Z");
+        let content = block_on(export_all_json(app.state(), app.state())).unwrap();
+        let bundle: Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(bundle["version"], BUNDLE_VERSION);
+        assert_eq!(
+            bundle["schemaVersion"],
+            migrations::current_version(&source.lock().unwrap()).unwrap()
+        );
+        let tables = &bundle["tables"];
+        assert!(tables.get("order_items").is_none());
+        assert_eq!(tables["orders"][0]["items"][0]["id"], "i1");
+        assert_eq!(tables["orders"][0]["customer"]["name"], "张三");
+        assert_eq!(tables["orders_archive"][0]["items"][0]["id"], "i2");
+        assert_eq!(tables["orders_archive"][0]["customer"]["id"], "c1");
+
+        let path = std::env::temp_dir().join(format!("bundle_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &content).unwrap();
+        let target = memory_db();
+        let (target_app, _clock) = test_app(target.clone(), "2024-03-10T04:00:00Z");
+        let counts = block_on(import_all_json(
+            path.to_string_lossy().to_string(),
+            None,
+            target_app.state(),
+            target_app.state(),
+        ))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(counts.iter().any(|c| c.table == "order_items" && c.rows == 1));
+        assert!(counts.iter().any(|c| c.table == "order_items_archive" && c.rows == 1));
+
+        let source_orders = OrderRepository::new(source.clone());
+        let target_orders = OrderRepository::new(target.clone());
+        assert_eq!(
+            serde_json::to_value(source_orders.get_all_with_details().unwrap()).unwrap(),
+            serde_json::to_value(target_orders.get_all_with_details().unwrap()).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(source_orders.get_archived().unwrap()).unwrap(),
+            serde_json::to_value(target_orders.get_archived().unwrap()).unwrap()
+        );
+    }
+
+    /// 导出源数据库的全部数据到临时文件，返回文件路径
+    fn export_to_file(source: &DbConnection) -> String {
+        let (app, _clock) = test_app(source.clone(), "2024-03-10T04:00:00Z");
+        let content = block_on(export_all_json(app.state(), app.state())).unwrap();
+        let path = std::env::temp_dir().join(format!("bundle_{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn insert_remark_preset(conn: &rusqlite::Connection, id: &str, content: &str) {
+        conn.execute(
+            "INSERT INTO remark_presets (id, content, type, created_at, updated_at) VALUES (?1, ?2, 'order', 'now', 'now')",
+            rusqlite::params![id, content],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn presets_only_import_keeps_existing_presets_and_other_tables() {
+        let source = memory_db();
+        {
+            let c = source.lock().unwrap();
+            insert_remark_preset(&c, "r1", "加急");
+            insert_remark_preset(&c, "r2", "送货上门");
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+        }
+        let path = export_to_file(&source);
+
+        let target = memory_db();
+        insert_remark_preset(&target.lock().unwrap(), "r1", "本机备注");
+        let (app, _clock) = test_app(target.clone(), "2024-03-10T04:00:00Z");
+        let counts = block_on(import_all_json(
+            path.clone(),
+            Some(vec!["remark_presets".to_string()]),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].table, "remark_presets");
+        assert_eq!(counts[0].skipped, vec!["r1".to_string()]);
+        let c = target.lock().unwrap();
+        let content = |id: &str| {
+            c.query_row("SELECT content FROM remark_presets WHERE id = ?1", [id], |row| row.get::<_, String>(0))
+                .unwrap()
+        };
+        assert_eq!(content("r1"), "本机备注");
+        assert_eq!(content("r2"), "送货上门");
+        let orders: i64 = c.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+        assert_eq!(orders, 0);
+    }
+
+    #[test]
+    fn conflicting_records_are_skipped_with_the_data_that_belongs_to_them() {
+        let source = memory_db();
+        {
+            let c = source.lock().unwrap();
+            insert_category(&c, "cat1", None, 0);
+            insert_product(&c, "p1", 10.0, Some("cat1"));
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 2.0, None);
+        }
+        let path = export_to_file(&source);
+
+        // 本机已有同 ID 的分类与客户，但内容不同
+        let target = memory_db();
+        {
+            let c = target.lock().unwrap();
+            insert_category(&c, "cat1", None, 0);
+            insert_customer(&c, "c1", "李四", "13900000000", "B67890");
+        }
+        let (app, _clock) = test_app(target.clone(), "2024-03-10T04:00:00Z");
+        let counts = block_on(import_all_json(path.clone(), None, app.state(), app.state())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let skipped = |table: &str| counts.iter().find(|c| c.table == table).unwrap().skipped.clone();
+        assert_eq!(skipped("categories"), vec!["cat1".to_string()]);
+        assert_eq!(skipped("customers"), vec!["c1".to_string()]);
+        assert_eq!(skipped("orders"), vec!["o1".to_string()]);
+        assert_eq!(skipped("order_items"), vec!["i1".to_string()]);
+
+        let c = target.lock().unwrap();
+        let name: String = c.query_row("SELECT name FROM customers WHERE id = 'c1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(name, "李四");
+        let orders: i64 = c.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap();
+        assert_eq!(orders, 0);
+        // 商品照常导入，指向被跳过分类的引用置空，不挂到本机的同 ID 分类下
+        let category: Option<String> =
+            c.query_row("SELECT category_id FROM products WHERE id = 'p1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(category, None);
+    }
+
+    /// 数据库中每个表的行数（按表名排序）
+    fn row_counts(conn: &rusqlite::Connection) -> Vec<(String, i64)> {
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        tables
+            .into_iter()
+            .map(|table| {
+                let count = conn
+                    .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))
+                    .unwrap();
+                (table, count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backup_opens_with_the_same_row_counts() {
+        let dir = std::env::temp_dir().join(format!("backups_{}", uuid::Uuid::new_v4()));
+        let source = memory_db();
+        {
+            let c = source.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 2.0, None);
+            c.execute("UPDATE app_settings SET backup_directory = ?1", [dir.to_string_lossy()]).unwrap();
+        }
+        let (app, _clock) = test_app(source.clone(), "2024-03-10T04:00:00Z");
+        let path = block_on(backup_database(app.state(), app.state(), app.state())).unwrap();
+
+        assert!(Path::new(&path).starts_with(&dir));
+        assert!(path.ends_with(".db"));
+        let backup = rusqlite::Connection::open(&path).unwrap();
+        let integrity: String = backup.query_row("PRAGMA integrity_check", [], |row| row.get(0)).unwrap();
+        assert_eq!(integrity, "ok");
+        assert_eq!(row_counts(&backup), row_counts(&source.lock().unwrap()));
+        drop(backup);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
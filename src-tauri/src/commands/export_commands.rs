@@ -0,0 +1,728 @@
+use tauri::State;
+use crate::database::{connection::DbConnection, schema::{CustomerRepository, OrderEventRepository, OrderRepository, Repository, SettingsRepository, TemplateRepository}, SettingsCache};
+use crate::models::{ExcelExport, Order, OrderItem, TemplateConfig};
+use crate::utils::clock::SharedClock;
+use crate::utils::filename::{build_output_filename, expand_filename_pattern, DEFAULT_FILENAME_PATTERN};
+use crate::utils::money::{from_cents, order_total_cents, to_cents};
+use crate::utils::validation::{validate_order_for_template, MISSING_FIELDS_ERROR};
+use crate::utils::xlsx::{self, Cell};
+use crate::utils::xlsx_template::{fill_template, shift_refs, RowInsertion, TemplateCell};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use umya_spreadsheet::{writer, Spreadsheet, Style};
+
+// 合并明细工作簿中金额列的数字格式
+const MONEY_FORMAT: &str = "¥#,##0.00";
+
+/// 按导出排序设置排列订单项（与前端 sortItemsForExport 一致）
+/// - entry：录入顺序
+/// - category：按分类快照分组，分组按首次出现的顺序，组内保持录入顺序
+/// - amount_desc：按行金额从高到低，金额相同保持录入顺序
+fn sort_items_for_export<'a>(items: &'a [OrderItem], mode: &str) -> Vec<&'a OrderItem> {
+    let mut sorted: Vec<&OrderItem> = items.iter().collect();
+    match mode {
+        "category" => {
+            let mut groups: Vec<&str> = Vec::new();
+            for item in items {
+                if !groups.contains(&item.category.as_str()) {
+                    groups.push(&item.category);
+                }
+            }
+            sorted.sort_by_key(|item| groups.iter().position(|g| *g == item.category));
+        }
+        "amount_desc" => {
+            sorted.sort_by_key(|item| std::cmp::Reverse(to_cents(item.line_total)));
+        }
+        _ => {}
+    }
+    sorted
+}
+
+/// 按订单的计税方式拆分金额（单位：分），返回 (税前小计, 税额, 总额)：明细合计扣减整单优惠后，
+/// 价格含税时从中倒算税额，不含税时按税率加税（与保存订单的 order_total_cents 一致）；
+/// 总额取订单保存的总额，抹零调整额只体现在总额中
+fn tax_breakdown(order: &Order, tax_rate: f64, prices_include_tax: bool) -> (i64, i64, i64) {
+    let lines_cents: i64 = order.items.iter().map(|item| to_cents(item.line_total)).sum();
+    let after_discount = (lines_cents - to_cents(order.order_discount)).max(0);
+    let (subtotal_cents, tax_cents) = if prices_include_tax {
+        let rate = if tax_rate > 0.0 { tax_rate / 100.0 } else { 0.0 };
+        let subtotal_cents = (after_discount as f64 / (1.0 + rate)).round() as i64;
+        (subtotal_cents, after_discount - subtotal_cents)
+    } else {
+        let (taxed, _) = order_total_cents(after_discount, 0, tax_rate, false, "none");
+        (after_discount, taxed - after_discount)
+    };
+    (subtotal_cents, tax_cents, to_cents(order.total_amount))
+}
+
+// Excel 日期格式中的年月日占位符
+static DATE_TOKEN_RE: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"YYYY|YY|MM|DD|M|D").unwrap());
+
+/// 按 Excel 日期格式（如 YYYY.MM.DD）格式化订单日期，支持 YYYY、YY、MM、DD、M、D（与单号格式的日期变量一致）。
+/// 格式为空或日期无法解析时原样返回
+fn format_excel_date(date: &str, format: &str) -> String {
+    let parsed = date
+        .get(..10)
+        .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+    let Some(parsed) = parsed.filter(|_| !format.trim().is_empty()) else {
+        return date.to_string();
+    };
+
+    // 一次扫描替换，避免 MM 替换后的数字再被 M 匹配
+    DATE_TOKEN_RE
+        .replace_all(format, |caps: &regex::Captures| {
+            let spec = match &caps[0] {
+                "YYYY" => "%Y",
+                "YY" => "%y",
+                "MM" => "%m",
+                "DD" => "%d",
+                "M" => "%-m",
+                _ => "%-d",
+            };
+            parsed.format(spec).to_string()
+        })
+        .into_owned()
+}
+
+/// 按模板映射生成要写入的单元格（未配置的映射跳过）。商品行数超过模板范围（起始行到结束行）时，
+/// 在结束行之前插入所需的行（沿用结束行的格式），结束行及以下的映射单元格随之下移。
+/// 金额按订单保存时的计税快照拆分，早于计税快照的订单使用 default_tax（当前设置的税率与是否含税）
+fn template_cells(
+    order: &Order,
+    template: &TemplateConfig,
+    default_tax: (f64, bool),
+    item_sort: &str,
+    date_format: &str,
+) -> (Vec<TemplateCell>, Option<RowInsertion>) {
+    let mappings = &template.mappings;
+    let columns = &mappings.columns;
+    let formats = &mappings.number_formats;
+    let has_items = mappings.item_start_row > 0 && !columns.name.trim().is_empty();
+    let start_row = mappings.item_start_row.max(0) as usize;
+    let end_row = mappings.item_end_row.max(0) as usize;
+    let capacity = end_row.saturating_sub(start_row) + 1;
+    let insertion = (has_items && end_row > 0 && order.items.len() > capacity).then(|| RowInsertion {
+        at: end_row.max(start_row) as u32,
+        count: (order.items.len() - capacity) as u32,
+    });
+
+    let mut cells: Vec<TemplateCell> = Vec::new();
+    let mut put = |address: &str, value: Cell, format: &str| {
+        if !address.trim().is_empty() {
+            let number_format = Some(format.trim().to_string()).filter(|f| !f.is_empty());
+            cells.push(TemplateCell { address: address.trim().to_string(), value, number_format });
+        }
+    };
+    // 客户、单号、金额等单元格按插入后的位置写入
+    let shifted = |address: &str| match insertion {
+        Some(RowInsertion { at, count }) => shift_refs(address.trim(), None, at, count),
+        None => address.to_string(),
+    };
+
+    put(&shifted(&mappings.customer_name), Cell::Text(order.customer.name.clone()), "");
+    put(&shifted(&mappings.customer_phone), Cell::Text(order.customer.phone.clone()), "");
+    put(&shifted(&mappings.customer_plate), Cell::Text(order.customer.license_plate.clone()), "");
+    put(&shifted(&mappings.date), Cell::Text(format_excel_date(&order.date, date_format)), "");
+    put(&shifted(&mappings.order_number), Cell::Text(order.order_number.clone()), "");
+    put(&shifted(&mappings.order_remark), Cell::Text(order.remark.clone().unwrap_or_default()), "");
+
+    let tax_rate = order.tax_rate.unwrap_or(default_tax.0);
+    let prices_include_tax = order.prices_include_tax.unwrap_or(default_tax.1);
+    let (subtotal_cents, tax_cents, total_cents) = tax_breakdown(order, tax_rate, prices_include_tax);
+    put(&shifted(&mappings.subtotal), Cell::Number(from_cents(subtotal_cents)), &formats.subtotal);
+    put(&shifted(&mappings.tax_amount), Cell::Number(from_cents(tax_cents)), &formats.tax_amount);
+    put(&shifted(&mappings.total_amount), Cell::Number(from_cents(total_cents)), &formats.total_amount);
+
+    if has_items {
+        for (index, item) in sort_items_for_export(&order.items, item_sort).into_iter().enumerate() {
+            let row = start_row + index;
+            let mut put_column =
+                |column: &str, value: Cell, format: &str| put(&format!("{}{}", column.trim(), row), value, format);
+            if !columns.index.trim().is_empty() {
+                put_column(&columns.index, Cell::Number((index + 1) as f64), "");
+            }
+            if !columns.name.trim().is_empty() {
+                put_column(&columns.name, Cell::Text(item.name.clone()), "");
+            }
+            if !columns.unit.trim().is_empty() {
+                put_column(&columns.unit, Cell::Text(item.unit.clone()), "");
+            }
+            if !columns.quantity.trim().is_empty() {
+                put_column(&columns.quantity, Cell::Number(item.quantity), &formats.quantity);
+            }
+            if !columns.price.trim().is_empty() {
+                put_column(&columns.price, Cell::Number(item.discount_price.unwrap_or(item.price)), &formats.price);
+            }
+            if !columns.total.trim().is_empty() {
+                put_column(&columns.total, Cell::Number(item.line_total), &formats.total);
+            }
+            if !columns.remark.trim().is_empty() {
+                put_column(&columns.remark, Cell::Text(item.remark.clone().unwrap_or_default()), "");
+            }
+        }
+    }
+
+    (cells, insertion)
+}
+
+/// 按模板导出订单：把客户、订单信息与商品行写入模板文件的第一个工作表，返回生成的 xlsx 文件内容（由前端保存）
+/// 与按文件名格式生成的文件名，并记录一次导出（导出次数加 1）。写入的单元格保留模板原有样式，模板配置了数字格式时改用配置的格式；
+/// 订单缺少模板要求的必填项时返回以「缺少必填项」开头的错误
+#[tauri::command]
+pub async fn export_order_to_excel(
+    order_id: String,
+    template_id: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<ExcelExport, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let mut order = order_repo.get_by_id(&order_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+        e => e.to_string(),
+    })?;
+    order.items = order_repo.get_order_items(&order.id).map_err(|e| e.to_string())?;
+    if let Ok(customer) = CustomerRepository::new(conn.inner().clone()).get_by_id(&order.customer_id) {
+        order.customer = customer;
+    }
+
+    let template = TemplateRepository::new(conn.inner().clone())
+        .get_by_id(&template_id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("模板不存在: {}", template_id),
+            e => e.to_string(),
+        })?;
+    validate_order_for_template(&order, &template.required_fields)
+        .map_err(|missing| format!("{}：{}", MISSING_FIELDS_ERROR, missing.join("；")))?;
+    if template.template_base64.is_empty() {
+        return Err("模板没有上传Excel文件，请先在设置中上传模板文件".to_string());
+    }
+    let template_file = BASE64
+        .decode(template.template_base64.trim())
+        .map_err(|e| format!("模板文件数据损坏: {}", e))?;
+
+    let settings = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?;
+    let (default_tax, item_sort, date_format) = settings
+        .as_ref()
+        .map(|s| ((s.tax_rate, s.prices_include_tax), s.export_item_sort.clone(), s.excel_date_format.clone()))
+        .unwrap_or(((0.0, true), "entry".to_string(), String::new()));
+    let filename = match &settings {
+        Some(settings) => build_output_filename(&template.filename_pattern, &order, settings),
+        None if template.filename_pattern.trim().is_empty() => expand_filename_pattern(DEFAULT_FILENAME_PATTERN, &order),
+        None => expand_filename_pattern(&template.filename_pattern, &order),
+    };
+
+    let (cells, insertion) = template_cells(&order, &template, default_tax, &item_sort, &date_format);
+    let bytes = fill_template(&template_file, &cells, insertion).map_err(|e| format!("生成 Excel 失败: {}", e))?;
+
+    let now = clock.now_rfc3339();
+    order_repo
+        .record_export(&order.id, &now)
+        .map_err(|e| e.to_string())?;
+    OrderEventRepository::new(conn.inner().clone())
+        .record(&order.id, "exported", Some(&filename), &now)
+        .map_err(|e| e.to_string())?;
+
+    log::info!("订单 {} 已按模板「{}」导出为 {}（{} 字节）", order.order_number, template.name, filename, bytes.len());
+    Ok(ExcelExport { filename, data: bytes })
+}
+
+/// 合并明细工作簿的单元格样式：(数据, 金额, 组标题, 小计)
+fn grouped_workbook_styles() -> (Style, Style, Style, Style) {
+    let data = xlsx::cell_style(false);
+    let mut money = data.clone();
+    money.get_number_format_mut().set_format_code(MONEY_FORMAT);
+
+    let mut group = xlsx::cell_style(false);
+    group.get_font_mut().set_bold(true);
+    group.set_background_color("FFDDEBF7");
+
+    let mut subtotal = money.clone();
+    subtotal.get_font_mut().set_bold(true);
+    subtotal.set_background_color("FFFFF0CC");
+    (data, money, group, subtotal)
+}
+
+/// 构建合并明细工作簿：
+/// - 「明细」表按订单分组，每组以订单号/日期/客户作为组标题（跨整行合并），组末为小计行（SUM 公式），组间空一行
+/// - 「汇总」表列出每个订单的总额（含整单优惠、税额与抹零）及总计
+fn build_grouped_orders_workbook(orders: &[Order], item_sort: &str) -> Result<Spreadsheet, String> {
+    let mut book = umya_spreadsheet::new_file_empty_worksheet();
+    let header_style = xlsx::cell_style(true);
+    let (data_style, money_style, group_style, subtotal_style) = grouped_workbook_styles();
+
+    let detail = book.new_sheet("明细").map_err(|e| e.to_string())?;
+    let columns = [("商品名称", 20.0), ("数量", 10.0), ("单位", 10.0), ("单价", 12.0), ("小计", 12.0), ("备注", 15.0)];
+    for (c, (title, width)) in columns.iter().enumerate() {
+        detail.get_column_dimension_mut(&xlsx::column_name(c)).set_width(*width);
+        detail
+            .get_cell_mut((c as u32 + 1, 1))
+            .set_value_string(*title)
+            .set_style(header_style.clone());
+    }
+    detail.set_sheets_views(xlsx::frozen_header_view(true));
+
+    let mut row = 2;
+    for order in orders {
+        detail
+            .get_cell_mut((1, row))
+            .set_value_string(format!("订单号: {}    日期: {}    客户: {}", order.order_number, order.date, order.customer.name))
+            .set_style(group_style.clone());
+        detail.add_merge_cells(format!("A{0}:F{0}", row));
+        row += 1;
+
+        let first_item_row = row;
+        let mut subtotal_cents = 0;
+        for item in sort_items_for_export(&order.items, item_sort) {
+            subtotal_cents += to_cents(item.line_total);
+            detail.get_cell_mut((1, row)).set_value_string(item.name.clone()).set_style(data_style.clone());
+            detail.get_cell_mut((2, row)).set_value_number(item.quantity).set_style(data_style.clone());
+            detail.get_cell_mut((3, row)).set_value_string(item.unit.clone()).set_style(data_style.clone());
+            detail
+                .get_cell_mut((4, row))
+                .set_value_number(item.discount_price.unwrap_or(item.price))
+                .set_style(money_style.clone());
+            detail.get_cell_mut((5, row)).set_value_number(item.line_total).set_style(money_style.clone());
+            detail
+                .get_cell_mut((6, row))
+                .set_value_string(item.remark.clone().unwrap_or_default())
+                .set_style(data_style.clone());
+            row += 1;
+        }
+
+        // 组小计：明细行合计，没有明细时为 0
+        detail.get_cell_mut((1, row)).set_value_string("小计").set_style(subtotal_style.clone());
+        let subtotal = detail.get_cell_mut((5, row));
+        if row > first_item_row {
+            subtotal
+                .set_formula(format!("SUM(E{}:E{})", first_item_row, row - 1))
+                .set_formula_result_default(from_cents(subtotal_cents).to_string());
+        } else {
+            subtotal.set_value_number(0.0);
+        }
+        subtotal.set_style(subtotal_style.clone());
+        row += 2;
+    }
+
+    let summary = book.new_sheet("汇总").map_err(|e| e.to_string())?;
+    let columns = [("订单号", 18.0), ("日期", 12.0), ("客户", 20.0), ("订单总额", 15.0)];
+    for (c, (title, width)) in columns.iter().enumerate() {
+        summary.get_column_dimension_mut(&xlsx::column_name(c)).set_width(*width);
+        summary
+            .get_cell_mut((c as u32 + 1, 1))
+            .set_value_string(*title)
+            .set_style(header_style.clone());
+    }
+    summary.set_sheets_views(xlsx::frozen_header_view(false));
+
+    let mut total_cents = 0;
+    for (index, order) in orders.iter().enumerate() {
+        let row = index as u32 + 2;
+        total_cents += to_cents(order.total_amount);
+        summary.get_cell_mut((1, row)).set_value_string(order.order_number.clone()).set_style(data_style.clone());
+        summary.get_cell_mut((2, row)).set_value_string(order.date.clone()).set_style(data_style.clone());
+        summary.get_cell_mut((3, row)).set_value_string(order.customer.name.clone()).set_style(data_style.clone());
+        summary.get_cell_mut((4, row)).set_value_number(order.total_amount).set_style(money_style.clone());
+    }
+
+    let total_row = orders.len() as u32 + 2;
+    summary.get_cell_mut((1, total_row)).set_value_string("总计").set_style(subtotal_style.clone());
+    let total = summary.get_cell_mut((4, total_row));
+    if orders.is_empty() {
+        total.set_value_number(0.0);
+    } else {
+        total
+            .set_formula(format!("SUM(D2:D{})", total_row - 1))
+            .set_formula_result_default(from_cents(total_cents).to_string());
+    }
+    total.set_style(subtotal_style);
+
+    Ok(book)
+}
+
+/// 导出合并明细工作簿（所有订单的明细在同一张表中按订单分组，附汇总表）到 path，
+/// 订单按 order_ids 的顺序排列，明细按导出排序设置排列，返回导出的订单数
+#[tauri::command]
+pub async fn export_orders_grouped(
+    order_ids: Vec<String>,
+    path: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<usize, String> {
+    let orders = OrderRepository::new(conn.inner().clone())
+        .get_by_ids_with_details(&order_ids)
+        .map_err(|e| e.to_string())?;
+    if orders.is_empty() {
+        return Err("没有可导出的订单".to_string());
+    }
+    let item_sort = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?
+        .map(|s| s.export_item_sort)
+        .unwrap_or_else(|| "entry".to_string());
+
+    let book = build_grouped_orders_workbook(&orders, &item_sort)?;
+    let mut content = std::io::Cursor::new(Vec::new());
+    writer::xlsx::write_writer(&book, &mut content).map_err(|e| format!("生成合并明细失败: {}", e))?;
+    std::fs::write(&path, content.into_inner()).map_err(|e| {
+        log::error!("写入合并明细失败: {}", e);
+        format!("写入合并明细失败: {}", e)
+    })?;
+
+    log::info!("合并明细已导出: {} ({} 个订单)", path, orders.len());
+    Ok(orders.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{RequiredFields, TemplateMappings};
+    use crate::test_support::new_order;
+    use std::io::Cursor;
+
+    /// 明细 100 + 50 元、整单优惠 10 元、抹零 -0.2 元的订单，行金额已按单价 × 数量算好
+    fn priced_order(tax_rate: Option<f64>, prices_include_tax: Option<bool>, total: f64) -> Order {
+        let mut order = new_order("o1", "c1", "completed", &[("p1", 50.0, 2.0), ("p2", 25.0, 2.0)]);
+        for item in &mut order.items {
+            item.line_total = item.price * item.quantity;
+        }
+        order.order_discount = 10.0;
+        order.rounding_adjustment = -0.2;
+        order.total_amount = total;
+        order.tax_rate = tax_rate;
+        order.prices_include_tax = prices_include_tax;
+        order
+    }
+
+    fn template(mappings: TemplateMappings) -> TemplateConfig {
+        TemplateConfig {
+            id: "t1".to_string(),
+            name: "报价单".to_string(),
+            template_base64: String::new(),
+            has_file: false,
+            file_name: "报价单.xlsx".to_string(),
+            filename_pattern: String::new(),
+            is_default: false,
+            mappings,
+            required_fields: RequiredFields::default(),
+            created_at: String::new(),
+            updated_at: String::new(),
+            number_format: None,
+        }
+    }
+
+    #[test]
+    fn tax_breakdown_follows_the_order_tax_snapshot_discount_and_rounding() {
+        // 含税 13%：优惠后 140 元中倒算税额，总额 139.8 含抹零
+        let included = priced_order(Some(13.0), Some(true), 139.8);
+        assert_eq!(tax_breakdown(&included, 13.0, true), (12389, 1611, 13980));
+
+        // 不含税 13%：优惠后 140 元加税 18.2 元，抹零后总额 158
+        let excluded = priced_order(Some(13.0), Some(false), 158.0);
+        assert_eq!(tax_breakdown(&excluded, 13.0, false), (14000, 1820, 15800));
+
+        // 不计税：小计为优惠后的金额
+        assert_eq!(tax_breakdown(&included, 0.0, true), (14000, 0, 13980));
+    }
+
+    #[test]
+    fn template_cells_use_the_snapshot_over_settings_and_apply_number_formats() {
+        let mut mappings = TemplateMappings {
+            subtotal: "F10".to_string(),
+            tax_amount: "F11".to_string(),
+            total_amount: "F12".to_string(),
+            item_start_row: 5,
+            item_end_row: 8,
+            ..Default::default()
+        };
+        mappings.columns.name = "B".to_string();
+        mappings.columns.quantity = "D".to_string();
+        mappings.columns.total = "F".to_string();
+        mappings.number_formats.quantity = "0.000".to_string();
+        mappings.number_formats.total_amount = "#,##0.00".to_string();
+
+        // 订单按不含税 13% 保存，之后设置改为含税 6% 不影响导出
+        let order = priced_order(Some(13.0), Some(false), 158.0);
+        let (cells, insertion) = template_cells(&order, &template(mappings), (6.0, true), "entry", "");
+        assert!(insertion.is_none());
+
+        let find = |address: &str| cells.iter().find(|c| c.address == address).expect(address);
+        let number = |address: &str| match find(address).value {
+            Cell::Number(n) => n,
+            _ => panic!("{} 不是数字", address),
+        };
+        assert_eq!(number("F10"), 140.0);
+        assert_eq!(number("F11"), 18.2);
+        assert_eq!(number("F12"), 158.0);
+        assert_eq!(find("F12").number_format.as_deref(), Some("#,##0.00"));
+        assert_eq!(find("F10").number_format, None);
+        assert_eq!(find("D5").number_format.as_deref(), Some("0.000"));
+        assert_eq!(find("F5").number_format, None);
+    }
+
+    #[test]
+    fn filled_template_reads_back_with_values_styles_and_formats() {
+        let mut book = umya_spreadsheet::new_file();
+        {
+            let sheet = book.get_sheet_mut(&0).unwrap();
+            sheet.get_cell_mut("A1").set_value_string("客户");
+            sheet.get_style_mut("B1").get_font_mut().set_bold(true);
+            sheet.get_style_mut("D5").get_number_format_mut().set_format_code("0.00");
+            sheet.get_cell_mut("F8").set_formula("SUM(F5:F7)").set_formula_result_default("0");
+        }
+        let mut bytes = Cursor::new(Vec::new());
+        umya_spreadsheet::writer::xlsx::write_writer(&book, &mut bytes).unwrap();
+
+        let mut mappings = TemplateMappings {
+            customer_name: "B1".to_string(),
+            total_amount: "F9".to_string(),
+            item_start_row: 5,
+            item_end_row: 7,
+            ..Default::default()
+        };
+        mappings.columns.name = "B".to_string();
+        mappings.columns.quantity = "D".to_string();
+        mappings.columns.total = "F".to_string();
+        mappings.number_formats.total = "#,##0.00".to_string();
+
+        let order = priced_order(Some(13.0), Some(false), 158.0);
+        let (cells, insertion) = template_cells(&order, &template(mappings), (0.0, true), "entry", "");
+        let output = fill_template(&bytes.into_inner(), &cells, insertion).unwrap();
+
+        let book = umya_spreadsheet::reader::xlsx::read_reader(Cursor::new(output), true).unwrap();
+        let sheet = book.get_sheet(&0).unwrap();
+        assert_eq!(sheet.get_value("A1"), "客户");
+        assert_eq!(sheet.get_value("B1"), "测试客户");
+        assert!(*sheet.get_style("B1").get_font().unwrap().get_bold());
+        assert_eq!(sheet.get_value("B5"), "p1");
+        assert_eq!(sheet.get_value("D6"), "2");
+        // 模板单元格原有的数字格式保留，配置了格式的列改用配置的格式
+        assert_eq!(sheet.get_style("D5").get_number_format().unwrap().get_format_code(), "0.00");
+        assert_eq!(sheet.get_style("F5").get_number_format().unwrap().get_format_code(), "#,##0.00");
+        assert_eq!(sheet.get_value("F9"), "158");
+        // 公式保留，旧的计算结果清空，打开时重新计算
+        assert_eq!(sheet.get_cell("F8").unwrap().get_formula(), "SUM(F5:F7)");
+        assert_eq!(sheet.get_value("F8"), "");
+    }
+
+    #[test]
+    fn item_rows_are_inserted_only_when_items_exceed_the_window() {
+        let mut mappings = TemplateMappings {
+            total_amount: "F8".to_string(),
+            item_start_row: 5,
+            item_end_row: 6,
+            ..Default::default()
+        };
+        mappings.columns.name = "B".to_string();
+        let template = template(mappings);
+        let order_with = |count: usize| {
+            let items: Vec<(String, f64, f64)> = (0..count).map(|i| (format!("p{}", i), 1.0, 1.0)).collect();
+            let items: Vec<(&str, f64, f64)> = items.iter().map(|(id, price, qty)| (id.as_str(), *price, *qty)).collect();
+            new_order("o1", "c1", "completed", &items)
+        };
+        let addresses = |cells: &[TemplateCell]| cells.iter().map(|c| c.address.clone()).collect::<Vec<_>>();
+
+        // 没有商品：商品行留空，合计位置不变
+        let (cells, insertion) = template_cells(&order_with(0), &template, (0.0, true), "entry", "");
+        assert!(insertion.is_none());
+        assert_eq!(addresses(&cells), vec!["F8"]);
+
+        // 正好填满：不插入
+        let (cells, insertion) = template_cells(&order_with(2), &template, (0.0, true), "entry", "");
+        assert!(insertion.is_none());
+        assert_eq!(addresses(&cells), vec!["F8", "B5", "B6"]);
+
+        // 多出 1 行：在结束行之前插入 1 行，合计随之下移
+        let (cells, insertion) = template_cells(&order_with(3), &template, (0.0, true), "entry", "");
+        let insertion = insertion.unwrap();
+        assert_eq!((insertion.at, insertion.count), (6, 1));
+        assert_eq!(addresses(&cells), vec!["F9", "B5", "B6", "B7"]);
+    }
+
+    #[test]
+    fn index_column_numbers_item_rows_from_one_for_each_order() {
+        let mut mappings = TemplateMappings { item_start_row: 5, item_end_row: 9, ..Default::default() };
+        mappings.columns.name = "B".to_string();
+        let index_cells = |template: &TemplateConfig, order: &Order, item_sort: &str| {
+            let (cells, _) = template_cells(order, template, (0.0, true), item_sort, "");
+            cells
+                .into_iter()
+                .filter(|c| c.address.starts_with('A'))
+                .map(|c| match c.value {
+                    Cell::Number(n) => (c.address, n),
+                    _ => panic!("{} 不是数字", c.address),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // 未配置序号列：不写序号
+        let first = new_order("o1", "c1", "completed", &[("p1", 1.0, 1.0), ("p2", 5.0, 1.0), ("p3", 3.0, 1.0)]);
+        assert!(index_cells(&template(mappings.clone()), &first, "entry").is_empty());
+
+        mappings.columns.index = "A".to_string();
+        let template = template(mappings);
+        let expected = vec![("A5".to_string(), 1.0), ("A6".to_string(), 2.0), ("A7".to_string(), 3.0)];
+        assert_eq!(index_cells(&template, &first, "entry"), expected);
+
+        // 下一张订单从 1 重新编号
+        let second = new_order("o2", "c1", "completed", &[("p4", 2.0, 1.0)]);
+        assert_eq!(index_cells(&template, &second, "entry"), vec![("A5".to_string(), 1.0)]);
+
+        // 序号按导出后的行顺序编排，而不是明细原顺序
+        let mut sorted = first.clone();
+        for item in &mut sorted.items {
+            item.line_total = item.price * item.quantity;
+        }
+        let (cells, _) = template_cells(&sorted, &template, (0.0, true), "amount_desc", "");
+        let name_at = |address: &str| match &cells.iter().find(|c| c.address == address).unwrap().value {
+            Cell::Text(name) => name.clone(),
+            _ => panic!("{} 不是文本", address),
+        };
+        assert_eq!((name_at("B5"), name_at("B6"), name_at("B7")), ("p2".to_string(), "p3".to_string(), "p1".to_string()));
+        assert_eq!(index_cells(&template, &sorted, "amount_desc"), expected);
+    }
+
+    #[test]
+    fn item_rows_follow_the_export_sort_mode() {
+        let mut mappings = TemplateMappings { item_start_row: 5, item_end_row: 10, ..Default::default() };
+        mappings.columns.name = "B".to_string();
+        let template = template(mappings);
+        let mut order =
+            new_order("o1", "c1", "completed", &[("p1", 5.0, 1.0), ("p2", 20.0, 1.0), ("p3", 10.0, 1.0), ("p4", 20.0, 1.0)]);
+        for (item, category) in order.items.iter_mut().zip(["保养", "配件", "保养", "配件"]) {
+            item.line_total = item.price * item.quantity;
+            item.category = category.to_string();
+        }
+        let rows = |item_sort: &str| {
+            let (cells, _) = template_cells(&order, &template, (0.0, true), item_sort, "");
+            cells
+                .into_iter()
+                .map(|c| match c.value {
+                    Cell::Text(name) => format!("{}={}", c.address, name),
+                    _ => panic!("{} 不是文本", c.address),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(rows("entry"), vec!["B5=p1", "B6=p2", "B7=p3", "B8=p4"]);
+        // 按分类快照分组，组按首次出现的顺序，组内保持录入顺序
+        assert_eq!(rows("category"), vec!["B5=p1", "B6=p3", "B7=p2", "B8=p4"]);
+        // 金额相同的行保持录入顺序
+        assert_eq!(rows("amount_desc"), vec!["B5=p2", "B6=p4", "B7=p3", "B8=p1"]);
+        // 未知的模式按录入顺序
+        assert_eq!(rows("unknown"), rows("entry"));
+    }
+
+    #[test]
+    fn each_template_export_increments_the_order_export_counter() {
+        use crate::commands::get_order_export_info;
+        use crate::test_support::{insert_customer, insert_item, insert_order, memory_db, test_app};
+        use tauri::Manager;
+
+        let conn = memory_db();
+        let mut book_bytes = Cursor::new(Vec::new());
+        umya_spreadsheet::writer::xlsx::write_writer(&umya_spreadsheet::new_file(), &mut book_bytes).unwrap();
+        let mut mappings = TemplateMappings { order_number: "B2".to_string(), ..Default::default() };
+        mappings.columns.name = "B".to_string();
+        let mut config = template(mappings);
+        config.template_base64 = BASE64.encode(book_bytes.into_inner());
+        TemplateRepository::new(conn.clone()).insert(&config).unwrap();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-03-01", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 1.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let export = |template_id: &str| {
+            tauri::async_runtime::block_on(export_order_to_excel(
+                "o1".to_string(),
+                template_id.to_string(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let info = || {
+            let info = tauri::async_runtime::block_on(get_order_export_info("o1".to_string(), app.state())).unwrap();
+            (info.export_count, info.last_exported_at)
+        };
+
+        assert_eq!(info(), (0, None));
+        export("t1").unwrap();
+        assert_eq!(info(), (1, Some("2024-03-10T04:00:00+00:00".to_string())));
+
+        clock.advance(chrono::Duration::hours(2));
+        export("t1").unwrap();
+        assert_eq!(info(), (2, Some("2024-03-10T06:00:00+00:00".to_string())));
+
+        // 导出失败不计数
+        assert_eq!(export("missing").unwrap_err(), "模板不存在: missing");
+        assert_eq!(info().0, 2);
+        let exported = OrderEventRepository::new(conn.clone())
+            .get_by_order("o1")
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event_type == "exported")
+            .count();
+        assert_eq!(exported, 2);
+    }
+
+    #[test]
+    fn grouped_export_writes_a_group_and_subtotal_per_order() {
+        use crate::test_support::{insert_customer, insert_item, insert_order, memory_db, test_app};
+        use tauri::Manager;
+
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_customer(&c, "c2", "李四", "13900000000", "B67890");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 2.0, None);
+            insert_item(&c, "i2", "o1", "p2", 5.5, 1.0, None);
+            c.execute("UPDATE orders SET total_amount = 25.5, total_amount_cents = 2550 WHERE id = 'o1'", []).unwrap();
+            insert_order(&c, "o2", "c2", "2024-01-06", "completed");
+            insert_item(&c, "i3", "o2", "p1", 10.0, 3.0, None);
+            c.execute("UPDATE orders SET total_amount = 30, total_amount_cents = 3000 WHERE id = 'o2'", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let path = std::env::temp_dir().join(format!("grouped_{}.xlsx", uuid::Uuid::new_v4()));
+        let exported = tauri::async_runtime::block_on(export_orders_grouped(
+            vec!["o1".to_string(), "o2".to_string()],
+            path.to_string_lossy().to_string(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(exported, 2);
+
+        let book = umya_spreadsheet::reader::xlsx::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let detail = book.get_sheet_by_name("明细").unwrap();
+        assert_eq!(detail.get_value("A1"), "商品名称");
+        // 第一组：组标题、两行明细、小计，之后空一行
+        assert_eq!(detail.get_value("A2"), "订单号: o1    日期: 2024-01-05    客户: 张三");
+        assert_eq!((detail.get_value("A3"), detail.get_value("E3")), ("p1".to_string(), "20".to_string()));
+        assert_eq!((detail.get_value("A4"), detail.get_value("E4")), ("p2".to_string(), "5.5".to_string()));
+        assert_eq!(detail.get_value("A5"), "小计");
+        assert_eq!(detail.get_cell("E5").unwrap().get_formula(), "SUM(E3:E4)");
+        assert_eq!(detail.get_value("A6"), "");
+        // 第二组
+        assert_eq!(detail.get_value("A7"), "订单号: o2    日期: 2024-01-06    客户: 李四");
+        assert_eq!(detail.get_value("A8"), "p1");
+        assert_eq!(detail.get_value("A9"), "小计");
+        assert_eq!(detail.get_cell("E9").unwrap().get_formula(), "SUM(E8:E8)");
+        let merged: Vec<String> = detail.get_merge_cells().iter().map(|r| r.get_range()).collect();
+        assert_eq!(merged, vec!["A2:F2".to_string(), "A7:F7".to_string()]);
+
+        let summary = book.get_sheet_by_name("汇总").unwrap();
+        assert_eq!((summary.get_value("A2"), summary.get_value("D2")), ("o1".to_string(), "25.5".to_string()));
+        assert_eq!((summary.get_value("A3"), summary.get_value("C3")), ("o2".to_string(), "李四".to_string()));
+        assert_eq!(summary.get_value("A4"), "总计");
+        assert_eq!(summary.get_cell("D4").unwrap().get_formula(), "SUM(D2:D3)");
+    }
+}
@@ -0,0 +1,463 @@
+use tauri::{AppHandle, Emitter, Runtime, State};
+use crate::database::{connection::DbConnection, schema::{ProductRepository, ProductStatsRepository, Repository, StockMovementRepository}};
+use crate::models::{DeadStockItem, LowStockAlert, Product, ReorderSuggestion, StockMovement, StockMovementSummary};
+use crate::utils::clock::SharedClock;
+use crate::utils::csv;
+use crate::utils::xlsx::{self, Cell, Column, Sheet};
+
+// 日均销量默认统计窗口（天）
+const DEFAULT_VELOCITY_WINDOW_DAYS: u32 = 30;
+
+// 补货建议默认覆盖天数
+const DEFAULT_REORDER_COVER_DAYS: f64 = 14.0;
+
+// 库存降到最低库存及以下时发给前端的事件
+const LOW_STOCK_EVENT: &str = "low-stock";
+
+/// 在库存扣减提交后发送 low-stock 事件，同一商品只发一次（取最后一次扣减后的库存）。
+/// 发送失败只记录日志，不影响已完成的保存
+pub(crate) fn emit_low_stock_alerts<R: Runtime>(app: &AppHandle<R>, alerts: Vec<LowStockAlert>) {
+    let mut latest: Vec<LowStockAlert> = Vec::new();
+    for alert in alerts {
+        match latest.iter_mut().find(|existing| existing.product_id == alert.product_id) {
+            Some(existing) => *existing = alert,
+            None => latest.push(alert),
+        }
+    }
+
+    for alert in latest {
+        log::warn!("商品「{}」库存已降至 {}（最低库存 {}）", alert.name, alert.stock, alert.min_stock);
+        if let Err(e) = app.emit(LOW_STOCK_EVENT, &alert) {
+            log::error!("发送低库存提醒失败: {}", e);
+        }
+    }
+}
+
+/// 库存进出汇总报表（按商品统计区间内的出库、入库与净变化）
+#[tauri::command]
+pub async fn get_movement_summary(
+    from: String,
+    to: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<StockMovementSummary>, String> {
+    let repo = StockMovementRepository::new(conn.inner().clone());
+    repo.get_summary(&from, &to).map_err(|e| e.to_string())
+}
+
+/// 手工调整库存（盘点修正、报损等）：delta 为正入库、为负出库，reason 必填，
+/// 库存修改与流水记录在同一事务中完成，返回新记录的流水
+#[tauri::command]
+pub async fn adjust_stock(
+    product_id: String,
+    delta: f64,
+    reason: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<StockMovement, String> {
+    if !delta.is_finite() || delta == 0.0 {
+        return Err("调整数量不能为 0".to_string());
+    }
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err("请填写库存调整原因".to_string());
+    }
+
+    let product = ProductRepository::new(conn.inner().clone())
+        .get_by_id(&product_id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("商品不存在: {}", product_id),
+            e => e.to_string(),
+        })?;
+    if product.track_stock != Some(true) {
+        return Err(format!("商品「{}」未开启库存跟踪", product.name));
+    }
+
+    // 读取当前库存、校验与写入在同一事务中完成；未设置库存的商品按 0 计算，调整后库存不能小于 0
+    let now = clock.now_rfc3339();
+    let mut db = conn.inner().lock().unwrap();
+    let mut adjust = || -> Result<StockMovement, String> {
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+        let previous = StockMovementRepository::tracked_stock_on(&tx, &product_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("商品「{}」未开启库存跟踪", product.name))?;
+        if previous + delta < 0.0 {
+            return Err(format!("调整后库存不能小于 0（当前库存 {}）", previous));
+        }
+        let movement = StockMovementRepository::adjust_on(&tx, &product_id, previous, delta, reason, &now)
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(movement)
+    };
+    let movement = adjust().map_err(|e| {
+        log::error!("调整商品「{}」库存失败: {}", product.name, e);
+        e
+    })?;
+
+    log::info!("商品「{}」库存调整 {:+}（{}），调整后 {}", product.name, delta, reason, movement.balance_after.unwrap_or_default());
+    Ok(movement)
+}
+
+/// 商品的库存流水（销售扣减、退回与手工调整），按时间顺序
+#[tauri::command]
+pub async fn get_stock_movements(
+    product_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<StockMovement>, String> {
+    let repo = StockMovementRepository::new(conn.inner().clone());
+    repo.get_by_product(&product_id).map_err(|e| e.to_string())
+}
+
+/// 获取缺货或低于最低库存的商品
+#[tauri::command]
+pub async fn get_low_stock_products(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Product>, String> {
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.get_low_stock().map_err(|e| e.to_string())
+}
+
+/// 滞销库存报表：近 days 天没有售出的有库存商品及其占用资金，按占用资金从高到低
+#[tauri::command]
+pub async fn get_dead_stock(
+    days: u32,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<Vec<DeadStockItem>, String> {
+    if days == 0 {
+        return Err("统计天数必须大于 0".to_string());
+    }
+
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.get_dead_stock(days, clock.today())
+        .map_err(|e| e.to_string())
+}
+
+/// 建议补货量：补到最低库存的两倍（最低库存未设置时至少补 1），向上取整
+fn suggested_reorder_quantity(stock: f64, min_stock: f64) -> f64 {
+    let target = (min_stock * 2.0).max(1.0);
+    (target - stock).max(0.0).ceil()
+}
+
+/// 导出补货清单 CSV（UTF-8 BOM），返回导出的商品数量
+#[tauri::command]
+pub async fn export_low_stock_csv(
+    path: String,
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let repo = ProductRepository::new(conn.inner().clone());
+    let products = repo.get_low_stock().map_err(|e| e.to_string())?;
+
+    let rows: Vec<Vec<String>> = products
+        .iter()
+        .map(|product| {
+            let stock = product.stock.unwrap_or(0.0);
+            let min_stock = product.min_stock.unwrap_or(0.0);
+            vec![
+                product.name.clone(),
+                product.unit.clone(),
+                stock.to_string(),
+                min_stock.to_string(),
+                suggested_reorder_quantity(stock, min_stock).to_string(),
+            ]
+        })
+        .collect();
+    let content = csv::write_rows(&["商品名称", "单位", "当前库存", "最低库存", "建议补货量"], &rows)?;
+
+    std::fs::write(&path, content).map_err(|e| format!("写入补货清单失败: {}", e))?;
+
+    log::info!("补货清单已导出: {} ({} 个商品)", path, products.len());
+    Ok(products.len())
+}
+
+/// 导出盘点表（xlsx）：列出跟踪库存的商品及系统库存，「实盘数量」列留空供盘点时填写。
+/// category_id 指定时只包含该分类及其子分类的商品，返回导出的商品数量
+#[tauri::command]
+pub async fn export_stocktake_sheet(
+    category_id: Option<String>,
+    path: String,
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let repo = ProductRepository::new(conn.inner().clone());
+    let items = repo
+        .get_stocktake_items(category_id.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    let sheet = Sheet {
+        name: "盘点表".to_string(),
+        columns: vec![
+            Column::new("分类", 16.0),
+            Column::new("商品名称", 30.0),
+            Column::new("单位", 8.0),
+            Column::new("系统库存", 12.0),
+            Column::new("实盘数量", 12.0),
+            Column::new("备注", 20.0),
+        ],
+        rows: items
+            .iter()
+            .map(|item| {
+                vec![
+                    Cell::Text(item.category_name.clone()),
+                    Cell::Text(item.name.clone()),
+                    Cell::Text(item.unit.clone()),
+                    item.stock.map_or(Cell::Empty, Cell::Number),
+                    Cell::Empty,
+                    Cell::Empty,
+                ]
+            })
+            .collect(),
+    };
+
+    let content = xlsx::write_workbook(&[sheet]).map_err(|e| format!("生成盘点表失败: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("写入盘点表失败: {}", e))?;
+
+    log::info!("盘点表已导出: {} ({} 个商品)", path, items.len());
+    Ok(items.len())
+}
+
+/// 重算商品日均销量缓存（建议定时执行，或在批量导入/修改订单后执行），返回统计的商品数
+#[tauri::command]
+pub async fn refresh_product_stats(
+    window_days: Option<u32>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    let window_days = window_days.unwrap_or(DEFAULT_VELOCITY_WINDOW_DAYS);
+    if window_days == 0 {
+        return Err("统计窗口必须大于 0 天".to_string());
+    }
+
+    let repo = ProductStatsRepository::new(conn.inner().clone());
+    let count = repo
+        .refresh(window_days, clock.today(), &clock.now_rfc3339())
+        .map_err(|e| e.to_string())?;
+    log::info!("商品销售速度已刷新: {} 个商品（近 {} 天）", count, window_days);
+    Ok(count)
+}
+
+/// 补货建议（读取商品日均销量缓存），cover_days 为希望覆盖的销售天数
+#[tauri::command]
+pub async fn get_reorder_suggestions(
+    cover_days: Option<f64>,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<ReorderSuggestion>, String> {
+    let cover_days = cover_days.unwrap_or(DEFAULT_REORDER_COVER_DAYS);
+    if !cover_days.is_finite() || cover_days < 0.0 {
+        return Err("覆盖天数不能为负数".to_string());
+    }
+
+    let repo = ProductStatsRepository::new(conn.inner().clone());
+    repo.get_reorder_suggestions(cover_days).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use rusqlite::params;
+    use tauri::Manager;
+
+    #[test]
+    fn adjust_stock_rejects_a_negative_balance_without_writing() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 10.0, None);
+            c.execute("UPDATE products SET track_stock = 1, stock = 3 WHERE id = 'p1'", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let adjust = |product_id: &str, delta: f64| {
+            tauri::async_runtime::block_on(adjust_stock(
+                product_id.to_string(),
+                delta,
+                "盘点".to_string(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let movements = || {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM stock_movements", [], |row| row.get::<_, i64>(0))
+                .unwrap()
+        };
+
+        assert_eq!(adjust("p1", -4.0).unwrap_err(), "调整后库存不能小于 0（当前库存 3）");
+        assert_eq!(adjust("p2", 1.0).unwrap_err(), "商品「p2」未开启库存跟踪");
+        assert_eq!(movements(), 0);
+
+        let movement = adjust("p1", -3.0).unwrap();
+        assert_eq!((movement.change, movement.reason.as_str(), movement.balance_after), (-3.0, "盘点", Some(0.0)));
+        assert_eq!(ProductRepository::new(conn.clone()).get_by_id("p1").unwrap().stock, Some(0.0));
+        assert_eq!(movements(), 1);
+    }
+
+    #[test]
+    fn movement_summary_totals_each_product_by_reason_within_the_range() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "a", 10.0, None);
+            insert_product(&c, "b", 10.0, None);
+            c.execute("UPDATE products SET track_stock = 1, stock = 7", []).unwrap();
+            for (id, product_id, change, reason, created_at) in [
+                ("m1", "a", -2.0, "sale", "2024-03-01T09:00:00+08:00"),
+                ("m2", "a", -3.0, "sale", "2024-03-05T09:00:00+08:00"),
+                ("m3", "a", 10.0, "restock", "2024-03-05T10:00:00+08:00"),
+                ("m4", "a", -1.0, "盘点", "2024-03-31T23:59:00+08:00"),
+                ("m5", "a", -9.0, "sale", "2024-04-01T00:00:00+08:00"),
+                ("m6", "b", 4.0, "restock", "2024-02-29T23:59:00+08:00"),
+                ("m7", "b", -1.5, "sale", "2024-03-02T09:00:00+08:00"),
+            ] {
+                c.execute(
+                    "INSERT INTO stock_movements (id, product_id, change, reason, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![id, product_id, change, reason, created_at],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-04-02T04:00:00Z");
+
+        let summary = tauri::async_runtime::block_on(get_movement_summary(
+            "2024-03-01".to_string(),
+            "2024-03-31".to_string(),
+            app.state(),
+        ))
+        .unwrap();
+
+        assert_eq!(summary.len(), 2);
+        let a = &summary[0];
+        assert_eq!(a.product_id, "a");
+        assert_eq!((a.deducted, a.added, a.net_change, a.current_stock), (6.0, 10.0, 4.0, Some(7.0)));
+        let reasons: Vec<(&str, f64)> = a.reasons.iter().map(|r| (r.reason.as_str(), r.change)).collect();
+        assert_eq!(reasons, vec![("restock", 10.0), ("sale", -5.0), ("盘点", -1.0)]);
+        let b = &summary[1];
+        assert_eq!(b.product_id, "b");
+        assert_eq!((b.deducted, b.added, b.net_change), (1.5, 0.0, -1.5));
+        assert_eq!(b.reasons.len(), 1);
+
+        let empty = tauri::async_runtime::block_on(get_movement_summary(
+            "2023-01-01".to_string(),
+            "2023-12-31".to_string(),
+            app.state(),
+        ))
+        .unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn low_stock_csv_lists_only_tracked_products_at_or_below_the_threshold() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            for (id, track_stock, stock, min_stock) in [
+                ("a", true, Some(0.0), None),
+                ("b", true, Some(2.0), Some(5.0)),
+                ("c", true, Some(5.0), Some(5.0)),
+                ("d", true, Some(6.0), Some(5.0)),
+                ("e", false, Some(0.0), Some(5.0)),
+                ("f", true, None, Some(5.0)),
+            ] {
+                insert_product(&c, id, 10.0, None);
+                c.execute(
+                    "UPDATE products SET track_stock = ?2, stock = ?3, min_stock = ?4 WHERE id = ?1",
+                    params![id, track_stock, stock, min_stock],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let path = std::env::temp_dir().join(format!("low_stock_{}.csv", uuid::Uuid::new_v4()));
+
+        let exported =
+            tauri::async_runtime::block_on(export_low_stock_csv(path.to_string_lossy().to_string(), app.state())).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 未跟踪、未盘点（库存为空）与高于最低库存的商品不导出；补到最低库存的两倍，至少补 1
+        assert_eq!(exported, 3);
+        assert_eq!(
+            content,
+            "\u{feff}商品名称,单位,当前库存,最低库存,建议补货量\r\na,个,0,0,1\r\nb,个,2,5,8\r\nc,个,5,5,5\r\n"
+        );
+    }
+
+    #[test]
+    fn refreshed_velocity_matches_completed_sales_in_the_window() {
+        let sales = [
+            ("o1", "2024-03-01", "completed", "p1", 4.0),
+            ("o2", "2024-03-10", "completed", "p1", 6.0),
+            ("o2", "2024-03-10", "completed", "p2", 3.0),
+            // 窗口外与未完成的订单不计入
+            ("o3", "2024-02-29", "completed", "p1", 100.0),
+            ("o4", "2024-03-05", "draft", "p1", 50.0),
+            ("o5", "2024-03-11", "completed", "p2", 70.0),
+        ];
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "", "");
+            for id in ["p1", "p2", "p3"] {
+                insert_product(&c, id, 10.0, None);
+            }
+            c.execute("UPDATE products SET track_stock = 1, stock = 2", []).unwrap();
+            for (index, (order_id, date, status, product_id, quantity)) in sales.iter().enumerate() {
+                let exists: i64 =
+                    c.query_row("SELECT COUNT(*) FROM orders WHERE id = ?1", [order_id], |row| row.get(0)).unwrap();
+                if exists == 0 {
+                    insert_order(&c, order_id, "c1", date, status);
+                }
+                insert_item(&c, &format!("i{}", index), order_id, product_id, 10.0, *quantity, None);
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let refresh = |window_days: Option<u32>| {
+            tauri::async_runtime::block_on(refresh_product_stats(window_days, app.state(), app.state()))
+        };
+
+        assert_eq!(refresh(Some(0)).unwrap_err(), "统计窗口必须大于 0 天");
+        assert_eq!(refresh(Some(10)), Ok(3));
+
+        // 直接按 2024-03-01..=2024-03-10 的已完成订单计算
+        let expected = |product_id: &str| {
+            let units: f64 = sales
+                .iter()
+                .filter(|(_, date, status, id, _)| {
+                    *id == product_id && *status == "completed" && ("2024-03-01"..="2024-03-10").contains(date)
+                })
+                .map(|(.., quantity)| quantity)
+                .sum();
+            (units, units / 10.0)
+        };
+        let cached: Vec<(String, f64, f64, i64, String)> = {
+            let c = conn.lock().unwrap();
+            let mut stmt = c
+                .prepare(
+                    "SELECT product_id, units_sold, units_per_day, window_days, refreshed_at
+                     FROM product_stats ORDER BY product_id",
+                )
+                .unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            rows
+        };
+        for (product_id, units_sold, units_per_day, window_days, refreshed_at) in &cached {
+            assert_eq!((*units_sold, *units_per_day), expected(product_id), "{}", product_id);
+            assert_eq!((*window_days, refreshed_at.as_str()), (10, "2024-03-10T04:00:00+00:00"));
+        }
+        assert_eq!(expected("p1"), (10.0, 1.0));
+
+        // 补货建议读取缓存：p1 覆盖 7 天需要 7 个，现有 2 个
+        let suggestions = tauri::async_runtime::block_on(get_reorder_suggestions(Some(7.0), app.state())).unwrap();
+        let p1 = suggestions.iter().find(|s| s.product.id == "p1").unwrap();
+        assert_eq!(
+            (p1.units_per_day, p1.suggested_quantity, p1.days_of_stock, p1.window_days),
+            (1.0, 5.0, Some(2.0), Some(10))
+        );
+        assert_eq!(suggestions[0].product.id, "p1");
+    }
+}
@@ -0,0 +1,10 @@
+use crate::models::LogEntry;
+use crate::utils::logger;
+
+const DEFAULT_LOG_LIMIT: usize = 200;
+
+/// 获取最近的运行日志，供设置页/技术支持排查问题
+#[tauri::command]
+pub async fn get_recent_logs(limit: Option<usize>) -> Result<Vec<LogEntry>, String> {
+    Ok(logger::recent_logs(limit.unwrap_or(DEFAULT_LOG_LIMIT)))
+}
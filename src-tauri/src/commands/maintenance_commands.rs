@@ -0,0 +1,537 @@
+use tauri::State;
+use crate::database::{connection::DbConnection, migrations, schema::{OrderEventRepository, OrderRepository, Repository, SettingsRepository}, SettingsCache};
+use crate::models::{DuplicateOrderNumber, OrphanedOrderItems, RestoredDefaults, SettingsReferenceCheck};
+use crate::utils::clock::SharedClock;
+use rusqlite::types::ValueRef;
+use serde_json::{json, Map, Value};
+
+// 诊断包中每张表导出的样本行数
+const DIAGNOSTIC_SAMPLE_ROWS: usize = 3;
+
+// 订单全文索引表名前缀（含 FTS5 影子表）
+const SEARCH_INDEX_TABLE: &str = "orders_fts";
+
+// 任何表中都需要脱敏的列
+const SENSITIVE_COLUMNS: &[&str] = &[
+    "phone",
+    "license_plate",
+    "address",
+    "remark",
+    "content",
+    "order_json",
+    "data_directory",
+    "output_directory",
+    "backup_directory",
+];
+
+/// 一次性修复：将所有订单的订单项 sort_value 重新编号为连续的 0..n（保持原有相对顺序）
+#[tauri::command]
+pub async fn normalize_all_order_item_sort(
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let repo = OrderRepository::new(conn.inner().clone());
+    let touched = repo.normalize_all_item_sort().map_err(|e| e.to_string())?;
+    log::info!("订单项排序修复完成，涉及 {} 个订单", touched);
+    Ok(touched)
+}
+
+/// 检查孤立订单项（所属订单已删除；外键约束开启后启动时会自动清理）
+#[tauri::command]
+pub async fn find_orphaned_order_items(
+    conn: State<'_, DbConnection>,
+) -> Result<OrphanedOrderItems, String> {
+    let repo = OrderRepository::new(conn.inner().clone());
+    repo.find_orphaned_items().map_err(|e| e.to_string())
+}
+
+/// 清理孤立订单项，返回删除数量
+#[tauri::command]
+pub async fn purge_orphaned_order_items(
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let repo = OrderRepository::new(conn.inner().clone());
+    let purged = repo.purge_orphaned_items().map_err(|e| {
+        log::error!("清理孤立订单项失败: {}", e);
+        e.to_string()
+    })?;
+    log::info!("已清理 {} 条孤立订单项", purged);
+    Ok(purged)
+}
+
+/// 检查重复的订单号（启动时也会自动检查并记录警告）
+#[tauri::command]
+pub async fn find_duplicate_order_numbers(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<DuplicateOrderNumber>, String> {
+    let repo = OrderRepository::new(conn.inner().clone());
+    repo.find_duplicate_numbers().map_err(|e| e.to_string())
+}
+
+/// 修复重复的订单号：每组保留最早创建的订单，其余按当前单号规则重新生成，返回改号的订单数
+#[tauri::command]
+pub async fn dedupe_order_numbers(
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    let repo = OrderRepository::new(conn.inner().clone());
+    let duplicates = repo.find_duplicate_numbers().map_err(|e| e.to_string())?;
+    if duplicates.is_empty() {
+        return Ok(0);
+    }
+
+    let settings = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "未找到系统设置".to_string())?;
+    let event_repo = OrderEventRepository::new(conn.inner().clone());
+
+    let mut renumbered = 0;
+    for duplicate in &duplicates {
+        for order_id in duplicate.order_ids.iter().skip(1) {
+            let order = repo.get_by_id(order_id).map_err(|e| e.to_string())?;
+
+            // 新单号仍被占用时（如序号规则已变更），在原单号后追加后缀
+            let mut new_number = repo
+                .generate_order_number(&settings, &order.date, order.template_id.as_deref(), clock.today())
+                .map_err(|e| e.to_string())?;
+            let mut suffix = 2;
+            while repo.number_exists(&new_number).map_err(|e| e.to_string())? {
+                new_number = format!("{}-{}", duplicate.order_number, suffix);
+                suffix += 1;
+            }
+
+            let now = clock.now_rfc3339();
+            repo.set_number(order_id, &new_number, &now)
+                .map_err(|e| e.to_string())?;
+            event_repo
+                .record(order_id, "renumbered", Some(&format!("{} -> {}", duplicate.order_number, new_number)), &now)
+                .map_err(|e| e.to_string())?;
+            log::warn!("重复订单号 {} 已改为 {}（订单 {}）", duplicate.order_number, new_number, order_id);
+            renumbered += 1;
+        }
+    }
+
+    Ok(renumbered)
+}
+
+/// 恢复默认分类、单位预设与默认模板（用户误删后使用），overwrite 为 true 时同时把已存在的默认项恢复为默认配置
+#[tauri::command]
+pub async fn restore_default_data(
+    overwrite: Option<bool>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<RestoredDefaults, String> {
+    let now = clock.now_rfc3339();
+    let restored = {
+        let mut db = conn.inner().lock().unwrap();
+        crate::database::restore_default_data(&mut db, overwrite.unwrap_or(false), &now).map_err(|e| e.to_string())?
+    };
+
+    // 默认模板可能刚被重新插入，修正设置中失效的默认模板引用
+    let check = SettingsRepository::new(conn.inner().clone())
+        .validate_references(true, &now)
+        .map_err(|e| e.to_string())?;
+    if check.healed {
+        settings_cache.invalidate();
+    }
+
+    log::info!(
+        "已恢复默认数据: 分类 {:?}, 单位 {:?}, 模板 {}, 覆盖 {} 项",
+        restored.categories_added,
+        restored.units_added,
+        restored.template_added,
+        restored.overwritten
+    );
+    Ok(restored)
+}
+
+/// 检查设置中的默认模板/默认分类是否仍然存在，heal 为 true 时自动修正失效的引用
+#[tauri::command]
+pub async fn validate_settings_references(
+    heal: Option<bool>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<SettingsReferenceCheck, String> {
+    let repo = SettingsRepository::new(conn.inner().clone());
+    let check = repo
+        .validate_references(heal.unwrap_or(false), &clock.now_rfc3339())
+        .map_err(|e| e.to_string())?;
+    if check.healed {
+        settings_cache.invalidate();
+        log::warn!(
+            "设置引用已修正: 默认模板 -> {:?}, 默认分类 -> {:?}",
+            check.default_template_id,
+            check.default_category_id
+        );
+    }
+    Ok(check)
+}
+
+fn is_sensitive_column(table: &str, column: &str) -> bool {
+    SENSITIVE_COLUMNS.contains(&column) || (table == "customers" && column == "name")
+}
+
+/// 脱敏：仅保留首字符，其余替换为 *
+fn mask_text(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => std::iter::once(first)
+            .chain(chars.map(|_| '*'))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+fn sample_value(table: &str, column: &str, value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(v) => json!(v),
+        ValueRef::Real(v) => json!(v),
+        ValueRef::Text(bytes) => {
+            let text = String::from_utf8_lossy(bytes);
+            if column == "template_base64" {
+                json!(format!("<{} bytes>", text.len()))
+            } else if is_sensitive_column(table, column) {
+                json!(mask_text(&text))
+            } else {
+                json!(text)
+            }
+        }
+        ValueRef::Blob(bytes) => json!(format!("<{} bytes>", bytes.len())),
+    }
+}
+
+/// 导出诊断包（表结构、行数、user_version、结构版本与脱敏样本数据）到指定 JSON 文件，供技术支持排查问题
+#[tauri::command]
+pub async fn export_diagnostic_bundle(
+    path: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<String, String> {
+    let bundle = {
+        let db = conn.inner().lock().unwrap();
+
+        let user_version: i64 = db
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let schema_version = migrations::current_version(&db).map_err(|e| e.to_string())?;
+
+        let schema: Vec<(String, String, String)> = {
+            let mut stmt = db
+                .prepare(
+                    "SELECT type, name, sql FROM sqlite_master
+                     WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+                     ORDER BY type DESC, name",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            rows
+        };
+
+        let mut tables = Map::new();
+        for (_, table, _) in schema.iter().filter(|(kind, _, _)| kind == "table") {
+            let row_count: i64 = db
+                .query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+
+            // 全文索引表及其影子表保存的是客户数据副本，只记录行数不导出样本
+            if table.starts_with(SEARCH_INDEX_TABLE) {
+                tables.insert(table.clone(), json!({ "rowCount": row_count }));
+                continue;
+            }
+
+            let mut stmt = db
+                .prepare(&format!("SELECT * FROM \"{}\" LIMIT {}", table, DIAGNOSTIC_SAMPLE_ROWS))
+                .map_err(|e| e.to_string())?;
+            let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+            let mut samples = Vec::new();
+            let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                let mut sample = Map::new();
+                for (index, column) in columns.iter().enumerate() {
+                    let value = row.get_ref(index).map_err(|e| e.to_string())?;
+                    sample.insert(column.clone(), sample_value(table, column, value));
+                }
+                samples.push(Value::Object(sample));
+            }
+
+            tables.insert(
+                table.clone(),
+                json!({
+                    "rowCount": row_count,
+                    "columns": columns,
+                    "samples": samples,
+                }),
+            );
+        }
+
+        json!({
+            "generatedAt": clock.now_rfc3339(),
+            "appVersion": env!("CARGO_PKG_VERSION"),
+            "userVersion": user_version,
+            "schemaVersion": schema_version,
+            "schema": schema
+                .iter()
+                .map(|(kind, name, sql)| json!({ "type": kind, "name": name, "sql": sql }))
+                .collect::<Vec<_>>(),
+            "tables": tables,
+        })
+    };
+
+    let content = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    std::fs::write(&path, content).map_err(|e| format!("写入诊断文件失败: {}", e))?;
+
+    log::info!("诊断包已导出: {}", path);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    #[test]
+    fn item_sort_is_renumbered_stably_and_only_for_orders_with_gaps_or_duplicates() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_order(&c, "o1", "c1", "2024-03-01", "completed");
+            insert_order(&c, "o2", "c1", "2024-03-02", "completed");
+            for (id, order_id, sort_value) in [("i1", "o1", 3), ("i2", "o1", 3), ("i3", "o1", 1), ("i4", "o2", 0), ("i5", "o2", 1)] {
+                insert_item(&c, id, order_id, "p1", 10.0, 1.0, None);
+                c.execute("UPDATE order_items SET sort_value = ?1 WHERE id = ?2", rusqlite::params![sort_value, id])
+                    .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        assert_eq!(tauri::async_runtime::block_on(normalize_all_order_item_sort(app.state())), Ok(1));
+        let sorted: Vec<(String, i64)> = {
+            let c = conn.lock().unwrap();
+            let mut stmt = c.prepare("SELECT id, sort_value FROM order_items ORDER BY order_id, sort_value").unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            rows
+        };
+        // 相同 sort_value 的行保持插入顺序
+        let expected = [("i3", 0), ("i1", 1), ("i2", 2), ("i4", 0), ("i5", 1)];
+        assert_eq!(sorted, expected.map(|(id, sort)| (id.to_string(), sort)));
+
+        assert_eq!(tauri::async_runtime::block_on(normalize_all_order_item_sort(app.state())), Ok(0));
+    }
+
+    #[test]
+    fn orphaned_items_are_listed_and_purged_without_touching_live_orders() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_order(&c, "o1", "c1", "2024-03-01", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 1.0, None);
+            // 模拟旧版本删除订单时未级联清理留下的订单项
+            c.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+            insert_item(&c, "i2", "gone", "p1", 10.0, 1.0, None);
+            insert_item(&c, "i3", "gone", "p1", 10.0, 2.0, None);
+            c.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let orphaned = tauri::async_runtime::block_on(find_orphaned_order_items(app.state())).unwrap();
+        assert_eq!((orphaned.count, orphaned.ids), (2, vec!["i2".to_string(), "i3".to_string()]));
+
+        assert_eq!(tauri::async_runtime::block_on(purge_orphaned_order_items(app.state())), Ok(2));
+        let remaining: Vec<String> = {
+            let c = conn.lock().unwrap();
+            let mut stmt = c.prepare("SELECT id FROM order_items ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+            rows
+        };
+        assert_eq!(remaining, vec!["i1".to_string()]);
+
+        let orphaned = tauri::async_runtime::block_on(find_orphaned_order_items(app.state())).unwrap();
+        assert_eq!(orphaned.count, 0);
+        assert_eq!(tauri::async_runtime::block_on(purge_orphaned_order_items(app.state())), Ok(0));
+    }
+
+    #[test]
+    fn settings_references_to_a_deleted_template_are_reported_and_healed() {
+        let conn = memory_db();
+        let (builtin_template, first_category): (String, String) = {
+            let c = conn.lock().unwrap();
+            c.execute(
+                "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, mappings, created_at, updated_at)
+                 VALUES ('t1', '报价单', '', 't1.xlsx', '', '{}', '2030-01-01', '2030-01-01')",
+                [],
+            )
+            .unwrap();
+            c.execute("UPDATE app_settings SET default_template_id = 't1', default_category_id = 'cat-gone'", [])
+                .unwrap();
+            // 模板删除后设置中仍指向它
+            c.execute("DELETE FROM templates WHERE id = 't1'", []).unwrap();
+            let id = |sql: &str| c.query_row(sql, [], |row| row.get::<_, String>(0)).unwrap();
+            (
+                id("SELECT id FROM templates WHERE is_default = 1"),
+                id("SELECT id FROM categories ORDER BY level, sort_order, name LIMIT 1"),
+            )
+        };
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let settings_repo = SettingsRepository::new(conn.clone());
+        let cached_default = || app.state::<SettingsCache>().get(&settings_repo).unwrap().unwrap().default_template_id;
+        assert_eq!(cached_default(), "t1");
+        let validate = |heal: Option<bool>| {
+            tauri::async_runtime::block_on(validate_settings_references(heal, app.state(), app.state(), app.state()))
+                .unwrap()
+        };
+
+        // 只检查时不修改设置
+        let check = validate(None);
+        assert_eq!((check.template_exists, check.category_exists, check.healed), (false, false, false));
+        assert_eq!((check.default_template_id.as_str(), check.default_category_id.as_str()), ("t1", "cat-gone"));
+        assert_eq!(cached_default(), "t1");
+
+        // 修正为默认模板与排在最前的顶级分类，并刷新设置缓存
+        let check = validate(Some(true));
+        assert!(check.healed);
+        assert_eq!(
+            (check.default_template_id.as_str(), check.default_category_id.as_str()),
+            (builtin_template.as_str(), first_category.as_str())
+        );
+        assert_eq!(cached_default(), builtin_template);
+
+        let check = validate(Some(true));
+        assert_eq!((check.template_exists, check.category_exists, check.healed), (true, true, false));
+    }
+
+    #[test]
+    fn duplicate_order_numbers_are_found_and_all_but_the_oldest_renumbered() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            // 模拟没有唯一约束的旧版数据库：去掉 order_number 的 UNIQUE 后重建订单表
+            let sql: String = c
+                .query_row("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'orders'", [], |row| row.get(0))
+                .unwrap();
+            let rebuilt = sql
+                .replacen("CREATE TABLE orders", "CREATE TABLE orders_rebuilt", 1)
+                .replace("order_number TEXT UNIQUE NOT NULL", "order_number TEXT NOT NULL");
+            assert!(!rebuilt.contains("UNIQUE"), "{}", rebuilt);
+            c.execute_batch("PRAGMA foreign_keys = OFF; PRAGMA legacy_alter_table = ON").unwrap();
+            c.execute_batch(&format!(
+                "{};
+                 INSERT INTO orders_rebuilt SELECT * FROM orders;
+                 DROP TABLE orders;
+                 ALTER TABLE orders_rebuilt RENAME TO orders;",
+                rebuilt
+            ))
+            .unwrap();
+            c.execute_batch("PRAGMA foreign_keys = ON; PRAGMA legacy_alter_table = OFF").unwrap();
+
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            for (id, date) in [("o2", "2024-03-02"), ("o1", "2024-03-01"), ("o3", "2024-03-03"), ("o4", "2024-03-04")] {
+                insert_order(&c, id, "c1", date, "completed");
+            }
+            c.execute("UPDATE orders SET order_number = 'NO.000001' WHERE id IN ('o1', 'o2', 'o3')", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let find = || tauri::async_runtime::block_on(find_duplicate_order_numbers(app.state())).unwrap();
+
+        // 按创建时间排列，最早的订单保留原单号
+        let duplicates = find();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].order_number, "NO.000001");
+        assert_eq!(duplicates[0].order_ids, vec!["o1", "o2", "o3"]);
+
+        let dedupe = || tauri::async_runtime::block_on(dedupe_order_numbers(app.state(), app.state(), app.state()));
+        assert_eq!(dedupe(), Ok(2));
+        assert!(find().is_empty());
+
+        let repo = OrderRepository::new(conn.clone());
+        let numbers: Vec<String> =
+            ["o1", "o2", "o3", "o4"].iter().map(|id| repo.get_by_id(id).unwrap().order_number).collect();
+        assert_eq!(numbers[0], "NO.000001");
+        assert_eq!(numbers[3], "o4");
+        let unique: std::collections::HashSet<&String> = numbers.iter().collect();
+        assert_eq!(unique.len(), 4);
+
+        // 改号记录在订单操作记录中
+        let events = OrderEventRepository::new(conn.clone()).get_by_order("o2").unwrap();
+        let renumbered = events.iter().find(|e| e.event_type == "renumbered").unwrap();
+        assert_eq!(renumbered.detail.as_deref(), Some(format!("NO.000001 -> {}", numbers[1]).as_str()));
+        assert!(OrderEventRepository::new(conn.clone()).get_by_order("o1").unwrap().is_empty());
+
+        assert_eq!(dedupe(), Ok(0));
+    }
+
+    #[test]
+    fn restoring_defaults_fills_emptied_tables_and_leaves_existing_data_unless_overwriting() {
+        let conn = memory_db();
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let restore = |overwrite: Option<bool>| {
+            tauri::async_runtime::block_on(restore_default_data(overwrite, app.state(), app.state(), app.state())).unwrap()
+        };
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+
+        // 默认数据齐全时不做任何修改
+        let restored = restore(None);
+        assert!(restored.categories_added.is_empty() && restored.units_added.is_empty());
+        assert_eq!((restored.template_added, restored.overwritten), (false, 0));
+        assert_eq!(count("SELECT COUNT(*) FROM templates"), 1);
+
+        // 全部删除后重新插入，设置中的默认模板改指向新模板
+        conn.lock()
+            .unwrap()
+            .execute_batch("DELETE FROM categories; DELETE FROM unit_presets; DELETE FROM templates;")
+            .unwrap();
+        let restored = restore(None);
+        assert_eq!(restored.categories_added, vec!["保养", "配件", "装饰", "清洗", "服务", "其他"]);
+        assert_eq!(restored.units_added.len(), 16);
+        assert_eq!(restored.units_added[..2], ["件".to_string(), "个".to_string()]);
+        assert!(restored.template_added);
+        assert_eq!(restored.overwritten, 0);
+        let settings = app.state::<SettingsCache>().get(&SettingsRepository::new(conn.clone())).unwrap().unwrap();
+        let template_id: String = conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT id FROM templates WHERE is_default = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(settings.default_template_id, template_id);
+
+        // 已有的默认项只在 overwrite 时恢复为默认配置，用户新增的数据不受影响
+        conn.lock()
+            .unwrap()
+            .execute_batch(
+                "UPDATE categories SET sort_order = 9 WHERE name = '保养';
+                 UPDATE unit_presets SET sort_order = 30 WHERE name = '件';
+                 INSERT INTO unit_presets (id, name, sort_order, use_count, created_at, updated_at)
+                 VALUES ('u-custom', '对', 40, 0, 'now', 'now');",
+            )
+            .unwrap();
+        let restored = restore(Some(false));
+        assert_eq!(restored.overwritten, 0);
+        assert_eq!(count("SELECT sort_order FROM categories WHERE name = '保养'"), 9);
+
+        let restored = restore(Some(true));
+        assert!(restored.categories_added.is_empty() && restored.units_added.is_empty() && !restored.template_added);
+        // 分类、单位各一项，加上默认模板的映射
+        assert_eq!(restored.overwritten, 3);
+        assert_eq!(count("SELECT sort_order FROM categories WHERE name = '保养'"), 0);
+        assert_eq!(count("SELECT sort_order FROM unit_presets WHERE name = '件'"), 0);
+        assert_eq!(count("SELECT sort_order FROM unit_presets WHERE id = 'u-custom'"), 40);
+        assert_eq!(count("SELECT COUNT(*) FROM templates"), 1);
+    }
+}
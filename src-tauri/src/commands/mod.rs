@@ -5,6 +5,12 @@ pub mod order_commands;
 pub mod remark_preset_commands;
 pub mod unit_preset_commands;
 pub mod template_commands;
+pub mod log_commands;
+pub mod maintenance_commands;
+pub mod inventory_commands;
+pub mod data_commands;
+pub mod attachment_commands;
+pub mod export_commands;
 
 pub use product_commands::*;
 pub use customer_commands::*;
@@ -13,3 +19,9 @@ pub use order_commands::*;
 pub use remark_preset_commands::*;
 pub use unit_preset_commands::*;
 pub use template_commands::*;
+pub use log_commands::*;
+pub use maintenance_commands::*;
+pub use inventory_commands::*;
+pub use data_commands::*;
+pub use attachment_commands::*;
+pub use export_commands::*;
@@ -1,51 +1,521 @@
-use tauri::State;
-use crate::database::connection::DbConnection;
-use crate::database::schema::{OrderRepository, CustomerRepository, ProductRepository, TemplateRepository, SettingsRepository, Repository};
-use crate::models::{Order, TemplateConfig, AppSettings};
-use chrono::Utc;
+use tauri::{AppHandle, Runtime, State};
+use crate::commands::attachment_commands::remove_attachment_dirs;
+use crate::commands::inventory_commands::emit_low_stock_alerts;
+use crate::database::{connection::DbConnection, OrderLocks, SettingsCache};
+use crate::database::schema::{generate_order_number_on, is_order_number_unique_violation, WriteError, OrderRepository, CategoryRepository, CustomerRepository, CustomerTransactionRepository, OrderDraftRepository, OrderEventRepository, ProductRepository, TemplateRepository, SettingsRepository, Repository};
+use crate::models::{CategorySales, CustomerSales, DailySales, LowStockAlert, Order, OrderEvent, OrderExportInfo, OrderPage, OrderTotalMismatch, PendingBuffers, ProductSales, ReportBundle, SalesTotal, SaveOrderResult, SplitOrderResult, TemplateConfig, AppSettings};
+use crate::utils::clock::SharedClock;
+use crate::utils::logger;
+use crate::utils::money::{from_cents, line_total_cents, order_total_cents, to_cents};
+use crate::utils::order_status::{self, holds_stock};
+use chrono::NaiveDate;
 
-fn is_order_number_unique_violation(err: &rusqlite::Error) -> bool {
-    err.to_string()
-        .contains("UNIQUE constraint failed: orders.order_number")
+#[tauri::command]
+pub async fn get_all_orders(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Order>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo.get_all_with_details().map_err(|e| e.to_string())
 }
 
+/// 获取单个订单（含客户与订单项），用于打开订单编辑而不必读取整个列表；客户已不存在时客户信息为空
 #[tauri::command]
-pub async fn get_all_orders(
+pub async fn get_order_by_id(
+    id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Order, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let mut order = order_repo.get_by_id(&id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", id),
+        e => e.to_string(),
+    })?;
+    order.items = order_repo.get_order_items(&order.id).map_err(|e| e.to_string())?;
+    if let Ok(customer) = CustomerRepository::new(conn.inner().clone()).get_by_id(&order.customer_id) {
+        order.customer = customer;
+    }
+    Ok(order)
+}
+
+/// 获取订单日期在 start 与 end 之间的订单（含客户与订单项），按日期排列。日期格式必须为 YYYY-MM-DD，
+/// 首尾都包含；start 晚于 end 时返回空列表
+#[tauri::command]
+pub async fn get_orders_by_date_range(
+    start: String,
+    end: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Order>, String> {
+    // 订单日期按字符串比较，必须是补零的 YYYY-MM-DD 才能得到正确的区间
+    for date in [&start, &end] {
+        if date.len() != 10 || NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(format!("日期格式错误（应为 YYYY-MM-DD）: {}", date));
+        }
+    }
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo.get_by_date_range(&start, &end).map_err(|e| e.to_string())
+}
+
+// 订单分页的最大每页条数
+const MAX_ORDER_PAGE_SIZE: u32 = 500;
+
+/// 分页获取订单（含客户与订单项）及订单总数，默认按创建时间从新到旧；
+/// sort 可选 date_desc、date_asc（订单日期）、amount_desc / total_desc（订单总额从高到低）
+#[tauri::command]
+pub async fn get_all_orders_paged(
+    limit: u32,
+    offset: u32,
+    sort: Option<String>,
+    conn: State<'_, DbConnection>,
+) -> Result<OrderPage, String> {
+    // 排序只映射到固定的列，不拼接传入的字符串；末尾按 ID 排序保证翻页稳定
+    let order_by = match sort.as_deref() {
+        None => "o.created_at DESC, o.id",
+        Some("date_desc") => "o.date DESC, o.created_at DESC, o.id",
+        Some("date_asc") => "o.date ASC, o.created_at ASC, o.id",
+        Some("amount_desc") | Some("total_desc") => {
+            "COALESCE(o.total_amount_cents / 100.0, o.total_amount) DESC, o.created_at DESC, o.id"
+        }
+        Some(other) => return Err(format!("不支持的排序方式: {}", other)),
+    };
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_page_with_details(order_by, limit.clamp(1, MAX_ORDER_PAGE_SIZE), offset)
+        .map_err(|e| e.to_string())
+}
+
+/// 按明细重新计算各行金额与订单总额（逐行按分累加），依次扣减整单优惠、计税、抹零，
+/// 记录抹零调整额与本次使用的计税设置（计税快照，供日后核对总额）
+fn recalculate_order_total(order: &mut Order, settings: &AppSettings) {
+    for item in &mut order.items {
+        item.line_total = from_cents(line_total_cents(item.price, item.discount_price, item.quantity));
+    }
+    let lines_cents: i64 = order.items.iter().map(|item| to_cents(item.line_total)).sum();
+    let (total_cents, adjustment_cents) = order_total_cents(
+        lines_cents,
+        to_cents(order.order_discount),
+        settings.tax_rate,
+        settings.prices_include_tax,
+        &settings.total_rounding,
+    );
+    order.total_amount = from_cents(total_cents);
+    order.rounding_adjustment = from_cents(adjustment_cents);
+    order.tax_rate = Some(settings.tax_rate);
+    order.prices_include_tax = Some(settings.prices_include_tax);
+}
+
+/// 只用订单自身保存的数据核对总额，不一致时返回按明细应有的总额（分）。
+/// 各行按单价（有折扣价时取折扣价）× 数量重新计算并与保存的行金额比较；总额按保存时的计税快照计税，
+/// 再加上保存的抹零调整额（抹零不超过 1 元）。早于计税快照的订单不知道当时的税率，
+/// 按不计税核对，只有总额少于不计税的结果时才算不一致
+fn check_stored_total(order: &Order) -> Option<i64> {
+    let mut lines_match = true;
+    let mut lines_cents = 0;
+    for item in &order.items {
+        let cents = line_total_cents(item.price, item.discount_price, item.quantity);
+        lines_match &= to_cents(item.line_total) == cents;
+        lines_cents += cents;
+    }
+    let stored = to_cents(order.total_amount);
+    let discount = to_cents(order.order_discount);
+    let adjustment = to_cents(order.rounding_adjustment);
+
+    let (expected, total_matches) = match (order.tax_rate, order.prices_include_tax) {
+        (Some(tax_rate), Some(prices_include_tax)) => {
+            let (before_rounding, _) = order_total_cents(lines_cents, discount, tax_rate, prices_include_tax, "none");
+            let expected = before_rounding + adjustment;
+            (expected, stored == expected && adjustment.abs() < 100)
+        }
+        _ => {
+            let untaxed = (lines_cents - discount).max(0) + adjustment;
+            (untaxed, stored >= untaxed)
+        }
+    };
+    if lines_match && total_matches {
+        None
+    } else {
+        Some(expected)
+    }
+}
+
+/// 在写入事务中检查库存是否足够扣减 demands（(商品ID, 数量)，负数表示退回），不足时返回列出各商品需要与可用数量的说明
+fn check_stock_available(conn: &rusqlite::Connection, demands: &[(String, f64)]) -> Result<(), WriteError> {
+    let shortages = ProductRepository::get_stock_shortages_on(conn, demands)?;
+    if shortages.is_empty() {
+        return Ok(());
+    }
+    let details: Vec<String> = shortages
+        .iter()
+        .map(|(name, requested, available)| format!("{}（需要 {}，可用 {}）", name, requested, available))
+        .collect();
+    Err(WriteError::Rejected(format!("库存不足：{}", details.join("；"))))
+}
+
+/// 按设置中的单行数量与订单总额上限检查订单（上限为 0 表示不限制），返回超出项说明
+fn check_order_limits(order: &Order, settings: &AppSettings) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if settings.max_item_quantity > 0.0 {
+        for item in &order.items {
+            if item.quantity > settings.max_item_quantity {
+                warnings.push(format!(
+                    "商品「{}」数量 {} 超过上限 {}",
+                    item.name, item.quantity, settings.max_item_quantity
+                ));
+            }
+        }
+    }
+
+    if settings.max_order_total > 0.0 && order.total_amount > settings.max_order_total {
+        warnings.push(format!(
+            "订单总额 {:.2} 超过上限 {:.2}",
+            order.total_amount, settings.max_order_total
+        ));
+    }
+
+    warnings
+}
+
+/// 核对已保存订单的总额与行金额：只依据订单保存的数据（明细、整单优惠、计税快照与抹零调整额），
+/// 不受之后修改的税率、抹零等设置影响，返回不一致的订单
+#[tauri::command]
+pub async fn verify_totals(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<OrderTotalMismatch>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let orders = order_repo.get_all_with_details().map_err(|e| e.to_string())?;
+
+    let mismatches: Vec<OrderTotalMismatch> = orders
+        .iter()
+        .filter_map(|order| {
+            check_stored_total(order).map(|expected_cents| OrderTotalMismatch {
+                order_id: order.id.clone(),
+                order_number: order.order_number.clone(),
+                stored_total: order.total_amount,
+                expected_total: from_cents(expected_cents),
+            })
+        })
+        .collect();
+
+    if !mismatches.is_empty() {
+        log::warn!("总额核对发现 {} 个订单不一致", mismatches.len());
+    }
+    Ok(mismatches)
+}
+
+/// 搜索订单（订单号、客户、车牌、商品名），按相关度排序
+#[tauri::command]
+pub async fn search_orders(
+    query: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Order>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo.search(&query).map_err(|e| e.to_string())
+}
+
+/// 销售合计（已完成订单，外币按汇率折算为本币），日期格式 YYYY-MM-DD，含首尾；
+/// include_archived 为 true 时包含归档订单
+#[tauri::command]
+pub async fn get_sales_total(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    conn: State<'_, DbConnection>,
+) -> Result<SalesTotal, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_sales_total(&from, &to, include_archived.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// 分类销售排行（已完成订单），上级分类的汇总额包含所有下级分类，日期格式 YYYY-MM-DD，含首尾；
+/// include_archived 为 true 时包含归档订单
+#[tauri::command]
+pub async fn get_category_sales_ranking(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<CategorySales>, String> {
+    let category_repo = CategoryRepository::new(conn.inner().clone());
+    category_repo
+        .get_sales_ranking(&from, &to, include_archived.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// 排行榜默认显示的条数
+const DEFAULT_RANKING_LIMIT: u32 = 10;
+
+/// 逐日销售汇总（已完成订单，外币按汇率折算为本币），只包含有订单的日期；日期格式 YYYY-MM-DD，含首尾
+#[tauri::command]
+pub async fn get_daily_sales(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<DailySales>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_daily_sales(&from, &to, include_archived.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// 商品销售排行（按销售额降序，默认前 10 名）；日期格式 YYYY-MM-DD，含首尾
+#[tauri::command]
+pub async fn get_top_products(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    limit: Option<u32>,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<ProductSales>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_top_products(&from, &to, include_archived.unwrap_or(false), limit.unwrap_or(DEFAULT_RANKING_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+/// 客户消费排行（按消费金额降序，默认前 10 名）；日期格式 YYYY-MM-DD，含首尾
+#[tauri::command]
+pub async fn get_top_customers(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    limit: Option<u32>,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<CustomerSales>, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_top_customers(&from, &to, include_archived.unwrap_or(false), limit.unwrap_or(DEFAULT_RANKING_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+/// 报表页一次取齐的统计：销售合计、逐日汇总、商品/客户排行与分类排行，只加一次锁；
+/// 各项与 get_sales_total、get_daily_sales、get_top_products、get_top_customers、get_category_sales_ranking 的结果相同
+#[tauri::command]
+pub async fn get_report_bundle(
+    from: String,
+    to: String,
+    include_archived: Option<bool>,
+    limit: Option<u32>,
+    conn: State<'_, DbConnection>,
+) -> Result<ReportBundle, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .get_report_bundle(&from, &to, include_archived.unwrap_or(false), limit.unwrap_or(DEFAULT_RANKING_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+/// 归档指定日期（YYYY-MM-DD，不含）之前的已完成/已取消订单，保持订单表精简；返回归档的订单数
+#[tauri::command]
+pub async fn archive_orders_before(
+    date: String,
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|_| format!("日期格式错误: {}", date))?;
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let archived = order_repo.archive_before(&date).map_err(|e| {
+        log::error!("归档订单失败: {}", e);
+        e.to_string()
+    })?;
+    log::info!("已归档 {} 之前的 {} 个订单", date, archived);
+    Ok(archived)
+}
+
+/// 获取归档订单（含客户信息与订单项）
+#[tauri::command]
+pub async fn get_archived_orders(
     conn: State<'_, DbConnection>,
 ) -> Result<Vec<Order>, String> {
     let order_repo = OrderRepository::new(conn.inner().clone());
     let customer_repo = CustomerRepository::new(conn.inner().clone());
-    
-    let mut orders = order_repo.get_all().map_err(|e| e.to_string())?;
-    
-    // 填充客户信息和订单项
+
+    let mut orders = order_repo.get_archived().map_err(|e| e.to_string())?;
+    let customer_ids: Vec<String> = orders.iter().map(|o| o.customer_id.clone()).collect();
+    let customers = customer_repo.get_by_ids(&customer_ids).map_err(|e| e.to_string())?;
     for order in &mut orders {
-        // 获取客户信息
-        if let Ok(customer) = customer_repo.get_by_id(&order.customer_id) {
-            order.customer = customer;
-        }
-        
-        // 获取订单项
-        if let Ok(items) = order_repo.get_order_items(&order.id) {
-            order.items = items;
+        if let Some(customer) = customers.get(&order.customer_id) {
+            order.customer = customer.clone();
         }
     }
-    
     Ok(orders)
 }
 
+/// 将归档订单恢复到订单表
+#[tauri::command]
+pub async fn restore_archived_order(
+    order_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<(), String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo.restore_archived(&order_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("归档中没有该订单: {}", order_id),
+        e if is_order_number_unique_violation(&e) => "订单号已被其他订单占用，无法恢复".to_string(),
+        e if e.to_string().contains("FOREIGN KEY constraint failed") => {
+            "订单的客户已被删除，无法恢复".to_string()
+        }
+        e => e.to_string(),
+    })?;
+    log::info!("归档订单已恢复: {}", order_id);
+    Ok(())
+}
+
+/// 重建订单搜索索引（批量导入数据后使用）
+#[tauri::command]
+pub async fn rebuild_order_search_index(
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let count = order_repo.rebuild_search_index().map_err(|e| {
+        log::error!("重建订单搜索索引失败: {}", e);
+        e.to_string()
+    })?;
+    log::info!("订单搜索索引已重建，共 {} 个订单", count);
+    Ok(count)
+}
+
+/// 自动保存订单草稿（不扣库存、不生成订单号）
+#[tauri::command]
+pub async fn save_order_draft(
+    order: Order,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<(), String> {
+    if order.id.trim().is_empty() {
+        return Err("草稿 ID 不能为空".to_string());
+    }
+    let order_json = serde_json::to_string(&order).map_err(|e| format!("草稿序列化失败: {}", e))?;
+    let repo = OrderDraftRepository::new(conn.inner().clone());
+    repo.upsert(&order.id, &order_json, &clock.now_rfc3339()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_draft_orders(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Order>, String> {
+    let repo = OrderDraftRepository::new(conn.inner().clone());
+    repo.get_all().map_err(|e| e.to_string())
+}
+
+/// 删除草稿（订单正式保存后由前端调用）
 #[tauri::command]
-pub async fn save_order(
+pub async fn delete_draft_order(
+    id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<(), String> {
+    let repo = OrderDraftRepository::new(conn.inner().clone());
+    repo.delete(&id).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 删除订单：在同一事务中退回订单项扣减的库存（仅跟踪库存的商品）、删除挂账记录、附件记录与订单（订单项级联删除），提交后删除订单的附件目录
+#[tauri::command]
+pub async fn delete_order(
+    id: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<(), String> {
+    let _order_lock = order_locks.lock(&id).await;
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let order = order_repo.get_by_id(&id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", id),
+        e => e.to_string(),
+    })?;
+    let stock_items: Vec<(String, f64)> = order_repo
+        .get_order_items(&id)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|item| (item.id, item.quantity))
+        .collect();
+
+    order_repo.delete_with_restock(&id, &stock_items, &clock.now_rfc3339()).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", id),
+        e => e.to_string(),
+    })?;
+    remove_attachment_dirs(conn.inner(), settings_cache.inner(), std::slice::from_ref(&id));
+
+    log::info!("订单 {} 已删除，{} 行商品的库存已退回", order.order_number, stock_items.len());
+    Ok(())
+}
+
+/// 批量删除订单：整批在同一事务中退回各订单扣减的库存、删除挂账记录、附件与订单，任一订单失败则整批回滚。
+/// 不存在的订单 ID 跳过，返回实际删除的订单数
+#[tauri::command]
+pub async fn batch_delete_orders(
+    ids: Vec<String>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<usize, String> {
+    let mut ids = ids;
+    // 按固定顺序加锁，避免与其他批量操作交叉等待
+    ids.sort();
+    ids.dedup();
+    let mut _order_locks = Vec::with_capacity(ids.len());
+    for id in &ids {
+        _order_locks.push(order_locks.lock(id).await);
+    }
+
+    let deleted = OrderRepository::new(conn.inner().clone())
+        .delete_batch_with_restock(&ids, &clock.now_rfc3339())
+        .map_err(|e| e.to_string())?;
+    remove_attachment_dirs(conn.inner(), settings_cache.inner(), &ids);
+
+    log::info!("批量删除订单：请求 {} 个，实际删除 {} 个，库存已退回", ids.len(), deleted);
+    Ok(deleted)
+}
+
+/// 查看当前暂存的未保存数据（草稿数量与最早/最近的修改时间）
+#[tauri::command]
+pub async fn get_pending_buffers(
+    conn: State<'_, DbConnection>,
+) -> Result<PendingBuffers, String> {
+    let repo = OrderDraftRepository::new(conn.inner().clone());
+    let (draft_count, oldest_draft_at, newest_draft_at) = repo.summary().map_err(|e| e.to_string())?;
+    Ok(PendingBuffers {
+        draft_count,
+        oldest_draft_at,
+        newest_draft_at,
+    })
+}
+
+/// 清空所有草稿，返回删除的草稿数量
+#[tauri::command]
+pub async fn clear_buffers(
+    conn: State<'_, DbConnection>,
+) -> Result<usize, String> {
+    let repo = OrderDraftRepository::new(conn.inner().clone());
+    let cleared = repo.delete_all().map_err(|e| e.to_string())?;
+    log::info!("已清空 {} 个订单草稿", cleared);
+    Ok(cleared)
+}
+
+/// 保存订单（新建或更新）。订单总额总是由后端按明细重新计算（行金额按分四舍五入到 2 位小数）；
+/// 与提交的总额相差超过 1 分时，strict_total 为 true 则拒绝保存，否则以重新计算的结果为准并记录日志
+#[tauri::command]
+pub async fn save_order<R: Runtime>(
+    app: AppHandle<R>,
     mut order: Order,
+    strict_total: Option<bool>,
     conn: State<'_, DbConnection>,
-) -> Result<String, String> {
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<SaveOrderResult, String> {
+    // 同一订单的保存依次执行（读取是否已存在、扣库存必须在同一临界区内）
+    let _order_lock = order_locks.lock(&order.id).await;
+
     let order_repo = OrderRepository::new(conn.inner().clone());
     let customer_repo = CustomerRepository::new(conn.inner().clone());
-    let product_repo = ProductRepository::new(conn.inner().clone());
 
-    // 获取设置以生成正确的订单号
+    // 获取设置以生成正确的订单号（读取缓存）
     let settings_repo = SettingsRepository::new(conn.inner().clone());
-    let settings = settings_repo.get_settings()
+    let settings = settings_cache.get(&settings_repo)
         .map_err(|e| e.to_string())?
         .unwrap_or_else(|| AppSettings {
             id: "settings".to_string(),
@@ -60,6 +530,7 @@ pub async fn save_order(
             order_number_format: "YYYYMMDD_{SEQ:6}".to_string(),
             order_number_prefix: "".to_string(),
             order_number_reset_daily: true,
+            order_number_reset_period: "daily".to_string(),
             order_number_digits: 6,
             retain_days: 90,
             auto_backup: true,
@@ -71,21 +542,73 @@ pub async fn save_order(
             auto_open_excel: false,
             skip_save_dialog: false,
             template_validation: None,
-            updated_at: Utc::now().to_rfc3339(),
+            log_level: "info".to_string(),
+            total_rounding: "none".to_string(),
+            tax_rate: 0.0,
+            prices_include_tax: true,
+            max_item_quantity: 0.0,
+            max_order_total: 0.0,
+            block_on_order_limits: false,
+            export_item_sort: "entry".to_string(),
+            default_track_stock: false,
+            default_min_stock: 0.0,
+            allow_zero_price: true,
+            strict_stock: false,
+            updated_at: clock.now_rfc3339(),
         });
 
-    // 只有在订单号为空时才生成
+    // 未填写订单日期时使用当天
+    if order.date.trim().is_empty() {
+        order.date = clock.today().format("%Y-%m-%d").to_string();
+    }
+
+    // 只有在订单号为空时才生成（在写入订单的事务中生成）
     let auto_generated_order_number = order.order_number.is_empty();
-    let mut order_number = if auto_generated_order_number {
-        let generated = order_repo.generate_order_number(&settings, &order.date)
-            .map_err(|e| e.to_string())?;
-        order.order_number = generated.clone();
-        generated
-    } else {
-        order.order_number.clone()
-    };
 
-    order.updated_at = Utc::now().to_rfc3339();
+    order.updated_at = clock.now_rfc3339();
+    // 前端未带创建时间时按当前时间记录（生成订单号时按创建时间取上一个单号）
+    if order.created_at.trim().is_empty() {
+        order.created_at = order.updated_at.clone();
+    }
+    if !(order.order_discount.is_finite() && order.order_discount >= 0.0) {
+        return Err("整单优惠不能为负数".to_string());
+    }
+    let submitted_total = order.total_amount;
+    recalculate_order_total(&mut order, &settings);
+    if (to_cents(submitted_total) - to_cents(order.total_amount)).abs() > 1 {
+        let message = format!(
+            "订单总额与明细不符：提交 {:.2}，按明细计算应为 {:.2}",
+            submitted_total, order.total_amount
+        );
+        if strict_total.unwrap_or(false) {
+            return Err(message);
+        }
+        log::warn!("订单 {} {}，已按计算结果保存", order.id, message);
+    }
+
+    // 数量/总额上限：默认只提示，开启 block_on_order_limits 时禁止保存
+    let warnings = check_order_limits(&order, &settings);
+    if !warnings.is_empty() {
+        if settings.block_on_order_limits {
+            return Err(format!("订单超出限制：{}", warnings.join("；")));
+        }
+        log::warn!("订单 {} 超出限制: {}", order.id, warnings.join("；"));
+    }
+
+    // 本币订单汇率固定为 1；外币订单必须给出有效汇率
+    order.currency = order.currency.take().map(|c| c.trim().to_uppercase()).filter(|c| !c.is_empty());
+    if order.currency.is_none() {
+        order.exchange_rate = 1.0;
+    } else if !(order.exchange_rate.is_finite() && order.exchange_rate > 0.0) {
+        return Err("汇率必须大于 0".to_string());
+    }
+
+    // 检查订单是否已存在来决定是插入还是更新；状态变更须符合状态流转规则
+    let existing = order_repo.get_by_id(&order.id);
+    match &existing {
+        Ok(previous) if previous.status != order.status => order_status::validate_transition(&previous.status, &order.status)?,
+        _ => order_status::validate_status(&order.status)?,
+    }
 
     // 处理客户引用：
     // - 正式客户：沿用 customer_id（不存在则写入 customers）
@@ -93,82 +616,436 @@ pub async fn save_order(
     if order.customer_id.starts_with("temp_") {
         let snapshot_customer_id = format!("order_customer_{}", order.id);
         order.customer_id = snapshot_customer_id.clone();
-        order.customer.id = snapshot_customer_id.clone();
+        order.customer.id = snapshot_customer_id;
+    }
+    let customer_exists = customer_repo.get_by_id(&order.customer_id).is_ok();
+    // 更新客户最后购买时间（仅正式客户）
+    let purchasing_customer = (!order.customer_id.starts_with("order_customer_")).then(|| {
+        let mut customer = order.customer.clone();
+        customer.last_purchase_at = Some(order.updated_at.clone());
+        customer.updated_at = order.updated_at.clone();
+        customer
+    });
 
-        let customer_exists = customer_repo.get_by_id(&snapshot_customer_id).is_ok();
-        if !customer_exists {
-            customer_repo.insert(&order.customer).map_err(|e| e.to_string())?;
-        }
-    } else {
-        let customer_exists = customer_repo.get_by_id(&order.customer_id).is_ok();
-        if !customer_exists {
-            customer_repo.insert(&order.customer).map_err(|e| e.to_string())?;
+    // 客户、订单与订单项、库存、挂账同步、操作记录在同一个立即加写锁的事务中写入；自动生成的单号也在该事务中生成
+    // （多台终端共用数据库文件时不会读到同一个“上一个单号”），仍因唯一约束冲突时重新生成
+    const MAX_ORDER_NUMBER_ATTEMPTS: usize = 5;
+    let is_new = existing.is_err();
+    let mut attempt = 1;
+    let low_stock_alerts = loop {
+        let mut write = || -> Result<Vec<LowStockAlert>, WriteError> {
+            let mut db = conn.inner().lock().unwrap();
+            let tx = db.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+            // 严格库存：写入任何数据之前检查整单所需库存（更新订单时只检查比原订单多占用的部分）
+            if settings.strict_stock {
+                let mut demands: Vec<(String, f64)> = Vec::new();
+                if holds_stock(&order.status) {
+                    demands.extend(order.items.iter().map(|item| (item.id.clone(), item.quantity)));
+                }
+                if existing.as_ref().is_ok_and(|previous| holds_stock(&previous.status)) {
+                    let previous_items = OrderRepository::item_quantities_on(&tx, &order.id)?;
+                    demands.extend(previous_items.into_iter().map(|(product_id, quantity)| (product_id, -quantity)));
+                }
+                check_stock_available(&tx, &demands)?;
+            }
+
+            if !customer_exists {
+                CustomerRepository::insert_on(&tx, &order.customer)?;
+            }
+
+            if auto_generated_order_number {
+                order.order_number =
+                    generate_order_number_on(&tx, &settings, &order.date, order.template_id.as_deref(), clock.today())?;
+            }
+            let alerts = if is_new {
+                OrderRepository::insert_on(&tx, &order)?;
+                // 新建的已确认/已完成订单扣减库存，草稿不扣减
+                if holds_stock(&order.status) {
+                    let stock_items: Vec<(String, f64)> = order.items.iter()
+                        .map(|item| (item.id.clone(), item.quantity))
+                        .collect();
+                    ProductRepository::adjust_stock_batch(&tx, &stock_items, Some(&order.id), &order.updated_at)?
+                } else {
+                    Vec::new()
+                }
+            } else {
+                // 订单项整体替换，库存按各商品数量的差额调整
+                OrderRepository::update_with_items_on(&tx, &order)?
+            };
+
+            // 挂账订单同步客户往来账
+            CustomerTransactionRepository::sync_order_charge_on(&tx, &order)?;
+
+            let (event_type, detail) = if is_new {
+                ("created", format!("订单号 {}，金额 {:.2}", order.order_number, order.total_amount))
+            } else {
+                ("updated", format!("金额 {:.2}", order.total_amount))
+            };
+            OrderEventRepository::record_on(&tx, &order.id, event_type, Some(&detail), &order.updated_at)?;
+
+            if let Some(customer) = &purchasing_customer {
+                CustomerRepository::update_on(&tx, customer)?;
+            }
+            tx.commit()?;
+            Ok(alerts)
+        };
+        match write() {
+            Ok(alerts) => break alerts,
+            Err(WriteError::Rejected(message)) => return Err(message),
+            Err(WriteError::Database(e))
+                if auto_generated_order_number && attempt < MAX_ORDER_NUMBER_ATTEMPTS && is_order_number_unique_violation(&e) =>
+            {
+                log::warn!("订单号 {} 已被占用，重新生成（第 {} 次）", order.order_number, attempt);
+                attempt += 1;
+            }
+            Err(WriteError::Database(e)) => {
+                log::error!("保存订单 {} 失败: {}", order.id, e);
+                return Err(if !is_order_number_unique_violation(&e) {
+                    e.to_string()
+                } else if auto_generated_order_number {
+                    format!("订单号冲突，已重试 {} 次仍未成功，请稍后重新保存", MAX_ORDER_NUMBER_ATTEMPTS)
+                } else {
+                    "订单号已存在，请修改后重试".to_string()
+                });
+            }
         }
+    };
+    emit_low_stock_alerts(&app, low_stock_alerts);
+
+    let order_number = order.order_number;
+    log::info!("订单已保存: {} ({})", order_number, order.id);
+    Ok(SaveOrderResult { order_number, warnings })
+}
+
+/// 拆分草稿订单：把指定订单项（按行 ID）移到新订单（新单号，客户/日期/模板等沿用原订单），两边分别重算总额。
+/// 已确认/已完成的订单已扣减库存并可能已挂账，不能拆分。整单优惠保留在原订单；
+/// 生成新单号、订单写入、挂账同步与两边的操作记录在同一事务中完成
+#[tauri::command]
+pub async fn split_order(
+    order_id: String,
+    line_ids_to_move: Vec<String>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<SplitOrderResult, String> {
+    // 与同一订单的保存、删除依次执行，避免拆分时读到的订单项已被修改
+    let _order_lock = order_locks.lock(&order_id).await;
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let mut order = order_repo.get_by_id(&order_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+        e => e.to_string(),
+    })?;
+    if order.status != order_status::DRAFT {
+        return Err(format!(
+            "只能拆分草稿订单，订单 {} 为{}",
+            order.order_number,
+            order_status::status_label(&order.status)
+        ));
     }
+    order.items = order_repo.get_order_items(&order_id).map_err(|e| e.to_string())?;
 
-    // 检查订单是否已存在来决定是插入还是更新
-    let existing = order_repo.get_by_id(&order.id);
-    if existing.is_ok() {
-        order_repo.update(&order).map_err(|e| {
+    if line_ids_to_move.is_empty() {
+        return Err("请选择要拆分出去的商品".to_string());
+    }
+    if let Some(missing) = line_ids_to_move
+        .iter()
+        .find(|id| !order.items.iter().any(|item| item.line_id.as_ref() == Some(*id)))
+    {
+        return Err(format!("订单中没有该订单项: {}", missing));
+    }
+
+    let (moved, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut order.items)
+        .into_iter()
+        .partition(|item| item.line_id.as_ref().is_some_and(|id| line_ids_to_move.contains(id)));
+    if kept.is_empty() {
+        return Err("不能把全部商品拆分出去".to_string());
+    }
+
+    let settings_repo = SettingsRepository::new(conn.inner().clone());
+    let settings = settings_cache
+        .get(&settings_repo)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "未找到系统设置".to_string())?;
+
+    let now = clock.now_rfc3339();
+    let mut new_order = order.clone();
+    new_order.id = uuid::Uuid::new_v4().to_string();
+    new_order.items = moved;
+    new_order.order_discount = 0.0;
+    new_order.created_at = now.clone();
+    new_order.updated_at = now.clone();
+    recalculate_order_total(&mut new_order, &settings);
+
+    order.items = kept;
+    order.updated_at = now;
+    recalculate_order_total(&mut order, &settings);
+
+    let mut split = || -> Result<(), WriteError> {
+        let mut db = conn.inner().lock().unwrap();
+        let tx = db.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        new_order.order_number =
+            generate_order_number_on(&tx, &settings, &new_order.date, new_order.template_id.as_deref(), clock.today())?;
+        OrderRepository::split_on(&tx, &order, &new_order)?;
+
+        // 挂账订单两边分别同步往来账
+        CustomerTransactionRepository::sync_order_charge_on(&tx, &order)?;
+        CustomerTransactionRepository::sync_order_charge_on(&tx, &new_order)?;
+
+        OrderEventRepository::record_on(
+            &tx,
+            &order.id,
+            "split",
+            Some(&format!("拆出 {} 项到订单 {}，剩余金额 {:.2}", new_order.items.len(), new_order.order_number, order.total_amount)),
+            &order.updated_at,
+        )?;
+        OrderEventRepository::record_on(
+            &tx,
+            &new_order.id,
+            "created",
+            Some(&format!("由订单 {} 拆分，金额 {:.2}", order.order_number, new_order.total_amount)),
+            &new_order.updated_at,
+        )?;
+        tx.commit()?;
+        Ok(())
+    };
+    split().map_err(|e| match e {
+        WriteError::Rejected(message) => message,
+        WriteError::Database(e) => {
+            log::error!("拆分订单 {} 失败: {}", order.order_number, e);
             if is_order_number_unique_violation(&e) {
-                "订单号已存在，请修改后重试".to_string()
+                "订单号冲突，请重试".to_string()
             } else {
                 e.to_string()
             }
+        }
+    })?;
+
+    log::info!("订单 {} 已拆分，新订单 {}", order.order_number, new_order.order_number);
+    Ok(SplitOrderResult {
+        original_order_id: order.id,
+        new_order_id: new_order.id,
+        new_order_number: new_order.order_number,
+    })
+}
+
+/// 合并订单：把 secondary 的商品并入 primary 并删除 secondary（拆分的逆操作）。
+/// 两个订单必须属于同一客户，且都未完成/未取消；商品已在保存时扣过库存，合并不再扣减
+#[tauri::command]
+pub async fn merge_orders(
+    primary_id: String,
+    secondary_id: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<Order, String> {
+    if primary_id == secondary_id {
+        return Err("不能把订单与自身合并".to_string());
+    }
+
+    // 两个订单都加锁，按固定顺序加锁避免与反向合并交叉等待
+    let (first, second) = if primary_id < secondary_id { (&primary_id, &secondary_id) } else { (&secondary_id, &primary_id) };
+    let _first_lock = order_locks.lock(first).await;
+    let _second_lock = order_locks.lock(second).await;
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let load = |id: &str| -> Result<Order, String> {
+        let mut order = order_repo.get_by_id(id).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", id),
+            e => e.to_string(),
         })?;
-    } else {
-        if auto_generated_order_number {
-            const MAX_ORDER_NUMBER_RETRIES: usize = 5;
-            let mut retry_count = 0usize;
-
-            loop {
-                order.order_number = order_number.clone();
-
-                match order_repo.insert(&order) {
-                    Ok(()) => break,
-                    Err(e) if is_order_number_unique_violation(&e) && retry_count + 1 < MAX_ORDER_NUMBER_RETRIES => {
-                        retry_count += 1;
-                        order_number = order_repo
-                            .generate_order_number(&settings, &order.date)
-                            .map_err(|gen_err| gen_err.to_string())?;
-                    }
-                    Err(e) => {
-                        if is_order_number_unique_violation(&e) {
-                            return Err("订单号冲突，请重试保存".to_string());
-                        }
-                        return Err(e.to_string());
-                    }
-                }
-            }
+        if order.status == "completed" || order.status == "cancelled" {
+            return Err(format!("订单 {} 已完成或已取消，不能合并", order.order_number));
+        }
+        order.items = order_repo.get_order_items(id).map_err(|e| e.to_string())?;
+        Ok(order)
+    };
+    let mut primary = load(&primary_id)?;
+    let secondary = load(&secondary_id)?;
+
+    if primary.customer_id != secondary.customer_id {
+        return Err("只能合并同一客户的订单".to_string());
+    }
+    // 草稿未扣库存，与已确认订单合并会使库存对不上
+    if holds_stock(&primary.status) != holds_stock(&secondary.status) {
+        return Err("草稿订单不能与已确认的订单合并".to_string());
+    }
+
+    // 同一商品的数量并入主订单中该商品的第一行（要求两边单价一致）；手工录入的行与主订单没有的商品追加到末尾
+    let primary_len = primary.items.len();
+    for item in secondary.items {
+        let target = if item.id.is_empty() {
+            None
         } else {
-            order_repo.insert(&order).map_err(|e| {
-                if is_order_number_unique_violation(&e) {
-                    "订单号已存在，请修改后重试".to_string()
-                } else {
-                    e.to_string()
+            primary.items[..primary_len].iter_mut().find(|existing| existing.id == item.id)
+        };
+        match target {
+            Some(existing) => {
+                if to_cents(existing.price) != to_cents(item.price)
+                    || existing.discount_price.map(to_cents) != item.discount_price.map(to_cents)
+                {
+                    return Err(format!("商品「{}」在两个订单中的单价不同，无法合并", item.name));
                 }
-            })?;
+                existing.quantity += item.quantity;
+            }
+            None => primary.items.push(item),
         }
     }
 
-    // 扣减库存（仅在新建订单时扣减，如果是更新，逻辑可能更复杂，暂保持原样或调整）
-    // 注意：如果是更新订单，可能需要处理旧商品的库存返还，这里简单处理只在新建时扣减
-    if existing.is_err() {
-        let stock_items: Vec<(String, f64)> = order.items.iter()
-            .map(|item| (item.id.clone(), item.quantity))
-            .collect();
-        product_repo.deduct_stock_batch(&stock_items).map_err(|e| e.to_string())?;
-    }
+    let settings_repo = SettingsRepository::new(conn.inner().clone());
+    let settings = settings_cache
+        .get(&settings_repo)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "未找到系统设置".to_string())?;
+    primary.order_discount += secondary.order_discount;
+    primary.updated_at = clock.now_rfc3339();
+    recalculate_order_total(&mut primary, &settings);
 
-    // 更新客户最后购买时间（仅正式客户）
-    if !order.customer_id.starts_with("order_customer_") {
-        let mut customer = order.customer;
-        customer.last_purchase_at = Some(Utc::now().to_rfc3339());
-        customer.updated_at = Utc::now().to_rfc3339();
-        customer_repo.update(&customer).map_err(|e| e.to_string())?;
+    // 订单合并与操作记录在同一事务中完成
+    let merge = || -> rusqlite::Result<()> {
+        let mut db = conn.inner().lock().unwrap();
+        let tx = db.transaction()?;
+        OrderRepository::merge_on(&tx, &primary, &secondary_id)?;
+        OrderEventRepository::record_on(
+            &tx,
+            &primary_id,
+            "merged",
+            Some(&format!("并入订单 {}，金额 {:.2}", secondary.order_number, primary.total_amount)),
+            &primary.updated_at,
+        )?;
+        tx.commit()
+    };
+    merge().map_err(|e| {
+        log::error!("合并订单 {} 与 {} 失败: {}", primary.order_number, secondary.order_number, e);
+        e.to_string()
+    })?;
+    primary.items = order_repo.get_order_items(&primary_id).map_err(|e| e.to_string())?;
+
+    log::info!("订单 {} 已并入 {}", secondary.order_number, primary.order_number);
+    Ok(primary)
+}
+
+/// 修改订单状态并记录状态变更。状态流转见 utils::order_status，不允许的变更返回错误；
+/// 进入已确认/已完成时扣减库存（如确认草稿，开启严格库存时库存不足则拒绝），离开时退回库存（如取消已完成的订单），
+/// 与状态修改在同一事务中完成
+#[tauri::command]
+pub async fn update_order_status<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    new_status: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+    order_locks: State<'_, OrderLocks>,
+) -> Result<(), String> {
+    let _order_lock = order_locks.lock(&id).await;
+
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let mut order = order_repo.get_by_id(&id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", id),
+        e => e.to_string(),
+    })?;
+
+    if order.status == new_status {
+        return Ok(());
     }
+    order_status::validate_transition(&order.status, &new_status)?;
+
+    let sign = match (holds_stock(&order.status), holds_stock(&new_status)) {
+        (false, true) => 1.0,
+        (true, false) => -1.0,
+        _ => 0.0,
+    };
+    let strict_stock = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?
+        .is_some_and(|s| s.strict_stock);
+
+    let old_status = std::mem::replace(&mut order.status, new_status.clone());
+    order.updated_at = clock.now_rfc3339();
+    // 状态、库存、挂账同步（状态变化可能影响挂账是否计入往来账）与操作记录在同一事务中完成
+    let update = || -> Result<Vec<LowStockAlert>, WriteError> {
+        let mut db = conn.inner().lock().unwrap();
+        let tx = db.transaction()?;
+        let stock_deltas: Vec<(String, f64)> = if sign == 0.0 {
+            Vec::new()
+        } else {
+            OrderRepository::item_quantities_on(&tx, &id)?
+                .into_iter()
+                .map(|(product_id, quantity)| (product_id, sign * quantity))
+                .collect()
+        };
+        // 严格库存：确认草稿等需要扣减库存时先检查库存是否足够
+        if strict_stock && sign > 0.0 {
+            check_stock_available(&tx, &stock_deltas)?;
+        }
+        let alerts = OrderRepository::update_with_stock_on(&tx, &order, &stock_deltas)?;
+        CustomerTransactionRepository::sync_order_charge_on(&tx, &order)?;
+        OrderEventRepository::record_on(&tx, &id, "status_changed", Some(&format!("{} → {}", old_status, new_status)), &order.updated_at)?;
+        tx.commit()?;
+        Ok(alerts)
+    };
+    let low_stock_alerts = update().map_err(|e| match e {
+        WriteError::Rejected(message) => message,
+        WriteError::Database(e) => {
+            log::error!("修改订单 {} 状态失败: {}", order.order_number, e);
+            e.to_string()
+        }
+    })?;
+    emit_low_stock_alerts(&app, low_stock_alerts);
 
-    Ok(order_number)
+    log::info!("订单 {} 状态变更: {} → {}", order.order_number, old_status, new_status);
+    Ok(())
+}
+
+/// 记录订单导出（导出在前端完成，导出成功后调用），导出次数加 1。
+/// export_order_to_excel 生成文件时已自动记录，无需再调用
+#[tauri::command]
+pub async fn record_order_export(
+    order_id: String,
+    file_path: Option<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<(), String> {
+    let now = clock.now_rfc3339();
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo
+        .record_export(&order_id, &now)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+            e => e.to_string(),
+        })?;
+
+    let event_repo = OrderEventRepository::new(conn.inner().clone());
+    event_repo
+        .record(&order_id, "exported", file_path.as_deref(), &now)
+        .map_err(|e| e.to_string())
+}
+
+/// 订单的导出/打印次数与最后导出时间（次数异常多可能说明客户反复补打或存在问题）
+#[tauri::command]
+pub async fn get_order_export_info(
+    order_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<OrderExportInfo, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    order_repo.get_export_info(&order_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+        e => e.to_string(),
+    })
+}
+
+/// 订单操作记录（创建、修改、状态变更、导出），按时间顺序
+#[tauri::command]
+pub async fn get_order_audit_trail(
+    order_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<OrderEvent>, String> {
+    let event_repo = OrderEventRepository::new(conn.inner().clone());
+    event_repo.get_by_order(&order_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -179,13 +1056,27 @@ pub async fn get_all_templates(
     repo.get_all().map_err(|e| e.to_string())
 }
 
+/// 按需读取模板文件（base64），get_all_templates 只返回模板信息不含文件内容
+#[tauri::command]
+pub async fn get_template_file(
+    id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<String, String> {
+    let repo = TemplateRepository::new(conn.inner().clone());
+    repo.get_file_base64(&id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("模板不存在: {}", id),
+        e => e.to_string(),
+    })
+}
+
 #[tauri::command]
 pub async fn save_template(
     mut template: TemplateConfig,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
     let repo = TemplateRepository::new(conn.inner().clone());
-    template.updated_at = Utc::now().to_rfc3339();
+    template.updated_at = clock.now_rfc3339();
 
     let existing = repo.get_by_id(&template.id);
     if existing.is_ok() {
@@ -203,24 +1094,1303 @@ pub async fn delete_template(
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = TemplateRepository::new(conn.inner().clone());
-    repo.delete(&id).map_err(|e| e.to_string())
+    repo.delete(&id).map_err(|e| {
+        log::error!("删除模板 {} 失败: {}", id, e);
+        e.to_string()
+    })?;
+    log::info!("模板已删除: {}", id);
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn save_settings(
     mut settings: AppSettings,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+    settings_cache: State<'_, SettingsCache>,
 ) -> Result<(), String> {
+    if !settings.tax_rate.is_finite() || !(0.0..=100.0).contains(&settings.tax_rate) {
+        return Err(format!("税率必须在 0 到 100 之间（当前为 {}）", settings.tax_rate));
+    }
+
     let repo = SettingsRepository::new(conn.inner().clone());
     settings.id = "settings".to_string();
-    settings.updated_at = Utc::now().to_rfc3339();
-    repo.save_settings(&settings).map_err(|e| e.to_string())
+    settings.updated_at = clock.now_rfc3339();
+    settings_cache.save(&repo, &settings).map_err(|e| {
+        log::error!("保存设置失败: {}", e);
+        e.to_string()
+    })?;
+
+    logger::set_level(&settings.log_level);
+    log::info!("设置已保存，日志级别: {}", settings.log_level);
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn get_settings(
     conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
 ) -> Result<Option<AppSettings>, String> {
     let repo = SettingsRepository::new(conn.inner().clone());
-    repo.get_settings().map_err(|e| e.to_string())
+    settings_cache.get(&repo).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use rusqlite::params;
+    use tauri::Manager;
+
+    /// 通过设置缓存修改设置（与 save_settings 命令相同的写入路径）
+    fn update_settings(app: &tauri::App<tauri::test::MockRuntime>, change: impl FnOnce(&mut AppSettings)) {
+        let repo = SettingsRepository::new(app.state::<DbConnection>().inner().clone());
+        let cache = app.state::<SettingsCache>();
+        let mut settings = cache.get(&repo).unwrap().unwrap();
+        change(&mut settings);
+        cache.save(&repo, &settings).unwrap();
+    }
+
+    #[test]
+    fn verify_totals_uses_the_tax_snapshot_saved_with_each_order() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        // 不含税 13%、四舍五入到元：30 元明细 - 2 元优惠 = 28，加税 3.64，抹零后 32
+        update_settings(&app, |s| {
+            s.tax_rate = 13.0;
+            s.prices_include_tax = false;
+            s.total_rounding = "round".to_string();
+        });
+        let mut taxed = new_order("o1", "c1", "draft", &[("p1", 10.0, 3.0)]);
+        taxed.order_discount = 2.0;
+        tauri::async_runtime::block_on(save_order(
+            app.handle().clone(),
+            taxed,
+            None,
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+
+        // 之后改为不计税、不抹零，已保存的订单仍按保存时的设置核对
+        update_settings(&app, |s| {
+            s.tax_rate = 0.0;
+            s.prices_include_tax = true;
+            s.total_rounding = "none".to_string();
+        });
+        let plain = new_order("o2", "c1", "draft", &[("p1", 10.0, 1.5)]);
+        tauri::async_runtime::block_on(save_order(
+            app.handle().clone(),
+            plain,
+            None,
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+
+        let saved = OrderRepository::new(conn.clone()).get_by_id("o1").unwrap();
+        assert_eq!((saved.total_amount, saved.tax_rate, saved.prices_include_tax), (32.0, Some(13.0), Some(false)));
+        assert!(tauri::async_runtime::block_on(verify_totals(app.state())).unwrap().is_empty());
+
+        // 篡改总额或行金额后才报告不一致
+        conn.lock().unwrap().execute("UPDATE orders SET total_amount_cents = 3300 WHERE id = 'o1'", []).unwrap();
+        conn.lock().unwrap().execute("UPDATE order_items SET line_total_cents = 1400 WHERE order_id = 'o2'", []).unwrap();
+        let mismatches = tauri::async_runtime::block_on(verify_totals(app.state())).unwrap();
+        let found: Vec<(String, f64, f64)> = mismatches
+            .into_iter()
+            .map(|m| (m.order_id, m.stored_total, m.expected_total))
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&("o1".to_string(), 33.0, 32.0)));
+        assert!(found.contains(&("o2".to_string(), 15.0, 15.0)));
+    }
+
+    #[test]
+    fn verify_totals_accepts_tax_on_orders_saved_before_the_snapshot() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            for (order_id, total_cents) in [("legacy_taxed", 2260), ("legacy_short", 1900)] {
+                insert_order(&c, order_id, "c1", "2023-05-01", "completed");
+                insert_item(&c, &format!("{}_p1", order_id), order_id, "p1", 10.0, 2.0, None);
+                c.execute(
+                    "UPDATE orders SET total_amount = ?1 / 100.0, total_amount_cents = ?1 WHERE id = ?2",
+                    params![total_cents, order_id],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let mismatches = tauri::async_runtime::block_on(verify_totals(app.state())).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!((mismatches[0].order_id.as_str(), mismatches[0].expected_total), ("legacy_short", 20.0));
+    }
+
+    #[test]
+    fn daily_order_numbers_restart_after_local_midnight() {
+        use chrono::{Local, TimeZone, Utc};
+
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        update_settings(&app, |s| {
+            s.order_number_format = "{YYYY}{MM}{DD}-{SEQ:3}".to_string();
+            s.order_number_prefix = String::new();
+            s.order_number_reset_period = "daily".to_string();
+        });
+        let save = |id: &str| {
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", "draft", &[("p1", 10.0, 1.0)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+            OrderRepository::new(conn.clone()).get_by_id(id).unwrap()
+        };
+
+        // 本地时间 3 月 10 日 23:58 与 23:59 各保存一单，拨过零点后再保存一单
+        clock.set(Local.with_ymd_and_hms(2024, 3, 10, 23, 58, 0).unwrap().with_timezone(&Utc));
+        let first = save("o1");
+        clock.advance(chrono::Duration::minutes(1));
+        let second = save("o2");
+        clock.advance(chrono::Duration::minutes(2));
+        let third = save("o3");
+
+        let found: Vec<(String, String)> =
+            [first, second, third].into_iter().map(|o| (o.date, o.order_number)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("2024-03-10".to_string(), "20240310-001".to_string()),
+                ("2024-03-10".to_string(), "20240310-002".to_string()),
+                ("2024-03-11".to_string(), "20240311-001".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn create_status_change_and_merge_events_commit_with_the_order() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str| {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", "draft", &[("p1", 10.0, 1.0)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let set_status = |id: &str, status: &str| {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(update_order_status(
+                app.handle().clone(),
+                id.to_string(),
+                status.to_string(),
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let reject = |event_type: &str| {
+            conn.lock()
+                .unwrap()
+                .execute_batch(&format!(
+                    "CREATE TRIGGER reject_event BEFORE INSERT ON order_events WHEN NEW.event_type = '{}'
+                     BEGIN SELECT RAISE(ABORT, '写入被拒绝'); END;",
+                    event_type
+                ))
+                .unwrap();
+        };
+        let allow = || conn.lock().unwrap().execute_batch("DROP TRIGGER reject_event").unwrap();
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        let events = OrderEventRepository::new(conn.clone());
+        let types = |id: &str| events.get_by_order(id).unwrap().into_iter().map(|e| e.event_type).collect::<Vec<_>>();
+
+        // 操作记录写不进去时订单也不保存
+        reject("created");
+        assert!(save("o1").is_err());
+        assert_eq!(count("SELECT COUNT(*) FROM orders"), 0);
+        allow();
+        save("o1").unwrap();
+        save("o2").unwrap();
+
+        reject("status_changed");
+        assert!(set_status("o1", "confirmed").is_err());
+        assert_eq!(OrderRepository::new(conn.clone()).get_by_id("o1").unwrap().status, "draft");
+        allow();
+        set_status("o1", "confirmed").unwrap();
+        set_status("o2", "confirmed").unwrap();
+        assert_eq!(types("o1"), vec!["created", "status_changed"]);
+
+        reject("merged");
+        let merge = || {
+            tauri::async_runtime::block_on(merge_orders(
+                "o1".to_string(),
+                "o2".to_string(),
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        assert!(merge().is_err());
+        assert_eq!(count("SELECT COUNT(*) FROM orders"), 2);
+        allow();
+        merge().unwrap();
+        // 被并入订单的操作记录改挂到主订单，按时间排列
+        assert_eq!(
+            types("o1"),
+            vec!["created", "created", "status_changed", "status_changed", "merged"]
+        );
+    }
+
+    #[test]
+    fn strict_stock_is_checked_inside_the_write_transaction() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            c.execute("UPDATE products SET track_stock = 1, stock = 5 WHERE id = 'p1'", []).unwrap();
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        update_settings(&app, |s| s.strict_stock = true);
+        let save = |id: &str, status: &str, quantity: f64| {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", status, &[("p1", 10.0, quantity)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        let stock = || {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT stock FROM products WHERE id = 'p1'", [], |row| row.get::<_, f64>(0))
+                .unwrap()
+        };
+
+        // 库存不足时不写入订单、操作记录与客户
+        let err = save("o1", "confirmed", 6.0).unwrap_err();
+        assert_eq!(err, "库存不足：p1（需要 6，可用 5）");
+        assert_eq!(count("SELECT COUNT(*) FROM orders"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_events"), 0);
+
+        // 更新已确认的订单只检查多占用的部分：已占用 4，改为 9 还需要 5
+        save("o1", "confirmed", 4.0).unwrap();
+        assert_eq!(stock(), 1.0);
+        save("o1", "confirmed", 5.0).unwrap();
+        assert!(save("o1", "confirmed", 7.0).unwrap_err().starts_with("库存不足"));
+        assert_eq!(stock(), 0.0);
+
+        // 确认草稿时同样在事务中检查
+        save("o2", "draft", 1.0).unwrap();
+        let err = tauri::async_runtime::block_on(update_order_status(
+            app.handle().clone(),
+            "o2".to_string(),
+            "confirmed".to_string(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap_err();
+        assert!(err.starts_with("库存不足"));
+        assert_eq!(OrderRepository::new(conn.clone()).get_by_id("o2").unwrap().status, "draft");
+
+        // 查询库存出错时返回错误，而不是当作库存充足
+        conn.lock().unwrap().execute_batch("ALTER TABLE products RENAME COLUMN stock TO stock_before").unwrap();
+        assert!(save("o3", "confirmed", 1.0).unwrap_err().contains("stock"));
+        assert_eq!(count("SELECT COUNT(*) FROM orders WHERE id = 'o3'"), 0);
+    }
+
+    #[test]
+    fn concurrent_saves_of_the_same_order_run_one_after_the_other() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            c.execute("UPDATE products SET track_stock = 1, stock = 10 WHERE id = 'p1'", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        // 同一个新订单同时提交两次：后一次必须看到前一次写入的订单，按更新处理并只扣减差额
+        let (first, second) = tauri::async_runtime::block_on(async {
+            tokio::join!(
+                save_order(
+                    app.handle().clone(),
+                    new_order("o1", "c1", "confirmed", &[("p1", 10.0, 3.0)]),
+                    None,
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                ),
+                save_order(
+                    app.handle().clone(),
+                    new_order("o1", "c1", "confirmed", &[("p1", 10.0, 4.0)]),
+                    None,
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                    app.state(),
+                ),
+            )
+        });
+        first.unwrap();
+        second.unwrap();
+
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM orders"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items"), 1);
+        let events = OrderEventRepository::new(conn.clone()).get_by_order("o1").unwrap();
+        assert_eq!(events.into_iter().map(|e| e.event_type).collect::<Vec<_>>(), vec!["created", "updated"]);
+        let stock: f64 = conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT stock FROM products WHERE id = 'p1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stock, 6.0);
+        let order = OrderRepository::new(conn.clone()).get_by_id("o1").unwrap();
+        assert_eq!(order.total_amount, 40.0);
+    }
+
+    #[test]
+    fn split_order_rolls_back_with_its_events_and_rejects_confirmed_orders() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        for (id, status) in [("o1", "draft"), ("o2", "confirmed")] {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", status, &[("p1", 10.0, 1.0), ("p2", 5.0, 2.0)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+        }
+        let split = |id: &str| {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(split_order(
+                id.to_string(),
+                vec!["o1_p2".to_string()],
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+
+        assert!(split("o2").unwrap_err().contains("只能拆分草稿订单"));
+
+        // 操作记录写入失败时订单也不拆分
+        conn.lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject_created BEFORE INSERT ON order_events WHEN NEW.event_type = 'created' AND NEW.detail LIKE '由订单%'
+                 BEGIN SELECT RAISE(ABORT, '写入被拒绝'); END;",
+            )
+            .unwrap();
+        assert!(split("o1").is_err());
+        assert_eq!(count("SELECT COUNT(*) FROM orders"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items WHERE order_id = 'o1'"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM order_events WHERE event_type = 'split'"), 0);
+
+        conn.lock().unwrap().execute_batch("DROP TRIGGER reject_created").unwrap();
+        let result = split("o1").unwrap();
+        let events = OrderEventRepository::new(conn.clone());
+        let types = |id: &str| events.get_by_order(id).unwrap().into_iter().map(|e| e.event_type).collect::<Vec<_>>();
+        assert_eq!(types("o1"), vec!["created", "split"]);
+        assert_eq!(types(&result.new_order_id), vec!["created"]);
+        assert_eq!(OrderRepository::new(conn.clone()).get_by_id("o1").unwrap().total_amount, 10.0);
+    }
+
+    #[test]
+    fn split_order_moves_the_selected_lines_including_manual_and_duplicate_product_lines() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+            insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+            insert_item(&c, "o1_p1", "o1", "p1", 10.0, 1.0, None);
+            insert_item(&c, "o1_p1_2", "o1", "p1", 10.0, 2.0, None);
+            insert_item(&c, "o1_p2", "o1", "p2", 5.0, 1.0, None);
+            c.execute(
+                "INSERT INTO order_items (id, order_id, product_id, name, unit, price, price_cents, quantity, line_total, line_total_cents)
+                 VALUES ('o1_', 'o1', NULL, '工时费', '次', 3, 300, 2, 6, 600)",
+                [],
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        // 只拆出 p1 的第二行和手工录入的行
+        let result = tauri::async_runtime::block_on(split_order(
+            "o1".to_string(),
+            vec!["o1_p1_2".to_string(), "o1_".to_string()],
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+
+        let lines = |order_id: &str| -> Vec<(String, Option<String>, f64)> {
+            let c = conn.lock().unwrap();
+            let mut stmt = c
+                .prepare("SELECT id, product_id, quantity FROM order_items WHERE order_id = ?1 ORDER BY sort_value, rowid")
+                .unwrap();
+            let rows = stmt
+                .query_map(params![order_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            rows
+        };
+        let line = |id: String, product: Option<&str>, quantity: f64| (id, product.map(str::to_string), quantity);
+        assert_eq!(
+            lines("o1"),
+            vec![line("o1_p1".to_string(), Some("p1"), 1.0), line("o1_p2".to_string(), Some("p2"), 1.0)]
+        );
+        let new_id = result.new_order_id.clone();
+        assert_eq!(
+            lines(&new_id),
+            vec![line(format!("{}_p1_2", new_id), Some("p1"), 2.0), line(format!("{}_", new_id), None, 2.0)]
+        );
+
+        let repo = OrderRepository::new(conn.clone());
+        assert_eq!(repo.get_by_id("o1").unwrap().total_amount, 15.0);
+        let new_order = repo.get_by_id(&new_id).unwrap();
+        assert_eq!(new_order.total_amount, 26.0);
+        assert_eq!(new_order.order_number, result.new_order_number);
+        assert_ne!(new_order.order_number, "o1");
+
+        // 不属于该订单的行 ID 被拒绝
+        let err = tauri::async_runtime::block_on(split_order(
+            "o1".to_string(),
+            vec!["p2".to_string()],
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap_err();
+        assert!(err.contains("订单中没有该订单项"));
+    }
+
+    #[test]
+    fn split_and_merge_wait_for_the_order_locks() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        for id in ["o1", "o2"] {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", "draft", &[("p1", 10.0, 1.0), ("p2", 5.0, 2.0)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+        }
+        clock.advance(chrono::Duration::seconds(1));
+
+        tauri::async_runtime::block_on(async {
+            let locks = app.state::<OrderLocks>();
+            let pending = std::time::Duration::from_millis(50);
+
+            // 订单被占用时拆分等待，释放后继续
+            let held = locks.lock("o1").await;
+            let split = split_order("o1".to_string(), vec!["o1_p2".to_string()], app.state(), app.state(), app.state(), app.state());
+            tokio::pin!(split);
+            assert!(tokio::time::timeout(pending, &mut split).await.is_err());
+            drop(held);
+            split.await.unwrap();
+
+            // 合并等待两个订单的锁（这里占用的是被并入的订单）
+            let held = locks.lock("o2").await;
+            let merge = merge_orders("o1".to_string(), "o2".to_string(), app.state(), app.state(), app.state(), app.state());
+            tokio::pin!(merge);
+            assert!(tokio::time::timeout(pending, &mut merge).await.is_err());
+            drop(held);
+            merge.await.unwrap();
+        });
+
+        assert!(OrderRepository::new(conn.clone()).get_by_id("o2").is_err());
+    }
+
+    #[test]
+    fn merge_orders_folds_products_into_one_line_and_keeps_manual_lines_separate() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+            insert_product(&c, "p3", 2.5, None);
+            insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+            insert_order(&c, "o2", "c1", "2024-03-10", "draft");
+            // 两个订单都有同一商品的两行和一行手工录入的项目
+            insert_item(&c, "o1_p1", "o1", "p1", 10.0, 1.0, None);
+            insert_item(&c, "o1_p1_2", "o1", "p1", 10.0, 2.0, None);
+            insert_item(&c, "o1_p2", "o1", "p2", 5.0, 1.0, None);
+            insert_item(&c, "o2_p1", "o2", "p1", 10.0, 4.0, None);
+            insert_item(&c, "o2_p1_2", "o2", "p1", 10.0, 1.0, None);
+            insert_item(&c, "o2_p3", "o2", "p3", 2.5, 2.0, None);
+            for (id, order_id, quantity) in [("o1_", "o1", 1.0), ("o2_", "o2", 2.0)] {
+                c.execute(
+                    "INSERT INTO order_items (id, order_id, product_id, name, unit, price, price_cents, quantity, line_total, line_total_cents)
+                     VALUES (?1, ?2, NULL, '工时费', '次', 3, 300, ?3, ?4, ?5)",
+                    params![id, order_id, quantity, 3.0 * quantity, (300.0 * quantity) as i64],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let merged = tauri::async_runtime::block_on(merge_orders(
+            "o1".to_string(),
+            "o2".to_string(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+
+        // p1 的两行都并入主订单 p1 的第一行；主订单自己的第二行 p1、两边的手工项目各自保留；p3 移到末尾
+        let rows: Vec<(String, Option<String>, f64, i64)> = {
+            let c = conn.lock().unwrap();
+            let mut stmt = c
+                .prepare("SELECT id, product_id, quantity, line_total_cents FROM order_items WHERE order_id = 'o1' ORDER BY sort_value, rowid")
+                .unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            rows
+        };
+        let line = |id: &str, product: Option<&str>, quantity: f64, cents: i64| (id.to_string(), product.map(str::to_string), quantity, cents);
+        assert_eq!(
+            rows,
+            vec![
+                line("o1_p1", Some("p1"), 6.0, 6000),
+                line("o1_p1_2", Some("p1"), 2.0, 2000),
+                line("o1_p2", Some("p2"), 1.0, 500),
+                line("o1_", None, 1.0, 300),
+                line("o1_p3", Some("p3"), 2.0, 500),
+                line("o1__2", None, 2.0, 600),
+            ]
+        );
+
+        let repo = OrderRepository::new(conn.clone());
+        let saved = repo.get_by_id("o1").unwrap();
+        assert_eq!((merged.total_amount, saved.total_amount), (99.0, 99.0));
+        assert_eq!(merged.items.len(), 6);
+        assert!(repo.get_by_id("o2").is_err());
+        let leftover: i64 = conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM order_items WHERE order_id = 'o2'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(leftover, 0);
+    }
+
+    #[test]
+    fn saved_settings_apply_to_the_next_order_number_without_a_restart() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+        }
+        let (app, clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str| {
+            clock.advance(chrono::Duration::seconds(1));
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                new_order(id, "c1", "draft", &[("p1", 10.0, 1.0)]),
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+            OrderRepository::new(conn.clone()).get_by_id(id).unwrap().order_number
+        };
+
+        // 第一单按默认格式生成，同时把设置载入缓存
+        assert_eq!(save("o1"), "NO.000001");
+
+        // 通过设置命令修改单号格式，下一单立即使用新格式
+        let mut settings = tauri::async_runtime::block_on(get_settings(app.state(), app.state()))
+            .unwrap()
+            .unwrap();
+        settings.order_number_format = "QS-{SEQ:3}".to_string();
+        settings.order_number_prefix = String::new();
+        tauri::async_runtime::block_on(save_settings(settings, app.state(), app.state(), app.state())).unwrap();
+
+        assert_eq!(save("o2"), "QS-001");
+    }
+
+    #[test]
+    fn save_settings_rejects_tax_rates_outside_0_to_100() {
+        let conn = memory_db();
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let settings = tauri::async_runtime::block_on(get_settings(app.state(), app.state()))
+            .unwrap()
+            .unwrap();
+        let save = |tax_rate: f64| {
+            let mut settings = settings.clone();
+            settings.tax_rate = tax_rate;
+            tauri::async_runtime::block_on(save_settings(settings, app.state(), app.state(), app.state()))
+        };
+
+        for invalid in [-1.0, 100.5, f64::NAN] {
+            let error = save(invalid).unwrap_err();
+            assert!(error.contains("税率必须在 0 到 100 之间"), "{}", error);
+        }
+        save(13.0).unwrap();
+        save(100.0).unwrap();
+
+        let saved = tauri::async_runtime::block_on(get_settings(app.state(), app.state()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.tax_rate, 100.0);
+    }
+
+    #[test]
+    fn saving_a_draft_again_overwrites_it() {
+        let (app, clock) = test_app(memory_db(), "2024-03-10T04:00:00Z");
+        let save = |order: Order| tauri::async_runtime::block_on(save_order_draft(order, app.state(), app.state()));
+
+        let mut draft = new_order("d1", "c1", "draft", &[("p1", 10.0, 1.0)]);
+        save(draft.clone()).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        draft.items[0].quantity = 3.0;
+        draft.remark = Some("改过".to_string());
+        save(draft.clone()).unwrap();
+
+        let drafts = tauri::async_runtime::block_on(get_draft_orders(app.state())).unwrap();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!((drafts[0].items[0].quantity, drafts[0].remark.as_deref()), (3.0, Some("改过")));
+
+        draft.id = " ".to_string();
+        assert_eq!(save(draft).unwrap_err(), "草稿 ID 不能为空");
+    }
+
+    #[test]
+    fn pending_buffers_report_drafts_until_cleared() {
+        let (app, clock) = test_app(memory_db(), "2024-03-10T04:00:00Z");
+        let buffers = || {
+            let b = tauri::async_runtime::block_on(get_pending_buffers(app.state())).unwrap();
+            (b.draft_count, b.oldest_draft_at, b.newest_draft_at)
+        };
+        assert_eq!(buffers(), (0, None, None));
+
+        for id in ["d1", "d2"] {
+            let draft = new_order(id, "c1", "draft", &[("p1", 10.0, 1.0)]);
+            tauri::async_runtime::block_on(save_order_draft(draft, app.state(), app.state())).unwrap();
+            clock.advance(chrono::Duration::hours(1));
+        }
+        assert_eq!(
+            buffers(),
+            (2, Some("2024-03-10T04:00:00+00:00".to_string()), Some("2024-03-10T05:00:00+00:00".to_string()))
+        );
+
+        assert_eq!(tauri::async_runtime::block_on(clear_buffers(app.state())), Ok(2));
+        assert_eq!(buffers(), (0, None, None));
+    }
+
+    #[test]
+    fn deleting_an_order_keeps_its_payments_and_refunds_in_the_customer_ledger() {
+        use crate::commands::{add_customer_charge, add_customer_payment, add_customer_refund, get_customer_balance};
+
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-03-01", "completed");
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let order_id = || Some("o1".to_string());
+        tauri::async_runtime::block_on(add_customer_charge("c1".to_string(), 100.0, order_id(), None, app.state(), app.state()))
+            .unwrap();
+        tauri::async_runtime::block_on(add_customer_charge("c1".to_string(), 50.0, None, None, app.state(), app.state()))
+            .unwrap();
+        tauri::async_runtime::block_on(add_customer_payment(
+            "c1".to_string(),
+            60.0,
+            order_id(),
+            Some("微信".to_string()),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        tauri::async_runtime::block_on(add_customer_refund("c1".to_string(), 10.0, order_id(), None, app.state(), app.state()))
+            .unwrap();
+        let balance = || {
+            let b = tauri::async_runtime::block_on(get_customer_balance("c1".to_string(), app.state())).unwrap();
+            (b.charged, b.paid, b.balance)
+        };
+        assert_eq!(balance(), (150.0, 50.0, 100.0));
+
+        tauri::async_runtime::block_on(delete_order("o1".to_string(), app.state(), app.state(), app.state(), app.state()))
+            .unwrap();
+
+        // 订单的挂账随订单删除，已收的 60 与退回的 10 仍计入余额
+        assert_eq!(balance(), (50.0, 50.0, 0.0));
+        let c = conn.lock().unwrap();
+        let mut stmt = c
+            .prepare("SELECT kind, order_id, remark FROM customer_transactions WHERE kind <> 'charge' ORDER BY kind")
+            .unwrap();
+        let kept: Vec<(String, Option<String>, Option<String>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            kept,
+            vec![
+                ("payment".to_string(), None, Some("微信；原订单 o1 已删除".to_string())),
+                ("refund".to_string(), None, Some("原订单 o1 已删除".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn total_rounding_adjusts_only_the_grand_total_and_reports_use_it() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 41.2, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        // 明细 41.2 × 3 = 123.6，各抹零方式只调整总额
+        for (id, mode) in [("o1", "none"), ("o2", "round"), ("o3", "floor")] {
+            update_settings(&app, |s| s.total_rounding = mode.to_string());
+            let mut order = new_order(id, "c1", "completed", &[("p1", 41.2, 3.0)]);
+            order.date = "2024-03-10".to_string();
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+        }
+
+        let repo = OrderRepository::new(conn.clone());
+        let saved: Vec<(f64, f64, f64)> = ["o1", "o2", "o3"]
+            .iter()
+            .map(|id| {
+                let order = repo.get_by_id(id).unwrap();
+                (order.total_amount, order.rounding_adjustment, repo.get_order_items(id).unwrap()[0].line_total)
+            })
+            .collect();
+        assert_eq!(saved, vec![(123.6, 0.0, 123.6), (124.0, 0.4, 123.6), (123.0, -0.6, 123.6)]);
+
+        let total = tauri::async_runtime::block_on(get_sales_total(
+            "2024-03-10".to_string(),
+            "2024-03-10".to_string(),
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!((total.order_count, total.total_amount), (3, 370.6));
+    }
+
+    #[test]
+    fn foreign_currency_orders_are_summed_in_the_base_currency() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str, currency: Option<&str>, exchange_rate: f64, quantity: f64| {
+            let mut order = new_order(id, "c1", "completed", &[("p1", 10.0, quantity)]);
+            order.date = "2024-03-10".to_string();
+            order.currency = currency.map(str::to_string);
+            order.exchange_rate = exchange_rate;
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+
+        assert_eq!(save("bad", Some("USD"), 0.0, 1.0).unwrap_err(), "汇率必须大于 0");
+        assert_eq!(save("bad", Some("USD"), f64::NAN, 1.0).unwrap_err(), "汇率必须大于 0");
+
+        // 本币 100；美元 10 × 7.2 = 72；港币 50 × 0.92 = 46
+        save("o1", None, 5.0, 10.0).unwrap();
+        save("o2", Some(" usd "), 7.2, 1.0).unwrap();
+        save("o3", Some("HKD"), 0.92, 5.0).unwrap();
+
+        let repo = OrderRepository::new(conn.clone());
+        let stored: Vec<(Option<String>, f64, f64)> = ["o1", "o2", "o3"]
+            .iter()
+            .map(|id| {
+                let order = repo.get_by_id(id).unwrap();
+                (order.currency, order.exchange_rate, order.total_amount)
+            })
+            .collect();
+        // 本币订单的汇率固定为 1，订单金额仍按原币保存
+        assert_eq!(
+            stored,
+            vec![(None, 1.0, 100.0), (Some("USD".to_string()), 7.2, 10.0), (Some("HKD".to_string()), 0.92, 50.0)]
+        );
+        assert!(matches!(repo.get_by_id("bad"), Err(rusqlite::Error::QueryReturnedNoRows)));
+
+        let total = tauri::async_runtime::block_on(get_sales_total(
+            "2024-03-10".to_string(),
+            "2024-03-10".to_string(),
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!((total.order_count, total.total_amount), (3, 218.0));
+    }
+
+    #[test]
+    fn order_size_limits_warn_by_default_and_block_only_when_configured() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 1.0, None);
+            insert_product(&c, "p2", 2.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str| {
+            let order = new_order(id, "c1", "completed", &[("p1", 1.0, 1000.0), ("p2", 2.0, 5.0)]);
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+
+        // 未设置上限（0）时不检查
+        assert!(save("o1").unwrap().warnings.is_empty());
+
+        // 数量 1000 超过 100：照常保存并返回提示；总额 1010 未超过 2000
+        update_settings(&app, |s| {
+            s.max_item_quantity = 100.0;
+            s.max_order_total = 2000.0;
+        });
+        assert_eq!(save("o2").unwrap().warnings, vec!["商品「p1」数量 1000 超过上限 100"]);
+
+        update_settings(&app, |s| s.max_order_total = 500.0);
+        let warnings = vec!["商品「p1」数量 1000 超过上限 100", "订单总额 1010.00 超过上限 500.00"];
+        assert_eq!(save("o3").unwrap().warnings, warnings);
+
+        // 开启阻止后拒绝保存，订单不写入
+        update_settings(&app, |s| s.block_on_order_limits = true);
+        assert_eq!(save("o4").unwrap_err(), format!("订单超出限制：{}", warnings.join("；")));
+        let repo = OrderRepository::new(conn.clone());
+        assert!(matches!(repo.get_by_id("o4"), Err(rusqlite::Error::QueryReturnedNoRows)));
+        assert!(repo.get_by_id("o3").is_ok());
+    }
+
+    #[test]
+    fn stored_line_totals_sum_to_the_order_total() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            for id in ["p1", "p2", "p3"] {
+                insert_product(&c, id, 10.0, None);
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |order: Order| {
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap()
+        };
+        let stored = || {
+            let c = conn.lock().unwrap();
+            let mut stmt = c
+                .prepare("SELECT line_total, line_total_cents FROM order_items WHERE order_id = 'o1' ORDER BY sort_value")
+                .unwrap();
+            let lines = stmt
+                .query_map([], |row| Ok((row.get::<_, f64>(0)?, row.get::<_, i64>(1)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let total: i64 =
+                c.query_row("SELECT total_amount_cents FROM orders WHERE id = 'o1'", [], |row| row.get(0)).unwrap();
+            (lines, total)
+        };
+
+        // 12.34 × 3 = 37.02；折扣价 8.5 × 1.5 = 12.75；0.333 × 3 = 0.999 按分四舍五入为 1.00
+        let mut order =
+            new_order("o1", "c1", "completed", &[("p1", 12.34, 3.0), ("p2", 10.0, 1.5), ("p3", 0.333, 3.0)]);
+        order.items[1].discount_price = Some(8.5);
+        save(order.clone());
+        let (lines, total) = stored();
+        assert_eq!(lines, vec![(37.02, 3702), (12.75, 1275), (1.0, 100)]);
+        assert_eq!(total, lines.iter().map(|(_, cents)| cents).sum::<i64>());
+        assert_eq!(total, 5077);
+
+        // 修改数量后重新保存，行金额随之更新
+        order.items[0].quantity = 1.0;
+        order.items.pop();
+        save(order);
+        let (lines, total) = stored();
+        assert_eq!(lines, vec![(12.34, 1234), (12.75, 1275)]);
+        assert_eq!(total, 2509);
+        let items = OrderRepository::new(conn.clone()).get_order_items("o1").unwrap();
+        assert_eq!(items.iter().map(|i| i.line_total).collect::<Vec<_>>(), vec![12.34, 12.75]);
+    }
+
+    #[test]
+    fn updating_an_order_applies_per_product_stock_deltas() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            for id in ["p1", "p2", "p3"] {
+                insert_product(&c, id, 10.0, None);
+            }
+            c.execute("UPDATE products SET track_stock = 1, stock = 10", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |order: Order| {
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap()
+        };
+        let stock = || {
+            let c = conn.lock().unwrap();
+            ["p1", "p2", "p3"].map(|id| {
+                c.query_row("SELECT stock FROM products WHERE id = ?1", params![id], |row| row.get::<_, f64>(0))
+                    .unwrap()
+            })
+        };
+        let movements = || -> i64 {
+            conn.lock()
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM stock_movements WHERE order_id = 'o1'", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let mut order = new_order("o1", "c1", "completed", &[("p1", 10.0, 3.0), ("p2", 10.0, 2.0)]);
+        save(order.clone());
+        assert_eq!(stock(), [7.0, 8.0, 10.0]);
+        assert_eq!(movements(), 2);
+
+        // p1 加到 5，删除 p2，新增 p3 × 4：只按差额调整
+        order.items[0].quantity = 5.0;
+        order.items[1] = new_order("o1", "c1", "completed", &[("p3", 10.0, 4.0)]).items.remove(0);
+        save(order.clone());
+        assert_eq!(stock(), [5.0, 10.0, 6.0]);
+        assert_eq!(movements(), 5);
+
+        // 明细不变再保存一次，库存与流水都不变
+        save(order);
+        assert_eq!(stock(), [5.0, 10.0, 6.0]);
+        assert_eq!(movements(), 5);
+    }
+
+    #[test]
+    fn report_bundle_matches_the_standalone_report_commands() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "k1", None, 1);
+            insert_category(&c, "k2", Some("k1"), 2);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_customer(&c, "c2", "李四", "13900000000", "B12345");
+            insert_customer(&c, "c3", "王五", "13700000000", "C12345");
+            insert_product(&c, "p1", 10.0, Some("k1"));
+            insert_product(&c, "p2", 25.0, Some("k2"));
+            insert_product(&c, "p3", 8.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str, customer: &str, status: &str, date: &str, items: &[(&str, f64, f64)]| {
+            let mut order = new_order(id, customer, status, items);
+            order.order_number = id.to_string();
+            order.date = date.to_string();
+            if id == "o4" {
+                order.currency = Some("USD".to_string());
+                order.exchange_rate = 7.2;
+            }
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap();
+        };
+        save("o1", "c1", "completed", "2024-03-01", &[("p1", 10.0, 2.0), ("p2", 25.0, 1.0)]);
+        save("o2", "c2", "completed", "2024-03-03", &[("p2", 25.0, 4.0)]);
+        save("o3", "c1", "completed", "2024-03-03", &[("p3", 8.0, 3.0), ("p1", 10.0, 1.0)]);
+        save("o4", "c3", "completed", "2024-03-05", &[("p1", 10.0, 5.0)]);
+        save("o5", "c2", "draft", "2024-03-05", &[("p2", 25.0, 10.0)]);
+        save("o6", "c3", "completed", "2024-04-01", &[("p2", 25.0, 10.0)]);
+        OrderRepository::new(conn.clone()).archive_before("2024-03-02").unwrap();
+
+        fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+            serde_json::to_value(value).unwrap()
+        }
+        let (from, to) = (|| "2024-03-01".to_string(), || "2024-03-31".to_string());
+        for include_archived in [None, Some(true)] {
+            let bundle = tauri::async_runtime::block_on(get_report_bundle(
+                from(),
+                to(),
+                include_archived,
+                Some(2),
+                app.state(),
+            ))
+            .unwrap();
+            let sales_total =
+                tauri::async_runtime::block_on(get_sales_total(from(), to(), include_archived, app.state())).unwrap();
+            let daily_sales =
+                tauri::async_runtime::block_on(get_daily_sales(from(), to(), include_archived, app.state())).unwrap();
+            let top_products = tauri::async_runtime::block_on(get_top_products(
+                from(),
+                to(),
+                include_archived,
+                Some(2),
+                app.state(),
+            ))
+            .unwrap();
+            let top_customers = tauri::async_runtime::block_on(get_top_customers(
+                from(),
+                to(),
+                include_archived,
+                Some(2),
+                app.state(),
+            ))
+            .unwrap();
+            let category_sales = tauri::async_runtime::block_on(get_category_sales_ranking(
+                from(),
+                to(),
+                include_archived,
+                app.state(),
+            ))
+            .unwrap();
+
+            assert_eq!((bundle.from.as_str(), bundle.to.as_str()), ("2024-03-01", "2024-03-31"));
+            assert_eq!(json(&bundle.sales_total), json(&sales_total));
+            assert_eq!(json(&bundle.daily_sales), json(&daily_sales));
+            assert_eq!(json(&bundle.top_products), json(&top_products));
+            assert_eq!(json(&bundle.top_customers), json(&top_customers));
+            assert_eq!(json(&bundle.category_sales), json(&category_sales));
+            assert_eq!(bundle.top_products.len(), 2);
+            assert_eq!(bundle.top_customers.len(), 2);
+
+            // 草稿与范围外的订单不计入；归档的 o1（45）只在 include_archived 时计入，o4 为 50 美元 × 7.2
+            let expected = if include_archived.is_some() { (4, 539.0) } else { (3, 494.0) };
+            assert_eq!((sales_total.order_count, sales_total.total_amount), expected);
+        }
+    }
+
+    #[test]
+    fn duplicate_product_lines_keep_their_line_ids_across_saves() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 20.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |order: Order| {
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                None,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+            .unwrap()
+        };
+        let reload = || OrderRepository::new(conn.clone()).get_order_items("o1").unwrap();
+        let lines = |items: &[crate::models::OrderItem]| {
+            items
+                .iter()
+                .map(|item| (item.line_id.clone().unwrap(), item.id.clone(), item.quantity, item.remark.clone()))
+                .collect::<Vec<_>>()
+        };
+        let line = |line_id: &str, product: &str, quantity: f64, remark: Option<&str>| {
+            (line_id.to_string(), product.to_string(), quantity, remark.map(str::to_string))
+        };
+
+        let mut order = new_order("o1", "c1", "completed", &[("p1", 10.0, 1.0), ("p1", 10.0, 2.0), ("p2", 20.0, 3.0)]);
+        order.items[1].remark = Some("赠送".to_string());
+        save(order.clone());
+        let items = reload();
+        assert_eq!(
+            lines(&items),
+            vec![line("o1_p1", "p1", 1.0, None), line("o1_p1_2", "p1", 2.0, Some("赠送")), line("o1_p2", "p2", 3.0, None)]
+        );
+
+        // 调换两行 p1 的顺序并只修改第二行：行 ID 跟着行走，不按位置重新分配
+        order.items = items;
+        order.items.swap(0, 1);
+        for (index, item) in order.items.iter_mut().enumerate() {
+            item.sort_value = index as i64;
+        }
+        order.items[1].quantity = 5.0;
+        save(order.clone());
+        let items = reload();
+        assert_eq!(
+            lines(&items),
+            vec![line("o1_p1_2", "p1", 2.0, Some("赠送")), line("o1_p1", "p1", 5.0, None), line("o1_p2", "p2", 3.0, None)]
+        );
+
+        // 新增的同商品行与带着其他订单行 ID 的行都分配未占用的新 ID
+        order.items = items;
+        let mut copied = new_order("o1", "c1", "completed", &[("p1", 10.0, 4.0)]).items.remove(0);
+        copied.line_id = Some("o9_p1".to_string());
+        copied.sort_value = 3;
+        order.items.push(copied);
+        save(order);
+        let items = reload();
+        assert_eq!(
+            items.iter().map(|item| item.line_id.clone().unwrap()).collect::<Vec<_>>(),
+            vec!["o1_p1_2", "o1_p1", "o1_p2", "o1_p1_3"]
+        );
+        assert_eq!(items[3].quantity, 4.0);
+    }
+
+    #[test]
+    fn submitted_totals_are_recomputed_and_rejected_only_in_strict_mode() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 12.34, None);
+            insert_product(&c, "p2", 10.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let save = |id: &str, submitted_total: f64, strict_total: Option<bool>| {
+            // 原价行 12.34 × 3 = 37.02；折扣行按折扣价 8.5 × 1.5 = 12.75；合计 49.77
+            let mut order = new_order(id, "c1", "completed", &[("p1", 12.34, 3.0), ("p2", 10.0, 1.5)]);
+            order.items[1].discount_price = Some(8.5);
+            order.total_amount = submitted_total;
+            tauri::async_runtime::block_on(save_order(
+                app.handle().clone(),
+                order,
+                strict_total,
+                app.state(),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let stored_total = |id: &str| OrderRepository::new(conn.clone()).get_by_id(id).map(|order| order.total_amount);
+
+        // 默认以重新计算的结果为准
+        save("o1", 59.77, None).unwrap();
+        assert_eq!(stored_total("o1").unwrap(), 49.77);
+        save("o2", 0.0, Some(false)).unwrap();
+        assert_eq!(stored_total("o2").unwrap(), 49.77);
+
+        // 严格模式：相差不超过 1 分时照常保存，超过则拒绝且不写入
+        save("o3", 49.77, Some(true)).unwrap();
+        save("o4", 49.78, Some(true)).unwrap();
+        assert_eq!(stored_total("o4").unwrap(), 49.77);
+        assert_eq!(
+            save("o5", 50.0, Some(true)).unwrap_err(),
+            "订单总额与明细不符：提交 50.00，按明细计算应为 49.77"
+        );
+        assert!(matches!(stored_total("o5"), Err(rusqlite::Error::QueryReturnedNoRows)));
+    }
 }
@@ -1,8 +1,15 @@
 use tauri::State;
-use crate::database::{connection::DbConnection, schema::{ProductRepository, Repository}};
-use crate::models::Product;
+use crate::database::{connection::DbConnection, SettingsCache, schema::{CategoryRepository, ProductRepository, Repository, SettingsRepository, WriteError}};
+use crate::models::{AppSettings, Category, CsvImportReport, CsvImportRow, PriceAnomaly, Product, ProductPage};
+use crate::utils::clock::SharedClock;
+use crate::utils::csv;
 use anyhow::Result;
 use pinyin::ToPinyin;
+use std::collections::HashMap;
+
+// 商品分页的默认与最大每页条数
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
 
 fn generate_search_pinyin(name: &str) -> String {
     let mut initials = String::new();
@@ -29,6 +36,20 @@ fn generate_search_pinyin(name: &str) -> String {
     }
 }
 
+// 将搜索词转换为与 products.pinyin 相同的形式：汉字转为全拼，字母数字转小写，去掉空格与其他符号。
+// 转换后不含空格，匹配 "首字母 全拼" 时只会落在首字母或全拼其中一段内
+fn normalize_pinyin_query(query: &str) -> String {
+    let mut normalized = String::new();
+    for c in query.chars() {
+        if let Some(py) = c.to_pinyin() {
+            normalized.push_str(&py.plain().to_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
 #[tauri::command]
 pub async fn get_all_products(
     conn: State<'_, DbConnection>,
@@ -46,13 +67,35 @@ pub async fn get_product_by_id(
     repo.get_by_id(&id).map_err(|e| e.to_string())
 }
 
+/// 商品搜索范围：name 只搜名称，pinyin 只搜拼音，all 同时搜名称、拼音、条码与分类名称
+const PRODUCT_SEARCH_SCOPES: &[&str] = &["name", "pinyin", "all"];
+
+/// 搜索商品，scope 未指定时为 all
 #[tauri::command]
 pub async fn search_products(
     query: String,
+    scope: Option<String>,
     conn: State<'_, DbConnection>,
 ) -> Result<Vec<Product>, String> {
+    let scope = scope.unwrap_or_else(|| "all".to_string());
+    if !PRODUCT_SEARCH_SCOPES.contains(&scope.as_str()) {
+        return Err(format!("不支持的搜索范围: {}（可用: {}）", scope, PRODUCT_SEARCH_SCOPES.join(", ")));
+    }
+    let query = query.trim();
     let repo = ProductRepository::new(conn.inner().clone());
-    repo.search(&query).map_err(|e| e.to_string())
+    repo.search(query, &normalize_pinyin_query(query), &scope).map_err(|e| e.to_string())
+}
+
+/// 通过全文索引搜索商品（名称、拼音码前缀匹配），适合商品较多时的输入联想；
+/// 全文索引不可用时回退到 LIKE 搜索
+#[tauri::command]
+pub async fn search_products_fts(
+    query: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<Product>, String> {
+    let query = query.trim();
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.search_fts(query, &normalize_pinyin_query(query)).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -64,20 +107,128 @@ pub async fn get_products_by_category(
     repo.get_by_category(&category_id).map_err(|e| e.to_string())
 }
 
+/// 分页获取商品（关键字、分类、价格区间筛选），page 从 1 开始
+#[tauri::command]
+pub async fn get_products(
+    query: Option<String>,
+    category_id: Option<String>,
+    min_price: Option<f64>,
+    max_price: Option<f64>,
+    page: Option<u32>,
+    page_size: Option<u32>,
+    conn: State<'_, DbConnection>,
+) -> Result<ProductPage, String> {
+    for price in [min_price, max_price].into_iter().flatten() {
+        if !price.is_finite() || price < 0.0 {
+            return Err("价格筛选条件必须是非负数".to_string());
+        }
+    }
+    if let (Some(min), Some(max)) = (min_price, max_price) {
+        if min > max {
+            return Err("最低价格不能高于最高价格".to_string());
+        }
+    }
+
+    let page = page.unwrap_or(1).max(1);
+    let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let pinyin_query = normalize_pinyin_query(query.as_deref().unwrap_or(""));
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.get_page(
+        query.as_deref().map(|q| (q, pinyin_query.as_str())),
+        category_id.as_deref(),
+        min_price,
+        max_price,
+        page_size,
+        (page - 1) * page_size,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// 新建商品未指定库存跟踪/最低库存时套用设置中的默认值；库存数量保持为空（表示尚未盘点）
+fn apply_product_defaults(product: &mut Product, settings: &AppSettings) {
+    if product.track_stock.is_none() {
+        product.track_stock = Some(settings.default_track_stock);
+    }
+    if product.min_stock.is_none() && product.track_stock == Some(true) && settings.default_min_stock > 0.0 {
+        product.min_stock = Some(settings.default_min_stock);
+    }
+}
+
+/// 按设置检查零售价：不允许零价时拒绝小于等于 0 的价格（多为漏填）
+fn check_product_price(price: f64, allow_zero_price: bool) -> Result<(), String> {
+    if !allow_zero_price && price <= 0.0 {
+        return Err(format!(
+            "零售价必须大于 0（当前为 {}），如需录入免费商品或服务，请在设置中允许零价",
+            price
+        ));
+    }
+    Ok(())
+}
+
+/// 设置中是否允许零价商品（未找到设置时允许）
+fn allow_zero_price(conn: &DbConnection, settings_cache: &SettingsCache) -> Result<bool, String> {
+    Ok(settings_cache
+        .get(&SettingsRepository::new(conn.clone()))
+        .map_err(|e| e.to_string())?
+        .is_none_or(|s| s.allow_zero_price))
+}
+
+/// 按条码查找商品（扫码录入），找不到时返回错误
+#[tauri::command]
+pub async fn get_product_by_barcode(
+    code: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Product, String> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Err("条码不能为空".to_string());
+    }
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.get_by_barcode(code).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("未找到条码为 {} 的商品", code),
+        e => e.to_string(),
+    })
+}
+
 #[tauri::command]
 pub async fn save_product(
-    product: Product,
+    mut product: Product,
     conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
 ) -> Result<(), String> {
     let repo = ProductRepository::new(conn.inner().clone());
+    let settings = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?;
+    check_product_price(product.price, settings.as_ref().is_none_or(|s| s.allow_zero_price))?;
+
+    // 条码去掉首尾空白，留空视为未设置；同一条码只能属于一个商品
+    product.barcode = product.barcode.take().map(|b| b.trim().to_string()).filter(|b| !b.is_empty());
+    if let Some(barcode) = &product.barcode {
+        match repo.get_by_barcode(barcode) {
+            Ok(other) if other.id != product.id => {
+                return Err(format!("条码 {} 已被商品「{}」使用", barcode, other.name));
+            }
+            Ok(_) | Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
 
     // 检查是新增还是更新
     let existing = repo.get_by_id(&product.id);
-    if existing.is_ok() {
-        repo.update(&product).map_err(|e| e.to_string())
+    let result = if existing.is_ok() {
+        repo.update(&product)
     } else {
-        repo.insert(&product).map_err(|e| e.to_string())
-    }
+        if let Some(settings) = settings {
+            apply_product_defaults(&mut product, &settings);
+        }
+        repo.insert(&product)
+    };
+    result.map_err(|e| {
+        log::error!("保存商品 {} 失败: {}", product.id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -86,21 +237,22 @@ pub async fn delete_product(
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = ProductRepository::new(conn.inner().clone());
-    repo.delete(&id).map_err(|e| e.to_string())
+    repo.delete(&id).map_err(|e| {
+        log::error!("删除商品 {} 失败: {}", id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
 pub async fn batch_delete_products(
     ids: Vec<String>,
     conn: State<'_, DbConnection>,
-) -> Result<(), String> {
+) -> Result<usize, String> {
     let repo = ProductRepository::new(conn.inner().clone());
-
-    for id in ids {
-        repo.delete(&id).map_err(|e| e.to_string())?;
-    }
-
-    Ok(())
+    repo.delete_batch(&ids).map_err(|e| {
+        log::error!("批量删除 {} 个商品失败: {}", ids.len(), e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -108,7 +260,10 @@ pub async fn update_product_price(
     product_id: String,
     new_price: f64,
     conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
+    check_product_price(new_price, allow_zero_price(conn.inner(), settings_cache.inner())?)?;
     let repo = ProductRepository::new(conn.inner().clone());
 
     // 获取现有商品
@@ -116,10 +271,92 @@ pub async fn update_product_price(
 
     // 更新价格
     product.price = new_price;
-    product.updated_at = chrono::Utc::now().to_rfc3339();
+    product.updated_at = clock.now_rfc3339();
 
     // 保存更新
-    repo.update(&product).map_err(|e| e.to_string())
+    repo.update(&product).map_err(|e| {
+        log::error!("更新商品 {} 价格失败: {}", product_id, e);
+        e.to_string()
+    })
+}
+
+/// 批量调价（如季节性调价）：category_id 指定时只调整该分类及其子分类的商品。新价格 = 原价 × (1 + percent%) + delta，
+/// round_to 指定时再四舍五入到 round_to 的整数倍（如 0.5），最后按分取整。percent 与 delta 至少指定一个；
+/// 任一商品调整后价格无效时整批不修改。返回价格实际变化的商品数
+#[tauri::command]
+pub async fn adjust_prices(
+    category_id: Option<String>,
+    percent: Option<f64>,
+    delta: Option<f64>,
+    round_to: Option<f64>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    if percent.is_none() && delta.is_none() {
+        return Err("请指定调价百分比或调价金额".to_string());
+    }
+    let percent = percent.unwrap_or(0.0);
+    let delta = delta.unwrap_or(0.0);
+    if !percent.is_finite() || percent <= -100.0 {
+        return Err("调价百分比必须大于 -100".to_string());
+    }
+    if !delta.is_finite() {
+        return Err("调价金额无效".to_string());
+    }
+    if round_to.is_some_and(|r| !r.is_finite() || r <= 0.0) {
+        return Err("取整单位必须大于 0".to_string());
+    }
+
+    let category_id = category_id.filter(|id| !id.trim().is_empty());
+    if let Some(id) = &category_id {
+        CategoryRepository::new(conn.inner().clone())
+            .get_by_id(id)
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => format!("分类不存在: {}", id),
+                e => e.to_string(),
+            })?;
+    }
+    let allow_zero_price = allow_zero_price(conn.inner(), settings_cache.inner())?;
+
+    let now = clock.now_rfc3339();
+    let repo = ProductRepository::new(conn.inner().clone());
+    let updated = repo
+        .adjust_prices(category_id.as_deref(), |name, price| {
+            let mut adjusted = price * (1.0 + percent / 100.0) + delta;
+            if let Some(round_to) = round_to {
+                adjusted = (adjusted / round_to).round() * round_to;
+            }
+            if adjusted < 0.0 {
+                return Err(format!("商品「{}」调价后价格为负数", name));
+            }
+            check_product_price(adjusted, allow_zero_price).map_err(|e| format!("商品「{}」: {}", name, e))?;
+            Ok(adjusted)
+        }, &now)
+        .map_err(|e| match e {
+            WriteError::Rejected(message) => message,
+            WriteError::Database(e) => {
+                log::error!("批量调价失败: {}", e);
+                e.to_string()
+            }
+        })?;
+
+    log::info!("批量调价完成：{} 个商品价格已更新", updated);
+    Ok(updated)
+}
+
+/// 查找价格异常的商品（偏离同分类其他商品均价超过 z_threshold 个标准差），用于批量改价后的数据检查
+#[tauri::command]
+pub async fn find_price_anomalies(
+    z_threshold: f64,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<PriceAnomaly>, String> {
+    if !z_threshold.is_finite() || z_threshold <= 0.0 {
+        return Err("偏离阈值必须是大于 0 的数".to_string());
+    }
+
+    let repo = ProductRepository::new(conn.inner().clone());
+    repo.find_price_anomalies(z_threshold).map_err(|e| e.to_string())
 }
 
 /// 生成商品名称的拼音简码
@@ -132,21 +369,582 @@ pub async fn generate_product_pinyin(name: String) -> Result<String, String> {
 #[tauri::command]
 pub async fn batch_update_pinyin(
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<usize, String> {
     let repo = ProductRepository::new(conn.inner().clone());
-    let mut products = repo.get_all().map_err(|e| e.to_string())?;
-    
-    let mut updated_count = 0;
-    for product in &mut products {
-        let pinyin_code = generate_search_pinyin(&product.name);
-        
-        if product.pinyin.as_ref().map(|v| v.to_lowercase()) != Some(pinyin_code.clone()) {
-            product.pinyin = Some(pinyin_code);
-            product.updated_at = chrono::Utc::now().to_rfc3339();
-            repo.update(product).map_err(|e| e.to_string())?;
-            updated_count += 1;
+    let products = repo.get_all().map_err(|e| e.to_string())?;
+
+    let updates: Vec<(String, String)> = products
+        .iter()
+        .map(|product| (product.id.clone(), generate_search_pinyin(&product.name)))
+        .collect();
+
+    // 只有拼音实际变化的商品会被更新，返回值为 SQLite 报告的修改行数
+    repo.update_pinyin_batch(&updates, &clock.now_rfc3339()).map_err(|e| {
+        log::error!("批量更新拼音简码失败: {}", e);
+        e.to_string()
+    })
+}
+
+fn parse_optional_number(value: Option<&String>) -> std::result::Result<Option<f64>, ()> {
+    match value.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        None => Ok(None),
+        Some(v) => v.parse::<f64>().ok().filter(|n| n.is_finite()).map(Some).ok_or(()),
+    }
+}
+
+/// 按导出格式（商品名称,单位,零售价,分类,拼音码,最小库存,启用库存,当前库存）逐行规划导入：
+/// 按名称匹配已有商品决定新增/更新，文件内重名、分类不存在、价格无效或内容无变化的行跳过。
+/// 预览与实际导入共用此函数，保证两者结果一致
+fn plan_product_import(
+    records: &[Vec<String>],
+    first_line: usize,
+    existing: &[Product],
+    categories: &[Category],
+    default_category_id: &str,
+    allow_zero_price: bool,
+    now: &str,
+) -> Vec<(CsvImportRow, Option<Product>)> {
+    let by_name: HashMap<&str, &Product> = existing.iter().map(|p| (p.name.trim(), p)).collect();
+    let category_by_name: HashMap<&str, &str> = categories
+        .iter()
+        .map(|c| (c.name.trim(), c.id.as_str()))
+        .collect();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| {
+            let line = first_line + index;
+            let field = |i: usize| record.get(i).map(|v| v.trim()).unwrap_or_default();
+            let name = field(0).to_string();
+            let skip = |reason: String| {
+                (
+                    CsvImportRow { line, name: name.clone(), action: "skip".to_string(), reason: Some(reason) },
+                    None,
+                )
+            };
+
+            if name.is_empty() {
+                return skip("商品名称为空".to_string());
+            }
+            if let Some(first) = seen.get(&name) {
+                return skip(format!("与第 {} 行重名", first));
+            }
+            seen.insert(name.clone(), line);
+
+            let price = match field(2).parse::<f64>() {
+                Ok(price) if price.is_finite() && price >= 0.0 => price,
+                _ => return skip(format!("零售价「{}」无效", field(2))),
+            };
+            if let Err(reason) = check_product_price(price, allow_zero_price) {
+                return skip(reason);
+            }
+            let (min_stock, stock) = match (parse_optional_number(record.get(5)), parse_optional_number(record.get(7))) {
+                (Ok(min_stock), Ok(stock)) => (min_stock, stock),
+                _ => return skip("库存数值无效".to_string()),
+            };
+            let current = by_name.get(name.as_str()).copied();
+            // 未填写时沿用已有商品的设置，新商品留空由默认设置决定
+            let track_stock = match field(6).to_lowercase().as_str() {
+                "" => current.and_then(|p| p.track_stock),
+                value => Some(matches!(value, "true" | "1" | "是")),
+            };
+
+            let category_id = match field(3) {
+                "" => match current {
+                    Some(product) => product.category_id.clone(),
+                    None if !default_category_id.is_empty() => default_category_id.to_string(),
+                    None => return skip("未指定分类且没有可用的默认分类".to_string()),
+                },
+                category_name => match category_by_name.get(category_name) {
+                    Some(id) => id.to_string(),
+                    None => return skip(format!("分类「{}」不存在", category_name)),
+                },
+            };
+            let pinyin = match field(4) {
+                "" => generate_search_pinyin(&name),
+                value => value.to_string(),
+            };
+
+            let product = Product {
+                id: current.map(|p| p.id.clone()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                name: name.clone(),
+                unit: field(1).to_string(),
+                price,
+                category_id,
+                pinyin: Some(pinyin),
+                stock,
+                min_stock,
+                track_stock,
+                created_at: current.map(|p| p.created_at.clone()).unwrap_or_else(|| now.to_string()),
+                updated_at: now.to_string(),
+                // 导入格式不含条码，沿用已有商品的条码
+                barcode: current.and_then(|p| p.barcode.clone()),
+            };
+
+            match current {
+                None => (
+                    CsvImportRow { line, name, action: "insert".to_string(), reason: None },
+                    Some(product),
+                ),
+                Some(old)
+                    if old.unit == product.unit
+                        && old.price == product.price
+                        && old.category_id == product.category_id
+                        && old.pinyin == product.pinyin
+                        && old.stock == product.stock
+                        && old.min_stock == product.min_stock
+                        && old.track_stock.unwrap_or(false) == product.track_stock.unwrap_or(false) =>
+                {
+                    skip("与现有商品一致，无需更新".to_string())
+                }
+                Some(_) => (
+                    CsvImportRow { line, name, action: "update".to_string(), reason: Some("同名商品已存在，将覆盖".to_string()) },
+                    Some(product),
+                ),
+            }
+        })
+        .collect()
+}
+
+/// 导出全部商品为 CSV 文本（带 BOM 的 UTF-8，由前端保存），列与商品导入格式一致：
+/// 商品名称,单位,零售价,分类,拼音码,最小库存,启用库存,当前库存。分类写分类名称，未设置的库存值留空
+#[tauri::command]
+pub async fn export_products_csv(
+    conn: State<'_, DbConnection>,
+) -> Result<String, String> {
+    let repo = ProductRepository::new(conn.inner().clone());
+    let products = repo.get_all_with_category_names().map_err(|e| e.to_string())?;
+    let optional_number = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    let rows: Vec<Vec<String>> = products
+        .iter()
+        .map(|(product, category_name)| {
+            vec![
+                product.name.clone(),
+                product.unit.clone(),
+                product.price.to_string(),
+                category_name.clone(),
+                product.pinyin.clone().unwrap_or_default(),
+                optional_number(product.min_stock),
+                product.track_stock.unwrap_or(false).to_string(),
+                optional_number(product.stock),
+            ]
+        })
+        .collect();
+    let content = csv::write_rows(&["商品名称", "单位", "零售价", "分类", "拼音码", "最小库存", "启用库存", "当前库存"], &rows)?;
+
+    log::info!("商品已导出为 CSV（{} 个商品）", products.len());
+    Ok(content)
+}
+
+/// 从 CSV 导入商品（格式同商品导出），全部写入在同一事务中完成，任一行失败时不导入任何商品。
+/// validate 为 true 时只返回逐行预览结果，不写入数据库
+#[tauri::command]
+pub async fn import_products_csv(
+    path: String,
+    validate: Option<bool>,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+    clock: State<'_, SharedClock>,
+) -> Result<CsvImportReport, String> {
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+    let mut records = csv::parse(&content)?;
+    let first_line = if csv::strip_header(&mut records, &["name", "商品名称"]) { 2 } else { 1 };
+
+    let repo = ProductRepository::new(conn.inner().clone());
+    let existing = repo.get_all().map_err(|e| e.to_string())?;
+    let categories = CategoryRepository::new(conn.inner().clone())
+        .get_all()
+        .map_err(|e| e.to_string())?;
+
+    let settings = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?;
+
+    // 未填写分类时使用设置中的默认分类，其次为第一个分类
+    let default_category_id = settings
+        .as_ref()
+        .map(|s| s.default_category_id.clone())
+        .filter(|id| categories.iter().any(|c| &c.id == id))
+        .or_else(|| categories.first().map(|c| c.id.clone()))
+        .unwrap_or_default();
+
+    let mut planned = plan_product_import(
+        &records,
+        first_line,
+        &existing,
+        &categories,
+        &default_category_id,
+        settings.as_ref().is_none_or(|s| s.allow_zero_price),
+        &clock.now_rfc3339(),
+    );
+    if let Some(settings) = &settings {
+        for (row, product) in &mut planned {
+            if let (Some(product), "insert") = (product, row.action.as_str()) {
+                apply_product_defaults(product, settings);
+            }
         }
     }
-    
-    Ok(updated_count)
+    let dry_run = validate.unwrap_or(false);
+
+    // 全部新增与更新在同一事务中写入，任一行失败时整批不导入
+    if !dry_run {
+        let mut db = conn.inner().lock().unwrap();
+        let mut import = || -> Result<(), String> {
+            let tx = db.transaction().map_err(|e| e.to_string())?;
+            for (row, product) in &planned {
+                let Some(product) = product else { continue };
+                let result = if row.action == "update" {
+                    ProductRepository::update_on(&tx, product)
+                } else {
+                    ProductRepository::insert_on(&tx, product)
+                };
+                result.map_err(|e| format!("第 {} 行「{}」导入失败: {}", row.line, row.name, e))?;
+            }
+            tx.commit().map_err(|e| e.to_string())
+        };
+        import().map_err(|e| {
+            log::error!("商品导入失败: {}", e);
+            e
+        })?;
+    }
+
+    let report = csv::import_report(planned.into_iter().map(|(row, _)| row).collect(), dry_run);
+    if !dry_run {
+        log::info!("商品导入完成: 新增 {} 个, 更新 {} 个, 跳过 {} 行", report.inserted, report.updated, report.skipped);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    #[test]
+    fn csv_import_writes_nothing_when_a_row_fails() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            c.execute_batch(
+                "CREATE TRIGGER reject_bad_row BEFORE INSERT ON products WHEN NEW.name = '坏行'
+                 BEGIN SELECT RAISE(ABORT, '写入被拒绝'); END;",
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let path = std::env::temp_dir().join(format!("products_{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "商品名称,单位,零售价\np1,个,12\n机油,瓶,45\n坏行,个,1\n").unwrap();
+        let import = |validate: bool| {
+            tauri::async_runtime::block_on(import_products_csv(
+                path.to_string_lossy().to_string(),
+                Some(validate),
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+
+        let preview = import(true).unwrap();
+        assert_eq!((preview.inserted, preview.updated, preview.skipped), (2, 1, 0));
+
+        let error = import(false).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("第 4 行「坏行」导入失败"), "{}", error);
+
+        // 之前的更新与新增随失败的行一起回滚
+        let repo = ProductRepository::new(conn.clone());
+        let products = repo.get_all().unwrap();
+        assert_eq!(products.iter().map(|p| (p.name.as_str(), p.price)).collect::<Vec<_>>(), vec![("p1", 10.0)]);
+    }
+
+    #[test]
+    fn exported_csv_imports_back_unchanged_with_commas_quotes_and_newlines() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "cat1", None, 1);
+            insert_product(&c, "p1", 10.0, Some("cat1"));
+            c.execute(
+                "UPDATE products SET name = ?1, unit = '\"套\"', pinyin = 'jy' WHERE id = 'p1'",
+                rusqlite::params!["机油, 5W-30\n\"全合成\""],
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let content = tauri::async_runtime::block_on(export_products_csv(app.state())).unwrap();
+        let path = std::env::temp_dir().join(format!("products_{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &content).unwrap();
+        let report = tauri::async_runtime::block_on(import_products_csv(
+            path.to_string_lossy().to_string(),
+            Some(true),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 名称与单位原样读回，与现有商品一致
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].name, "机油, 5W-30\n\"全合成\"");
+        assert_eq!((report.rows[0].action.as_str(), report.skipped), ("skip", 1));
+    }
+
+    #[test]
+    fn adjust_prices_rounds_to_the_step_and_counts_only_changed_prices() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "cat1", None, 1);
+            insert_category(&c, "cat2", Some("cat1"), 2);
+            insert_product(&c, "p1", 10.0, Some("cat1"));
+            insert_product(&c, "p2", 12.3, Some("cat2"));
+            insert_product(&c, "p3", 20.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let adjust = |percent: Option<f64>, delta: Option<f64>, round_to: Option<f64>| {
+            tauri::async_runtime::block_on(adjust_prices(
+                Some("cat1".to_string()),
+                percent,
+                delta,
+                round_to,
+                app.state(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let prices = || {
+            let repo = ProductRepository::new(conn.clone());
+            ["p1", "p2", "p3"].map(|id| repo.get_by_id(id).unwrap().price)
+        };
+
+        // 12.3 × 1.1 = 13.53，取整到 0.5 的倍数为 13.5；分类外的商品不变
+        assert_eq!(adjust(Some(10.0), None, Some(0.5)), Ok(2));
+        assert_eq!(prices(), [11.0, 13.5, 20.0]);
+
+        // 调整后按分取整与原价相同的商品不计数
+        assert_eq!(adjust(None, Some(0.001), None), Ok(0));
+        // 11 × 1.02 = 11.22 取整后仍为 11；13.5 × 1.02 = 13.77 取整为 14
+        assert_eq!(adjust(Some(2.0), None, Some(0.5)), Ok(1));
+        assert_eq!(prices(), [11.0, 14.0, 20.0]);
+
+        assert_eq!(adjust(None, Some(1.0), Some(0.0)), Err("取整单位必须大于 0".to_string()));
+    }
+
+    #[test]
+    fn adjust_prices_rolls_back_the_whole_batch_when_one_price_is_invalid() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 3.0, None);
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+
+        let error = tauri::async_runtime::block_on(adjust_prices(None, None, Some(-5.0), None, app.state(), app.state(), app.state()))
+            .unwrap_err();
+        assert_eq!(error, "商品「p2」调价后价格为负数");
+
+        // p1 的新价格随 p2 一起回滚
+        let repo = ProductRepository::new(conn.clone());
+        let products = repo.get_all().unwrap();
+        let mut prices: Vec<(&str, f64)> = products.iter().map(|p| (p.id.as_str(), p.price)).collect();
+        prices.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(prices, vec![("p1", 10.0), ("p2", 3.0)]);
+    }
+
+    #[test]
+    fn product_page_matches_pinyin_with_the_normalized_query() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 10.0, None);
+            c.execute_batch(
+                "UPDATE products SET name = '机油', pinyin = 'jy jiyou' WHERE id = 'p1';
+                 UPDATE products SET name = '刹车片', pinyin = 'scp shachepian' WHERE id = 'p2';",
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let search = |query: &str| {
+            let page = tauri::async_runtime::block_on(get_products(
+                Some(query.to_string()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            page.items.into_iter().map(|p| p.id).collect::<Vec<_>>()
+        };
+
+        // 大写、带空格的拼音与按汉字输入的拼音都转换后再匹配拼音码
+        assert_eq!(search("Ji You"), vec!["p1"]);
+        assert_eq!(search("SCP"), vec!["p2"]);
+        assert_eq!(search("机油"), vec!["p1"]);
+        assert!(search("-").is_empty());
+    }
+
+    #[test]
+    fn product_page_filters_by_an_inclusive_price_band_with_search_and_category() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "cat1", None, 1);
+            for (id, price, category) in [
+                ("p1", 5.0, None),
+                ("p2", 9.99, Some("cat1")),
+                ("p3", 10.0, Some("cat1")),
+                ("p4", 20.0, None),
+                ("p5", 20.01, Some("cat1")),
+                ("p6", 50.0, Some("cat1")),
+            ] {
+                insert_product(&c, id, price, category);
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let page = |query: Option<&str>, category: Option<&str>, min: Option<f64>, max: Option<f64>, page_size: Option<u32>| {
+            tauri::async_runtime::block_on(get_products(
+                query.map(str::to_string),
+                category.map(str::to_string),
+                min,
+                max,
+                None,
+                page_size,
+                app.state(),
+            ))
+            .map(|page| (page.items.into_iter().map(|p| p.id).collect::<Vec<_>>(), page.total))
+        };
+        let ids = |ids: &[&str], total: i64| Ok((ids.iter().map(|id| id.to_string()).collect::<Vec<_>>(), total));
+
+        // 区间两端都包含在内，按分比较不受浮点误差影响
+        assert_eq!(page(None, None, Some(10.0), Some(20.0), None), ids(&["p3", "p4"], 2));
+        assert_eq!(page(None, None, Some(20.0), None, None), ids(&["p4", "p5", "p6"], 3));
+        assert_eq!(page(None, None, None, Some(9.99), None), ids(&["p1", "p2"], 2));
+        assert_eq!(page(None, Some("cat1"), Some(9.0), Some(30.0), None), ids(&["p2", "p3", "p5"], 3));
+        assert_eq!(page(Some("p5"), Some("cat1"), Some(9.0), Some(30.0), None), ids(&["p5"], 1));
+        // 总数不受分页影响
+        assert_eq!(page(None, None, Some(9.0), None, Some(2)), ids(&["p2", "p3"], 5));
+
+        assert_eq!(page(None, None, Some(20.0), Some(10.0), None).unwrap_err(), "最低价格不能高于最高价格");
+        assert_eq!(page(None, None, Some(-1.0), None, None).unwrap_err(), "价格筛选条件必须是非负数");
+        assert_eq!(page(None, None, None, Some(f64::NAN), None).unwrap_err(), "价格筛选条件必须是非负数");
+    }
+
+    #[test]
+    fn new_products_take_the_stock_defaults_from_settings() {
+        let conn = memory_db();
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        {
+            let repo = SettingsRepository::new(conn.clone());
+            let cache = app.state::<SettingsCache>();
+            let mut settings = cache.get(&repo).unwrap().unwrap();
+            settings.default_track_stock = true;
+            settings.default_min_stock = 3.0;
+            cache.save(&repo, &settings).unwrap();
+        }
+        let product = |id: &str, track_stock: Option<bool>, min_stock: Option<f64>| Product {
+            id: id.to_string(),
+            name: id.to_string(),
+            unit: "个".to_string(),
+            price: 10.0,
+            category_id: String::new(),
+            pinyin: None,
+            stock: None,
+            min_stock,
+            track_stock,
+            created_at: "2024-03-10T04:00:00Z".to_string(),
+            updated_at: "2024-03-10T04:00:00Z".to_string(),
+            barcode: None,
+        };
+        let save = |product: Product| {
+            tauri::async_runtime::block_on(save_product(product, app.state(), app.state())).unwrap()
+        };
+        let repo = ProductRepository::new(conn.clone());
+        let stock_fields = |id: &str| {
+            let product = repo.get_by_id(id).unwrap();
+            (product.track_stock, product.min_stock, product.stock)
+        };
+
+        // 未指定时套用默认值，库存保持为空（尚未盘点）
+        save(product("p1", None, None));
+        assert_eq!(stock_fields("p1"), (Some(true), Some(3.0), None));
+        // 已指定的字段不覆盖；不跟踪库存的商品不设最低库存
+        save(product("p2", None, Some(1.0)));
+        assert_eq!(stock_fields("p2"), (Some(true), Some(1.0), None));
+        save(product("p3", Some(false), None));
+        assert_eq!(stock_fields("p3"), (Some(false), None, None));
+        // 更新已有商品时不套用默认值
+        save(product("p3", None, None));
+        assert_eq!(stock_fields("p3").1, None);
+
+        // CSV 导入的新商品同样套用
+        let path = std::env::temp_dir().join(format!("products_{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "商品名称,单位,零售价\n机油,瓶,45\n").unwrap();
+        tauri::async_runtime::block_on(import_products_csv(
+            path.to_string_lossy().to_string(),
+            Some(false),
+            app.state(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let imported = repo.get_all().unwrap().into_iter().find(|p| p.name == "机油").unwrap();
+        assert_eq!((imported.track_stock, imported.min_stock, imported.stock), (Some(true), Some(3.0), None));
+    }
+
+    #[test]
+    fn name_scope_ignores_pinyin_and_category_matches() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "cat1", None, 1);
+            c.execute("UPDATE categories SET name = '机油类' WHERE id = 'cat1'", []).unwrap();
+            for (id, name, pinyin, category) in [
+                ("p1", "机油", Some("jy jiyou"), None),
+                ("p2", "全合成", Some("qhc quanhecheng"), Some("cat1")),
+                ("p3", "刹车片", Some("scp shachepian"), None),
+                ("p4", "jy专用", None, None),
+            ] {
+                insert_product(&c, id, 10.0, category);
+                c.execute(
+                    "UPDATE products SET name = ?2, pinyin = ?3 WHERE id = ?1",
+                    rusqlite::params![id, name, pinyin],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let search = |query: &str, scope: Option<&str>| {
+            tauri::async_runtime::block_on(search_products(query.to_string(), scope.map(str::to_string), app.state()))
+                .map(|products| products.into_iter().map(|p| p.id).collect::<Vec<_>>())
+        };
+
+        // 默认 all：名称、拼音码与分类名都参与匹配
+        assert_eq!(search("机油", None), Ok(vec!["p2".to_string(), "p1".to_string()]));
+        assert_eq!(search("jy", Some("all")), Ok(vec!["p4".to_string(), "p1".to_string()]));
+
+        // name：只有名称匹配，分类名与拼音码的匹配被排除
+        assert_eq!(search("机油", Some("name")), Ok(vec!["p1".to_string()]));
+        assert_eq!(search("jy", Some("name")), Ok(vec!["p4".to_string()]));
+
+        assert_eq!(search("JY", Some("pinyin")), Ok(vec!["p1".to_string()]));
+        assert_eq!(search("机油", Some("pinyin")), Ok(vec!["p1".to_string()]));
+
+        assert_eq!(
+            search("机油", Some("category")).unwrap_err(),
+            format!("不支持的搜索范围: category（可用: {}）", PRODUCT_SEARCH_SCOPES.join(", "))
+        );
+    }
 }
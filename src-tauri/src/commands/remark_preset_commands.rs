@@ -1,6 +1,8 @@
 use tauri::State;
 use crate::database::{connection::DbConnection, schema::{RemarkPresetRepository, Repository}};
 use crate::models::RemarkPreset;
+use crate::utils::clock::SharedClock;
+use rusqlite::params;
 
 #[tauri::command]
 pub async fn get_all_remark_presets(
@@ -23,16 +25,25 @@ pub async fn get_remark_presets_by_type(
 pub async fn save_remark_preset(
     mut preset: RemarkPreset,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
     let repo = RemarkPresetRepository::new(conn.inner().clone());
-    preset.updated_at = chrono::Utc::now().to_rfc3339();
+    preset.updated_at = clock.now_rfc3339();
 
     let existing = repo.get_by_id(&preset.id);
-    if existing.is_ok() {
-        repo.update(&preset).map_err(|e| e.to_string())
+    let result = if existing.is_ok() {
+        repo.update(&preset)
     } else {
-        repo.insert(&preset).map_err(|e| e.to_string())
-    }
+        // 新预设未指定排序时排到最后
+        if preset.sort_order == 0 {
+            preset.sort_order = repo.next_sort_order().map_err(|e| e.to_string())?;
+        }
+        repo.insert(&preset)
+    };
+    result.map_err(|e| {
+        log::error!("保存备注预设 {} 失败: {}", preset.id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -41,7 +52,10 @@ pub async fn delete_remark_preset(
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = RemarkPresetRepository::new(conn.inner().clone());
-    repo.delete(&id).map_err(|e| e.to_string())
+    repo.delete(&id).map_err(|e| {
+        log::error!("删除备注预设 {} 失败: {}", id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -52,3 +66,100 @@ pub async fn increment_remark_use_count(
     let repo = RemarkPresetRepository::new(conn.inner().clone());
     repo.increment_use_count(&id).map_err(|e| e.to_string())
 }
+
+/// 一次保存中用到的备注预设和单位预设统一在同一事务中累加使用次数，返回实际更新的预设数
+#[tauri::command]
+pub async fn increment_presets_batch(
+    remark_ids: Vec<String>,
+    unit_ids: Vec<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    let mut db = conn.inner().lock().unwrap();
+    let tx = db.transaction().map_err(|e| e.to_string())?;
+    let now = clock.now_rfc3339();
+
+    let mut updated = 0;
+    {
+        let mut stmt_remark = tx
+            .prepare("UPDATE remark_presets SET use_count = use_count + 1 WHERE id = ?1")
+            .map_err(|e| e.to_string())?;
+        for id in &remark_ids {
+            updated += stmt_remark.execute(params![id]).map_err(|e| e.to_string())?;
+        }
+
+        let mut stmt_unit = tx
+            .prepare("UPDATE unit_presets SET use_count = use_count + 1, updated_at = ?2 WHERE id = ?1")
+            .map_err(|e| e.to_string())?;
+        for id in &unit_ids {
+            updated += stmt_unit.execute(params![id, now]).map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    #[test]
+    fn batch_increment_bumps_each_listed_preset_once() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            for (id, use_count) in [("r1", 0), ("r2", 5), ("r3", 2)] {
+                c.execute(
+                    "INSERT INTO remark_presets (id, content, type, use_count, created_at, updated_at)
+                     VALUES (?1, ?1, 'order', ?2, 'now', 'now')",
+                    params![id, use_count],
+                )
+                .unwrap();
+            }
+            for (id, use_count) in [("u1", 1), ("u2", 0)] {
+                c.execute(
+                    "INSERT INTO unit_presets (id, name, use_count, created_at, updated_at)
+                     VALUES (?1, ?1, ?2, 'now', 'now')",
+                    params![id, use_count],
+                )
+                .unwrap();
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        // 默认数据中已有单位预设，只读取本测试插入的预设
+        let use_counts = |table: &str, ids: &[&str]| {
+            let c = conn.lock().unwrap();
+            ids.iter()
+                .map(|id| {
+                    c.query_row(&format!("SELECT use_count FROM {} WHERE id = ?1", table), params![id], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        // 不存在的预设不计入更新数量
+        let updated = tauri::async_runtime::block_on(increment_presets_batch(
+            ids(&["r1", "r2", "missing"]),
+            ids(&["u1", "u2"]),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(updated, 4);
+
+        assert_eq!(use_counts("remark_presets", &["r1", "r2", "r3"]), vec![1, 6, 2]);
+        assert_eq!(use_counts("unit_presets", &["u1", "u2"]), vec![2, 1]);
+        let unit_updated: String = conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT updated_at FROM unit_presets WHERE id = 'u1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(unit_updated, "2024-03-10T04:00:00+00:00");
+    }
+}
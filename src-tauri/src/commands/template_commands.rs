@@ -1,19 +1,622 @@
-use crate::database::DbConnection;
+use crate::database::{DbConnection, SettingsCache};
+use crate::database::schema::{OrderRepository, Repository, SettingsRepository, TemplateRepository};
+use crate::models::{ExportFilenamePreview, Order, ResolvedTemplate, TemplateAuditIssue, TemplateConfig, TemplateFileInfo};
+use crate::utils::clock::SharedClock;
+use crate::utils::filename::{build_output_filename, validate_filename_pattern, DEFAULT_FILENAME_PATTERN};
+use crate::utils::validation::validate_order_for_template;
+use crate::utils::xlsx_template::inspect_workbook;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rusqlite::params;
 use tauri::State;
 
+// 模板文件的大小上限（字节）
+const MAX_TEMPLATE_BYTES: usize = 10 * 1024 * 1024;
+
 #[tauri::command]
 pub async fn update_all_template_filename_patterns(
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    set_all_template_filename_patterns(DEFAULT_FILENAME_PATTERN.to_string(), conn, clock).await
+}
+
+/// 将所有模板的文件名格式统一设置为指定格式（先校验占位符与非法字符），返回更新的模板数
+#[tauri::command]
+pub async fn set_all_template_filename_patterns(
+    pattern: String,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<usize, String> {
+    validate_filename_pattern(&pattern)?;
+
     let conn = conn.lock().unwrap();
     
     let updated = conn.execute(
         "UPDATE templates 
-         SET filename_pattern = '{date}_{customerName}_{orderNumber}',
-             updated_at = datetime('now')
-         WHERE filename_pattern != '{date}_{customerName}_{orderNumber}'",
-        [],
-    ).map_err(|e| format!("Failed to update templates: {}", e))?;
+         SET filename_pattern = ?1,
+             updated_at = ?2
+         WHERE filename_pattern != ?1",
+        params![&pattern, clock.now_rfc3339()],
+    ).map_err(|e| format!("更新模板失败: {}", e))?;
     
+    log::info!("已将 {} 个模板的文件名格式更新为 {}", updated, pattern);
     Ok(updated)
 }
+
+/// 预览日期区间内订单批量导出的文件名，标出会互相覆盖的重名文件。
+/// 文件名与导出一致（build_output_filename）：优先使用设置中的格式，其次为订单模板的格式
+#[tauri::command]
+pub async fn preview_export_filenames(
+    from: String,
+    to: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<Vec<ExportFilenamePreview>, String> {
+    use std::collections::HashMap;
+
+    let settings = settings_cache
+        .get(&SettingsRepository::new(conn.inner().clone()))
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "未找到系统设置".to_string())?;
+
+    // 只查询区间内的订单（含客户信息）
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let template_repo = TemplateRepository::new(conn.inner().clone());
+    let mut orders = order_repo.get_by_date_range(&from, &to).map_err(|e| e.to_string())?;
+    orders.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.order_number.cmp(&b.order_number)));
+
+    // 同一模板只解析一次；没有可用模板时按默认格式
+    let mut template_patterns: HashMap<String, String> = HashMap::new();
+    let mut previews = Vec::with_capacity(orders.len());
+    for order in orders {
+        let key = order.template_id.clone().unwrap_or_default();
+        let template_pattern = match template_patterns.get(&key) {
+            Some(pattern) => pattern.clone(),
+            None => {
+                let pattern = match template_repo.resolve_effective(order.template_id.as_deref(), &settings.default_template_id) {
+                    Ok(template) => template.filename_pattern,
+                    Err(rusqlite::Error::QueryReturnedNoRows) => String::new(),
+                    Err(e) => return Err(e.to_string()),
+                };
+                template_patterns.insert(key, pattern.clone());
+                pattern
+            }
+        };
+
+        previews.push(ExportFilenamePreview {
+            filename: build_output_filename(&template_pattern, &order, &settings),
+            order_id: order.id,
+            order_number: order.order_number,
+            collision: false,
+            collision_group: None,
+        });
+    }
+
+    // Windows 文件名不区分大小写，按小写比较；分组序号按首次出现的顺序编号
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for preview in &previews {
+        *counts.entry(preview.filename.to_lowercase()).or_insert(0) += 1;
+    }
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    for preview in &mut previews {
+        let key = preview.filename.to_lowercase();
+        if counts[&key] > 1 {
+            let next = groups.len() + 1;
+            preview.collision = true;
+            preview.collision_group = Some(*groups.entry(key).or_insert(next));
+        }
+    }
+
+    let collisions = previews.iter().filter(|p| p.collision).count();
+    if collisions > 0 {
+        log::warn!("批量导出预览: {} 个订单的文件名重复", collisions);
+    }
+    Ok(previews)
+}
+
+/// 批量为订单指定模板（如启用新模板后把草稿订单改过去），status 指定时只修改该状态的订单
+/// （例如传 "draft" 以保证已完成订单不受影响），返回修改的订单数
+#[tauri::command]
+pub async fn set_orders_template(
+    order_ids: Vec<String>,
+    template_id: String,
+    status: Option<String>,
+    conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
+) -> Result<usize, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let updated = order_repo
+        .set_template(&order_ids, &template_id, status.as_deref(), &clock.now_rfc3339())
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("模板不存在: {}", template_id),
+            e => e.to_string(),
+        })?;
+
+    log::info!("{} 个订单已改用模板 {}", updated, template_id);
+    Ok(updated)
+}
+
+/// 解析订单导出时实际使用的模板（订单模板优先，否则回退到默认模板）及其商品行数，不含模板文件数据
+#[tauri::command]
+pub async fn resolve_order_template(
+    order_id: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<ResolvedTemplate, String> {
+    let order_repo = OrderRepository::new(conn.inner().clone());
+    let order = order_repo.get_by_id(&order_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("订单不存在: {}", order_id),
+        e => e.to_string(),
+    })?;
+
+    let settings_repo = SettingsRepository::new(conn.inner().clone());
+    let default_template_id = settings_cache
+        .get(&settings_repo)
+        .map_err(|e| e.to_string())?
+        .map(|s| s.default_template_id)
+        .unwrap_or_default();
+
+    let template_repo = TemplateRepository::new(conn.inner().clone());
+    let template = template_repo
+        .resolve_effective(order.template_id.as_deref(), &default_template_id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => "没有可用的模板，请先在设置中添加模板".to_string(),
+            e => e.to_string(),
+        })?;
+
+    let mappings = &template.mappings;
+    let capacity = (mappings.item_start_row > 0 && mappings.item_end_row >= mappings.item_start_row)
+        .then(|| (mappings.item_end_row - mappings.item_start_row + 1) as u32);
+    Ok(ResolvedTemplate { template, capacity })
+}
+
+/// 导出前按模板的必填设置检查订单（规则与导出时相同），返回问题列表（为空表示通过）
+#[tauri::command]
+pub async fn validate_order_against_template(
+    order: Order,
+    template_id: String,
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<String>, String> {
+    let template_repo = TemplateRepository::new(conn.inner().clone());
+    let template = template_repo.get_by_id(&template_id).map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => format!("模板不存在: {}", template_id),
+        e => e.to_string(),
+    })?;
+
+    Ok(validate_order_for_template(&order, &template.required_fields)
+        .err()
+        .unwrap_or_default())
+}
+
+/// 删除模板，并将引用该模板的订单改为使用替代模板（而不是置空），返回改指向的订单数
+#[tauri::command]
+pub async fn delete_template_with_reassign(
+    id: String,
+    replacement_id: String,
+    conn: State<'_, DbConnection>,
+    settings_cache: State<'_, SettingsCache>,
+) -> Result<usize, String> {
+    if id == replacement_id {
+        return Err("替代模板不能是要删除的模板本身".to_string());
+    }
+
+    let template_repo = TemplateRepository::new(conn.inner().clone());
+    let reassigned = template_repo
+        .delete_with_reassign(&id, &replacement_id)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => format!("替代模板不存在: {}", replacement_id),
+            e => {
+                log::error!("删除模板 {} 失败: {}", id, e);
+                e.to_string()
+            }
+        })?;
+    // 默认模板可能已被改指向替代模板
+    settings_cache.invalidate();
+
+    log::info!("模板已删除: {}，{} 个订单改用模板 {}", id, reassigned, replacement_id);
+    Ok(reassigned)
+}
+
+/// 列出模板中设为必填、但没有配置对应单元格/列的字段
+fn unmapped_required_fields(template: &TemplateConfig) -> Vec<String> {
+    let required = &template.required_fields;
+    let mappings = &template.mappings;
+    let columns = &mappings.columns;
+
+    [
+        (required.require_customer_name, &mappings.customer_name, "客户姓名"),
+        (required.require_customer_phone, &mappings.customer_phone, "联系电话"),
+        (required.require_customer_plate, &mappings.customer_plate, "车牌号"),
+        (required.require_date, &mappings.date, "日期"),
+        (required.require_order_number, &mappings.order_number, "订单号"),
+        (required.require_order_remark, &mappings.order_remark, "订单备注"),
+        (required.require_total_amount, &mappings.total_amount, "总金额"),
+        (required.require_item_name, &columns.name, "商品名称列"),
+        (required.require_item_unit, &columns.unit, "单位列"),
+        (required.require_item_quantity, &columns.quantity, "数量列"),
+        (required.require_item_price, &columns.price, "单价列"),
+        (required.require_item_total, &columns.total, "小计列"),
+        (required.require_item_remark, &columns.remark, "商品备注列"),
+    ]
+    .into_iter()
+    .filter(|(is_required, cell, _)| *is_required && cell.trim().is_empty())
+    .map(|(_, _, label)| label.to_string())
+    .collect()
+}
+
+/// 模板预检：返回必填字段未配置映射的模板（导出必然失败或缺项）
+#[tauri::command]
+pub async fn audit_templates(
+    conn: State<'_, DbConnection>,
+) -> Result<Vec<TemplateAuditIssue>, String> {
+    let template_repo = TemplateRepository::new(conn.inner().clone());
+    let templates = template_repo.get_all().map_err(|e| e.to_string())?;
+
+    Ok(templates
+        .iter()
+        .filter_map(|template| {
+            let unmapped_fields = unmapped_required_fields(template);
+            (!unmapped_fields.is_empty()).then(|| TemplateAuditIssue {
+                template_id: template.id.clone(),
+                template_name: template.name.clone(),
+                unmapped_fields,
+            })
+        })
+        .collect())
+}
+
+/// 检查准备保存为模板的 xlsx（base64）：能否解码、打开，大小是否在上限内，并返回工作表名称与第一个工作表的使用范围。
+/// 文件无效时返回 valid 为 false 的结果（error 为原因），供上传对话框即时提示
+#[tauri::command]
+pub async fn inspect_template_file(base64: String) -> Result<TemplateFileInfo, String> {
+    let mut info = TemplateFileInfo {
+        valid: false,
+        sheet_names: Vec::new(),
+        max_row: 0,
+        max_col: 0,
+        size_bytes: 0,
+        error: None,
+    };
+
+    let bytes = match BASE64.decode(base64.trim()) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            info.error = Some(format!("文件数据无法解码: {}", e));
+            return Ok(info);
+        }
+    };
+    info.size_bytes = bytes.len();
+    if bytes.is_empty() {
+        info.error = Some("文件为空".to_string());
+        return Ok(info);
+    }
+    if bytes.len() > MAX_TEMPLATE_BYTES {
+        info.error = Some(format!("文件过大（上限 {} MB）", MAX_TEMPLATE_BYTES / 1024 / 1024));
+        return Ok(info);
+    }
+
+    match inspect_workbook(&bytes) {
+        Ok(outline) => {
+            info.valid = true;
+            info.sheet_names = outline.sheet_names;
+            info.max_row = outline.max_row;
+            info.max_col = outline.max_col;
+        }
+        Err(e) => info.error = Some(format!("无法打开 Excel 文件: {}", e)),
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use tauri::Manager;
+
+    /// 没有上传文件、未配置映射的模板
+    fn template_config(id: &str) -> TemplateConfig {
+        TemplateConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            template_base64: String::new(),
+            file_name: format!("{}.xlsx", id),
+            filename_pattern: "{orderNo}".to_string(),
+            is_default: false,
+            mappings: Default::default(),
+            required_fields: Default::default(),
+            created_at: "2024-03-10T04:00:00Z".to_string(),
+            updated_at: "2024-03-10T04:00:00Z".to_string(),
+            number_format: None,
+            has_file: false,
+        }
+    }
+
+    #[test]
+    fn filename_preview_uses_each_orders_customer() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            c.execute("UPDATE app_settings SET excel_filename_format = '{customerName}_{customerPlate}'", [])
+                .unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_customer(&c, "c2", "李四", "13900000000", "B67890");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_order(&c, "o2", "c2", "2024-01-06", "completed");
+            insert_order(&c, "o3", "c1", "2024-01-07", "completed");
+            // 区间外的订单不参与预览
+            insert_order(&c, "o4", "c2", "2024-02-01", "completed");
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+
+        let previews = tauri::async_runtime::block_on(preview_export_filenames(
+            "2024-01-01".to_string(),
+            "2024-01-31".to_string(),
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+
+        let summary: Vec<(&str, &str, bool)> = previews
+            .iter()
+            .map(|p| (p.order_id.as_str(), p.filename.as_str(), p.collision))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("o1", "张三_A12345.xlsx", true),
+                ("o2", "李四_B67890.xlsx", false),
+                ("o3", "张三_A12345.xlsx", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_order_against_template_reports_every_missing_field() {
+        use crate::models::RequiredFields;
+
+        let conn = memory_db();
+        TemplateRepository::new(conn.clone())
+            .insert(&TemplateConfig {
+                required_fields: RequiredFields {
+                    require_customer_phone: true,
+                    require_order_remark: true,
+                    require_item_unit: true,
+                    min_items: 2,
+                    ..RequiredFields::default()
+                },
+                ..template_config("t1")
+            })
+            .unwrap();
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let validate = |order: Order, template_id: &str| {
+            tauri::async_runtime::block_on(validate_order_against_template(order, template_id.to_string(), app.state()))
+        };
+
+        let mut order = new_order("o1", "c1", "draft", &[("p1", 10.0, 1.0)]);
+        order.items[0].unit.clear();
+        assert_eq!(
+            validate(order.clone(), "t1").unwrap(),
+            vec!["联系电话", "订单备注", "至少需要 2 行商品，当前只有 1 行", "第 1 行商品的单位"]
+        );
+
+        order.customer.phone = "13800000000".to_string();
+        order.remark = Some("加急".to_string());
+        order.items[0].unit = "个".to_string();
+        order.items.push(order.items[0].clone());
+        assert!(validate(order.clone(), "t1").unwrap().is_empty());
+
+        assert_eq!(validate(order, "missing").unwrap_err(), "模板不存在: missing");
+    }
+
+    #[test]
+    fn resolve_order_template_falls_back_to_the_default_and_reports_capacity() {
+        let conn = memory_db();
+        let repo = TemplateRepository::new(conn.clone());
+        let mut with_rows = template_config("t1");
+        with_rows.template_base64 = BASE64.encode(b"xlsx");
+        with_rows.mappings.item_start_row = 5;
+        with_rows.mappings.item_end_row = 14;
+        repo.insert(&with_rows).unwrap();
+        repo.insert(&template_config("t2")).unwrap();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "", "");
+            insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+            insert_order(&c, "o2", "c1", "2024-03-10", "draft");
+            c.execute_batch(
+                "UPDATE orders SET template_id = 't1' WHERE id = 'o1';
+                 UPDATE app_settings SET default_template_id = 't2';",
+            )
+            .unwrap();
+        }
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+        let resolve =
+            |order_id: &str| tauri::async_runtime::block_on(resolve_order_template(order_id.to_string(), app.state(), app.state()));
+
+        // 订单指定的模板优先，不回传文件内容
+        let resolved = resolve("o1").unwrap();
+        assert_eq!((resolved.template.id.as_str(), resolved.capacity), ("t1", Some(10)));
+        assert!(resolved.template.template_base64.is_empty());
+        assert!(resolved.template.has_file);
+
+        // 未指定模板时使用设置中的默认模板；未配置商品行范围时不限制行数
+        let resolved = resolve("o2").unwrap();
+        assert_eq!((resolved.template.id.as_str(), resolved.capacity), ("t2", None));
+        assert!(!resolved.template.has_file);
+
+        assert_eq!(resolve("missing").unwrap_err(), "订单不存在: missing");
+    }
+
+    #[test]
+    fn deleting_with_a_replacement_moves_orders_and_the_default_template() {
+        let conn = memory_db();
+        let repo = TemplateRepository::new(conn.clone());
+        let mut old = template_config("t1");
+        old.template_base64 = BASE64.encode(b"xlsx");
+        repo.insert(&old).unwrap();
+        repo.insert(&template_config("t2")).unwrap();
+        repo.insert(&template_config("t3")).unwrap();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "", "");
+            for (order_id, template_id) in [("o1", Some("t1")), ("o2", Some("t1")), ("o3", Some("t3")), ("o4", None)] {
+                insert_order(&c, order_id, "c1", "2024-03-10", "draft");
+                c.execute("UPDATE orders SET template_id = ?1 WHERE id = ?2", params![template_id, order_id]).unwrap();
+            }
+            c.execute("UPDATE app_settings SET default_template_id = 't1'", []).unwrap();
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        // 先读一次设置，确认删除后缓存会失效
+        let settings_cache = app.state::<SettingsCache>();
+        let default_template = || {
+            settings_cache.get(&SettingsRepository::new(conn.clone())).unwrap().unwrap().default_template_id
+        };
+        assert_eq!(default_template(), "t1");
+        let delete = |id: &str, replacement_id: &str| {
+            tauri::async_runtime::block_on(delete_template_with_reassign(
+                id.to_string(),
+                replacement_id.to_string(),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let order_templates = || {
+            let c = conn.lock().unwrap();
+            let mut stmt = c.prepare("SELECT id, template_id FROM orders ORDER BY id").unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            rows
+        };
+        let before = order_templates();
+
+        // 替代模板无效时不做任何修改
+        assert_eq!(delete("t1", "t1").unwrap_err(), "替代模板不能是要删除的模板本身");
+        assert_eq!(delete("t1", "missing").unwrap_err(), "替代模板不存在: missing");
+        assert_eq!(order_templates(), before);
+        assert!(repo.get_by_id("t1").is_ok());
+
+        assert_eq!(delete("t1", "t2"), Ok(2));
+        let expected = [("o1", Some("t2")), ("o2", Some("t2")), ("o3", Some("t3")), ("o4", None)];
+        assert_eq!(order_templates(), expected.map(|(id, t)| (id.to_string(), t.map(str::to_string))));
+        assert_eq!(default_template(), "t2");
+        assert!(matches!(repo.get_by_id("t1"), Err(rusqlite::Error::QueryReturnedNoRows)));
+        let files: i64 = conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM template_files WHERE template_id = 't1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 0);
+    }
+
+    #[test]
+    fn audit_lists_only_templates_with_required_but_unmapped_fields() {
+        use crate::models::RequiredFields;
+
+        let conn = memory_db();
+        let repo = TemplateRepository::new(conn.clone());
+        let required = RequiredFields {
+            require_customer_plate: true,
+            require_total_amount: true,
+            require_item_unit: true,
+            ..RequiredFields::default()
+        };
+        // 车牌号未配置、单位列只有空格，总金额已配置
+        let mut broken = TemplateConfig { required_fields: required.clone(), ..template_config("t1") };
+        broken.mappings.total_amount = "F20".to_string();
+        broken.mappings.columns.unit = "  ".to_string();
+        repo.insert(&broken).unwrap();
+        let mut complete = TemplateConfig { required_fields: required, ..template_config("t2") };
+        complete.mappings.customer_plate = "B2".to_string();
+        complete.mappings.total_amount = "F20".to_string();
+        complete.mappings.columns.unit = "C".to_string();
+        repo.insert(&complete).unwrap();
+        // 没有必填项的模板即使没有映射也不报告
+        repo.insert(&template_config("t3")).unwrap();
+        let (app, _clock) = test_app(conn, "2024-03-10T04:00:00Z");
+
+        let issues = tauri::async_runtime::block_on(audit_templates(app.state())).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].template_id.as_str(), issues[0].template_name.as_str()), ("t1", "t1"));
+        assert_eq!(issues[0].unmapped_fields, vec!["车牌号", "单位列"]);
+    }
+
+    #[test]
+    fn bulk_template_assignment_respects_the_status_filter_and_rejects_unknown_templates() {
+        let conn = memory_db();
+        TemplateRepository::new(conn.clone()).insert(&template_config("t-new")).unwrap();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "", "");
+            for (id, status) in [("o1", "draft"), ("o2", "draft"), ("o3", "completed"), ("o4", "draft")] {
+                insert_order(&c, id, "c1", "2024-03-01", status);
+            }
+        }
+        let (app, _clock) = test_app(conn.clone(), "2024-03-10T04:00:00Z");
+        let assign = |ids: &[&str], template_id: &str, status: Option<&str>| {
+            tauri::async_runtime::block_on(set_orders_template(
+                ids.iter().map(|id| id.to_string()).collect(),
+                template_id.to_string(),
+                status.map(str::to_string),
+                app.state(),
+                app.state(),
+            ))
+        };
+        let templates = || {
+            let c = conn.lock().unwrap();
+            let mut stmt = c.prepare("SELECT template_id, updated_at FROM orders ORDER BY id").unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            rows
+        };
+        let before = templates();
+
+        assert_eq!(assign(&["o1"], "missing", None).unwrap_err(), "模板不存在: missing");
+        assert_eq!(templates(), before);
+
+        // 只改草稿订单，已完成的 o3 与未列出的 o4 不变
+        assert_eq!(assign(&["o1", "o2", "o3", "unknown"], "t-new", Some("draft")), Ok(2));
+        let after = templates();
+        let now = "2024-03-10T04:00:00+00:00".to_string();
+        assert_eq!(after[0], (Some("t-new".to_string()), now.clone()));
+        assert_eq!(after[1], (Some("t-new".to_string()), now));
+        assert_eq!(after[2..], before[2..]);
+
+        // 不限状态时包括已完成订单；已经是该模板的订单不重复计数
+        assert_eq!(assign(&["o1", "o3"], "t-new", None), Ok(1));
+        assert_eq!(templates()[2].0.as_deref(), Some("t-new"));
+    }
+
+    #[test]
+    fn inspect_template_file_reports_dimensions_or_why_the_file_is_invalid() {
+        let inspect = |base64: String| tauri::async_runtime::block_on(inspect_template_file(base64)).unwrap();
+
+        let mut book = umya_spreadsheet::new_file();
+        book.get_sheet_mut(&0).unwrap().set_name("报价单");
+        book.get_sheet_mut(&0).unwrap().get_cell_mut("C7").set_value_string("合计");
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        umya_spreadsheet::writer::xlsx::write_writer(&book, &mut bytes).unwrap();
+        let bytes = bytes.into_inner();
+
+        let info = inspect(BASE64.encode(&bytes));
+        assert!(info.valid, "{:?}", info.error);
+        assert_eq!(info.sheet_names, vec!["报价单"]);
+        assert_eq!((info.max_row, info.max_col, info.size_bytes, info.error), (7, 3, bytes.len(), None));
+
+        // 能解码但不是 xlsx：返回 valid 为 false 与原因，而不是命令错误
+        let info = inspect(BASE64.encode("商品名称,单价\n机油,45\n"));
+        assert!(!info.valid);
+        assert!(info.sheet_names.is_empty());
+        assert_eq!(info.size_bytes, "商品名称,单价\n机油,45\n".len());
+        assert!(info.error.as_deref().unwrap().starts_with("无法打开 Excel 文件: "), "{:?}", info.error);
+
+        let info = inspect("不是base64".to_string());
+        assert!(!info.valid && info.error.as_deref().unwrap().starts_with("文件数据无法解码: "));
+        assert_eq!(inspect(String::new()).error.as_deref(), Some("文件为空"));
+    }
+}
@@ -2,6 +2,7 @@ use tauri::State;
 use crate::database::DbConnection;
 use crate::models::UnitPreset;
 use crate::database::schema::{UnitPresetRepository, Repository};
+use crate::utils::clock::SharedClock;
 use rusqlite::Error as SqliteError;
 
 #[tauri::command]
@@ -25,7 +26,7 @@ pub async fn get_unit_preset_by_id(
 
 #[tauri::command]
 pub async fn save_unit_preset(
-    preset: UnitPreset,
+    mut preset: UnitPreset,
     conn: State<'_, DbConnection>,
 ) -> Result<(), String> {
     let repo = UnitPresetRepository::new(conn.inner().clone());
@@ -33,15 +34,22 @@ pub async fn save_unit_preset(
     // 检查是否已存在
     let existing = repo.get_by_id(&preset.id);
     
-    if existing.is_ok() {
+    let result = if existing.is_ok() {
         // 更新
         repo.update(&preset)
-            .map_err(|e: SqliteError| e.to_string())
     } else {
+        // 新预设未指定排序时排到最后
+        if preset.sort_order == 0 {
+            preset.sort_order = repo.next_sort_order()
+                .map_err(|e: SqliteError| e.to_string())?;
+        }
         // 插入
         repo.insert(&preset)
-            .map_err(|e: SqliteError| e.to_string())
-    }
+    };
+    result.map_err(|e: SqliteError| {
+        log::error!("保存单位预设 {} 失败: {}", preset.id, e);
+        e.to_string()
+    })
 }
 
 #[tauri::command]
@@ -51,13 +59,17 @@ pub async fn delete_unit_preset(
 ) -> Result<(), String> {
     let repo = UnitPresetRepository::new(conn.inner().clone());
     repo.delete(&id)
-        .map_err(|e: SqliteError| e.to_string())
+        .map_err(|e: SqliteError| {
+            log::error!("删除单位预设 {} 失败: {}", id, e);
+            e.to_string()
+        })
 }
 
 #[tauri::command]
 pub async fn increment_unit_preset_use_count(
     id: String,
     conn: State<'_, DbConnection>,
+    clock: State<'_, SharedClock>,
 ) -> Result<(), String> {
     let repo = UnitPresetRepository::new(conn.inner().clone());
     
@@ -67,7 +79,7 @@ pub async fn increment_unit_preset_use_count(
     
     // 增加使用次数
     preset.use_count += 1;
-    preset.updated_at = chrono::Utc::now().to_rfc3339();
+    preset.updated_at = clock.now_rfc3339();
     
     // 更新
     repo.update(&preset)
@@ -1,29 +1,110 @@
-use anyhow::{Context, Result};
-use rusqlite::{params, Connection};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crate::database::migrations;
+use crate::models::RestoredDefaults;
+use rusqlite::{params, Connection, DatabaseName, OpenFlags};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub type DbConnection = Arc<Mutex<Connection>>;
 
+// 默认分类（名称, 排序）
+const DEFAULT_CATEGORIES: &[(&str, i32)] = &[
+    ("保养", 0),
+    ("配件", 1),
+    ("装饰", 2),
+    ("清洗", 3),
+    ("服务", 4),
+    ("其他", 5),
+];
+
+// 默认单位预设（名称, 排序）
+const DEFAULT_UNITS: &[(&str, i32)] = &[
+    ("件", 0),
+    ("个", 1),
+    ("套", 2),
+    ("箱", 3),
+    ("包", 4),
+    ("瓶", 5),
+    ("盒", 6),
+    ("袋", 7),
+    ("桶", 8),
+    ("斤", 9),
+    ("公斤", 10),
+    ("克", 11),
+    ("升", 12),
+    ("毫升", 13),
+    ("米", 14),
+    ("厘米", 15),
+];
+
+// 默认模板名称
+const DEFAULT_TEMPLATE_NAME: &str = "默认模板";
+
+// 订单归档：原表与对应的归档表
+pub const ARCHIVE_TABLES: &[(&str, &str)] = &[
+    ("orders", "orders_archive"),
+    ("order_items", "order_items_archive"),
+];
+
+// 恢复备份时必须存在的表：各版本的 QuickSales 数据库都有这些表，
+// 之后的版本新增的表（库存流水、订单记录、附件等）在恢复后由 init_tables 补建
+const REQUIRED_BACKUP_TABLES: &[&str] = &[
+    "categories",
+    "products",
+    "customers",
+    "orders",
+    "order_items",
+    "app_settings",
+    "templates",
+    "remark_presets",
+    "unit_presets",
+];
+
 pub struct Database {
     pub conn: DbConnection,
 }
 
 impl Database {
-    pub fn new(db_path: &str) -> Result<Self> {
+    /// now 为打开时刻（RFC 3339），用作迁移与回填记录的时间
+    pub fn new(db_path: &str, now: &str) -> Result<Self> {
         let conn = Connection::open(db_path).context("Failed to open database")?;
+        Self::configure_connection(&conn)?;
 
         let db = Database {
             conn: Arc::new(Mutex::new(conn)),
         };
 
         // 初始化数据库表
-        db.init_tables()?;
+        db.init_tables(now)?;
 
         Ok(db)
     }
 
-    fn init_tables(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+    /// 连接参数：忙等待 5 秒避免偶发的 database is locked；WAL 日志让读写互不阻塞；
+    /// 开启外键约束，使表结构中声明的级联删除与置空生效
+    fn configure_connection(conn: &Connection) -> Result<()> {
+        conn.busy_timeout(Duration::from_secs(5))
+            .context("Failed to set busy timeout")?;
+
+        // 不支持 WAL 的文件系统（部分网络共享目录）上保持原日志模式继续运行
+        match conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)) {
+            Ok(mode) if mode.eq_ignore_ascii_case("wal") => {
+                // WAL 模式下 NORMAL 已能保证断电不损坏数据库，回滚日志模式仍使用默认的 FULL
+                conn.execute_batch("PRAGMA synchronous = NORMAL")?;
+            }
+            Ok(mode) if mode.eq_ignore_ascii_case("memory") => {}
+            Ok(mode) => log::warn!("数据库未能切换到 WAL 模式，继续使用 {} 日志模式", mode),
+            Err(e) => log::warn!("数据库未能切换到 WAL 模式: {}", e),
+        }
+
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        Ok(())
+    }
+
+    fn init_tables(&self, now: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
 
         // 商品分类表（支持多级分类）
         conn.execute(
@@ -48,6 +129,7 @@ impl Database {
                 name TEXT NOT NULL,
                 unit TEXT NOT NULL,
                 price REAL NOT NULL,
+                price_cents INTEGER,
                 category_id TEXT,
                 pinyin TEXT,
                 stock REAL,
@@ -55,19 +137,12 @@ impl Database {
                 track_stock INTEGER DEFAULT 0,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
+                barcode TEXT,
                 FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE SET NULL
             )",
             [],
         )?;
 
-        // 尝试添加新列（用于升级旧数据库）
-        let _ = conn.execute("ALTER TABLE products ADD COLUMN stock REAL", []);
-        let _ = conn.execute("ALTER TABLE products ADD COLUMN min_stock REAL", []);
-        let _ = conn.execute(
-            "ALTER TABLE products ADD COLUMN track_stock INTEGER DEFAULT 0",
-            [],
-        );
-
         // 客户表
         conn.execute(
             "CREATE TABLE IF NOT EXISTS customers (
@@ -91,9 +166,19 @@ impl Database {
                 date TEXT NOT NULL,
                 customer_id TEXT NOT NULL,
                 total_amount REAL NOT NULL,
+                total_amount_cents INTEGER,
                 remark TEXT,
                 template_id TEXT,
                 status TEXT NOT NULL,
+                on_account INTEGER DEFAULT 0,
+                rounding_adjustment REAL DEFAULT 0,
+                rounding_adjustment_cents INTEGER DEFAULT 0,
+                currency TEXT,
+                exchange_rate REAL DEFAULT 1,
+                order_discount REAL DEFAULT 0,
+                order_discount_cents INTEGER DEFAULT 0,
+                tax_rate REAL,
+                prices_include_tax INTEGER,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
                 FOREIGN KEY (customer_id) REFERENCES customers(id) ON DELETE CASCADE,
@@ -111,17 +196,17 @@ impl Database {
                 name TEXT NOT NULL,
                 unit TEXT NOT NULL,
                 price REAL NOT NULL,
+                price_cents INTEGER,
                 quantity REAL NOT NULL,
                 discount_price REAL,
+                discount_price_cents INTEGER,
                 remark TEXT,
                 sort_value INTEGER DEFAULT 0,
+                category TEXT,
                 FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE
             )",
             [],
         )?;
-        // 尝试升级旧数据库（将 sort_value 列类型改为支持 i64）
-        // SQLite 的 INTEGER 本身就是 64 位的，所以不需要 ALTER COLUMN
-
         // 应用设置表
         conn.execute(
             "CREATE TABLE IF NOT EXISTS app_settings (
@@ -137,6 +222,7 @@ impl Database {
                 order_number_format TEXT DEFAULT 'NO.{SEQ:6}',
                 order_number_prefix TEXT,
                 order_number_reset_daily INTEGER DEFAULT 1,
+                order_number_reset_period TEXT DEFAULT 'daily',
                 order_number_digits INTEGER DEFAULT 6,
                 retain_days INTEGER DEFAULT 0,
                 auto_backup INTEGER DEFAULT 1,
@@ -148,37 +234,23 @@ impl Database {
                 auto_open_excel INTEGER DEFAULT 0,
                 skip_save_dialog INTEGER DEFAULT 0,
                 template_validation TEXT DEFAULT '{}',
+                log_level TEXT DEFAULT 'info',
+                total_rounding TEXT DEFAULT 'none',
+                tax_rate REAL DEFAULT 0,
+                prices_include_tax INTEGER DEFAULT 1,
+                max_item_quantity REAL DEFAULT 0,
+                max_order_total REAL DEFAULT 0,
+                block_on_order_limits INTEGER DEFAULT 0,
+                export_item_sort TEXT DEFAULT 'entry',
+                default_track_stock INTEGER DEFAULT 0,
+                default_min_stock REAL DEFAULT 0,
+                allow_zero_price INTEGER DEFAULT 1,
+                strict_stock INTEGER DEFAULT 0,
                 updated_at TEXT NOT NULL
             )",
             [],
         )?;
 
-        // 尝试为旧版本数据库添加缺失的列
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN default_template_id TEXT",
-            [],
-        );
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN default_category_id TEXT",
-            [],
-        );
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN template_validation TEXT",
-            [],
-        );
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN excel_filename_format TEXT DEFAULT '{date}_{customerName}_{orderNumber}'",
-            [],
-        );
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN auto_open_excel INTEGER DEFAULT 0",
-            [],
-        );
-        let _ = conn.execute(
-            "ALTER TABLE app_settings ADD COLUMN skip_save_dialog INTEGER DEFAULT 0",
-            [],
-        );
-
         // 模板配置表
         conn.execute(
             "CREATE TABLE IF NOT EXISTS templates (
@@ -191,23 +263,24 @@ impl Database {
                 mappings TEXT NOT NULL,
                 item_end_row INTEGER DEFAULT 0,
                 required_fields TEXT NOT NULL DEFAULT '{}',
+                number_format TEXT,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL
             )",
             [],
         )?;
 
-        // 尝试添加 required_fields 列（用于升级旧数据库）
-        let _ = conn.execute(
-            "ALTER TABLE templates ADD COLUMN required_fields TEXT NOT NULL DEFAULT '{}'",
-            [],
-        );
-
-        // 尝试添加 item_end_row 列（用于升级旧数据库）
-        let _ = conn.execute(
-            "ALTER TABLE templates ADD COLUMN item_end_row INTEGER DEFAULT 0",
+        // 模板文件表（xlsx 原始字节以 BLOB 存储，比 base64 文本节省约三分之一空间）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS template_files (
+                template_id TEXT PRIMARY KEY,
+                data BLOB NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (template_id) REFERENCES templates(id) ON DELETE CASCADE
+            )",
             [],
-        );
+        )?;
+        Self::migrate_template_files(&conn, now)?;
 
         // 备注预设表
         conn.execute(
@@ -236,6 +309,136 @@ impl Database {
             [],
         )?;
 
+        // 客户合并记录（被合并客户 ID -> 合并目标），用于旧客户 ID 的解析
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS customer_merges (
+                source_id TEXT PRIMARY KEY,
+                target_id TEXT NOT NULL,
+                merged_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // 客户往来账（挂账/收款）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS customer_transactions (
+                id TEXT PRIMARY KEY,
+                customer_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                amount_cents INTEGER,
+                kind TEXT NOT NULL,
+                order_id TEXT,
+                remark TEXT,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // 库存变动流水表
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS stock_movements (
+                id TEXT PRIMARY KEY,
+                product_id TEXT NOT NULL,
+                change REAL NOT NULL,
+                reason TEXT NOT NULL,
+                order_id TEXT,
+                balance_after REAL,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // 商品销售速度缓存（由 refresh_product_stats 按滚动窗口重算）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS product_stats (
+                product_id TEXT PRIMARY KEY,
+                units_sold REAL NOT NULL DEFAULT 0,
+                units_per_day REAL NOT NULL DEFAULT 0,
+                window_days INTEGER NOT NULL,
+                refreshed_at TEXT NOT NULL,
+                FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // 订单操作记录表（创建、修改、状态变更、导出等，用于审计追溯）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_events (
+                id TEXT PRIMARY KEY,
+                order_id TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                detail TEXT,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // 订单附件表（施工照片、签字单等，文件复制到数据目录下的 attachments 文件夹）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_attachments (
+                id TEXT PRIMARY KEY,
+                order_id TEXT NOT NULL,
+                file_path TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // 订单草稿表（收银中途自动保存，不扣库存、不占订单号、不计入报表）
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_drafts (
+                id TEXT PRIMARY KEY,
+                order_json TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // 旧数据库补齐新增的列（按版本号顺序执行，记录在 schema_version 表）
+        migrations::run_migrations(&mut conn, now).context("Failed to migrate database")?;
+
+        // 回填尚未转换的金额（仅处理为空的行，可重复执行）
+        conn.execute(
+            "UPDATE products SET price_cents = CAST(ROUND(price * 100) AS INTEGER)
+             WHERE price_cents IS NULL",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE orders SET total_amount_cents = CAST(ROUND(total_amount * 100) AS INTEGER)
+             WHERE total_amount_cents IS NULL",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE order_items SET price_cents = CAST(ROUND(price * 100) AS INTEGER)
+             WHERE price_cents IS NULL",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE order_items SET discount_price_cents = CAST(ROUND(discount_price * 100) AS INTEGER)
+             WHERE discount_price_cents IS NULL AND discount_price IS NOT NULL",
+            [],
+        )?;
+
+        // 订单归档表（与订单表同结构）
+        Self::sync_archive_tables(&conn)?;
+        backfill_line_totals(&conn)?;
+        backfill_item_categories(&conn)?;
+        clear_dangling_references(&conn)?;
+        let orphaned = delete_orphaned_order_items(&conn)?;
+        if orphaned > 0 {
+            log::info!("已清理 {} 条孤立订单项", orphaned);
+        }
+
+        // 订单全文检索索引（FTS5 trigram，支持与 LIKE 一致的子串匹配）
+        Self::init_order_search_index(&conn)?;
+        // 商品全文检索索引（FTS5，按名称与拼音码前缀匹配）
+        Self::init_product_search_index(&conn)?;
+
         // 创建索引
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_products_category ON products(category_id)",
@@ -245,6 +448,10 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_products_name ON products(name)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_products_barcode ON products(barcode)",
+            [],
+        )?;
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_customers_name ON customers(name)",
             [],
@@ -273,6 +480,10 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_order_items_order ON order_items(order_id)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_order_attachments_order ON order_attachments(order_id)",
+            [],
+        )?;
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_templates_default ON templates(is_default)",
             [],
@@ -293,33 +504,203 @@ impl Database {
             "CREATE INDEX IF NOT EXISTS idx_categories_level ON categories(level)",
             [],
         )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_customer_transactions_customer ON customer_transactions(customer_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_stock_movements_product ON stock_movements(product_id, created_at)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_order_events_order ON order_events(order_id, created_at)",
+            [],
+        )?;
 
         Ok(())
     }
 
-    pub fn insert_default_data(&self) -> Result<()> {
+    /// 将旧版存放在 templates.template_base64 中的模板文件解码后迁移到 template_files，
+    /// 迁移成功的行清空 base64 文本；无法解码的数据保留原样并记录警告
+    fn migrate_template_files(conn: &Connection, now: &str) -> Result<()> {
+        let pending: Vec<(String, String)> = {
+            let mut stmt = conn.prepare(
+                "SELECT id, template_base64 FROM templates
+                 WHERE template_base64 <> '' AND id NOT IN (SELECT template_id FROM template_files)",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        for (id, encoded) in pending {
+            match BASE64.decode(encoded.trim()) {
+                Ok(bytes) => {
+                    conn.execute(
+                        "INSERT INTO template_files (template_id, data, updated_at) VALUES (?1, ?2, ?3)",
+                        params![&id, &bytes, now],
+                    )?;
+                    conn.execute("UPDATE templates SET template_base64 = '' WHERE id = ?1", [&id])?;
+                    log::info!("模板文件已迁移为 BLOB 存储: {} ({} 字节)", id, bytes.len());
+                }
+                Err(e) => log::warn!("模板 {} 的文件数据无法解码，保留原始内容: {}", id, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 创建订单/订单项归档表，并补齐订单表后来新增的列（归档表不带约束，列与原表一一对应）
+    fn sync_archive_tables(conn: &Connection) -> Result<()> {
+        for (table, archive) in ARCHIVE_TABLES {
+            conn.execute(
+                &format!("CREATE TABLE IF NOT EXISTS {} AS SELECT * FROM {} WHERE 0", archive, table),
+                [],
+            )?;
+            let archived = table_column_names(conn, archive)?;
+            for column in table_column_names(conn, table)? {
+                if !archived.contains(&column) {
+                    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}", archive, column), [])?;
+                }
+            }
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_order_items_archive_order ON order_items_archive(order_id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn init_order_search_index(conn: &Connection) -> Result<()> {
+        let created = conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS orders_fts USING fts5(
+                order_id UNINDEXED,
+                order_number,
+                customer_name,
+                customer_phone,
+                customer_plate,
+                item_names,
+                tokenize = 'trigram'
+            )",
+            [],
+        );
+        if let Err(e) = created {
+            // 旧版 SQLite 不支持 FTS5/trigram 时回退到 LIKE 搜索
+            log::warn!("订单全文索引不可用，将使用 LIKE 搜索: {}", e);
+            return Ok(());
+        }
+
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS orders_fts_ai AFTER INSERT ON orders BEGIN
+                 {insert_new_order}
+             END;
+             CREATE TRIGGER IF NOT EXISTS orders_fts_au AFTER UPDATE ON orders BEGIN
+                 DELETE FROM orders_fts WHERE order_id = OLD.id;
+                 {insert_new_order}
+             END;
+             CREATE TRIGGER IF NOT EXISTS orders_fts_ad AFTER DELETE ON orders BEGIN
+                 DELETE FROM orders_fts WHERE order_id = OLD.id;
+             END;
+             CREATE TRIGGER IF NOT EXISTS order_items_fts_ai AFTER INSERT ON order_items BEGIN
+                 DELETE FROM orders_fts WHERE order_id = NEW.order_id;
+                 {insert_item_order}
+             END;
+             CREATE TRIGGER IF NOT EXISTS order_items_fts_au AFTER UPDATE ON order_items BEGIN
+                 DELETE FROM orders_fts WHERE order_id IN (OLD.order_id, NEW.order_id);
+                 {insert_changed_item_orders}
+             END;
+             CREATE TRIGGER IF NOT EXISTS order_items_fts_ad AFTER DELETE ON order_items BEGIN
+                 DELETE FROM orders_fts WHERE order_id = OLD.order_id;
+                 {insert_old_item_order}
+             END;
+             CREATE TRIGGER IF NOT EXISTS customers_fts_au AFTER UPDATE ON customers BEGIN
+                 DELETE FROM orders_fts WHERE order_id IN (SELECT id FROM orders WHERE customer_id = NEW.id);
+                 {insert_customer_orders}
+             END;",
+            insert_new_order = order_search_insert_sql("o.id = NEW.id"),
+            insert_item_order = order_search_insert_sql("o.id = NEW.order_id"),
+            insert_changed_item_orders =
+                order_search_insert_sql("o.id IN (OLD.order_id, NEW.order_id)"),
+            insert_old_item_order = order_search_insert_sql("o.id = OLD.order_id"),
+            insert_customer_orders = order_search_insert_sql("o.customer_id = NEW.id"),
+        ))?;
+
+        // 首次创建或索引与订单数不一致时重建
+        let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM orders_fts", [], |row| row.get(0))?;
+        let orders: i64 = conn.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))?;
+        if indexed != orders {
+            rebuild_order_search_index(conn)?;
+        }
+
+        Ok(())
+    }
+
+    fn init_product_search_index(conn: &Connection) -> Result<()> {
+        let created = conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS products_fts USING fts5(
+                product_id UNINDEXED,
+                name,
+                pinyin,
+                prefix = '1 2'
+            )",
+            [],
+        );
+        if let Err(e) = created {
+            // 旧版 SQLite 不支持 FTS5 时回退到 LIKE 搜索
+            log::warn!("商品全文索引不可用，将使用 LIKE 搜索: {}", e);
+            return Ok(());
+        }
+
+        // 只在名称、拼音码变化时更新索引，扣减库存等更新不触发
+        conn.execute_batch(
+            "CREATE TRIGGER IF NOT EXISTS products_fts_ai AFTER INSERT ON products BEGIN
+                 INSERT INTO products_fts (product_id, name, pinyin)
+                 VALUES (NEW.id, NEW.name, COALESCE(NEW.pinyin, ''));
+             END;
+             CREATE TRIGGER IF NOT EXISTS products_fts_au AFTER UPDATE OF id, name, pinyin ON products BEGIN
+                 DELETE FROM products_fts WHERE product_id = OLD.id;
+                 INSERT INTO products_fts (product_id, name, pinyin)
+                 VALUES (NEW.id, NEW.name, COALESCE(NEW.pinyin, ''));
+             END;
+             CREATE TRIGGER IF NOT EXISTS products_fts_ad AFTER DELETE ON products BEGIN
+                 DELETE FROM products_fts WHERE product_id = OLD.id;
+             END;",
+        )?;
+
+        // 首次创建（已有商品尚未建索引）或索引与商品数不一致时重建
+        let indexed: i64 = conn.query_row("SELECT COUNT(*) FROM products_fts", [], |row| row.get(0))?;
+        let products: i64 = conn.query_row("SELECT COUNT(*) FROM products", [], |row| row.get(0))?;
+        if indexed != products {
+            rebuild_product_search_index(conn)?;
+        }
+
+        Ok(())
+    }
+
+    /// 用备份文件的内容替换当前数据库（SQLite 在线备份接口反向复制，连接保持不变），
+    /// 替换后按当前程序版本补齐表结构、索引与全文索引。备份文件无效、缺少必需的表
+    /// 或来自更新版本的程序时拒绝恢复，当前数据保持不变。
+    /// 备份只包含数据库：附件文件在数据目录的 attachments 下，不随恢复回到备份时的状态，
+    /// 恢复后附件记录指向的文件可能已不存在（附件目录需另行备份）
+    pub fn restore_from(&self, backup_path: &Path, now: &str) -> Result<()> {
+        {
+            let mut conn = self.conn.lock().unwrap();
+            check_backup_file(backup_path)?;
+            conn.restore(DatabaseName::Main, backup_path, None::<fn(rusqlite::backup::Progress)>)
+                .context("Failed to restore database")?;
+        }
+        self.init_tables(now)
+    }
+
+    pub fn insert_default_data(&self, now: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let now = chrono::Utc::now().to_rfc3339();
 
         // 1. 检查并插入默认分类
         let category_count: i64 =
             conn.query_row("SELECT COUNT(*) FROM categories", [], |row| row.get(0))?;
         if category_count == 0 {
-            let categories = vec![
-                ("保养", 0),
-                ("配件", 1),
-                ("装饰", 2),
-                ("清洗", 3),
-                ("服务", 4),
-                ("其他", 5),
-            ];
-
-            for (name, sort_order) in categories {
-                let id = uuid::Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at) VALUES (?1, ?2, NULL, 0, ?2, ?3, ?4, ?4)",
-                    params![&id, name, &sort_order, &now],
-                )?;
+            for (name, sort_order) in DEFAULT_CATEGORIES {
+                insert_default_category(&conn, name, *sort_order, now)?;
             }
         }
 
@@ -327,31 +708,8 @@ impl Database {
         let unit_count: i64 =
             conn.query_row("SELECT COUNT(*) FROM unit_presets", [], |row| row.get(0))?;
         if unit_count == 0 {
-            let default_units = vec![
-                ("件", 0),
-                ("个", 1),
-                ("套", 2),
-                ("箱", 3),
-                ("包", 4),
-                ("瓶", 5),
-                ("盒", 6),
-                ("袋", 7),
-                ("桶", 8),
-                ("斤", 9),
-                ("公斤", 10),
-                ("克", 11),
-                ("升", 12),
-                ("毫升", 13),
-                ("米", 14),
-                ("厘米", 15),
-            ];
-
-            for (name, sort_order) in default_units {
-                let id = uuid::Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO unit_presets (id, name, sort_order, use_count, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?4)",
-                    params![&id, name, &sort_order, &now],
-                )?;
+            for (name, sort_order) in DEFAULT_UNITS {
+                insert_default_unit(&conn, name, *sort_order, now)?;
             }
         }
 
@@ -359,53 +717,7 @@ impl Database {
         let template_count: i64 =
             conn.query_row("SELECT COUNT(*) FROM templates", [], |row| row.get(0))?;
         if template_count == 0 {
-            let template_id = uuid::Uuid::new_v4().to_string();
-            let template_mappings = serde_json::json!({
-                "customerName": "C3",
-                "customerPhone": "E3",
-                "customerPlate": "B3",
-                "date": "G3",
-                "orderNumber": "G2",
-                "orderRemark": "G15",
-                "totalAmount": "",
-                "itemStartRow": 5,
-                "itemEndRow": 14,
-                "columns": {
-                    "name": "B",
-                    "unit": "C",
-                    "quantity": "D",
-                    "price": "E",
-                    "total": "F",
-                    "remark": "G"
-                }
-            });
-            let template_required_fields = serde_json::json!({
-                "customerName": false,
-                "customerPhone": false,
-                "customerPlate": true,
-                "date": true,
-                "orderNumber": true,
-                "orderRemark": false,
-                "totalAmount": false,
-                "itemName": true,
-                "itemUnit": false,
-                "itemQuantity": true,
-                "itemPrice": true,
-                "itemTotal": false,
-                "itemRemark": false
-            });
-
-            conn.execute(
-                "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at)
-                 VALUES (?1, ?2, '', 'template1.xlsx', '{date}_{customerName}_{orderNumber}', 1, ?3, 14, ?4, ?5, ?5)",
-                params![
-                    &template_id,
-                    "默认模板",
-                    &template_mappings.to_string(),
-                    &template_required_fields.to_string(),
-                    &now
-                ],
-            )?;
+            insert_default_template(&conn, true, now)?;
         }
 
         // 4. 检查并插入默认设置
@@ -435,17 +747,529 @@ impl Database {
                 "INSERT INTO app_settings (
                     id, data_directory, output_directory, backup_directory,
                     font_size, theme, remember_window, date_format, excel_date_format,
-                    order_number_format, order_number_prefix, order_number_reset_daily,
+                    order_number_format, order_number_prefix, order_number_reset_daily, order_number_reset_period,
                     order_number_digits, retain_days, auto_backup, backup_interval,
                     backup_keep_count, default_template_id, default_category_id,
                     excel_filename_format, auto_open_excel, skip_save_dialog,
-                    template_validation, updated_at
+                    template_validation, log_level, total_rounding, tax_rate, prices_include_tax, max_item_quantity, max_order_total, block_on_order_limits, export_item_sort, default_track_stock, default_min_stock, allow_zero_price, updated_at
                 ) VALUES (?1, '', '', '', 16, 'light', 1, 'YYYY-MM-DD', 'YYYY.MM.DD',
-                          'NO.{SEQ:6}', '', 1, 6, 0, 1, 7, 10, ?2, '', '{date}_{customerName}_{orderNumber}', 0, 0, '{}', ?3)",
-                params![settings_id, &default_template_id, &now],
+                          'NO.{SEQ:6}', '', 1, 'daily', 6, 0, 1, 7, 10, ?2, '', '{date}_{customerName}_{orderNumber}', 0, 0, '{}', 'info', 'none', 0, 1, 0, 0, 0, 'entry', 0, 0, 1, ?3)",
+                params![settings_id, &default_template_id, now],
             )?;
         }
 
         Ok(())
     }
 }
+
+fn insert_default_category(conn: &Connection, name: &str, sort_order: i32, now: &str) -> rusqlite::Result<()> {
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at) VALUES (?1, ?2, NULL, 0, ?2, ?3, ?4, ?4)",
+        params![&id, name, &sort_order, now],
+    )?;
+    Ok(())
+}
+
+fn insert_default_unit(conn: &Connection, name: &str, sort_order: i32, now: &str) -> rusqlite::Result<()> {
+    let id = uuid::Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO unit_presets (id, name, sort_order, use_count, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?4)",
+        params![&id, name, &sort_order, now],
+    )?;
+    Ok(())
+}
+
+/// 默认模板的单元格映射与必填字段（JSON 文本）
+fn default_template_config() -> (String, String) {
+    let template_mappings = serde_json::json!({
+        "customerName": "C3",
+        "customerPhone": "E3",
+        "customerPlate": "B3",
+        "date": "G3",
+        "orderNumber": "G2",
+        "orderRemark": "G15",
+        "totalAmount": "",
+        "itemStartRow": 5,
+        "itemEndRow": 14,
+        "columns": {
+            "name": "B",
+            "unit": "C",
+            "quantity": "D",
+            "price": "E",
+            "total": "F",
+            "remark": "G"
+        }
+    });
+    let template_required_fields = serde_json::json!({
+        "customerName": false,
+        "customerPhone": false,
+        "customerPlate": true,
+        "date": true,
+        "orderNumber": true,
+        "orderRemark": false,
+        "totalAmount": false,
+        "itemName": true,
+        "itemUnit": false,
+        "itemQuantity": true,
+        "itemPrice": true,
+        "itemTotal": false,
+        "itemRemark": false
+    });
+    (template_mappings.to_string(), template_required_fields.to_string())
+}
+
+fn insert_default_template(conn: &Connection, is_default: bool, now: &str) -> rusqlite::Result<()> {
+    let template_id = uuid::Uuid::new_v4().to_string();
+    let (mappings, required_fields) = default_template_config();
+    conn.execute(
+        "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at)
+         VALUES (?1, ?2, '', 'template1.xlsx', '{date}_{customerName}_{orderNumber}', ?3, ?4, 14, ?5, ?6, ?6)",
+        params![&template_id, DEFAULT_TEMPLATE_NAME, is_default, &mappings, &required_fields, now],
+    )?;
+    Ok(())
+}
+
+/// 按需恢复默认分类、单位预设与默认模板（按名称匹配）：缺失的重新插入，
+/// overwrite 为 true 时把已存在的同名项恢复为默认配置（分类与单位恢复排序，模板恢复映射与必填设置），
+/// 否则保持不变；整个过程在同一事务中完成
+pub fn restore_default_data(conn: &mut Connection, overwrite: bool, now: &str) -> rusqlite::Result<RestoredDefaults> {
+    let tx = conn.transaction()?;
+    let mut restored = RestoredDefaults::default();
+
+    for (name, sort_order) in DEFAULT_CATEGORIES {
+        let existing: Vec<String> = {
+            let mut stmt = tx.prepare("SELECT id FROM categories WHERE name = ?1")?;
+            let rows = stmt
+                .query_map(params![name], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        match existing.first() {
+            None => {
+                insert_default_category(&tx, name, *sort_order, now)?;
+                restored.categories_added.push(name.to_string());
+            }
+            Some(id) if overwrite => {
+                // 只恢复顶级分类的排序，已被移到其他分类下的保持原位（移动需同步下级路径）
+                let changed = tx.execute(
+                    "UPDATE categories SET sort_order = ?1, updated_at = ?2
+                     WHERE id = ?3 AND parent_id IS NULL AND sort_order <> ?1",
+                    params![sort_order, now, id],
+                )?;
+                restored.overwritten += changed;
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (name, sort_order) in DEFAULT_UNITS {
+        let exists: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM unit_presets WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        if exists == 0 {
+            insert_default_unit(&tx, name, *sort_order, now)?;
+            restored.units_added.push(name.to_string());
+        } else if overwrite {
+            restored.overwritten += tx.execute(
+                "UPDATE unit_presets SET sort_order = ?1, updated_at = ?2 WHERE name = ?3 AND sort_order <> ?1",
+                params![sort_order, now, name],
+            )?;
+        }
+    }
+
+    let template_id: Option<String> = match tx.query_row(
+        "SELECT id FROM templates WHERE name = ?1 ORDER BY created_at LIMIT 1",
+        params![DEFAULT_TEMPLATE_NAME],
+        |row| row.get::<_, String>(0),
+    ) {
+        Ok(id) => Some(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e),
+    };
+    match template_id {
+        None => {
+            let has_default: i64 =
+                tx.query_row("SELECT COUNT(*) FROM templates WHERE is_default = 1", [], |row| row.get(0))?;
+            insert_default_template(&tx, has_default == 0, now)?;
+            restored.template_added = true;
+        }
+        Some(id) if overwrite => {
+            let (mappings, required_fields) = default_template_config();
+            restored.overwritten += tx.execute(
+                "UPDATE templates SET mappings = ?1, required_fields = ?2, item_end_row = 14,
+                        filename_pattern = '{date}_{customerName}_{orderNumber}', updated_at = ?3
+                 WHERE id = ?4",
+                params![&mappings, &required_fields, now, &id],
+            )?;
+        }
+        Some(_) => {}
+    }
+
+    tx.commit()?;
+    Ok(restored)
+}
+
+/// 表的列名（按定义顺序）
+pub fn table_column_names(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+/// 回填尚未计算行金额的订单项（含归档订单项，仅处理为空的行，可重复执行），返回回填的行数
+pub fn backfill_line_totals(conn: &Connection) -> rusqlite::Result<usize> {
+    let mut count = 0;
+    for table in ["order_items", "order_items_archive"] {
+        // 与 money::line_total_cents 的计算方式一致：实际单价 × 数量，按分四舍五入
+        count += conn.execute(
+            &format!(
+                "UPDATE {} SET
+                    line_total_cents = CAST(ROUND(COALESCE(discount_price, price) * quantity * 100) AS INTEGER),
+                    line_total = ROUND(COALESCE(discount_price, price) * quantity * 100) / 100.0
+                 WHERE line_total_cents IS NULL",
+                table
+            ),
+            [],
+        )?;
+    }
+    Ok(count)
+}
+
+/// 外键约束开启前删除分类、模板留下的失效引用改为空（与 ON DELETE SET NULL 的结果一致），
+/// 否则修改这些记录时会因外键检查失败；返回修改的行数
+pub fn clear_dangling_references(conn: &Connection) -> rusqlite::Result<usize> {
+    let mut cleared = conn.execute(
+        "UPDATE products SET category_id = NULL
+         WHERE category_id IS NOT NULL AND category_id NOT IN (SELECT id FROM categories)",
+        [],
+    )?;
+    cleared += conn.execute(
+        "UPDATE categories SET parent_id = NULL
+         WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM categories)",
+        [],
+    )?;
+    cleared += conn.execute(
+        "UPDATE orders SET template_id = NULL
+         WHERE template_id IS NOT NULL AND template_id NOT IN (SELECT id FROM templates)",
+        [],
+    )?;
+    if cleared > 0 {
+        log::info!("已清除 {} 处指向已删除分类或模板的引用", cleared);
+    }
+    Ok(cleared)
+}
+
+/// 删除所属订单已不存在的订单项（外键约束开启前删除、归档订单时未级联清理），返回删除数量。
+/// 外键约束开启后不会再产生孤立订单项，启动时执行只在首次升级时有实际删除
+pub fn delete_orphaned_order_items(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM order_items WHERE order_id NOT IN (SELECT id FROM orders)",
+        [],
+    )
+}
+
+/// 检查备份文件能否恢复：是可读且未损坏的 SQLite 数据库、包含核心表、结构版本不高于当前程序
+fn check_backup_file(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        bail!("备份文件不存在: {}", path.display());
+    }
+    let backup = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("无法打开备份文件 {}", path.display()))?;
+
+    let tables: Vec<String> = backup
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("{} 不是有效的数据库文件", path.display()))?;
+    let missing: Vec<&str> = REQUIRED_BACKUP_TABLES
+        .iter()
+        .copied()
+        .filter(|table| !tables.iter().any(|t| t == table))
+        .collect();
+    if !missing.is_empty() {
+        bail!("备份文件缺少数据表：{}，不是 QuickSales 的数据库备份", missing.join("、"));
+    }
+
+    let check: String = backup.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        bail!("备份文件已损坏：{}", check);
+    }
+
+    if tables.iter().any(|t| t == "schema_version") {
+        let version = migrations::current_version(&backup)?;
+        if version > migrations::latest_version() {
+            bail!("备份文件来自更新版本的程序（结构版本 {}），请升级程序后再恢复", version);
+        }
+    }
+    Ok(())
+}
+
+/// 为没有分类快照的订单项（含归档订单项）补上商品当前的分类，商品已删除或未分类的保持为空；
+/// 仅处理为空的行，可重复执行，返回回填的行数
+pub fn backfill_item_categories(conn: &Connection) -> rusqlite::Result<usize> {
+    let mut count = 0;
+    for table in ["order_items", "order_items_archive"] {
+        count += conn.execute(
+            &format!(
+                "UPDATE {0} SET category = (SELECT p.category_id FROM products p WHERE p.id = {0}.product_id)
+                 WHERE COALESCE(category, '') = ''
+                   AND EXISTS (SELECT 1 FROM products p WHERE p.id = {0}.product_id AND COALESCE(p.category_id, '') <> '')",
+                table
+            ),
+            [],
+        )?;
+    }
+    Ok(count)
+}
+
+/// 生成写入订单全文索引的 SQL（按条件选取订单）
+fn order_search_insert_sql(condition: &str) -> String {
+    format!(
+        "INSERT INTO orders_fts (order_id, order_number, customer_name, customer_phone, customer_plate, item_names)
+         SELECT o.id, o.order_number, COALESCE(c.name, ''), COALESCE(c.phone, ''), COALESCE(c.license_plate, ''),
+                COALESCE((SELECT group_concat(i.name, ' ') FROM order_items i WHERE i.order_id = o.id), '')
+         FROM orders o
+         LEFT JOIN customers c ON c.id = o.customer_id
+         WHERE {};",
+        condition
+    )
+}
+
+/// 重建订单全文索引（批量导入后使用），返回索引的订单数
+pub fn rebuild_order_search_index(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM orders_fts", [])?;
+    conn.execute(order_search_insert_sql("1").trim_end_matches(';'), [])
+}
+
+/// 重建商品全文索引，返回索引的商品数
+pub fn rebuild_product_search_index(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM products_fts", [])?;
+    conn.execute(
+        "INSERT INTO products_fts (product_id, name, pinyin)
+         SELECT id, name, COALESCE(pinyin, '') FROM products",
+        [],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::schema::{OrderEventRepository, OrderRepository, Repository};
+    use crate::test_support::{insert_customer, insert_item, insert_order, insert_product, memory_db};
+
+    const NOW: &str = "2024-01-01T00:00:00+00:00";
+
+    fn temp_db_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("restore_{}.db", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn restore_brings_back_the_backed_up_state() {
+        let db = Database { conn: memory_db() };
+        {
+            let c = db.conn.lock().unwrap();
+            insert_product(&c, "p1", 10.0, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 2.0, None);
+        }
+        let backup = temp_db_path();
+        db.conn.lock().unwrap().backup(DatabaseName::Main, &backup, None).unwrap();
+
+        {
+            let c = db.conn.lock().unwrap();
+            c.execute("DELETE FROM orders WHERE id = 'o1'", []).unwrap();
+            c.execute("UPDATE products SET price = 99, price_cents = 9900 WHERE id = 'p1'", []).unwrap();
+            insert_customer(&c, "c2", "李四", "13900000000", "B67890");
+        }
+        db.restore_from(&backup, NOW).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+
+        let c = db.conn.lock().unwrap();
+        let count = |sql: &str| c.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM orders WHERE id = 'o1'"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items WHERE order_id = 'o1'"), 1);
+        assert_eq!(count("SELECT price_cents FROM products WHERE id = 'p1'"), 1000);
+        assert_eq!(count("SELECT COUNT(*) FROM customers WHERE id = 'c2'"), 0);
+        // 恢复后的数据库仍可正常读写（全文索引等随 init_tables 重建）
+        assert_eq!(count("SELECT COUNT(*) FROM orders_fts WHERE order_id = 'o1'"), 1);
+    }
+
+    #[test]
+    fn restore_rejects_a_backup_missing_required_tables() {
+        let db = Database { conn: memory_db() };
+        insert_product(&db.conn.lock().unwrap(), "p1", 10.0, None);
+
+        let backup = temp_db_path();
+        {
+            let other = Connection::open(&backup).unwrap();
+            other
+                .execute_batch(
+                    "CREATE TABLE products (id TEXT PRIMARY KEY);
+                     CREATE TABLE customers (id TEXT PRIMARY KEY);
+                     CREATE TABLE orders (id TEXT PRIMARY KEY);
+                     CREATE TABLE order_items (id TEXT PRIMARY KEY);",
+                )
+                .unwrap();
+        }
+        let error = db.restore_from(&backup, NOW).unwrap_err().to_string();
+        std::fs::remove_file(&backup).unwrap();
+
+        assert!(error.contains("categories"), "{}", error);
+        let c = db.conn.lock().unwrap();
+        let products: i64 = c.query_row("SELECT COUNT(*) FROM products", [], |row| row.get(0)).unwrap();
+        assert_eq!(products, 1);
+    }
+
+    #[test]
+    fn deleting_an_order_cascades_to_items_attachments_and_events() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            assert_eq!(c.query_row("PRAGMA foreign_keys", [], |row| row.get::<_, i64>(0)).unwrap(), 1);
+            insert_product(&c, "p1", 10.0, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            for id in ["o1", "o2", "old"] {
+                let date = if id == "old" { "2023-06-01" } else { "2024-01-05" };
+                insert_order(&c, id, "c1", date, "completed");
+                insert_item(&c, &format!("{}_i", id), id, "p1", 10.0, 1.0, None);
+                c.execute(
+                    "INSERT INTO order_attachments (id, order_id, file_path, file_name, kind, size_bytes, created_at)
+                     VALUES (?1, ?2, '', '签字单.pdf', 'document', 1, ?3)",
+                    params![format!("{}_a", id), id, NOW],
+                )
+                .unwrap();
+                OrderEventRepository::record_on(&c, id, "created", None, NOW).unwrap();
+            }
+            // 带附件的订单不归档，先去掉 old 的附件
+            c.execute("DELETE FROM order_attachments WHERE order_id = 'old'", []).unwrap();
+        }
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+
+        // 直接删除订单行时，订单项与附件记录由外键级联删除
+        conn.lock().unwrap().execute("DELETE FROM orders WHERE id = 'o1'", []).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM order_items WHERE order_id = 'o1'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_attachments WHERE order_id = 'o1'"), 0);
+
+        // 通过仓库删除时操作记录一并删除
+        let repo = OrderRepository::new(conn.clone());
+        repo.delete("o2").unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM order_items WHERE order_id = 'o2'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_attachments WHERE order_id = 'o2'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_events WHERE order_id = 'o2'"), 0);
+
+        // 归档会从订单表移走订单，但操作记录保留
+        assert_eq!(repo.archive_before("2024-01-01").unwrap(), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM order_events WHERE order_id = 'old'"), 1);
+    }
+
+    #[test]
+    fn money_backfill_rounds_float_artifacts_to_exact_cents_and_is_stable() {
+        let path = temp_db_path();
+        let path_str = path.to_string_lossy().to_string();
+        {
+            let db = Database::new(&path_str, NOW).unwrap();
+            let c = db.conn.lock().unwrap();
+            insert_product(&c, "p1", 19.99, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p1", 19.99, 1.0, None);
+            // 旧版本只写 REAL 列，长期累加后带有浮点误差
+            c.execute_batch(
+                "UPDATE products SET price = 19.990000000000002, price_cents = NULL;
+                 UPDATE orders SET total_amount = 0.30000000000000004, total_amount_cents = NULL;
+                 UPDATE order_items SET price = 12.340000000000001, price_cents = NULL,
+                                        discount_price = 9.95, discount_price_cents = NULL;",
+            )
+            .unwrap();
+        }
+
+        let cents = |db: &Database| {
+            db.conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT p.price_cents, o.total_amount_cents, i.price_cents, i.discount_price_cents
+                     FROM products p, orders o, order_items i",
+                    [],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)),
+                )
+                .unwrap()
+        };
+        let db = Database::new(&path_str, NOW).unwrap();
+        assert_eq!(cents(&db), (1999, 30, 1234, 995));
+        let order = OrderRepository::new(db.conn.clone()).get_by_id("o1").unwrap();
+        let item = OrderRepository::new(db.conn.clone()).get_order_items("o1").unwrap().remove(0);
+        assert_eq!((order.total_amount, item.price, item.discount_price), (0.3, 12.34, Some(9.95)));
+        drop(db);
+
+        // 再次打开不会重复换算
+        let db = Database::new(&path_str, NOW).unwrap();
+        assert_eq!(cents(&db), (1999, 30, 1234, 995));
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path_str, suffix));
+        }
+    }
+
+    #[test]
+    fn template_base64_migrates_to_identical_blob_bytes() {
+        use crate::database::schema::TemplateRepository;
+
+        // 含非 UTF-8 字节与 0 字节，确认按原始字节保存
+        let bytes: Vec<u8> = vec![0x50, 0x4b, 0x03, 0x04, 0x00, 0xff, 0xfe, 0x80, 0x0a, 0x00];
+        let path = temp_db_path();
+        let path_str = path.to_string_lossy().to_string();
+        {
+            let db = Database::new(&path_str, NOW).unwrap();
+            let c = db.conn.lock().unwrap();
+            // 旧版本把文件以 base64 文本存在 templates 行中
+            for (id, encoded) in [("t1", BASE64.encode(&bytes)), ("t2", "不是base64".to_string())] {
+                c.execute(
+                    "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, mappings, created_at, updated_at)
+                     VALUES (?1, ?1, ?2, 't.xlsx', '', '{}', ?3, ?3)",
+                    params![id, encoded, NOW],
+                )
+                .unwrap();
+            }
+        }
+
+        let db = Database::new(&path_str, NOW).unwrap();
+        {
+            let c = db.conn.lock().unwrap();
+            let stored: Vec<u8> =
+                c.query_row("SELECT data FROM template_files WHERE template_id = 't1'", [], |row| row.get(0)).unwrap();
+            assert_eq!(stored, bytes);
+            let texts: Vec<String> = {
+                let mut stmt = c.prepare("SELECT template_base64 FROM templates ORDER BY id").unwrap();
+                let rows = stmt.query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+                rows
+            };
+            // 迁移成功的行清空文本，无法解码的保留原样
+            assert_eq!(texts, vec!["".to_string(), "不是base64".to_string()]);
+            let files: i64 = c.query_row("SELECT COUNT(*) FROM template_files", [], |row| row.get(0)).unwrap();
+            assert_eq!(files, 1);
+        }
+        // 读取模板时在接口边界重新编码为 base64
+        let template = TemplateRepository::new(db.conn.clone()).get_by_id("t1").unwrap();
+        assert_eq!(BASE64.decode(&template.template_base64).unwrap(), bytes);
+        drop(db);
+
+        // 再次打开不会重复迁移
+        let db = Database::new(&path_str, NOW).unwrap();
+        let files: i64 =
+            db.conn.lock().unwrap().query_row("SELECT COUNT(*) FROM template_files", [], |row| row.get(0)).unwrap();
+        assert_eq!(files, 1);
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path_str, suffix));
+        }
+    }
+}
@@ -0,0 +1,282 @@
+// 数据库结构迁移：按版本号顺序执行，每一步在独立事务中完成并记录到 schema_version 表。
+// 新安装的数据库由 init_tables 直接建出当前结构，迁移只为旧数据库补齐缺失的列；
+// 旧版本不记录版本号，首次升级时会从第 1 步开始执行，已存在的列会被跳过。
+// 以后修改表结构时在 MIGRATIONS 末尾追加新的步骤，不要修改已发布的步骤。
+
+use crate::database::table_column_names;
+use rusqlite::{params, Connection, Result};
+
+/// 一个迁移步骤
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> Result<()>,
+}
+
+/// 全部迁移步骤，版本号从 1 开始连续递增
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "商品库存列",
+        up: |conn| {
+            add_column(conn, "products", "stock", "REAL")?;
+            add_column(conn, "products", "min_stock", "REAL")?;
+            add_column(conn, "products", "track_stock", "INTEGER DEFAULT 0")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 2,
+        description: "金额整数分列",
+        up: |conn| {
+            add_column(conn, "products", "price_cents", "INTEGER")?;
+            add_column(conn, "orders", "total_amount_cents", "INTEGER")?;
+            add_column(conn, "order_items", "price_cents", "INTEGER")?;
+            add_column(conn, "order_items", "discount_price_cents", "INTEGER")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 3,
+        description: "订单挂账、抹零、外币与整单优惠",
+        up: |conn| {
+            add_column(conn, "orders", "on_account", "INTEGER DEFAULT 0")?;
+            add_column(conn, "orders", "rounding_adjustment", "REAL DEFAULT 0")?;
+            add_column(conn, "orders", "rounding_adjustment_cents", "INTEGER DEFAULT 0")?;
+            add_column(conn, "orders", "currency", "TEXT")?;
+            add_column(conn, "orders", "exchange_rate", "REAL DEFAULT 1")?;
+            add_column(conn, "orders", "order_discount", "REAL DEFAULT 0")?;
+            add_column(conn, "orders", "order_discount_cents", "INTEGER DEFAULT 0")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 4,
+        description: "订单导出次数与最后导出时间",
+        up: |conn| {
+            add_column(conn, "orders", "export_count", "INTEGER DEFAULT 0")?;
+            add_column(conn, "orders", "last_exported_at", "TEXT")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 5,
+        description: "订单项分类快照与行金额",
+        up: |conn| {
+            add_column(conn, "order_items", "category", "TEXT")?;
+            add_column(conn, "order_items", "line_total", "REAL")?;
+            add_column(conn, "order_items", "line_total_cents", "INTEGER")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 6,
+        description: "设置：默认模板、默认分类与导出选项",
+        up: |conn| {
+            add_column(conn, "app_settings", "default_template_id", "TEXT")?;
+            add_column(conn, "app_settings", "default_category_id", "TEXT")?;
+            add_column(conn, "app_settings", "template_validation", "TEXT")?;
+            add_column(
+                conn,
+                "app_settings",
+                "excel_filename_format",
+                "TEXT DEFAULT '{date}_{customerName}_{orderNumber}'",
+            )?;
+            add_column(conn, "app_settings", "auto_open_excel", "INTEGER DEFAULT 0")?;
+            add_column(conn, "app_settings", "skip_save_dialog", "INTEGER DEFAULT 0")?;
+            add_column(conn, "app_settings", "log_level", "TEXT DEFAULT 'info'")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 7,
+        description: "设置：抹零、税率与订单上限",
+        up: |conn| {
+            add_column(conn, "app_settings", "total_rounding", "TEXT DEFAULT 'none'")?;
+            add_column(conn, "app_settings", "tax_rate", "REAL DEFAULT 0")?;
+            add_column(conn, "app_settings", "prices_include_tax", "INTEGER DEFAULT 1")?;
+            add_column(conn, "app_settings", "max_item_quantity", "REAL DEFAULT 0")?;
+            add_column(conn, "app_settings", "max_order_total", "REAL DEFAULT 0")?;
+            add_column(conn, "app_settings", "block_on_order_limits", "INTEGER DEFAULT 0")?;
+            add_column(conn, "app_settings", "export_item_sort", "TEXT DEFAULT 'entry'")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 8,
+        description: "设置：新商品默认值与库存选项",
+        up: |conn| {
+            add_column(conn, "app_settings", "default_track_stock", "INTEGER DEFAULT 0")?;
+            add_column(conn, "app_settings", "default_min_stock", "REAL DEFAULT 0")?;
+            add_column(conn, "app_settings", "allow_zero_price", "INTEGER DEFAULT 1")?;
+            add_column(conn, "app_settings", "strict_stock", "INTEGER DEFAULT 0")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 9,
+        description: "设置：订单号序号重置周期",
+        up: |conn| {
+            // 序号重置周期取代 order_number_reset_daily：新增列后按原开关填入 daily / never
+            if add_column(conn, "app_settings", "order_number_reset_period", "TEXT")? {
+                conn.execute(
+                    "UPDATE app_settings
+                     SET order_number_reset_period = CASE WHEN COALESCE(order_number_reset_daily, 1) <> 0 THEN 'daily' ELSE 'never' END",
+                    [],
+                )?;
+            }
+            Ok(())
+        },
+    },
+    Migration {
+        version: 10,
+        description: "模板必填字段、明细结束行与独立单号格式",
+        up: |conn| {
+            add_column(conn, "templates", "required_fields", "TEXT NOT NULL DEFAULT '{}'")?;
+            add_column(conn, "templates", "item_end_row", "INTEGER DEFAULT 0")?;
+            add_column(conn, "templates", "number_format", "TEXT")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 11,
+        description: "商品条码",
+        up: |conn| {
+            add_column(conn, "products", "barcode", "TEXT")?;
+            Ok(())
+        },
+    },
+    Migration {
+        version: 12,
+        description: "订单计税快照",
+        up: |conn| {
+            // 旧订单保存时的税率设置无从得知，保持为空
+            add_column(conn, "orders", "tax_rate", "REAL")?;
+            add_column(conn, "orders", "prices_include_tax", "INTEGER")?;
+            Ok(())
+        },
+    },
+];
+
+/// 最新的结构版本
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// 当前数据库已执行到的版本，未执行过任何迁移时为 0
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+/// 依次执行尚未执行的迁移，返回本次执行的步骤数。某一步失败时该步整体回滚并返回错误，
+/// 已完成的步骤保留，下次启动从失败的步骤继续
+pub fn run_migrations(conn: &mut Connection, now: &str) -> Result<usize> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        log::warn!(
+            "数据库结构版本 {} 高于当前程序支持的版本 {}，可能由更新版本的程序创建",
+            current,
+            latest_version()
+        );
+        return Ok(0);
+    }
+
+    let mut applied = 0;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![migration.version, migration.description, now],
+        )?;
+        tx.commit()?;
+        log::info!("数据库已升级到版本 {}：{}", migration.version, migration.description);
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// 列不存在时添加，返回是否实际添加（已存在的列跳过，其他错误照常返回）
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<bool> {
+    if table_column_names(conn, table)?.iter().any(|c| c == column) {
+        return Ok(false);
+    }
+    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 不记录版本号的旧数据库：只有最初的列，另有部分后来的列已由旧版本的 ALTER 补上
+    fn legacy_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE products (
+                id TEXT PRIMARY KEY, name TEXT NOT NULL, unit TEXT NOT NULL, price REAL NOT NULL,
+                category_id TEXT, pinyin TEXT, stock REAL, created_at TEXT NOT NULL, updated_at TEXT NOT NULL
+            );
+            CREATE TABLE orders (
+                id TEXT PRIMARY KEY, order_number TEXT UNIQUE NOT NULL, date TEXT NOT NULL,
+                customer_id TEXT NOT NULL, total_amount REAL NOT NULL, remark TEXT, template_id TEXT,
+                status TEXT NOT NULL, created_at TEXT NOT NULL, updated_at TEXT NOT NULL
+            );
+            CREATE TABLE order_items (
+                id TEXT PRIMARY KEY, order_id TEXT NOT NULL, product_id TEXT, name TEXT NOT NULL,
+                unit TEXT NOT NULL, price REAL NOT NULL, quantity REAL NOT NULL, discount_price REAL,
+                remark TEXT, sort_value INTEGER DEFAULT 0
+            );
+            CREATE TABLE app_settings (
+                id TEXT PRIMARY KEY, order_number_format TEXT, order_number_reset_daily INTEGER DEFAULT 1,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE templates (
+                id TEXT PRIMARY KEY, name TEXT NOT NULL, template_base64 TEXT NOT NULL, file_name TEXT NOT NULL,
+                filename_pattern TEXT NOT NULL, is_default INTEGER DEFAULT 0, mappings TEXT NOT NULL,
+                created_at TEXT NOT NULL, updated_at TEXT NOT NULL
+            );
+            INSERT INTO app_settings (id, order_number_format, order_number_reset_daily, updated_at)
+            VALUES ('settings', 'NO.{SEQ:6}', 0, 'now');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn upgrades_a_pre_versioned_database_and_is_idempotent() {
+        let mut conn = legacy_db();
+
+        let applied = run_migrations(&mut conn, "2024-01-01T00:00:00+00:00").unwrap();
+        assert_eq!(applied, MIGRATIONS.len());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        // 已存在的 stock 列被跳过，缺失的列全部补齐
+        let products = table_column_names(&conn, "products").unwrap();
+        for column in ["stock", "min_stock", "track_stock", "price_cents", "barcode"] {
+            assert!(products.contains(&column.to_string()), "products 缺少 {}", column);
+        }
+        let items = table_column_names(&conn, "order_items").unwrap();
+        assert!(items.contains(&"line_total_cents".to_string()));
+        let period: String = conn
+            .query_row("SELECT order_number_reset_period FROM app_settings", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(period, "never");
+
+        // 再次执行不做任何改动
+        assert_eq!(run_migrations(&mut conn, "2024-02-01T00:00:00+00:00").unwrap(), 0);
+        let recorded: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(recorded, MIGRATIONS.len() as i64);
+    }
+}
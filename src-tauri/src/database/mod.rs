@@ -1,5 +1,10 @@
 pub mod connection;
+pub mod migrations;
+pub mod order_locks;
 pub mod schema;
+pub mod settings_cache;
 
 pub use connection::*;
+pub use order_locks::OrderLocks;
+pub use settings_cache::SettingsCache;
 // schema exports are used via explicit imports in commands
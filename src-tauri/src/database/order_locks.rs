@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// 按订单 ID 串行化保存（由 Tauri 托管）：同一订单的连续保存（如双击保存）依次执行，
+/// 避免读-改-写交错导致改动丢失或重复扣库存；不同订单互不影响
+#[derive(Default)]
+pub struct OrderLocks {
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+/// 订单锁守卫，离开作用域（含出错提前返回）时释放
+pub struct OrderLockGuard<'a> {
+    owner: &'a OrderLocks,
+    order_id: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl OrderLocks {
+    /// 获取订单锁，同一订单已被占用时等待其释放
+    pub async fn lock(&self, order_id: &str) -> OrderLockGuard<'_> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(order_id.to_string())
+            .or_default()
+            .clone();
+        OrderLockGuard {
+            owner: self,
+            order_id: order_id.to_string(),
+            _guard: lock.lock_owned().await,
+        }
+    }
+}
+
+impl Drop for OrderLockGuard<'_> {
+    fn drop(&mut self) {
+        // 没有其他等待者时（仅剩表中与本守卫的引用）移除条目，避免锁表随订单数增长
+        let mut locks = self.owner.locks.lock().unwrap();
+        if locks.get(&self.order_id).is_some_and(|lock| Arc::strong_count(lock) <= 2) {
+            locks.remove(&self.order_id);
+        }
+    }
+}
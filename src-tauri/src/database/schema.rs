@@ -1,11 +1,15 @@
-use crate::database::DbConnection;
+use crate::database::{delete_orphaned_order_items, rebuild_order_search_index, table_column_names, DbConnection};
 use crate::models::{
-    AppSettings, Category, Customer, Order, OrderItem, Product, RemarkPreset, RequiredFields,
-    TemplateConfig, TemplateMappings, UnitPreset,
+    AppSettings, Category, CategoryCrumb, CategoryImportResult, CategorySales, Customer, CustomerPage, CustomerBalance, CustomerResolution, CustomerSales, CustomerTransaction, DailySales, DeadStockItem, DuplicateOrderNumber, LowStockAlert, MovementReasonTotal,
+    Order, OrderAttachment, OrderEvent, OrderExportInfo, OrderItem, OrderPage, OrphanedOrderItems, PriceAnomaly, Product, ProductPage, ProductSales, RemarkPreset, ReorderSuggestion, ReportBundle, RequiredFields,
+    SalesTotal, SettingsReferenceCheck, StockMovement, StockMovementSummary, StocktakeItem, TemplateConfig, TemplateMappings, UnitPreset,
 };
+use crate::utils::money::{from_cents, to_cents};
+use crate::utils::order_status::holds_stock;
 
-use chrono::{NaiveDate, Utc};
-use rusqlite::{params, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::NaiveDate;
+use rusqlite::{params, params_from_iter, types::Value, OptionalExtension, Result};
 use serde_json;
 
 // ========== Repository Trait ==========
@@ -18,6 +22,28 @@ pub trait Repository<T> {
     fn delete(&self, id: &str) -> Result<()>;
 }
 
+/// 写入事务中的失败：数据校验不通过时为给用户看的说明（事务随之回滚），其余为数据库错误，由调用方处理（如单号冲突时重试）
+#[derive(Debug)]
+pub enum WriteError {
+    Rejected(String),
+    Database(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for WriteError {
+    fn from(e: rusqlite::Error) -> Self {
+        WriteError::Database(e)
+    }
+}
+
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::Rejected(message) => write!(f, "{}", message),
+            WriteError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
 // ========== Product Repository ==========
 
 pub struct ProductRepository {
@@ -29,33 +55,100 @@ impl ProductRepository {
         Self { conn }
     }
 
-    pub fn search(&self, query: &str) -> Result<Vec<Product>> {
+    /// 在调用方的连接或事务上插入商品，以便批量导入在同一事务中完成
+    pub fn insert_on(conn: &rusqlite::Connection, product: &Product) -> Result<()> {
+        conn.execute(
+            "INSERT INTO products (id, name, unit, price, price_cents, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                &product.id,
+                &product.name,
+                &product.unit,
+                &product.price,
+                &to_cents(product.price),
+                &category_ref(&product.category_id),
+                &product.pinyin,
+                &product.stock,
+                &product.min_stock,
+                &product.track_stock.map(|v| if v { 1 } else { 0 }),
+                &product.created_at,
+                &product.updated_at,
+                &product.barcode,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// 在调用方的连接或事务上更新商品
+    pub fn update_on(conn: &rusqlite::Connection, product: &Product) -> Result<()> {
+        conn.execute(
+            "UPDATE products SET name = ?1, unit = ?2, price = ?3, price_cents = ?4, category_id = ?5,
+             pinyin = ?6, stock = ?7, min_stock = ?8, track_stock = ?9, updated_at = ?10, barcode = ?12 WHERE id = ?11",
+            params![
+                &product.name,
+                &product.unit,
+                &product.price,
+                &to_cents(product.price),
+                &category_ref(&product.category_id),
+                &product.pinyin,
+                &product.stock,
+                &product.min_stock,
+                &product.track_stock.map(|v| if v { 1 } else { 0 }),
+                &product.updated_at,
+                &product.id,
+                &product.barcode,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// 按范围（name/pinyin/all）搜索商品：名称、条码、分类名按原搜索词匹配，拼音码按 pinyin_query（小写拼音、无空格，为空时不匹配）匹配
+    pub fn search(&self, query: &str, pinyin_query: &str, scope: &str) -> Result<Vec<Product>> {
         let conn = self.conn.lock().unwrap();
         let pattern = format!("%{}%", query);
-
-        let mut stmt = conn.prepare(
-            "SELECT id, name, unit, price, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at
+        let pinyin_pattern = if pinyin_query.is_empty() {
+            Value::Null
+        } else {
+            Value::Text(format!("%{}%", pinyin_query))
+        };
+
+        let filter = match scope {
+            "name" => "name LIKE ?1",
+            "pinyin" => "pinyin LIKE ?2",
+            _ => "(name LIKE ?1 OR pinyin LIKE ?2 OR barcode LIKE ?1 OR category_id IN (
+                     SELECT id FROM categories WHERE name LIKE ?1
+                 ))",
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
              FROM products
-             WHERE name LIKE ?1 OR pinyin LIKE ?2 OR id IN (
-                 SELECT category_id FROM categories WHERE name LIKE ?1
-             )
-             ORDER BY name"
-        )?;
+             WHERE {}
+             ORDER BY name",
+            filter
+        ))?;
+        // 按名称搜索时语句中没有 ?2，只绑定一个参数
+        let values = match scope {
+            "name" => vec![Value::Text(pattern)],
+            _ => vec![Value::Text(pattern), pinyin_pattern],
+        };
 
         let products = stmt
-            .query_map(params![pattern, pattern], |row: &rusqlite::Row| {
+            .query_map(params_from_iter(values.iter()), |row: &rusqlite::Row| {
                 Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
                     unit: row.get::<_, String>(2)?,
                     price: row.get::<_, f64>(3)?,
-                    category_id: row.get::<_, String>(4)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
                     pinyin: row.get::<_, Option<String>>(5)?,
                     stock: row.get::<_, Option<f64>>(6)?,
                     min_stock: row.get::<_, Option<f64>>(7)?,
                     track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
                     created_at: row.get::<_, String>(9)?,
                     updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -63,30 +156,56 @@ impl ProductRepository {
         Ok(products)
     }
 
-    pub fn get_by_category(&self, category_id: &str) -> Result<Vec<Product>> {
-        let conn = self.conn.lock().unwrap();
+    /// 通过全文索引搜索商品：名称按原搜索词做短语前缀匹配，拼音码按 pinyin_query 做前缀匹配（首字母或全拼），
+    /// 按相关度排序。搜索词为空时返回全部商品，索引不可用时回退到 LIKE 搜索
+    pub fn search_fts(&self, query: &str, pinyin_query: &str) -> Result<Vec<Product>> {
+        if query.is_empty() {
+            return self.get_all();
+        }
+
+        let fts_result = {
+            let conn = self.conn.lock().unwrap();
+            Self::search_fts_on(&conn, query, pinyin_query)
+        };
+        match fts_result {
+            Ok(products) => Ok(products),
+            Err(e) => {
+                log::debug!("商品全文搜索失败，回退到 LIKE 搜索: {}", e);
+                self.search(query, pinyin_query, "all")
+            }
+        }
+    }
+
+    fn search_fts_on(conn: &rusqlite::Connection, query: &str, pinyin_query: &str) -> Result<Vec<Product>> {
+        // 搜索词整体作为短语，避免用户输入被解析为 FTS 语法
+        let mut expr = format!("name : \"{}\"*", query.replace('"', "\"\""));
+        if !pinyin_query.is_empty() {
+            expr.push_str(&format!(" OR pinyin : \"{}\"*", pinyin_query));
+        }
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, unit, price, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at
-             FROM products
-             WHERE category_id = ?1
-             ORDER BY name"
+            "SELECT p.id, p.name, p.unit, COALESCE(p.price_cents / 100.0, p.price), p.category_id, p.pinyin, p.stock, p.min_stock, p.track_stock, p.created_at, p.updated_at, p.barcode
+             FROM products_fts f
+             JOIN products p ON p.id = f.product_id
+             WHERE products_fts MATCH ?1
+             ORDER BY f.rank, p.name",
         )?;
 
         let products = stmt
-            .query_map(params![category_id], |row: &rusqlite::Row| {
+            .query_map(params![expr], |row: &rusqlite::Row| {
                 Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
                     unit: row.get::<_, String>(2)?,
                     price: row.get::<_, f64>(3)?,
-                    category_id: row.get::<_, String>(4)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
                     pinyin: row.get::<_, Option<String>>(5)?,
                     stock: row.get::<_, Option<f64>>(6)?,
                     min_stock: row.get::<_, Option<f64>>(7)?,
                     track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
                     created_at: row.get::<_, String>(9)?,
                     updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -94,899 +213,3670 @@ impl ProductRepository {
         Ok(products)
     }
 
-    /// 扣减库存
-    pub fn deduct_stock(&self, product_id: &str, quantity: f64) -> Result<()> {
+    /// 全部商品及其分类名称（分类已不存在时为空），按名称排序，用于导出
+    pub fn get_all_with_category_names(&self) -> Result<Vec<(Product, String)>> {
         let conn = self.conn.lock().unwrap();
-
-        // 只扣减启用了库存跟踪的商品
-        conn.execute(
-            "UPDATE products
-             SET stock = CASE WHEN track_stock = 1 AND stock IS NOT NULL THEN MAX(0, stock - ?1) ELSE stock END,
-                 updated_at = ?2
-             WHERE id = ?3 AND track_stock = 1",
-            params![
-                quantity,
-                Utc::now().to_rfc3339(),
-                product_id,
-            ],
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.name, p.unit, COALESCE(p.price_cents / 100.0, p.price), p.category_id, p.pinyin, p.stock, p.min_stock, p.track_stock, p.created_at, p.updated_at, p.barcode,
+                    COALESCE(c.name, '')
+             FROM products p
+             LEFT JOIN categories c ON c.id = p.category_id
+             ORDER BY p.name",
         )?;
 
-        Ok(())
+        let products = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok((
+                    Product {
+                        id: row.get::<_, String>(0)?,
+                        name: row.get::<_, String>(1)?,
+                        unit: row.get::<_, String>(2)?,
+                        price: row.get::<_, f64>(3)?,
+                        category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                        pinyin: row.get::<_, Option<String>>(5)?,
+                        stock: row.get::<_, Option<f64>>(6)?,
+                        min_stock: row.get::<_, Option<f64>>(7)?,
+                        track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                        created_at: row.get::<_, String>(9)?,
+                        updated_at: row.get::<_, String>(10)?,
+                        barcode: row.get::<_, Option<String>>(11)?,
+                    },
+                    row.get::<_, String>(12)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(products)
     }
 
-    /// 批量扣减库存
-    pub fn deduct_stock_batch(&self, items: &[(String, f64)]) -> Result<()> {
-        for (product_id, quantity) in items {
-            self.deduct_stock(product_id, *quantity)?;
+    /// 返回价格偏离同分类其他商品均价超过 z_threshold 个标准差的商品（疑似录入错误）。
+    /// 每个商品只与同分类的其他商品比较（留一法），异常价格不会拉高自身的比较基准；
+    /// 其他商品价格完全相同时按 1 分计标准差。商品少于 3 个的分类不参与统计
+    pub fn find_price_anomalies(&self, z_threshold: f64) -> Result<Vec<PriceAnomaly>> {
+        const MIN_CATEGORY_SIZE: usize = 3;
+        const MIN_STD_DEV: f64 = 0.01;
+
+        let mut by_category: std::collections::BTreeMap<String, Vec<Product>> = std::collections::BTreeMap::new();
+        for product in self.get_all()? {
+            by_category.entry(product.category_id.clone()).or_default().push(product);
         }
-        Ok(())
+
+        let mut anomalies = Vec::new();
+        for products in by_category.into_values() {
+            if products.len() < MIN_CATEGORY_SIZE {
+                continue;
+            }
+
+            let others_count = (products.len() - 1) as f64;
+            let sum: f64 = products.iter().map(|p| p.price).sum();
+            let sum_sq: f64 = products.iter().map(|p| p.price * p.price).sum();
+            for product in products {
+                let mean = (sum - product.price) / others_count;
+                let variance = ((sum_sq - product.price * product.price) / others_count - mean * mean).max(0.0);
+                let std_dev = variance.sqrt().max(MIN_STD_DEV);
+                let z_score = (product.price - mean) / std_dev;
+                if z_score.abs() > z_threshold {
+                    anomalies.push(PriceAnomaly {
+                        price: product.price,
+                        category_mean: from_cents(to_cents(mean)),
+                        z_score,
+                        product,
+                    });
+                }
+            }
+        }
+
+        anomalies.sort_by(|a, b| b.z_score.abs().total_cmp(&a.z_score.abs()));
+        Ok(anomalies)
     }
-}
 
-impl Repository<Product> for ProductRepository {
-    fn get_all(&self) -> Result<Vec<Product>> {
+    /// 滞销库存：有库存的跟踪商品中，截至 today（含当天）的 days 天内没有已完成订单售出的，
+    /// 按占用资金（库存 × 零售价）从高到低排序
+    pub fn get_dead_stock(&self, days: u32, today: NaiveDate) -> Result<Vec<DeadStockItem>> {
         let conn = self.conn.lock().unwrap();
+        let from = today - chrono::Duration::days(days.max(1) as i64 - 1);
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, unit, price, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at
-             FROM products
-             ORDER BY name"
+            "SELECT p.id, p.name, p.unit, COALESCE(p.price_cents / 100.0, p.price), p.category_id, p.pinyin,
+                    p.stock, p.min_stock, p.track_stock, p.created_at, p.updated_at, p.barcode, s.last_sold
+             FROM products p
+             LEFT JOIN (
+                 SELECT i.product_id, MAX(substr(o.date, 1, 10)) AS last_sold
+                 FROM order_items i
+                 JOIN orders o ON o.id = i.order_id
+                 WHERE o.status = 'completed'
+                 GROUP BY i.product_id
+             ) s ON s.product_id = p.id
+             WHERE p.track_stock = 1 AND p.stock > 0
+               AND (s.last_sold IS NULL OR s.last_sold < ?1)",
         )?;
 
-        let products = stmt
-            .query_map([], |row: &rusqlite::Row| {
-                Ok(Product {
+        let mut items = stmt
+            .query_map(params![from.format("%Y-%m-%d").to_string()], |row: &rusqlite::Row| {
+                let product = Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
                     unit: row.get::<_, String>(2)?,
                     price: row.get::<_, f64>(3)?,
-                    category_id: row.get::<_, String>(4)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
                     pinyin: row.get::<_, Option<String>>(5)?,
                     stock: row.get::<_, Option<f64>>(6)?,
                     min_stock: row.get::<_, Option<f64>>(7)?,
                     track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
                     created_at: row.get::<_, String>(9)?,
                     updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
+                };
+                let tied_up_value = from_cents((to_cents(product.price) as f64 * product.stock.unwrap_or(0.0)).round() as i64);
+                Ok(DeadStockItem {
+                    product,
+                    last_sold_date: row.get::<_, Option<String>>(12)?,
+                    tied_up_value,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(products)
+        items.sort_by(|a, b| {
+            b.tied_up_value
+                .partial_cmp(&a.tied_up_value)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.product.name.cmp(&b.product.name))
+        });
+        Ok(items)
     }
 
-    fn get_by_id(&self, id: &str) -> Result<Product> {
+    /// 盘点清单：跟踪库存的商品，可限定为某分类及其全部子分类，按分类、商品名称排序
+    pub fn get_stocktake_items(&self, category_id: Option<&str>) -> Result<Vec<StocktakeItem>> {
         let conn = self.conn.lock().unwrap();
 
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE scope(id) AS (
+                 SELECT id FROM categories WHERE id = ?1
+                 UNION
+                 SELECT c.id FROM categories c JOIN scope s ON c.parent_id = s.id
+             )
+             SELECT p.id, p.name, p.unit, COALESCE(c.name, ''), p.stock
+             FROM products p
+             LEFT JOIN categories c ON c.id = p.category_id
+             WHERE p.track_stock = 1
+               AND (?1 IS NULL OR p.category_id IN (SELECT id FROM scope))
+             ORDER BY COALESCE(c.name, ''), p.name",
+        )?;
+
+        let items = stmt
+            .query_map(params![category_id], |row: &rusqlite::Row| {
+                Ok(StocktakeItem {
+                    product_id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    unit: row.get::<_, String>(2)?,
+                    category_name: row.get::<_, String>(3)?,
+                    stock: row.get::<_, Option<f64>>(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    /// 库存不足的商品：demands 为 (商品ID, 需要扣减的数量)，返回 (商品名称, 需要数量, 可用库存)。
+    /// 只检查启用了库存跟踪且已设置库存的商品，同一商品出现多次时数量累加。传入调用方的事务，以便检查与扣减之间库存不被改动
+    pub fn get_stock_shortages_on(conn: &rusqlite::Connection, demands: &[(String, f64)]) -> Result<Vec<(String, f64, f64)>> {
+        let mut totals: Vec<(&str, f64)> = Vec::new();
+        for (product_id, quantity) in demands {
+            match totals.iter_mut().find(|(id, _)| id == product_id) {
+                Some((_, total)) => *total += quantity,
+                None => totals.push((product_id, *quantity)),
+            }
+        }
+
+        let mut shortages = Vec::new();
+        for (product_id, requested) in totals {
+            if requested <= 0.0 {
+                continue;
+            }
+            let available: Option<(String, f64)> = conn
+                .query_row(
+                    "SELECT name, stock FROM products WHERE id = ?1 AND track_stock = 1 AND stock IS NOT NULL",
+                    params![product_id],
+                    |row: &rusqlite::Row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            if let Some((name, stock)) = available {
+                if requested > stock + 1e-9 {
+                    shortages.push((name, requested, stock));
+                }
+            }
+        }
+        Ok(shortages)
+    }
+
+    /// 按条码查找商品（完全匹配），不存在时返回 QueryReturnedNoRows
+    pub fn get_by_barcode(&self, barcode: &str) -> Result<Product> {
+        let conn = self.conn.lock().unwrap();
         conn.query_row(
-            "SELECT id, name, unit, price, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at
-             FROM products WHERE id = ?1",
-            params![id],
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products WHERE barcode = ?1
+             ORDER BY updated_at DESC LIMIT 1",
+            params![barcode],
             |row: &rusqlite::Row| {
                 Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
                     unit: row.get::<_, String>(2)?,
                     price: row.get::<_, f64>(3)?,
-                    category_id: row.get::<_, String>(4)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
                     pinyin: row.get::<_, Option<String>>(5)?,
                     stock: row.get::<_, Option<f64>>(6)?,
                     min_stock: row.get::<_, Option<f64>>(7)?,
                     track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
                     created_at: row.get::<_, String>(9)?,
                     updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             },
         )
     }
 
-    fn insert(&self, product: &Product) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-
-        conn.execute(
-            "INSERT INTO products (id, name, unit, price, category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            params![
-                &product.id,
-                &product.name,
-                &product.unit,
-                &product.price,
-                &product.category_id,
-                &product.pinyin,
-                &product.stock,
-                &product.min_stock,
-                &product.track_stock.map(|v| if v { 1 } else { 0 }),
-                &product.created_at,
-                &product.updated_at,
-            ],
-        )?;
-
-        Ok(())
-    }
-
-    fn update(&self, product: &Product) -> Result<()> {
+    /// 查询需要补货的商品：跟踪库存且已缺货或库存不高于最低库存
+    pub fn get_low_stock(&self) -> Result<Vec<Product>> {
         let conn = self.conn.lock().unwrap();
 
-        conn.execute(
-            "UPDATE products SET name = ?1, unit = ?2, price = ?3, category_id = ?4,
-             pinyin = ?5, stock = ?6, min_stock = ?7, track_stock = ?8, updated_at = ?9 WHERE id = ?10",
-            params![
-                &product.name,
-                &product.unit,
-                &product.price,
-                &product.category_id,
-                &product.pinyin,
-                &product.stock,
-                &product.min_stock,
-                &product.track_stock.map(|v| if v { 1 } else { 0 }),
-                &product.updated_at,
-                &product.id,
-            ],
+        let mut stmt = conn.prepare(
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products
+             WHERE track_stock = 1 AND stock IS NOT NULL
+               AND (stock <= 0 OR (min_stock IS NOT NULL AND stock <= min_stock))
+             ORDER BY stock, name"
         )?;
 
-        Ok(())
-    }
-
-    fn delete(&self, id: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM products WHERE id = ?1", params![id])?;
-        Ok(())
-    }
-}
-
-// ========== Customer Repository ==========
-
-pub struct CustomerRepository {
-    pub conn: DbConnection,
-}
-
-impl CustomerRepository {
-    pub fn new(conn: DbConnection) -> Self {
-        Self { conn }
-    }
-
-    pub fn find_by_identity(
-        &self,
-        phone: &str,
-        license_plate: &str,
-    ) -> Result<Option<Customer>> {
-        let conn = self.conn.lock().unwrap();
-
-        let result = conn.query_row(
-            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
-             FROM customers
-             WHERE (?1 <> '' AND phone = ?1)
-                OR (?2 <> '' AND license_plate = ?2)
-             ORDER BY updated_at DESC
-             LIMIT 1",
-            params![phone, license_plate],
-            |row: &rusqlite::Row| {
-                Ok(Customer {
+        let products = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    phone: row.get::<_, String>(2)?,
-                    license_plate: row.get::<_, String>(3)?,
-                    address: row.get::<_, Option<String>>(4)?,
-                    last_purchase_at: row.get::<_, Option<String>>(5)?,
-                    created_at: row.get::<_, String>(6)?,
-                    updated_at: row.get::<_, String>(7)?,
+                    unit: row.get::<_, String>(2)?,
+                    price: row.get::<_, f64>(3)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    pinyin: row.get::<_, Option<String>>(5)?,
+                    stock: row.get::<_, Option<f64>>(6)?,
+                    min_stock: row.get::<_, Option<f64>>(7)?,
+                    track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
-            },
-        );
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        match result {
-            Ok(customer) => Ok(Some(customer)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e),
-        }
+        Ok(products)
     }
 
-    pub fn search(&self, query: &str) -> Result<Vec<Customer>> {
+    pub fn get_by_category(&self, category_id: &str) -> Result<Vec<Product>> {
         let conn = self.conn.lock().unwrap();
-        let pattern = format!("%{}%", query);
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
-             FROM customers
-             WHERE id NOT LIKE 'temp_%'
-               AND id NOT LIKE 'order_customer_%'
-               AND id NOT LIKE 'deleted_%'
-               AND (name LIKE ?1 OR license_plate LIKE ?2 OR phone LIKE ?3)
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products
+             WHERE category_id = ?1
              ORDER BY name"
         )?;
 
-        let customers = stmt
-            .query_map(params![pattern, pattern, pattern], |row: &rusqlite::Row| {
-                Ok(Customer {
+        let products = stmt
+            .query_map(params![category_id], |row: &rusqlite::Row| {
+                Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    phone: row.get::<_, String>(2)?,
-                    license_plate: row.get::<_, String>(3)?,
-                    address: row.get::<_, Option<String>>(4)?,
-                    last_purchase_at: row.get::<_, Option<String>>(5)?,
-                    created_at: row.get::<_, String>(6)?,
-                    updated_at: row.get::<_, String>(7)?,
+                    unit: row.get::<_, String>(2)?,
+                    price: row.get::<_, f64>(3)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    pinyin: row.get::<_, Option<String>>(5)?,
+                    stock: row.get::<_, Option<f64>>(6)?,
+                    min_stock: row.get::<_, Option<f64>>(7)?,
+                    track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(customers)
+        Ok(products)
     }
-}
 
-impl Repository<Customer> for CustomerRepository {
-    fn get_all(&self) -> Result<Vec<Customer>> {
+    /// 分页查询商品，可按关键字、分类和价格区间（含边界）筛选。query 为 (关键字, 拼音搜索词)：关键字匹配名称，
+    /// 拼音码按拼音搜索词（由关键字转换的小写拼音、无空格，为空时不匹配）匹配
+    pub fn get_page(
+        &self,
+        query: Option<(&str, &str)>,
+        category_id: Option<&str>,
+        min_price: Option<f64>,
+        max_price: Option<f64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<ProductPage> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some((query, pinyin_query)) = query.map(|(q, py)| (q.trim(), py)).filter(|(q, _)| !q.is_empty()) {
+            conditions.push("(name LIKE ? OR pinyin LIKE ?)");
+            values.push(Value::Text(format!("%{}%", query)));
+            values.push(if pinyin_query.is_empty() {
+                Value::Null
+            } else {
+                Value::Text(format!("%{}%", pinyin_query))
+            });
+        }
+        if let Some(category_id) = category_id.filter(|c| !c.is_empty()) {
+            conditions.push("category_id = ?");
+            values.push(Value::Text(category_id.to_string()));
+        }
+        // 价格按分比较，避免浮点误差导致边界价格被漏掉
+        if let Some(min_price) = min_price {
+            conditions.push("COALESCE(price_cents, CAST(ROUND(price * 100) AS INTEGER)) >= ?");
+            values.push(Value::Integer(to_cents(min_price)));
+        }
+        if let Some(max_price) = max_price {
+            conditions.push("COALESCE(price_cents, CAST(ROUND(price * 100) AS INTEGER)) <= ?");
+            values.push(Value::Integer(to_cents(max_price)));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM products {}", where_clause),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(offset as i64));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products
+             {}
+             ORDER BY name
+             LIMIT ? OFFSET ?",
+            where_clause
+        ))?;
+
+        let items = stmt
+            .query_map(params_from_iter(values.iter()), |row: &rusqlite::Row| {
+                Ok(Product {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    unit: row.get::<_, String>(2)?,
+                    price: row.get::<_, f64>(3)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    pinyin: row.get::<_, Option<String>>(5)?,
+                    stock: row.get::<_, Option<f64>>(6)?,
+                    min_stock: row.get::<_, Option<f64>>(7)?,
+                    track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ProductPage { items, total })
+    }
+
+    /// 在同一事务中批量删除商品，返回 SQLite 实际删除的行数（不存在的 id 不计入）
+    pub fn delete_batch(&self, ids: &[String]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mut deleted = 0;
+        {
+            let mut stmt = tx.prepare("DELETE FROM products WHERE id = ?1")?;
+            for id in ids {
+                stmt.execute(params![id])?;
+                deleted += tx.changes();
+            }
+        }
+
+        tx.commit()?;
+        Ok(deleted as usize)
+    }
+
+    /// 批量更新商品拼音简码，返回 SQLite 实际修改的行数
+    pub fn update_pinyin_batch(&self, updates: &[(String, String)], now: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mut updated = 0;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE products SET pinyin = ?1, updated_at = ?2
+                 WHERE id = ?3 AND (pinyin IS NULL OR lower(pinyin) != ?1)",
+            )?;
+            for (id, pinyin) in updates {
+                stmt.execute(params![pinyin, now, id])?;
+                updated += tx.changes();
+            }
+        }
+
+        tx.commit()?;
+        Ok(updated as usize)
+    }
+
+    /// 批量改价：对 category_id 分类及其子分类（为空时为全部商品）的商品按 new_price(商品名称, 原价) 计算新价格，
+    /// 全部在同一事务中完成，new_price 返回错误时整批回滚并原样返回 Rejected。返回价格（按分）实际变化的商品数
+    pub fn adjust_prices<F>(
+        &self,
+        category_id: Option<&str>,
+        new_price: F,
+        now: &str,
+    ) -> std::result::Result<usize, WriteError>
+    where
+        F: Fn(&str, f64) -> std::result::Result<f64, String>,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let products: Vec<(String, String, f64)> = {
+            let mut stmt = tx.prepare(
+                "WITH RECURSIVE scope(id) AS (
+                     SELECT id FROM categories WHERE id = ?1
+                     UNION
+                     SELECT c.id FROM categories c JOIN scope s ON c.parent_id = s.id
+                 )
+                 SELECT id, name, COALESCE(price_cents / 100.0, price) FROM products
+                 WHERE ?1 IS NULL OR category_id IN (SELECT id FROM scope)",
+            )?;
+            let rows = stmt
+                .query_map(params![category_id], |row: &rusqlite::Row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+
+        let mut updated = 0;
+        for (id, name, price) in products {
+            let adjusted_cents = to_cents(new_price(&name, price).map_err(WriteError::Rejected)?);
+            // 按分比较，价格不变的商品不写入也不计数
+            updated += tx.execute(
+                "UPDATE products SET price = ?1, price_cents = ?2, updated_at = ?3
+                 WHERE id = ?4 AND COALESCE(price_cents, CAST(ROUND(price * 100) AS INTEGER)) <> ?2",
+                params![from_cents(adjusted_cents), adjusted_cents, now, &id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    /// 批量退回库存（与扣减相反）并记录流水（关联 order_id），只处理启用了库存跟踪且已设置库存的商品。
+    /// 传入调用方的连接或事务，以便与删除订单等操作在同一事务中完成
+    pub fn restock_batch(conn: &rusqlite::Connection, items: &[(String, f64)], order_id: Option<&str>, now: &str) -> Result<()> {
+        for (product_id, quantity) in items {
+            let previous: Option<f64> = conn
+                .query_row(
+                    "SELECT stock FROM products WHERE id = ?1 AND track_stock = 1",
+                    params![product_id],
+                    |row: &rusqlite::Row| row.get::<_, Option<f64>>(0),
+                )
+                .optional()?
+                .flatten();
+            let Some(previous) = previous else {
+                continue;
+            };
+
+            let balance_after = previous + quantity;
+            conn.execute(
+                "UPDATE products SET stock = ?1, updated_at = ?2 WHERE id = ?3",
+                params![balance_after, now, product_id],
+            )?;
+            conn.execute(
+                "INSERT INTO stock_movements (id, product_id, change, reason, order_id, balance_after, created_at)
+                 VALUES (?1, ?2, ?3, 'restock', ?4, ?5, ?6)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    product_id,
+                    quantity,
+                    order_id,
+                    balance_after,
+                    now,
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// 按订单数量的变化调整库存：delta 为正表示多卖出（扣减），为负表示退回（增加），为 0 的忽略。
+    /// 传入调用方的连接或事务，以便与订单更新在同一事务中完成。返回因扣减降到最低库存及以下的商品
+    pub fn adjust_stock_batch(conn: &rusqlite::Connection, deltas: &[(String, f64)], order_id: Option<&str>, now: &str) -> Result<Vec<LowStockAlert>> {
+        let mut returned = Vec::new();
+        let mut alerts = Vec::new();
+        for (product_id, delta) in deltas {
+            if *delta > 0.0 {
+                alerts.extend(deduct_stock_on(conn, product_id, *delta, order_id, now)?);
+            } else if *delta < 0.0 {
+                returned.push((product_id.clone(), -delta));
+            }
+        }
+        ProductRepository::restock_batch(conn, &returned, order_id, now)?;
+        Ok(alerts)
+    }
+}
+
+/// 在给定连接（或事务）上扣减库存并记录流水，只扣减启用了库存跟踪的商品，库存不会低于 0。
+/// 设置了最低库存的商品因本次扣减从高于最低库存降到最低库存及以下时返回提醒
+fn deduct_stock_on(
+    conn: &rusqlite::Connection,
+    product_id: &str,
+    quantity: f64,
+    order_id: Option<&str>,
+    now: &str,
+) -> Result<Option<LowStockAlert>> {
+    // 商品不存在或未开启库存跟踪时不扣减，其余查询错误直接返回
+    let (previous, min_stock, name): (Option<f64>, Option<f64>, String) = match conn.query_row(
+        "SELECT stock, min_stock, name FROM products WHERE id = ?1 AND track_stock = 1",
+        params![product_id],
+        |row: &rusqlite::Row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ) {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => (None, None, String::new()),
+        Err(e) => return Err(e),
+    };
+
+    // 只扣减启用了库存跟踪的商品
+    conn.execute(
+        "UPDATE products
+         SET stock = CASE WHEN track_stock = 1 AND stock IS NOT NULL THEN MAX(0, stock - ?1) ELSE stock END,
+             updated_at = ?2
+         WHERE id = ?3 AND track_stock = 1",
+        params![
+            quantity,
+            now,
+            product_id,
+        ],
+    )?;
+
+    // 记录库存流水（按实际扣减量，库存不会低于 0）
+    let Some(previous) = previous else {
+        return Ok(None);
+    };
+    let balance_after = (previous - quantity).max(0.0);
+    conn.execute(
+        "INSERT INTO stock_movements (id, product_id, change, reason, order_id, balance_after, created_at)
+         VALUES (?1, ?2, ?3, 'sale', ?4, ?5, ?6)",
+        params![
+            uuid::Uuid::new_v4().to_string(),
+            product_id,
+            balance_after - previous,
+            order_id,
+            balance_after,
+            now,
+        ],
+    )?;
+
+    Ok(min_stock
+        .filter(|min_stock| previous > *min_stock && balance_after <= *min_stock)
+        .map(|min_stock| LowStockAlert {
+            product_id: product_id.to_string(),
+            name,
+            stock: balance_after,
+            min_stock,
+        }))
+}
+
+impl Repository<Product> for ProductRepository {
+    fn get_all(&self) -> Result<Vec<Product>> {
         let conn = self.conn.lock().unwrap();
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
-             FROM customers
-             WHERE id NOT LIKE 'temp_%'
-               AND id NOT LIKE 'order_customer_%'
-               AND id NOT LIKE 'deleted_%'
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products
              ORDER BY name"
         )?;
 
-        let customers = stmt
+        let products = stmt
             .query_map([], |row: &rusqlite::Row| {
-                Ok(Customer {
+                Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    phone: row.get::<_, String>(2)?,
-                    license_plate: row.get::<_, String>(3)?,
-                    address: row.get::<_, Option<String>>(4)?,
-                    last_purchase_at: row.get::<_, Option<String>>(5)?,
-                    created_at: row.get::<_, String>(6)?,
-                    updated_at: row.get::<_, String>(7)?,
+                    unit: row.get::<_, String>(2)?,
+                    price: row.get::<_, f64>(3)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    pinyin: row.get::<_, Option<String>>(5)?,
+                    stock: row.get::<_, Option<f64>>(6)?,
+                    min_stock: row.get::<_, Option<f64>>(7)?,
+                    track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(customers)
+        Ok(products)
     }
 
-    fn get_by_id(&self, id: &str) -> Result<Customer> {
+    fn get_by_id(&self, id: &str) -> Result<Product> {
         let conn = self.conn.lock().unwrap();
 
         conn.query_row(
-            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
-             FROM customers WHERE id = ?1",
+            "SELECT id, name, unit, COALESCE(price_cents / 100.0, price), category_id, pinyin, stock, min_stock, track_stock, created_at, updated_at, barcode
+             FROM products WHERE id = ?1",
             params![id],
             |row: &rusqlite::Row| {
-                Ok(Customer {
+                Ok(Product {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    phone: row.get::<_, String>(2)?,
-                    license_plate: row.get::<_, String>(3)?,
-                    address: row.get::<_, Option<String>>(4)?,
-                    last_purchase_at: row.get::<_, Option<String>>(5)?,
-                    created_at: row.get::<_, String>(6)?,
-                    updated_at: row.get::<_, String>(7)?,
+                    unit: row.get::<_, String>(2)?,
+                    price: row.get::<_, f64>(3)?,
+                    category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    pinyin: row.get::<_, Option<String>>(5)?,
+                    stock: row.get::<_, Option<f64>>(6)?,
+                    min_stock: row.get::<_, Option<f64>>(7)?,
+                    track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    barcode: row.get::<_, Option<String>>(11)?,
                 })
             },
         )
     }
 
-    fn insert(&self, customer: &Customer) -> Result<()> {
+    fn insert(&self, product: &Product) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-
-        conn.execute(
-            "INSERT INTO customers (id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                &customer.id,
-                &customer.name,
-                &customer.phone,
-                &customer.license_plate,
-                &customer.address,
-                &customer.last_purchase_at,
-                &customer.created_at,
-                &customer.updated_at,
-            ],
-        )?;
-
-        Ok(())
+        Self::insert_on(&conn, product)
     }
 
-    fn update(&self, customer: &Customer) -> Result<()> {
+    fn update(&self, product: &Product) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-
-        conn.execute(
-            "UPDATE customers SET name = ?1, phone = ?2, license_plate = ?3,
-             address = ?4, updated_at = ?5 WHERE id = ?6",
-            params![
-                &customer.name,
-                &customer.phone,
-                &customer.license_plate,
-                &customer.address,
-                &customer.updated_at,
-                &customer.id,
-            ],
-        )?;
-
-        Ok(())
+        Self::update_on(&conn, product)
     }
 
     fn delete(&self, id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM customers WHERE id = ?1", params![id])?;
+        conn.execute("DELETE FROM products WHERE id = ?1", params![id])?;
         Ok(())
     }
 }
 
-// ========== Category Repository ==========
+// ========== Stock Movement Repository ==========
 
-pub struct CategoryRepository {
+pub struct StockMovementRepository {
     pub conn: DbConnection,
 }
 
-impl CategoryRepository {
+impl StockMovementRepository {
     pub fn new(conn: DbConnection) -> Self {
         Self { conn }
     }
 
-    pub fn get_tree(&self) -> Result<Vec<Category>> {
+    /// 商品的库存流水，按时间顺序
+    pub fn get_by_product(&self, product_id: &str) -> Result<Vec<StockMovement>> {
         let conn = self.conn.lock().unwrap();
-
         let mut stmt = conn.prepare(
-            "SELECT id, name, parent_id, level, path, sort_order, created_at, updated_at
-             FROM categories
-             ORDER BY sort_order, name",
+            "SELECT id, product_id, change, reason, order_id, balance_after, created_at
+             FROM stock_movements WHERE product_id = ?1 ORDER BY created_at, rowid",
         )?;
-
-        let categories = stmt
-            .query_map([], |row: &rusqlite::Row| {
-                Ok(Category {
+        let movements = stmt
+            .query_map(params![product_id], |row: &rusqlite::Row| {
+                Ok(StockMovement {
                     id: row.get::<_, String>(0)?,
-                    name: row.get::<_, String>(1)?,
-                    parent_id: row.get::<_, Option<String>>(2)?,
-                    level: row.get::<_, i32>(3)?,
-                    path: row.get::<_, String>(4)?,
-                    sort_order: row.get::<_, i32>(5)?,
+                    product_id: row.get::<_, String>(1)?,
+                    change: row.get::<_, f64>(2)?,
+                    reason: row.get::<_, String>(3)?,
+                    order_id: row.get::<_, Option<String>>(4)?,
+                    balance_after: row.get::<_, Option<f64>>(5)?,
                     created_at: row.get::<_, String>(6)?,
-                    updated_at: row.get::<_, String>(7)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
+        Ok(movements)
+    }
 
-        Ok(categories)
+    /// 启用了库存跟踪的商品的当前库存（未设置库存的按 0 计算）；商品不存在或未开启库存跟踪时返回 None
+    pub fn tracked_stock_on(conn: &rusqlite::Connection, product_id: &str) -> Result<Option<f64>> {
+        let stock: Option<Option<f64>> = conn
+            .query_row(
+                "SELECT stock FROM products WHERE id = ?1 AND track_stock = 1",
+                params![product_id],
+                |row: &rusqlite::Row| row.get(0),
+            )
+            .optional()?;
+        Ok(stock.map(|stock| stock.unwrap_or(0.0)))
     }
-    pub fn save_batch(&self, categories: &[Category]) -> Result<()> {
-        let mut conn = self.conn.lock().unwrap();
-        let tx = conn.transaction()?;
 
-        {
-            let mut stmt_insert = tx.prepare(
-                "INSERT OR REPLACE INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
-            )?;
+    /// 手工调整库存（如盘点后修正）：把商品库存从 previous 改为 previous + delta 并记录一条流水，返回该流水。
+    /// 传入调用方的事务，previous 由调用方在同一事务中读取并校验
+    pub fn adjust_on(conn: &rusqlite::Connection, product_id: &str, previous: f64, delta: f64, reason: &str, now: &str) -> Result<StockMovement> {
+        let balance_after = previous + delta;
+        conn.execute(
+            "UPDATE products SET stock = ?1, updated_at = ?2 WHERE id = ?3",
+            params![balance_after, now, product_id],
+        )?;
+        let movement = StockMovement {
+            id: uuid::Uuid::new_v4().to_string(),
+            product_id: product_id.to_string(),
+            change: delta,
+            reason: reason.to_string(),
+            order_id: None,
+            balance_after: Some(balance_after),
+            created_at: now.to_string(),
+        };
+        conn.execute(
+            "INSERT INTO stock_movements (id, product_id, change, reason, order_id, balance_after, created_at)
+             VALUES (?1, ?2, ?3, ?4, NULL, ?5, ?6)",
+            params![&movement.id, product_id, delta, reason, balance_after, now],
+        )?;
+        Ok(movement)
+    }
 
-            for category in categories {
-                stmt_insert.execute(params![
-                    &category.id,
-                    &category.name,
-                    &category.parent_id,
-                    &category.level,
-                    &category.path,
-                    &category.sort_order,
-                    &category.created_at,
-                    &category.updated_at,
-                ])?;
+    /// 按商品汇总日期区间内（YYYY-MM-DD，含两端）的库存变动
+    pub fn get_summary(&self, from: &str, to: &str) -> Result<Vec<StockMovementSummary>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT m.product_id, COALESCE(p.name, ''), COALESCE(p.unit, ''), p.stock, m.reason,
+                    SUM(CASE WHEN m.change < 0 THEN -m.change ELSE 0 END),
+                    SUM(CASE WHEN m.change > 0 THEN m.change ELSE 0 END),
+                    SUM(m.change)
+             FROM stock_movements m
+             LEFT JOIN products p ON p.id = m.product_id
+             WHERE substr(m.created_at, 1, 10) BETWEEN ?1 AND ?2
+             GROUP BY m.product_id, m.reason
+             ORDER BY p.name, m.product_id, m.reason",
+        )?;
+
+        let rows = stmt
+            .query_map(params![from, to], |row: &rusqlite::Row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<f64>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, f64>(6)?,
+                    row.get::<_, f64>(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut summaries: Vec<StockMovementSummary> = Vec::new();
+        for (product_id, name, unit, stock, reason, deducted, added, change) in rows {
+            match summaries.last_mut() {
+                Some(summary) if summary.product_id == product_id => {
+                    summary.deducted += deducted;
+                    summary.added += added;
+                    summary.net_change += change;
+                    summary.reasons.push(MovementReasonTotal { reason, change });
+                }
+                _ => summaries.push(StockMovementSummary {
+                    product_id,
+                    product_name: name,
+                    unit,
+                    deducted,
+                    added,
+                    net_change: change,
+                    current_stock: stock,
+                    reasons: vec![MovementReasonTotal { reason, change }],
+                }),
             }
         }
 
-        tx.commit()?;
-        Ok(())
+        Ok(summaries)
     }
 }
 
-impl Repository<Category> for CategoryRepository {
-    fn get_all(&self) -> Result<Vec<Category>> {
-        self.get_tree()
+// ========== Product Stats Repository ==========
+
+pub struct ProductStatsRepository {
+    pub conn: DbConnection,
+}
+
+impl ProductStatsRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
     }
 
-    fn get_by_id(&self, id: &str) -> Result<Category> {
+    /// 按截至 today（含当天）的 window_days 天窗口重算每个商品的销量与日均销量（仅统计已完成订单），
+    /// 整表替换，返回写入的商品数
+    pub fn refresh(&self, window_days: u32, today: NaiveDate, now: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let window_days = window_days.max(1);
+        let from = today - chrono::Duration::days(window_days as i64 - 1);
+
+        tx.execute("DELETE FROM product_stats", [])?;
+        let count = tx.execute(
+            "INSERT INTO product_stats (product_id, units_sold, units_per_day, window_days, refreshed_at)
+             SELECT p.id, COALESCE(s.units, 0), COALESCE(s.units, 0) / ?3, ?3, ?4
+             FROM products p
+             LEFT JOIN (
+                 SELECT i.product_id, SUM(i.quantity) AS units
+                 FROM order_items i
+                 JOIN orders o ON o.id = i.order_id
+                 WHERE o.status = 'completed' AND substr(o.date, 1, 10) BETWEEN ?1 AND ?2
+                 GROUP BY i.product_id
+             ) s ON s.product_id = p.id",
+            params![
+                from.format("%Y-%m-%d").to_string(),
+                today.format("%Y-%m-%d").to_string(),
+                window_days,
+                now
+            ],
+        )?;
+
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// 库存跟踪商品的补货建议，日均销量读取 product_stats 缓存（未刷新过的商品按 0 计）。
+    /// 目标库存为 cover_days 天的预计销量加最低库存，且不低于最低库存的两倍（至少为 1）
+    pub fn get_reorder_suggestions(&self, cover_days: f64) -> Result<Vec<ReorderSuggestion>> {
         let conn = self.conn.lock().unwrap();
 
-        conn.query_row(
-            "SELECT id, name, parent_id, level, path, sort_order, created_at, updated_at
-             FROM categories WHERE id = ?1",
-            params![id],
-            |row: &rusqlite::Row| {
-                Ok(Category {
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.name, p.unit, COALESCE(p.price_cents / 100.0, p.price), p.category_id, p.pinyin,
+                    p.stock, p.min_stock, p.track_stock, p.created_at, p.updated_at, p.barcode,
+                    s.units_per_day, s.window_days, s.refreshed_at
+             FROM products p
+             LEFT JOIN product_stats s ON s.product_id = p.id
+             WHERE p.track_stock = 1 AND p.stock IS NOT NULL",
+        )?;
+
+        let rows = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok((
+                    Product {
+                        id: row.get::<_, String>(0)?,
+                        name: row.get::<_, String>(1)?,
+                        unit: row.get::<_, String>(2)?,
+                        price: row.get::<_, f64>(3)?,
+                        category_id: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                        pinyin: row.get::<_, Option<String>>(5)?,
+                        stock: row.get::<_, Option<f64>>(6)?,
+                        min_stock: row.get::<_, Option<f64>>(7)?,
+                        track_stock: row.get::<_, Option<i32>>(8)?.map(|v| v != 0),
+                        created_at: row.get::<_, String>(9)?,
+                        updated_at: row.get::<_, String>(10)?,
+                        barcode: row.get::<_, Option<String>>(11)?,
+                    },
+                    row.get::<_, Option<f64>>(12)?,
+                    row.get::<_, Option<i64>>(13)?,
+                    row.get::<_, Option<String>>(14)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut suggestions: Vec<ReorderSuggestion> = rows
+            .into_iter()
+            .filter_map(|(product, units_per_day, window_days, refreshed_at)| {
+                let stock = product.stock.unwrap_or(0.0);
+                let min_stock = product.min_stock.unwrap_or(0.0);
+                let units_per_day = units_per_day.unwrap_or(0.0);
+
+                let target = (units_per_day * cover_days + min_stock).max(min_stock * 2.0).max(1.0);
+                let suggested_quantity = (target - stock).max(0.0).ceil();
+                if suggested_quantity <= 0.0 {
+                    return None;
+                }
+
+                Some(ReorderSuggestion {
+                    product,
+                    units_per_day,
+                    days_of_stock: (units_per_day > 0.0).then(|| stock.max(0.0) / units_per_day),
+                    suggested_quantity,
+                    window_days,
+                    refreshed_at,
+                })
+            })
+            .collect();
+
+        // 预计可售天数少的排前面，无销量的按库存升序排在后面
+        suggestions.sort_by(|a, b| match (a.days_of_stock, b.days_of_stock) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.product.stock.unwrap_or(0.0).total_cmp(&b.product.stock.unwrap_or(0.0)),
+        });
+
+        Ok(suggestions)
+    }
+}
+
+// ========== Customer Repository ==========
+
+pub struct CustomerRepository {
+    pub conn: DbConnection,
+}
+
+/// 客户列表排除的占位客户：临时客户、订单快照客户与合并/删除后留下的占位记录
+const CUSTOMER_VISIBLE_FILTER: &str = "id NOT LIKE 'temp_%'
+               AND id NOT LIKE 'order_customer_%'
+               AND id NOT LIKE 'deleted_%'";
+
+/// 客户关键字匹配（姓名、车牌、电话），三个占位符传入同一个 LIKE 模式
+const CUSTOMER_SEARCH_FILTER: &str = "(name LIKE ?1 OR license_plate LIKE ?2 OR phone LIKE ?3)";
+
+impl CustomerRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    /// 一次查询取出多个客户（含已合并的客户），按 ID 索引；不存在的 ID 不在结果中
+    pub fn get_by_ids(&self, ids: &[String]) -> Result<std::collections::HashMap<String, Customer>> {
+        use std::collections::HashMap;
+
+        let mut unique: Vec<&String> = ids.iter().collect();
+        unique.sort();
+        unique.dedup();
+        if unique.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let placeholders = (1..=unique.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers WHERE id IN ({})",
+            placeholders
+        ))?;
+        let customers = stmt
+            .query_map(params_from_iter(unique), |row: &rusqlite::Row| {
+                Ok(Customer {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    parent_id: row.get::<_, Option<String>>(2)?,
-                    level: row.get::<_, i32>(3)?,
-                    path: row.get::<_, String>(4)?,
-                    sort_order: row.get::<_, i32>(5)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
                     created_at: row.get::<_, String>(6)?,
                     updated_at: row.get::<_, String>(7)?,
                 })
-            },
-        )
+            })?
+            .map(|customer| customer.map(|c| (c.id.clone(), c)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(customers)
     }
 
-    fn insert(&self, category: &Category) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-
+    /// 在调用方的连接或事务上插入客户，以便批量导入在同一事务中完成
+    pub fn insert_on(conn: &rusqlite::Connection, customer: &Customer) -> Result<()> {
         conn.execute(
-            "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
+            "INSERT INTO customers (id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
-                &category.id,
-                &category.name,
-                &category.parent_id,
-                &category.level,
-                &category.path,
-                &category.sort_order,
-                &category.created_at,
-                &category.updated_at,
+                &customer.id,
+                &customer.name,
+                &customer.phone,
+                &customer.license_plate,
+                &customer.address,
+                &customer.last_purchase_at,
+                &customer.created_at,
+                &customer.updated_at,
             ],
         )?;
 
         Ok(())
     }
 
-    fn update(&self, category: &Category) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-
+    /// 在调用方的连接或事务上更新客户
+    pub fn update_on(conn: &rusqlite::Connection, customer: &Customer) -> Result<()> {
         conn.execute(
-            "UPDATE categories SET name = ?1, parent_id = ?2, level = ?3,
-             path = ?4, sort_order = ?5, updated_at = ?6 WHERE id = ?7",
+            "UPDATE customers SET name = ?1, phone = ?2, license_plate = ?3,
+             address = ?4, updated_at = ?5 WHERE id = ?6",
             params![
-                &category.name,
-                &category.parent_id,
-                &category.level,
-                &category.path,
-                &category.sort_order,
-                &category.updated_at,
-                &category.id,
+                &customer.name,
+                &customer.phone,
+                &customer.license_plate,
+                &customer.address,
+                &customer.updated_at,
+                &customer.id,
             ],
         )?;
 
         Ok(())
     }
 
-    fn delete(&self, id: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM categories WHERE id = ?1", params![id])?;
-        Ok(())
-    }
-}
-
-// ========== Template Repository ==========
-
-pub struct TemplateRepository {
-    pub conn: DbConnection,
-}
+    /// 将任意客户 ID 解析为当前有效的客户：
+    /// - 占位 ID（临时/订单快照/deleted_*）不对应有效客户，返回 None
+    /// - 被合并的客户沿合并记录找到最终的目标客户
+    /// - 其余 ID 直接查找，不存在时返回 None
+    pub fn resolve(&self, id: &str) -> Result<CustomerResolution> {
+        let is_placeholder = ["temp_", "order_customer_", "deleted_"]
+            .iter()
+            .any(|prefix| id.starts_with(prefix));
+        if is_placeholder {
+            return Ok(CustomerResolution {
+                customer: None,
+                is_placeholder,
+                merged: false,
+            });
+        }
 
-impl TemplateRepository {
-    pub fn new(conn: DbConnection) -> Self {
-        Self { conn }
+        let resolved_id = {
+            let conn = self.conn.lock().unwrap();
+            let mut current = id.to_string();
+            let mut visited = std::collections::HashSet::new();
+            while visited.insert(current.clone()) {
+                match conn.query_row(
+                    "SELECT target_id FROM customer_merges WHERE source_id = ?1",
+                    params![&current],
+                    |row: &rusqlite::Row| row.get::<_, String>(0),
+                ) {
+                    Ok(target_id) => current = target_id,
+                    Err(rusqlite::Error::QueryReturnedNoRows) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            current
+        };
+
+        let customer = match self.get_by_id(&resolved_id) {
+            Ok(customer) => Some(customer),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(CustomerResolution {
+            customer,
+            is_placeholder,
+            merged: resolved_id != id,
+        })
     }
-}
 
-impl Repository<TemplateConfig> for TemplateRepository {
-    fn get_all(&self) -> Result<Vec<TemplateConfig>> {
+    /// 分页查询客户（排除占位客户），order_by 由调用方从白名单中选定
+    pub fn get_page(
+        &self,
+        query: Option<&str>,
+        order_by: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<CustomerPage> {
         let conn = self.conn.lock().unwrap();
 
-        let mut stmt = conn.prepare(
-            "SELECT id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at
-             FROM templates
-             ORDER BY name"
-        )?;
+        let mut values: Vec<Value> = Vec::new();
+        let mut where_clause = format!("WHERE {}", CUSTOMER_VISIBLE_FILTER);
+        if let Some(query) = query.map(str::trim).filter(|q| !q.is_empty()) {
+            where_clause.push_str(&format!(" AND {}", CUSTOMER_SEARCH_FILTER));
+            let pattern = format!("%{}%", query);
+            values.extend(std::iter::repeat_n(Value::Text(pattern), 3));
+        }
 
-        let templates = stmt
-            .query_map([], |row| {
-                let mappings_json: String = row.get(6)?;
-                let mut mappings: TemplateMappings =
-                    serde_json::from_str(&mappings_json).map_err(|e| {
-                        rusqlite::Error::ToSqlConversionFailure(
-                            Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-                        )
-                    })?;
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM customers {}", where_clause),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
 
-                let item_end_row: i32 = row.get(7)?;
-                // 将 item_end_row 合并到 mappings 中
-                mappings.item_end_row = item_end_row;
+        let limit_index = values.len() + 1;
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(offset as i64));
 
-                let required_fields_json: String = row.get(8)?;
-                let required_fields: RequiredFields = serde_json::from_str(&required_fields_json)
-                    .map_err(|e| {
-                    rusqlite::Error::ToSqlConversionFailure(
-                        Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-                    )
-                })?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers
+             {}
+             ORDER BY {}
+             LIMIT ?{} OFFSET ?{}",
+            where_clause,
+            order_by,
+            limit_index,
+            limit_index + 1
+        ))?;
 
-                Ok(TemplateConfig {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    template_base64: row.get(2)?,
-                    file_name: row.get(3)?,
-                    filename_pattern: row.get(4)?,
-                    is_default: row.get(5)?,
-                    mappings,
-                    required_fields,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
+        let items = stmt
+            .query_map(params_from_iter(values.iter()), |row: &rusqlite::Row| {
+                Ok(Customer {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(templates)
+        Ok(CustomerPage { items, total })
     }
 
-    fn get_by_id(&self, id: &str) -> Result<TemplateConfig> {
+    pub fn find_by_identity(
+        &self,
+        phone: &str,
+        license_plate: &str,
+    ) -> Result<Option<Customer>> {
         let conn = self.conn.lock().unwrap();
 
-        conn.query_row(
-            "SELECT id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at
-             FROM templates WHERE id = ?1",
-            params![id],
+        let result = conn.query_row(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers
+             WHERE (?1 <> '' AND phone = ?1)
+                OR (?2 <> '' AND license_plate = ?2)
+             ORDER BY updated_at DESC
+             LIMIT 1",
+            params![phone, license_plate],
             |row: &rusqlite::Row| {
-                let mappings_json: String = row.get(6)?;
-                let mut mappings: TemplateMappings = serde_json::from_str(&mappings_json).unwrap_or_default();
-
-                let item_end_row: i32 = row.get(7)?;
-                // 将 item_end_row 合并到 mappings 中
-                mappings.item_end_row = item_end_row;
-
-                let required_fields_json: Option<String> = row.get(8).ok();
-                let required_fields: RequiredFields = required_fields_json
-                    .and_then(|s| serde_json::from_str(&s).ok())
-                    .unwrap_or_default();
-
-                Ok(TemplateConfig {
+                Ok(Customer {
                     id: row.get::<_, String>(0)?,
                     name: row.get::<_, String>(1)?,
-                    template_base64: row.get::<_, String>(2)?,
-                    file_name: row.get::<_, String>(3)?,
-                    filename_pattern: row.get::<_, String>(4)?,
-                    is_default: row.get::<_, i32>(5)? != 0,
-                    mappings,
-                    required_fields,
-                    created_at: row.get::<_, String>(9)?,
-                    updated_at: row.get::<_, String>(10)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
                 })
             },
-        )
+        );
+
+        match result {
+            Ok(customer) => Ok(Some(customer)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
-    fn insert(&self, template: &TemplateConfig) -> Result<()> {
+    pub fn search(&self, query: &str) -> Result<Vec<Customer>> {
         let conn = self.conn.lock().unwrap();
+        let pattern = format!("%{}%", query);
 
-        let mappings_json = serde_json::to_string(&template.mappings).map_err(|e| {
-            rusqlite::Error::ToSqlConversionFailure(
-                Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-            )
-        })?;
-
-        let required_fields_json =
-            serde_json::to_string(&template.required_fields).map_err(|e| {
-                rusqlite::Error::ToSqlConversionFailure(
-                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-                )
-            })?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers
+             WHERE {} AND {}
+             ORDER BY name",
+            CUSTOMER_VISIBLE_FILTER, CUSTOMER_SEARCH_FILTER
+        ))?;
 
-        conn.execute(
-            "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-            params![
-                &template.id,
-                &template.name,
-                &template.template_base64,
-                &template.file_name,
-                &template.filename_pattern,
-                &template.is_default,
-                &mappings_json,
-                &template.mappings.item_end_row,
-                &required_fields_json,
-                &template.created_at,
-                &template.updated_at,
-            ],
-        )?;
+        let customers = stmt
+            .query_map(params![pattern, pattern, pattern], |row: &rusqlite::Row| {
+                Ok(Customer {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(())
+        Ok(customers)
     }
+}
 
-    fn update(&self, template: &TemplateConfig) -> Result<()> {
+impl Repository<Customer> for CustomerRepository {
+    fn get_all(&self) -> Result<Vec<Customer>> {
         let conn = self.conn.lock().unwrap();
 
-        let mappings_json = serde_json::to_string(&template.mappings).map_err(|e| {
-            rusqlite::Error::ToSqlConversionFailure(
-                Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-            )
-        })?;
-
-        let required_fields_json =
-            serde_json::to_string(&template.required_fields).map_err(|e| {
-                rusqlite::Error::ToSqlConversionFailure(
-                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
-                )
-            })?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers
+             WHERE {}
+             ORDER BY name",
+            CUSTOMER_VISIBLE_FILTER
+        ))?;
 
-        conn.execute(
-            "UPDATE templates SET name = ?1, template_base64 = ?2, file_name = ?3,
-             filename_pattern = ?4, is_default = ?5, mappings = ?6, item_end_row = ?7, required_fields = ?8, updated_at = ?9 WHERE id = ?10",
-            params![
-                &template.name,
-                &template.template_base64,
-                &template.file_name,
-                &template.filename_pattern,
-                &template.is_default,
-                &mappings_json,
-                &template.mappings.item_end_row,
-                &required_fields_json,
-                &template.updated_at,
-                &template.id,
+        let customers = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok(Customer {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(customers)
+    }
+
+    fn get_by_id(&self, id: &str) -> Result<Customer> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT id, name, phone, license_plate, address, last_purchase_at, created_at, updated_at
+             FROM customers WHERE id = ?1",
+            params![id],
+            |row: &rusqlite::Row| {
+                Ok(Customer {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    phone: row.get::<_, String>(2)?,
+                    license_plate: row.get::<_, String>(3)?,
+                    address: row.get::<_, Option<String>>(4)?,
+                    last_purchase_at: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
+                })
+            },
+        )
+    }
+
+    fn insert(&self, customer: &Customer) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        Self::insert_on(&conn, customer)
+    }
+
+    fn update(&self, customer: &Customer) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        Self::update_on(&conn, customer)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM customers WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+// ========== Order Event Repository ==========
+
+pub struct OrderEventRepository {
+    pub conn: DbConnection,
+}
+
+impl OrderEventRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    /// 记录一条订单操作
+    pub fn record(&self, order_id: &str, event_type: &str, detail: Option<&str>, now: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        Self::record_on(&conn, order_id, event_type, detail, now)
+    }
+
+    /// 在调用方的连接或事务上记录订单操作，以便与订单修改在同一事务中完成
+    pub fn record_on(conn: &rusqlite::Connection, order_id: &str, event_type: &str, detail: Option<&str>, now: &str) -> Result<()> {
+        conn.execute(
+            "INSERT INTO order_events (id, order_id, event_type, detail, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                uuid::Uuid::new_v4().to_string(),
+                order_id,
+                event_type,
+                detail,
+                now,
             ],
         )?;
-
         Ok(())
     }
 
-    fn delete(&self, id: &str) -> Result<()> {
+    /// 按时间顺序获取订单的操作记录
+    pub fn get_by_order(&self, order_id: &str) -> Result<Vec<OrderEvent>> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM templates WHERE id = ?1", params![id])?;
+        let mut stmt = conn.prepare(
+            "SELECT id, order_id, event_type, detail, created_at
+             FROM order_events WHERE order_id = ?1
+             ORDER BY created_at, rowid",
+        )?;
+        let events = stmt
+            .query_map(params![order_id], |row: &rusqlite::Row| {
+                Ok(OrderEvent {
+                    id: row.get::<_, String>(0)?,
+                    order_id: row.get::<_, String>(1)?,
+                    event_type: row.get::<_, String>(2)?,
+                    detail: row.get::<_, Option<String>>(3)?,
+                    created_at: row.get::<_, String>(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+}
+
+// ========== Order Attachment Repository ==========
+
+pub struct OrderAttachmentRepository {
+    pub conn: DbConnection,
+}
+
+impl OrderAttachmentRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    pub fn insert(&self, attachment: &OrderAttachment) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO order_attachments (id, order_id, file_path, file_name, kind, size_bytes, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                &attachment.id,
+                &attachment.order_id,
+                &attachment.file_path,
+                &attachment.file_name,
+                &attachment.kind,
+                &attachment.size_bytes,
+                &attachment.created_at,
+            ],
+        )?;
         Ok(())
     }
+
+    /// 按添加顺序获取订单的附件
+    pub fn get_by_order(&self, order_id: &str) -> Result<Vec<OrderAttachment>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, order_id, file_path, file_name, kind, size_bytes, created_at
+             FROM order_attachments WHERE order_id = ?1
+             ORDER BY created_at, rowid",
+        )?;
+        let attachments = stmt
+            .query_map(params![order_id], |row: &rusqlite::Row| {
+                Ok(OrderAttachment {
+                    id: row.get::<_, String>(0)?,
+                    order_id: row.get::<_, String>(1)?,
+                    file_path: row.get::<_, String>(2)?,
+                    file_name: row.get::<_, String>(3)?,
+                    kind: row.get::<_, String>(4)?,
+                    size_bytes: row.get::<_, i64>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attachments)
+    }
+
+    /// 数据库文件所在目录（未设置数据目录时附件保存在这里）
+    pub fn database_dir(&self) -> Option<std::path::PathBuf> {
+        let conn = self.conn.lock().unwrap();
+        conn.path()
+            .filter(|path| !path.is_empty())
+            .and_then(|path| std::path::Path::new(path).parent().map(|dir| dir.to_path_buf()))
+    }
 }
 
-// ========== Settings Repository ==========
+// ========== Order Draft Repository ==========
 
-pub struct SettingsRepository {
+pub struct OrderDraftRepository {
     pub conn: DbConnection,
 }
 
-impl SettingsRepository {
+impl OrderDraftRepository {
     pub fn new(conn: DbConnection) -> Self {
         Self { conn }
     }
 
-    pub fn get_settings(&self) -> Result<Option<AppSettings>> {
+    /// 保存草稿（order_json 为序列化后的订单，按客户端草稿 id 覆盖）
+    pub fn upsert(&self, id: &str, order_json: &str, now: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO order_drafts (id, order_json, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT(id) DO UPDATE SET order_json = excluded.order_json, updated_at = excluded.updated_at",
+            params![id, order_json, now],
+        )?;
+        Ok(())
+    }
+
+    /// 获取所有草稿（最近修改的在前），无法解析的草稿会被跳过
+    pub fn get_all(&self) -> Result<Vec<Order>> {
         let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, order_json FROM order_drafts ORDER BY updated_at DESC",
+        )?;
+        let rows = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let result = conn.query_row(
-            "SELECT id, data_directory, output_directory, backup_directory, font_size, theme,
-              remember_window, date_format, excel_date_format, order_number_format, order_number_prefix,
-              order_number_reset_daily, order_number_digits, retain_days, auto_backup, backup_interval,
-              backup_keep_count, default_template_id, default_category_id,
-              excel_filename_format, auto_open_excel, skip_save_dialog,
-              template_validation, updated_at
-              FROM app_settings WHERE id = 'settings'",
+        let drafts = rows
+            .into_iter()
+            .filter_map(|(id, order_json)| match serde_json::from_str::<Order>(&order_json) {
+                Ok(draft) => Some(draft),
+                Err(e) => {
+                    log::warn!("订单草稿 {} 解析失败，已跳过: {}", id, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(drafts)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM order_drafts WHERE id = ?1", params![id])
+    }
+
+    /// 草稿数量与最早/最近的修改时间（不解析草稿内容，损坏的草稿也计入）
+    pub fn summary(&self) -> Result<(usize, Option<String>, Option<String>)> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*), MIN(updated_at), MAX(updated_at) FROM order_drafts",
             [],
-            |row: &rusqlite::Row| {
-                Ok(AppSettings {
+            |row| Ok((row.get::<_, i64>(0)? as usize, row.get(1)?, row.get(2)?)),
+        )
+    }
+
+    /// 删除全部草稿，返回删除的数量
+    pub fn delete_all(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM order_drafts", [])
+    }
+}
+
+// ========== Customer Transaction Repository ==========
+
+pub struct CustomerTransactionRepository {
+    pub conn: DbConnection,
+}
+
+impl CustomerTransactionRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    /// 在调用方的连接或事务上写入往来账，以便与订单操作记录在同一事务中完成
+    pub fn insert_on(conn: &rusqlite::Connection, transaction: &CustomerTransaction) -> Result<()> {
+        conn.execute(
+            "INSERT INTO customer_transactions (id, customer_id, amount, amount_cents, kind, order_id, remark, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                &transaction.id,
+                &transaction.customer_id,
+                &transaction.amount,
+                &to_cents(transaction.amount),
+                &transaction.kind,
+                &transaction.order_id,
+                &transaction.remark,
+                &transaction.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_customer(&self, customer_id: &str) -> Result<Vec<CustomerTransaction>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, customer_id, COALESCE(amount_cents / 100.0, amount), kind, order_id, remark, created_at
+             FROM customer_transactions WHERE customer_id = ?1 ORDER BY created_at",
+        )?;
+        let transactions = stmt
+            .query_map(params![customer_id], |row: &rusqlite::Row| {
+                Ok(CustomerTransaction {
                     id: row.get::<_, String>(0)?,
-                    data_directory: row.get::<_, String>(1)?,
-                    output_directory: row.get::<_, String>(2)?,
-                    backup_directory: row.get::<_, String>(3)?,
-                    font_size: row.get::<_, i32>(4)?,
-                    theme: row.get::<_, String>(5)?,
-                    remember_window: row.get::<_, i32>(6)? != 0,
-                    date_format: row.get::<_, String>(7)?,
-                    excel_date_format: row.get::<_, String>(8)?,
-                    order_number_format: row.get::<_, String>(9)?,
-                    order_number_prefix: row.get::<_, String>(10)?,
-                    order_number_reset_daily: row.get::<_, i32>(11)? != 0,
-                    order_number_digits: row.get::<_, i32>(12)?,
-                    retain_days: row.get::<_, i32>(13)?,
-                    auto_backup: row.get::<_, i32>(14)? != 0,
-                    backup_interval: row.get::<_, i32>(15)?,
-                    backup_keep_count: row.get::<_, i32>(16)?,
-                    default_template_id: row.get::<_, String>(17)?,
-                    default_category_id: row.get::<_, String>(18)?,
-                    excel_filename_format: row.get::<_, String>(19)?,
-                    auto_open_excel: row.get::<_, i32>(20)? != 0,
-                    skip_save_dialog: row.get::<_, i32>(21)? != 0,
-                    template_validation: {
-                        let val: Option<String> = row.get(22)?;
-                        val.and_then(|v| serde_json::from_str::<crate::models::RequiredFields>(&v).ok())
-                    },
-                    updated_at: row.get::<_, String>(23)?,
+                    customer_id: row.get::<_, String>(1)?,
+                    amount: row.get::<_, f64>(2)?,
+                    kind: row.get::<_, String>(3)?,
+                    order_id: row.get::<_, Option<String>>(4)?,
+                    remark: row.get::<_, Option<String>>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(transactions)
+    }
+
+    /// 在调用方的连接或事务上同步订单的挂账记录：挂账且已完成的订单保持一条与订单金额一致的 charge，否则移除
+    pub fn sync_order_charge_on(conn: &rusqlite::Connection, order: &Order) -> Result<()> {
+        if order.on_account && order.status == "completed" {
+            let updated = conn.execute(
+                "UPDATE customer_transactions SET customer_id = ?1, amount = ?2, amount_cents = ?3
+                 WHERE order_id = ?4 AND kind = 'charge'",
+                params![
+                    &order.customer_id,
+                    &order.total_amount,
+                    &to_cents(order.total_amount),
+                    &order.id,
+                ],
+            )?;
+            if updated == 0 {
+                conn.execute(
+                    "INSERT INTO customer_transactions (id, customer_id, amount, amount_cents, kind, order_id, remark, created_at)
+                     VALUES (?1, ?2, ?3, ?4, 'charge', ?5, ?6, ?7)",
+                    params![
+                        uuid::Uuid::new_v4().to_string(),
+                        &order.customer_id,
+                        &order.total_amount,
+                        &to_cents(order.total_amount),
+                        &order.id,
+                        format!("订单挂账: {}", order.order_number),
+                        &order.updated_at,
+                    ],
+                )?;
+            }
+        } else {
+            conn.execute(
+                "DELETE FROM customer_transactions WHERE order_id = ?1 AND kind = 'charge'",
+                params![&order.id],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn query_balances(conn: &rusqlite::Connection, filter: &str, args: &[&dyn rusqlite::ToSql]) -> Result<Vec<CustomerBalance>> {
+        let sql = format!(
+            "SELECT t.customer_id, COALESCE(c.name, ''),
+                    COALESCE(SUM(CASE WHEN t.kind = 'charge' THEN t.amount_cents ELSE 0 END), 0),
+                    COALESCE(SUM(CASE t.kind WHEN 'payment' THEN t.amount_cents WHEN 'refund' THEN -t.amount_cents ELSE 0 END), 0)
+             FROM customer_transactions t
+             LEFT JOIN customers c ON c.id = t.customer_id
+             {}
+             GROUP BY t.customer_id",
+            filter
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let balances = stmt
+            .query_map(args, |row: &rusqlite::Row| {
+                let charged = row.get::<_, i64>(2)?;
+                let paid = row.get::<_, i64>(3)?;
+                Ok(CustomerBalance {
+                    customer_id: row.get::<_, String>(0)?,
+                    customer_name: row.get::<_, String>(1)?,
+                    charged: from_cents(charged),
+                    paid: from_cents(paid),
+                    balance: from_cents(charged - paid),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(balances)
+    }
+
+    pub fn get_balance(&self, customer_id: &str, customer_name: &str) -> Result<CustomerBalance> {
+        let conn = self.conn.lock().unwrap();
+        let balance = Self::query_balances(&conn, "WHERE t.customer_id = ?1", &[&customer_id])?
+            .into_iter()
+            .next()
+            .unwrap_or(CustomerBalance {
+                customer_id: customer_id.to_string(),
+                customer_name: customer_name.to_string(),
+                charged: 0.0,
+                paid: 0.0,
+                balance: 0.0,
+            });
+        Ok(balance)
+    }
+
+    /// 所有仍有欠款的客户，按欠款金额从高到低
+    pub fn get_outstanding(&self) -> Result<Vec<CustomerBalance>> {
+        let conn = self.conn.lock().unwrap();
+        let mut balances = Self::query_balances(&conn, "", &[])?;
+        balances.retain(|b| b.balance > 0.0);
+        balances.sort_by(|a, b| b.balance.total_cmp(&a.balance));
+        Ok(balances)
+    }
+}
+
+// ========== Category Repository ==========
+
+pub struct CategoryRepository {
+    pub conn: DbConnection,
+}
+
+impl CategoryRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    /// 分类及其全部子孙分类的 ID（按上级关系递归，不依赖 path）；分类不存在时为空
+    pub fn get_subtree_ids(&self, id: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        query_subtree_ids(&conn, id)
+    }
+
+    /// 在同一事务中删除分类及其全部子孙分类，其中的商品改到 reassign_to 分类（为空时商品不属于任何分类）。
+    /// 返回 (删除的分类数, 改分类的商品数)；调用方需保证 reassign_to 不在被删除的子树中
+    pub fn delete_subtree(&self, id: &str, reassign_to: Option<&str>, now: &str) -> Result<(usize, usize)> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mut deleted = 0;
+        let mut reassigned = 0;
+        for category_id in query_subtree_ids(&tx, id)? {
+            reassigned += tx.execute(
+                "UPDATE products SET category_id = ?1, updated_at = ?2 WHERE category_id = ?3",
+                params![reassign_to, now, &category_id],
+            )?;
+            deleted += tx.execute("DELETE FROM categories WHERE id = ?1", params![&category_id])?;
+        }
+
+        tx.commit()?;
+        Ok((deleted, reassigned))
+    }
+
+    pub fn get_tree(&self) -> Result<Vec<Category>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, name, parent_id, level, path, sort_order, created_at, updated_at
+             FROM categories
+             ORDER BY sort_order, name",
+        )?;
+
+        let categories = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok(Category {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    parent_id: row.get::<_, Option<String>>(2)?,
+                    level: row.get::<_, i32>(3)?,
+                    path: row.get::<_, String>(4)?,
+                    sort_order: row.get::<_, i32>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(categories)
+    }
+
+    /// 日期区间内已完成订单的分类销售额排行（外币按汇率折算为本币）。
+    /// 每个分类同时返回自身销售额与含全部下级分类的汇总额；
+    /// 上级关系沿 parent_id 逐级查找，不依赖 path 的格式。结果按汇总额降序；
+    /// 订单项按保存时的分类快照归类，没有分类（或分类已删除）的销售额归入分类 ID 为空的“未分类”
+    pub fn get_sales_ranking(&self, from: &str, to: &str, include_archived: bool) -> Result<Vec<CategorySales>> {
+        let conn = self.conn.lock().unwrap();
+        query_category_sales(&conn, from, to, include_archived)
+    }
+
+    /// 同一上级下一个可用的排序值（parent_id 为空表示顶级分类）
+    pub fn next_sort_order(&self, parent_id: Option<&str>) -> Result<i32> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COALESCE(MAX(sort_order) + 1, 0) FROM categories WHERE COALESCE(parent_id, '') = ?1",
+            params![parent_id.unwrap_or("")],
+            |row: &rusqlite::Row| row.get::<_, i32>(0),
+        )
+    }
+
+    /// 从根到指定分类的路径（面包屑），按 parent_id 逐级向上查找，
+    /// 遇到缺失的上级分类时停止（只返回能找到的部分）
+    pub fn get_breadcrumb(&self, category_id: &str) -> Result<Vec<CategoryCrumb>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT name, parent_id FROM categories WHERE id = ?1")?;
+
+        let mut crumbs = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut current = Some(category_id.to_string());
+
+        while let Some(id) = current.take() {
+            // 防止 parent_id 成环导致死循环
+            if !visited.insert(id.clone()) {
+                break;
+            }
+
+            let found = stmt.query_row(params![id], |row: &rusqlite::Row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            });
+            match found {
+                Ok((name, parent_id)) => {
+                    crumbs.push(CategoryCrumb { id, name });
+                    current = parent_id.filter(|p| !p.is_empty());
+                }
+                Err(rusqlite::Error::QueryReturnedNoRows) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        crumbs.reverse();
+        Ok(crumbs)
+    }
+
+    /// 批量导入分类（名称、上级名称、排序），两遍处理并在同一事务内完成：
+    /// 先创建全部节点，再按名称关联上级并计算 level/path，因此子分类可以排在上级之前。
+    /// 已存在的同名分类会被跳过（但可作为上级被引用），找不到的上级与循环引用会降级为顶级分类并给出提示
+    pub fn import_batch(&self, rows: &[(String, Option<String>, i32)], now: &str) -> Result<CategoryImportResult> {
+        use std::collections::{HashMap, HashSet};
+
+        enum ParentRef {
+            Imported(usize),
+            Existing(String, i32, String),
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut warnings = Vec::new();
+
+        // 已有分类：名称 -> (id, level, path)
+        let mut existing: HashMap<String, (String, i32, String)> = HashMap::new();
+        {
+            let mut stmt = tx.prepare("SELECT id, name, level, path FROM categories")?;
+            let rows = stmt.query_map([], |row: &rusqlite::Row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i32>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+            for row in rows {
+                let (id, name, level, path) = row?;
+                existing.entry(name).or_insert((id, level, path));
+            }
+        }
+
+        // 第一遍：创建所有节点，暂不关联上级
+        let mut nodes: Vec<(String, String, Option<String>)> = Vec::new(); // (id, 名称, 上级名称)
+        let mut imported: HashMap<String, usize> = HashMap::new();
+        for (name, parent_name, sort_order) in rows {
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            if existing.contains_key(name) || imported.contains_key(name) {
+                warnings.push(format!("分类「{}」已存在，已跳过", name));
+                continue;
+            }
+
+            let id = uuid::Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
+                 VALUES (?1, ?2, NULL, 0, '', ?3, ?4, ?4)",
+                params![&id, name, sort_order, now],
+            )?;
+            imported.insert(name.to_string(), nodes.len());
+            nodes.push((
+                id,
+                name.to_string(),
+                parent_name.as_deref().map(str::trim).filter(|p| !p.is_empty()).map(String::from),
+            ));
+        }
+
+        // 第二遍：按名称解析上级（优先匹配本次导入的分类）
+        let mut parents: Vec<Option<ParentRef>> = Vec::with_capacity(nodes.len());
+        for (_, name, parent_name) in &nodes {
+            let parent = match parent_name {
+                None => None,
+                Some(parent_name) => {
+                    if let Some(&index) = imported.get(parent_name) {
+                        Some(ParentRef::Imported(index))
+                    } else if let Some((id, level, path)) = existing.get(parent_name) {
+                        Some(ParentRef::Existing(id.clone(), *level, path.clone()))
+                    } else {
+                        warnings.push(format!("分类「{}」的上级「{}」不存在，已作为顶级分类导入", name, parent_name));
+                        None
+                    }
+                }
+            };
+            parents.push(parent);
+        }
+
+        // 沿上级链计算 level/path，发现循环时断开链尾节点的上级关系
+        let mut resolved: Vec<Option<(i32, String)>> = vec![None; nodes.len()];
+        for start in 0..nodes.len() {
+            let mut chain: Vec<usize> = Vec::new();
+            let mut on_chain = HashSet::new();
+            let mut current = start;
+            loop {
+                if resolved[current].is_some() {
+                    break;
+                }
+                if !on_chain.insert(current) {
+                    let last = *chain.last().unwrap();
+                    parents[last] = None;
+                    warnings.push(format!("分类「{}」的上级形成循环引用，已作为顶级分类导入", nodes[last].1));
+                    break;
+                }
+                chain.push(current);
+                match &parents[current] {
+                    Some(ParentRef::Imported(index)) => current = *index,
+                    _ => break,
+                }
+            }
+
+            for &index in chain.iter().rev() {
+                let (level, path) = match &parents[index] {
+                    None => (0, String::new()),
+                    Some(ParentRef::Existing(id, level, path)) => (level + 1, format!("{}/{}", path, id)),
+                    Some(ParentRef::Imported(parent)) => {
+                        let (level, path) = resolved[*parent].clone().unwrap_or_default();
+                        (level + 1, format!("{}/{}", path, nodes[*parent].0))
+                    }
+                };
+                resolved[index] = Some((level, path));
+            }
+        }
+
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE categories SET parent_id = ?1, level = ?2, path = ?3 WHERE id = ?4",
+            )?;
+            for (index, (id, _, _)) in nodes.iter().enumerate() {
+                let parent_id = match &parents[index] {
+                    None => None,
+                    Some(ParentRef::Existing(parent_id, _, _)) => Some(parent_id.clone()),
+                    Some(ParentRef::Imported(parent)) => Some(nodes[*parent].0.clone()),
+                };
+                let (level, path) = resolved[index].clone().unwrap_or_default();
+                stmt.execute(params![parent_id, level, path, id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(CategoryImportResult {
+            inserted: nodes.len(),
+            warnings,
+        })
+    }
+
+    pub fn save_batch(&self, categories: &[Category]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        {
+            let mut stmt_insert = tx.prepare(
+                "INSERT OR REPLACE INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+            )?;
+
+            for category in categories {
+                stmt_insert.execute(params![
+                    &category.id,
+                    &category.name,
+                    &category.parent_id,
+                    &category.level,
+                    &category.path,
+                    &category.sort_order,
+                    &category.created_at,
+                    &category.updated_at,
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// 递归查询分类及其子孙分类的 ID（UNION 去重，上级关系有环时也能结束）
+fn query_subtree_ids(conn: &rusqlite::Connection, id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT id FROM categories WHERE id = ?1
+             UNION
+             SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+         )
+         SELECT id FROM subtree",
+    )?;
+    let ids = stmt
+        .query_map(params![id], |row: &rusqlite::Row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
+
+impl Repository<Category> for CategoryRepository {
+    fn get_all(&self) -> Result<Vec<Category>> {
+        self.get_tree()
+    }
+
+    fn get_by_id(&self, id: &str) -> Result<Category> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT id, name, parent_id, level, path, sort_order, created_at, updated_at
+             FROM categories WHERE id = ?1",
+            params![id],
+            |row: &rusqlite::Row| {
+                Ok(Category {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    parent_id: row.get::<_, Option<String>>(2)?,
+                    level: row.get::<_, i32>(3)?,
+                    path: row.get::<_, String>(4)?,
+                    sort_order: row.get::<_, i32>(5)?,
+                    created_at: row.get::<_, String>(6)?,
+                    updated_at: row.get::<_, String>(7)?,
+                })
+            },
+        )
+    }
+
+    fn insert(&self, category: &Category) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                &category.id,
+                &category.name,
+                &category.parent_id,
+                &category.level,
+                &category.path,
+                &category.sort_order,
+                &category.created_at,
+                &category.updated_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn update(&self, category: &Category) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE categories SET name = ?1, parent_id = ?2, level = ?3,
+             path = ?4, sort_order = ?5, updated_at = ?6 WHERE id = ?7",
+            params![
+                &category.name,
+                &category.parent_id,
+                &category.level,
+                &category.path,
+                &category.sort_order,
+                &category.updated_at,
+                &category.id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM categories WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+// ========== Template Repository ==========
+
+pub struct TemplateRepository {
+    pub conn: DbConnection,
+}
+
+/// 模板文件以 BLOB 存储，前端接口仍使用 base64 文本；尚未迁移的旧数据直接返回原 base64
+fn encode_template_file(data: Option<Vec<u8>>, legacy_base64: String) -> String {
+    match data {
+        Some(bytes) => BASE64.encode(bytes),
+        None => legacy_base64,
+    }
+}
+
+/// 将前端传入的 base64 模板文件解码后写入 template_files；为空表示没有模板文件
+fn save_template_file(
+    conn: &rusqlite::Connection,
+    template_id: &str,
+    encoded: &str,
+    updated_at: &str,
+) -> Result<()> {
+    if encoded.trim().is_empty() {
+        conn.execute("DELETE FROM template_files WHERE template_id = ?1", params![template_id])?;
+        return Ok(());
+    }
+
+    let bytes = BASE64.decode(encoded.trim()).map_err(|e| {
+        rusqlite::Error::ToSqlConversionFailure(
+            Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+        )
+    })?;
+    conn.execute(
+        "INSERT OR REPLACE INTO template_files (template_id, data, updated_at) VALUES (?1, ?2, ?3)",
+        params![template_id, bytes, updated_at],
+    )?;
+    Ok(())
+}
+
+/// 模板列表不带文件内容，前端据此保存时 template_base64 为空而 has_file 为 true，此时保留已存的模板文件
+fn keeps_existing_file(template: &TemplateConfig) -> bool {
+    template.template_base64.trim().is_empty() && template.has_file
+}
+
+impl TemplateRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    /// 读取模板文件（base64），模板列表不含文件内容，导出时按需加载；未上传文件时返回空字符串
+    pub fn get_file_base64(&self, id: &str) -> Result<String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT t.template_base64, f.data
+             FROM templates t LEFT JOIN template_files f ON f.template_id = t.id WHERE t.id = ?1",
+            params![id],
+            |row: &rusqlite::Row| {
+                Ok(encode_template_file(
+                    row.get::<_, Option<Vec<u8>>>(1)?,
+                    row.get::<_, String>(0)?,
+                ))
+            },
+        )
+    }
+
+    /// 删除模板前将引用它的订单（以及默认模板设置）改为替代模板，整个过程在同一事务中完成，
+    /// 返回被改指向的订单数；替代模板不存在时返回 QueryReturnedNoRows 且不做任何修改
+    pub fn delete_with_reassign(&self, id: &str, replacement_id: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.query_row(
+            "SELECT 1 FROM templates WHERE id = ?1",
+            params![replacement_id],
+            |_| Ok(()),
+        )?;
+
+        let reassigned = tx.execute(
+            "UPDATE orders SET template_id = ?2 WHERE template_id = ?1",
+            params![id, replacement_id],
+        )?;
+        tx.execute(
+            "UPDATE app_settings SET default_template_id = ?2 WHERE default_template_id = ?1",
+            params![id, replacement_id],
+        )?;
+        tx.execute("DELETE FROM template_files WHERE template_id = ?1", params![id])?;
+        tx.execute("DELETE FROM templates WHERE id = ?1", params![id])?;
+
+        tx.commit()?;
+        Ok(reassigned)
+    }
+
+    /// 解析实际生效的模板（不含模板文件内容）：指定模板 → 设置中的默认模板 → is_default 模板 → 最近更新的模板
+    pub fn resolve_effective(
+        &self,
+        template_id: Option<&str>,
+        default_template_id: &str,
+    ) -> Result<TemplateConfig> {
+        let candidates = [template_id.unwrap_or(""), default_template_id];
+        for id in candidates.iter().filter(|id| !id.trim().is_empty()) {
+            match self.get_metadata(id) {
+                Ok(template) => return Ok(template),
+                Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let fallback_id: String = {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                "SELECT id FROM templates ORDER BY is_default DESC, updated_at DESC LIMIT 1",
+                [],
+                |row: &rusqlite::Row| row.get::<_, String>(0),
+            )?
+        };
+
+        self.get_metadata(&fallback_id)
+    }
+
+    /// 读取模板（不含模板文件内容，has_file 表示是否已上传文件）
+    pub fn get_metadata(&self, id: &str) -> Result<TemplateConfig> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("{} WHERE t.id = ?1", TEMPLATE_METADATA_SELECT),
+            params![id],
+            template_metadata_from_row,
+        )
+    }
+}
+
+// 模板列表与解析模板时读取的列（不含模板文件内容，最后一列为是否已上传文件）
+const TEMPLATE_METADATA_SELECT: &str =
+    "SELECT t.id, t.name, t.file_name, t.filename_pattern, t.is_default, t.mappings, t.item_end_row, t.required_fields, t.created_at, t.updated_at, t.number_format,
+            EXISTS (SELECT 1 FROM template_files f WHERE f.template_id = t.id) OR t.template_base64 != ''
+     FROM templates t";
+
+/// 将 TEMPLATE_METADATA_SELECT 的一行转换为不含文件内容的模板
+fn template_metadata_from_row(row: &rusqlite::Row) -> Result<TemplateConfig> {
+    let mappings_json: String = row.get(5)?;
+    let mut mappings: TemplateMappings = serde_json::from_str(&mappings_json).map_err(|e| {
+        rusqlite::Error::ToSqlConversionFailure(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    })?;
+
+    let item_end_row: i32 = row.get(6)?;
+    // 将 item_end_row 合并到 mappings 中
+    mappings.item_end_row = item_end_row;
+
+    let required_fields_json: String = row.get(7)?;
+    let required_fields: RequiredFields = serde_json::from_str(&required_fields_json).map_err(|e| {
+        rusqlite::Error::ToSqlConversionFailure(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    })?;
+
+    Ok(TemplateConfig {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        template_base64: String::new(),
+        file_name: row.get(2)?,
+        filename_pattern: row.get(3)?,
+        is_default: row.get(4)?,
+        mappings,
+        required_fields,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        number_format: row.get(10)?,
+        has_file: row.get(11)?,
+    })
+}
+
+impl Repository<TemplateConfig> for TemplateRepository {
+    fn get_all(&self) -> Result<Vec<TemplateConfig>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(&format!("{} ORDER BY t.name", TEMPLATE_METADATA_SELECT))?;
+
+        let templates = stmt
+            .query_map([], template_metadata_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(templates)
+    }
+
+    fn get_by_id(&self, id: &str) -> Result<TemplateConfig> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT t.id, t.name, t.template_base64, t.file_name, t.filename_pattern, t.is_default, t.mappings, t.item_end_row, t.required_fields, t.created_at, t.updated_at, t.number_format, f.data
+             FROM templates t LEFT JOIN template_files f ON f.template_id = t.id WHERE t.id = ?1",
+            params![id],
+            |row: &rusqlite::Row| {
+                let mappings_json: String = row.get(6)?;
+                let mut mappings: TemplateMappings = serde_json::from_str(&mappings_json).unwrap_or_default();
+
+                let item_end_row: i32 = row.get(7)?;
+                // 将 item_end_row 合并到 mappings 中
+                mappings.item_end_row = item_end_row;
+
+                let required_fields_json: Option<String> = row.get(8).ok();
+                let required_fields: RequiredFields = required_fields_json
+                    .and_then(|s| serde_json::from_str(&s).ok())
+                    .unwrap_or_default();
+
+                let template_base64 = encode_template_file(
+                    row.get::<_, Option<Vec<u8>>>(12)?,
+                    row.get::<_, String>(2)?,
+                );
+                Ok(TemplateConfig {
+                    id: row.get::<_, String>(0)?,
+                    name: row.get::<_, String>(1)?,
+                    has_file: !template_base64.is_empty(),
+                    template_base64,
+                    file_name: row.get::<_, String>(3)?,
+                    filename_pattern: row.get::<_, String>(4)?,
+                    is_default: row.get::<_, i32>(5)? != 0,
+                    mappings,
+                    required_fields,
+                    created_at: row.get::<_, String>(9)?,
+                    updated_at: row.get::<_, String>(10)?,
+                    number_format: row.get::<_, Option<String>>(11)?,
+                })
+            },
+        )
+    }
+
+    fn insert(&self, template: &TemplateConfig) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mappings_json = serde_json::to_string(&template.mappings).map_err(|e| {
+            rusqlite::Error::ToSqlConversionFailure(
+                Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+            )
+        })?;
+
+        let required_fields_json =
+            serde_json::to_string(&template.required_fields).map_err(|e| {
+                rusqlite::Error::ToSqlConversionFailure(
+                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+                )
+            })?;
+
+        tx.execute(
+            "INSERT INTO templates (id, name, template_base64, file_name, filename_pattern, is_default, mappings, item_end_row, required_fields, created_at, updated_at, number_format)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                &template.id,
+                &template.name,
+                "",
+                &template.file_name,
+                &template.filename_pattern,
+                &template.is_default,
+                &mappings_json,
+                &template.mappings.item_end_row,
+                &required_fields_json,
+                &template.created_at,
+                &template.updated_at,
+                &template.number_format,
+            ],
+        )?;
+        if !keeps_existing_file(template) {
+            save_template_file(&tx, &template.id, &template.template_base64, &template.updated_at)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update(&self, template: &TemplateConfig) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mappings_json = serde_json::to_string(&template.mappings).map_err(|e| {
+            rusqlite::Error::ToSqlConversionFailure(
+                Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+            )
+        })?;
+
+        let required_fields_json =
+            serde_json::to_string(&template.required_fields).map_err(|e| {
+                rusqlite::Error::ToSqlConversionFailure(
+                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+                )
+            })?;
+
+        tx.execute(
+            "UPDATE templates SET name = ?1, template_base64 = ?2, file_name = ?3,
+             filename_pattern = ?4, is_default = ?5, mappings = ?6, item_end_row = ?7, required_fields = ?8, updated_at = ?9, number_format = ?10 WHERE id = ?11",
+            params![
+                &template.name,
+                "",
+                &template.file_name,
+                &template.filename_pattern,
+                &template.is_default,
+                &mappings_json,
+                &template.mappings.item_end_row,
+                &required_fields_json,
+                &template.updated_at,
+                &template.number_format,
+                &template.id,
+            ],
+        )?;
+        if !keeps_existing_file(template) {
+            save_template_file(&tx, &template.id, &template.template_base64, &template.updated_at)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM template_files WHERE template_id = ?1", params![id])?;
+        conn.execute("DELETE FROM templates WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+// ========== Settings Repository ==========
+
+pub struct SettingsRepository {
+    pub conn: DbConnection,
+}
+
+impl SettingsRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    pub fn get_settings(&self) -> Result<Option<AppSettings>> {
+        let conn = self.conn.lock().unwrap();
+
+        let result = conn.query_row(
+            "SELECT id, data_directory, output_directory, backup_directory, font_size, theme,
+              remember_window, date_format, excel_date_format, order_number_format, order_number_prefix,
+              order_number_reset_daily, order_number_digits, retain_days, auto_backup, backup_interval,
+              backup_keep_count, default_template_id, default_category_id,
+              excel_filename_format, auto_open_excel, skip_save_dialog,
+              template_validation, log_level, total_rounding, tax_rate, prices_include_tax, max_item_quantity, max_order_total, block_on_order_limits, export_item_sort, default_track_stock, default_min_stock, allow_zero_price, updated_at,
+              order_number_reset_period, strict_stock
+              FROM app_settings WHERE id = 'settings'",
+            [],
+            |row: &rusqlite::Row| {
+                Ok(AppSettings {
+                    id: row.get::<_, String>(0)?,
+                    data_directory: row.get::<_, String>(1)?,
+                    output_directory: row.get::<_, String>(2)?,
+                    backup_directory: row.get::<_, String>(3)?,
+                    font_size: row.get::<_, i32>(4)?,
+                    theme: row.get::<_, String>(5)?,
+                    remember_window: row.get::<_, i32>(6)? != 0,
+                    date_format: row.get::<_, String>(7)?,
+                    excel_date_format: row.get::<_, String>(8)?,
+                    order_number_format: row.get::<_, String>(9)?,
+                    order_number_prefix: row.get::<_, String>(10)?,
+                    order_number_reset_daily: row.get::<_, i32>(11)? != 0,
+                    order_number_reset_period: row.get::<_, Option<String>>(35)?.unwrap_or_default(),
+                    order_number_digits: row.get::<_, i32>(12)?,
+                    retain_days: row.get::<_, i32>(13)?,
+                    auto_backup: row.get::<_, i32>(14)? != 0,
+                    backup_interval: row.get::<_, i32>(15)?,
+                    backup_keep_count: row.get::<_, i32>(16)?,
+                    default_template_id: row.get::<_, String>(17)?,
+                    default_category_id: row.get::<_, String>(18)?,
+                    excel_filename_format: row.get::<_, String>(19)?,
+                    auto_open_excel: row.get::<_, i32>(20)? != 0,
+                    skip_save_dialog: row.get::<_, i32>(21)? != 0,
+                    template_validation: {
+                        let val: Option<String> = row.get(22)?;
+                        val.and_then(|v| serde_json::from_str::<crate::models::RequiredFields>(&v).ok())
+                    },
+                    log_level: row
+                        .get::<_, Option<String>>(23)?
+                        .unwrap_or_else(|| "info".to_string()),
+                    total_rounding: row
+                        .get::<_, Option<String>>(24)?
+                        .unwrap_or_else(|| "none".to_string()),
+                    tax_rate: row.get::<_, Option<f64>>(25)?.unwrap_or(0.0),
+                    prices_include_tax: row.get::<_, Option<i32>>(26)?.unwrap_or(1) != 0,
+                    max_item_quantity: row.get::<_, Option<f64>>(27)?.unwrap_or(0.0),
+                    max_order_total: row.get::<_, Option<f64>>(28)?.unwrap_or(0.0),
+                    block_on_order_limits: row.get::<_, Option<i32>>(29)?.unwrap_or(0) != 0,
+                    export_item_sort: row
+                        .get::<_, Option<String>>(30)?
+                        .unwrap_or_else(|| "entry".to_string()),
+                    default_track_stock: row.get::<_, Option<i32>>(31)?.unwrap_or(0) != 0,
+                    default_min_stock: row.get::<_, Option<f64>>(32)?.unwrap_or(0.0),
+                    allow_zero_price: row.get::<_, Option<i32>>(33)?.unwrap_or(1) != 0,
+                    strict_stock: row.get::<_, Option<i32>>(36)?.unwrap_or(0) != 0,
+                    updated_at: row.get::<_, String>(34)?,
+                })
+            },
+        );
+
+        match result {
+            Ok(settings) => Ok(Some(settings)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_settings(&self, settings: &AppSettings) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        // 旧开关与重置周期保持一致
+        let reset_period = order_number_reset_period(settings);
+
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings
+             (id, data_directory, output_directory, backup_directory, font_size, theme,
+              remember_window, date_format, excel_date_format, order_number_format, order_number_prefix,
+              order_number_reset_daily, order_number_digits, retain_days, auto_backup, backup_interval,
+              backup_keep_count, default_template_id, default_category_id,
+              excel_filename_format, auto_open_excel, skip_save_dialog,
+              template_validation, log_level, total_rounding, tax_rate, prices_include_tax, max_item_quantity, max_order_total, block_on_order_limits, export_item_sort, default_track_stock, default_min_stock, allow_zero_price, updated_at,
+              order_number_reset_period, strict_stock)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34, ?35, ?36, ?37)",
+            params![
+                &settings.id,
+                &settings.data_directory,
+                &settings.output_directory,
+                &settings.backup_directory,
+                &settings.font_size,
+                &settings.theme,
+                &settings.remember_window,
+                &settings.date_format,
+                &settings.excel_date_format,
+                &settings.order_number_format,
+                &settings.order_number_prefix,
+                &(reset_period == "daily"),
+                &settings.order_number_digits,
+                &settings.retain_days,
+                &settings.auto_backup,
+                &settings.backup_interval,
+                &settings.backup_keep_count,
+                &settings.default_template_id,
+                &settings.default_category_id,
+                &settings.excel_filename_format,
+                &settings.auto_open_excel,
+                &settings.skip_save_dialog,
+                &serde_json::to_string(&settings.template_validation.clone().unwrap_or_default()).unwrap_or_else(|_| "{}".to_string()),
+                &settings.log_level,
+                &settings.total_rounding,
+                &settings.tax_rate,
+                &settings.prices_include_tax,
+                &settings.max_item_quantity,
+                &settings.max_order_total,
+                &settings.block_on_order_limits,
+                &settings.export_item_sort,
+                &settings.default_track_stock,
+                &settings.default_min_stock,
+                &settings.allow_zero_price,
+                &settings.updated_at,
+                reset_period,
+                &settings.strict_stock,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// 检查设置中的默认模板/默认分类是否仍然存在（为空视为未设置）。
+    /// heal 为 true 时将失效的引用修正为默认模板 / 第一个分类（没有可用项时清空）
+    pub fn validate_references(&self, heal: bool, now: &str) -> Result<SettingsReferenceCheck> {
+        let conn = self.conn.lock().unwrap();
+
+        let (settings_id, template_id, category_id): (String, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT id, default_template_id, default_category_id FROM app_settings LIMIT 1",
+                [],
+                |row: &rusqlite::Row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+        let template_id = template_id.unwrap_or_default();
+        let category_id = category_id.unwrap_or_default();
+
+        let exists = |sql: &str, id: &str| -> Result<bool> {
+            if id.trim().is_empty() {
+                return Ok(true);
+            }
+            conn.query_row(sql, params![id], |row: &rusqlite::Row| row.get::<_, i64>(0))
+                .map(|n| n > 0)
+        };
+        let template_exists = exists("SELECT COUNT(*) FROM templates WHERE id = ?1", &template_id)?;
+        let category_exists = exists("SELECT COUNT(*) FROM categories WHERE id = ?1", &category_id)?;
+
+        let mut check = SettingsReferenceCheck {
+            template_exists,
+            category_exists,
+            default_template_id: template_id,
+            default_category_id: category_id,
+            healed: false,
+        };
+        if !heal || (template_exists && category_exists) {
+            return Ok(check);
+        }
+
+        if !template_exists {
+            check.default_template_id = conn
+                .query_row(
+                    "SELECT COALESCE((SELECT id FROM templates ORDER BY is_default DESC, updated_at DESC LIMIT 1), '')",
+                    [],
+                    |row: &rusqlite::Row| row.get::<_, String>(0),
+                )?;
+        }
+        if !category_exists {
+            check.default_category_id = conn
+                .query_row(
+                    "SELECT COALESCE((SELECT id FROM categories ORDER BY level, sort_order, name LIMIT 1), '')",
+                    [],
+                    |row: &rusqlite::Row| row.get::<_, String>(0),
+                )?;
+        }
+
+        conn.execute(
+            "UPDATE app_settings SET default_template_id = ?1, default_category_id = ?2, updated_at = ?3 WHERE id = ?4",
+            params![
+                &check.default_template_id,
+                &check.default_category_id,
+                now,
+                &settings_id
+            ],
+        )?;
+        check.healed = true;
+
+        Ok(check)
+    }
+}
+
+// ========== Order Repository ==========
+
+pub struct OrderRepository {
+    pub conn: DbConnection,
+}
+
+impl OrderRepository {
+    pub fn new(conn: DbConnection) -> Self {
+        Self { conn }
+    }
+
+    pub fn get_order_items(&self, order_id: &str) -> Result<Vec<OrderItem>> {
+        self.query_items("order_items", order_id)
+    }
+
+    /// 从订单项表或订单项归档表读取订单项
+    fn query_items(&self, table: &str, order_id: &str) -> Result<Vec<OrderItem>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM {} i WHERE i.order_id = ?1 ORDER BY i.sort_value",
+            ORDER_ITEM_COLUMNS, table
+        ))?;
+        let items = stmt
+            .query_map(params![order_id], order_item_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    /// 获取全部订单（含客户与订单项），与 get_all 顺序相同。只执行两条查询：
+    /// 订单关联客户一次读出，订单项一次读出后在内存中按订单分组，避免逐个订单查询
+    pub fn get_all_with_details(&self) -> Result<Vec<Order>> {
+        let conn = self.conn.lock().unwrap();
+        Self::query_with_details(&conn, "", Vec::new(), "o.created_at DESC", None)
+    }
+
+    /// 获取订单日期在 start 与 end 之间（YYYY-MM-DD，含首尾）的订单（含客户与订单项），按日期排列。
+    /// 日期按字符串比较，只取订单日期的前 10 位，带时间的日期也归入当天；start 晚于 end 时返回空列表
+    pub fn get_by_date_range(&self, start: &str, end: &str) -> Result<Vec<Order>> {
+        let conn = self.conn.lock().unwrap();
+        Self::query_with_details(
+            &conn,
+            "WHERE substr(o.date, 1, 10) BETWEEN ?1 AND ?2",
+            vec![Value::Text(start.to_string()), Value::Text(end.to_string())],
+            "o.date, o.created_at",
+            None,
+        )
+    }
+
+    /// 按 ID 获取订单（含客户与订单项），按 ids 的顺序返回，不存在的 ID 跳过
+    pub fn get_by_ids_with_details(&self, ids: &[String]) -> Result<Vec<Order>> {
+        use std::collections::HashMap;
+
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn.lock().unwrap();
+        let placeholders = (1..=ids.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+        let orders = Self::query_with_details(
+            &conn,
+            &format!("WHERE o.id IN ({})", placeholders),
+            ids.iter().map(|id| Value::Text(id.clone())).collect(),
+            "o.created_at DESC",
+            None,
+        )?;
+
+        let mut by_id: HashMap<String, Order> = orders.into_iter().map(|o| (o.id.clone(), o)).collect();
+        Ok(ids.iter().filter_map(|id| by_id.remove(id)).collect())
+    }
+
+    /// 分页获取订单（含客户与订单项）及订单总数；order_by 须为调用方给定的固定排序子句（表别名为 o），不能来自用户输入
+    pub fn get_page_with_details(&self, order_by: &str, limit: u32, offset: u32) -> Result<OrderPage> {
+        let conn = self.conn.lock().unwrap();
+        let total: i64 = conn.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))?;
+        let items = Self::query_with_details(&conn, "", Vec::new(), order_by, Some((limit, offset)))?;
+        Ok(OrderPage { items, total })
+    }
+
+    /// 按条件与排序读取订单（where_clause 以 WHERE 开头或为空，表别名为 o，参数按 ?1、?2… 编号；
+    /// page 为 (limit, offset) 时只读一页），订单项只读取这些订单的
+    fn query_with_details(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        mut values: Vec<Value>,
+        order_by: &str,
+        page: Option<(u32, u32)>,
+    ) -> Result<Vec<Order>> {
+        use std::collections::HashMap;
+
+        let page_clause = match page {
+            Some((limit, offset)) => {
+                let limit_index = values.len() + 1;
+                values.push(Value::Integer(limit as i64));
+                values.push(Value::Integer(offset as i64));
+                format!("LIMIT ?{} OFFSET ?{}", limit_index, limit_index + 1)
+            }
+            None => String::new(),
+        };
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {},
+                    c.id, c.name, c.phone, c.license_plate, c.address, c.last_purchase_at, c.created_at, c.updated_at
+             FROM orders o LEFT JOIN customers c ON c.id = o.customer_id
+             {}
+             ORDER BY {}
+             {}",
+            ORDER_COLUMNS, where_clause, order_by, page_clause
+        ))?;
+        let mut orders = stmt
+            .query_map(params_from_iter(values.iter()), |row: &rusqlite::Row| {
+                let mut order = order_from_row(row)?;
+                // 客户已不存在时保留空的客户信息（与逐个查询时一致）
+                if let Some(customer_id) = row.get::<_, Option<String>>(17)? {
+                    order.customer = Customer {
+                        id: customer_id,
+                        name: row.get::<_, String>(18)?,
+                        phone: row.get::<_, String>(19)?,
+                        license_plate: row.get::<_, String>(20)?,
+                        address: row.get::<_, Option<String>>(21)?,
+                        last_purchase_at: row.get::<_, Option<String>>(22)?,
+                        created_at: row.get::<_, String>(23)?,
+                        updated_at: row.get::<_, String>(24)?,
+                    };
+                }
+                Ok(order)
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let items_filter = if page.is_some() || !where_clause.is_empty() {
+            format!(
+                "WHERE i.order_id IN (SELECT o.id FROM orders o {} ORDER BY {} {})",
+                where_clause, order_by, page_clause
+            )
+        } else {
+            String::new()
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM order_items i {} ORDER BY i.order_id, i.sort_value",
+            ORDER_ITEM_COLUMNS, items_filter
+        ))?;
+        let mut items_by_order: HashMap<String, Vec<OrderItem>> = HashMap::new();
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row: &rusqlite::Row| {
+            Ok((row.get::<_, String>(0)?, order_item_from_row(row)?))
+        })?;
+        for row in rows {
+            let (order_id, item) = row?;
+            items_by_order.entry(order_id).or_default().push(item);
+        }
+
+        for order in &mut orders {
+            order.items = items_by_order.remove(&order.id).unwrap_or_default();
+        }
+        Ok(orders)
+    }
+
+    /// 搜索订单（订单号、客户名/电话/车牌、商品名），优先走全文索引并按相关度排序，
+    /// 少于 3 个字符或索引不可用时回退到 LIKE 搜索。结果含客户与订单项，一次性批量读取
+    pub fn search(&self, query: &str) -> Result<Vec<Order>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return self.get_all();
+        }
+
+        let ids = {
+            let conn = self.conn.lock().unwrap();
+            let fts_ids = if query.chars().count() >= 3 {
+                Self::search_ids_fts(&conn, query).ok()
+            } else {
+                None
+            };
+            match fts_ids {
+                Some(ids) => ids,
+                None => Self::search_ids_like(&conn, query)?,
+            }
+        };
+
+        self.get_by_ids_with_details(&ids)
+    }
+
+    fn search_ids_fts(conn: &rusqlite::Connection, query: &str) -> Result<Vec<String>> {
+        // 整体作为短语匹配，避免用户输入被解析为 FTS 语法
+        let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+        let mut stmt = conn.prepare(
+            "SELECT f.order_id FROM orders_fts f
+             JOIN orders o ON o.id = f.order_id
+             WHERE orders_fts MATCH ?1
+             ORDER BY f.rank, o.created_at DESC",
+        )?;
+        let ids = stmt
+            .query_map(params![phrase], |row: &rusqlite::Row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    fn search_ids_like(conn: &rusqlite::Connection, query: &str) -> Result<Vec<String>> {
+        let pattern = format!("%{}%", query);
+        let mut stmt = conn.prepare(
+            "SELECT o.id FROM orders o
+             LEFT JOIN customers c ON c.id = o.customer_id
+             WHERE o.order_number LIKE ?1
+                OR c.name LIKE ?1
+                OR c.phone LIKE ?1
+                OR c.license_plate LIKE ?1
+                OR EXISTS (SELECT 1 FROM order_items i WHERE i.order_id = o.id AND i.name LIKE ?1)
+             ORDER BY o.created_at DESC",
+        )?;
+        let ids = stmt
+            .query_map(params![pattern], |row: &rusqlite::Row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    /// 统计日期区间内已完成订单的数量与金额，外币订单按记录的汇率折算为本币
+    pub fn get_sales_total(&self, from: &str, to: &str, include_archived: bool) -> Result<SalesTotal> {
+        let conn = self.conn.lock().unwrap();
+        query_sales_total(&conn, from, to, include_archived)
+    }
+
+    /// 按日汇总已完成订单的数量与金额
+    pub fn get_daily_sales(&self, from: &str, to: &str, include_archived: bool) -> Result<Vec<DailySales>> {
+        let conn = self.conn.lock().unwrap();
+        query_daily_sales(&conn, from, to, include_archived)
+    }
+
+    /// 销售额前 limit 名的商品
+    pub fn get_top_products(&self, from: &str, to: &str, include_archived: bool, limit: u32) -> Result<Vec<ProductSales>> {
+        let conn = self.conn.lock().unwrap();
+        query_top_products(&conn, from, to, include_archived, limit)
+    }
+
+    /// 消费金额前 limit 名的客户
+    pub fn get_top_customers(&self, from: &str, to: &str, include_archived: bool, limit: u32) -> Result<Vec<CustomerSales>> {
+        let conn = self.conn.lock().unwrap();
+        query_top_customers(&conn, from, to, include_archived, limit)
+    }
+
+    /// 在同一次加锁内计算报表页需要的全部统计（合计、逐日汇总、商品与客户排行、分类排行）
+    pub fn get_report_bundle(&self, from: &str, to: &str, include_archived: bool, limit: u32) -> Result<ReportBundle> {
+        let conn = self.conn.lock().unwrap();
+        Ok(ReportBundle {
+            from: from.to_string(),
+            to: to.to_string(),
+            sales_total: query_sales_total(&conn, from, to, include_archived)?,
+            daily_sales: query_daily_sales(&conn, from, to, include_archived)?,
+            top_products: query_top_products(&conn, from, to, include_archived, limit)?,
+            top_customers: query_top_customers(&conn, from, to, include_archived, limit)?,
+            category_sales: query_category_sales(&conn, from, to, include_archived)?,
+        })
+    }
+
+    /// 重建订单全文索引，返回索引的订单数
+    pub fn rebuild_search_index(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        rebuild_order_search_index(&conn)
+    }
+
+    /// 按当前顺序（sort_value，其次插入顺序）将每个订单的订单项重新编号为 0..n，返回被修改的订单数
+    pub fn normalize_all_item_sort(&self) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let rows: Vec<(String, String, i64)> = {
+            let mut stmt = tx.prepare(
+                "SELECT order_id, id, sort_value FROM order_items
+                 ORDER BY order_id, sort_value, rowid",
+            )?;
+            let rows = stmt
+                .query_map([], |row: &rusqlite::Row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+
+        let mut touched_orders = 0;
+        {
+            let mut stmt_update = tx.prepare("UPDATE order_items SET sort_value = ?1 WHERE id = ?2")?;
+
+            let mut start = 0;
+            while start < rows.len() {
+                let order_id = &rows[start].0;
+                let end = rows[start..]
+                    .iter()
+                    .position(|(id, _, _)| id != order_id)
+                    .map_or(rows.len(), |offset| start + offset);
+
+                let mut touched = false;
+                for (index, (_, item_id, sort_value)) in rows[start..end].iter().enumerate() {
+                    let index = index as i64;
+                    if *sort_value != index {
+                        stmt_update.execute(params![index, item_id])?;
+                        touched = true;
+                    }
+                }
+                if touched {
+                    touched_orders += 1;
+                }
+
+                start = end;
+            }
+        }
+
+        tx.commit()?;
+        Ok(touched_orders)
+    }
+
+    /// 查找所属订单已不存在的订单项（旧版本删除订单时未级联清理，启动时会自动删除）
+    pub fn find_orphaned_items(&self) -> Result<OrphanedOrderItems> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id FROM order_items
+             WHERE order_id NOT IN (SELECT id FROM orders)
+             ORDER BY order_id, sort_value",
+        )?;
+        let ids = stmt
+            .query_map([], |row: &rusqlite::Row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OrphanedOrderItems { count: ids.len(), ids })
+    }
+
+    /// 删除孤立订单项，返回删除数量
+    pub fn purge_orphaned_items(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        delete_orphaned_order_items(&conn)
+    }
+
+    /// 查找重复的订单号（旧版本导入或外部编辑的数据库可能绕过了唯一约束）
+    pub fn find_duplicate_numbers(&self) -> Result<Vec<DuplicateOrderNumber>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT order_number, id FROM orders
+             WHERE order_number IN (SELECT order_number FROM orders GROUP BY order_number HAVING COUNT(*) > 1)
+             ORDER BY order_number, created_at, rowid",
+        )?;
+        let rows = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut duplicates: Vec<DuplicateOrderNumber> = Vec::new();
+        for (order_number, id) in rows {
+            match duplicates.last_mut() {
+                Some(last) if last.order_number == order_number => last.order_ids.push(id),
+                _ => duplicates.push(DuplicateOrderNumber { order_number, order_ids: vec![id] }),
+            }
+        }
+        Ok(duplicates)
+    }
+
+    /// 在调用方的连接或事务上读取订单各行的 (商品ID, 数量)，手工录入的行商品ID为空
+    pub fn item_quantities_on(conn: &rusqlite::Connection, order_id: &str) -> Result<Vec<(String, f64)>> {
+        let mut stmt = conn.prepare("SELECT COALESCE(product_id, ''), quantity FROM order_items WHERE order_id = ?1 ORDER BY sort_value, rowid")?;
+        let rows = stmt
+            .query_map(params![order_id], |row: &rusqlite::Row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// 在调用方的连接或事务上写入订单及订单项
+    pub fn insert_on(conn: &rusqlite::Connection, order: &Order) -> Result<()> {
+        insert_order_row(conn, order)?;
+        insert_order_items(conn, order)
+    }
+
+    /// 订单号是否已被占用
+    pub fn number_exists(&self, order_number: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM orders WHERE order_number = ?1",
+            params![order_number],
+            |row: &rusqlite::Row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// 修改订单号
+    pub fn set_number(&self, order_id: &str, order_number: &str, updated_at: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE orders SET order_number = ?1, updated_at = ?2 WHERE id = ?3",
+            params![order_number, updated_at, order_id],
+        )?;
+        Ok(())
+    }
+
+    /// 获取客户的全部订单（含订单项），按创建时间从早到晚排列；订单项用一条查询批量读取
+    pub fn get_by_customer(&self, customer_id: &str) -> Result<Vec<Order>> {
+        use std::collections::HashMap;
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM orders o WHERE o.customer_id = ?1 ORDER BY o.created_at",
+            ORDER_COLUMNS
+        ))?;
+        let mut orders = stmt
+            .query_map(params![customer_id], order_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM order_items i
+             WHERE i.order_id IN (SELECT id FROM orders WHERE customer_id = ?1)
+             ORDER BY i.order_id, i.sort_value",
+            ORDER_ITEM_COLUMNS
+        ))?;
+        let mut items_by_order: HashMap<String, Vec<OrderItem>> = HashMap::new();
+        let rows = stmt.query_map(params![customer_id], |row: &rusqlite::Row| {
+            Ok((row.get::<_, String>(0)?, order_item_from_row(row)?))
+        })?;
+        for row in rows {
+            let (order_id, item) = row?;
+            items_by_order.entry(order_id).or_default().push(item);
+        }
+
+        for order in &mut orders {
+            order.items = items_by_order.remove(&order.id).unwrap_or_default();
+        }
+        Ok(orders)
+    }
+
+    /// 将指定日期（不含）之前的已完成/已取消订单连同订单项移入归档表，整个过程在同一事务中完成，
+    /// 返回归档的订单数；带附件的订单不归档（附件记录会随订单删除而级联删除）
+    pub fn archive_before(&self, before: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM orders
+                 WHERE status IN ('completed', 'cancelled') AND substr(date, 1, 10) < ?1
+                   AND id NOT IN (SELECT order_id FROM order_attachments)",
+            )?;
+            let rows = stmt
+                .query_map(params![before], |row: &rusqlite::Row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+
+        let order_columns = table_column_names(&tx, "orders")?.join(", ");
+        let item_columns = table_column_names(&tx, "order_items")?.join(", ");
+        {
+            let mut copy_order = tx.prepare(&format!(
+                "INSERT INTO orders_archive ({0}) SELECT {0} FROM orders WHERE id = ?1",
+                order_columns
+            ))?;
+            let mut copy_items = tx.prepare(&format!(
+                "INSERT INTO order_items_archive ({0}) SELECT {0} FROM order_items WHERE order_id = ?1",
+                item_columns
+            ))?;
+            // 显式删除订单项，不依赖连接是否开启了外键级联
+            let mut delete_items = tx.prepare("DELETE FROM order_items WHERE order_id = ?1")?;
+            let mut delete_order = tx.prepare("DELETE FROM orders WHERE id = ?1")?;
+            for id in &ids {
+                copy_order.execute(params![id])?;
+                copy_items.execute(params![id])?;
+                delete_items.execute(params![id])?;
+                delete_order.execute(params![id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(ids.len())
+    }
+
+    /// 获取归档订单（含订单项，客户信息由调用方填充），按日期倒序；订单项用一条查询批量读取
+    pub fn get_archived(&self) -> Result<Vec<Order>> {
+        use std::collections::HashMap;
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM orders_archive o ORDER BY o.date DESC, o.created_at DESC",
+            ORDER_COLUMNS
+        ))?;
+        let mut orders = stmt
+            .query_map([], order_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM order_items_archive i ORDER BY i.order_id, i.sort_value",
+            ORDER_ITEM_COLUMNS
+        ))?;
+        let mut items_by_order: HashMap<String, Vec<OrderItem>> = HashMap::new();
+        let rows = stmt.query_map([], |row: &rusqlite::Row| {
+            Ok((row.get::<_, String>(0)?, order_item_from_row(row)?))
+        })?;
+        for row in rows {
+            let (order_id, item) = row?;
+            items_by_order.entry(order_id).or_default().push(item);
+        }
+
+        for order in &mut orders {
+            order.items = items_by_order.remove(&order.id).unwrap_or_default();
+        }
+        Ok(orders)
+    }
+
+    /// 将归档订单连同订单项恢复到订单表；订单不在归档中时返回 QueryReturnedNoRows
+    pub fn restore_archived(&self, order_id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.query_row(
+            "SELECT 1 FROM orders_archive WHERE id = ?1",
+            params![order_id],
+            |_| Ok(()),
+        )?;
+
+        // 归档后删除的模板不会被置空，恢复前先清除，避免外键检查失败
+        tx.execute(
+            "UPDATE orders_archive SET template_id = NULL
+             WHERE id = ?1 AND template_id IS NOT NULL AND template_id NOT IN (SELECT id FROM templates)",
+            params![order_id],
+        )?;
+
+        let order_columns = table_column_names(&tx, "orders")?.join(", ");
+        let item_columns = table_column_names(&tx, "order_items")?.join(", ");
+        tx.execute(
+            &format!("INSERT INTO orders ({0}) SELECT {0} FROM orders_archive WHERE id = ?1", order_columns),
+            params![order_id],
+        )?;
+        tx.execute(
+            &format!(
+                "INSERT INTO order_items ({0}) SELECT {0} FROM order_items_archive WHERE order_id = ?1",
+                item_columns
+            ),
+            params![order_id],
+        )?;
+        tx.execute("DELETE FROM order_items_archive WHERE order_id = ?1", params![order_id])?;
+        tx.execute("DELETE FROM orders_archive WHERE id = ?1", params![order_id])?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 批量设置订单模板（status 指定时只修改该状态的订单），整个过程在同一事务中完成，
+    /// 返回实际修改的订单数；模板不存在时返回 QueryReturnedNoRows 且不做任何修改
+    pub fn set_template(&self, order_ids: &[String], template_id: &str, status: Option<&str>, updated_at: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.query_row(
+            "SELECT 1 FROM templates WHERE id = ?1",
+            params![template_id],
+            |_| Ok(()),
+        )?;
+
+        let mut updated = 0;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE orders SET template_id = ?1, updated_at = ?2
+                 WHERE id = ?3 AND (?4 IS NULL OR status = ?4) AND COALESCE(template_id, '') <> ?1",
+            )?;
+            for id in order_ids {
+                updated += stmt.execute(params![template_id, updated_at, id, status])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    /// 拆分订单：写入新订单，把 new_order.items 对应的订单项（按 line_id）从原订单移到新订单，
+    /// 并更新原订单总额。传入调用方的事务，以便与挂账同步、操作记录一起提交。订单项只是改变归属，不涉及库存
+    pub fn split_on(conn: &rusqlite::Connection, source: &Order, new_order: &Order) -> Result<()> {
+        conn.execute(
+            "INSERT INTO orders (id, order_number, date, customer_id, total_amount, total_amount_cents, remark, template_id, status, on_account, rounding_adjustment, rounding_adjustment_cents, currency, exchange_rate, order_discount, order_discount_cents, tax_rate, prices_include_tax, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            params![
+                &new_order.id, &new_order.order_number, &new_order.date, &new_order.customer_id,
+                &new_order.total_amount, &to_cents(new_order.total_amount), &new_order.remark, &new_order.template_id, &new_order.status,
+                &new_order.on_account, &new_order.rounding_adjustment, &to_cents(new_order.rounding_adjustment),
+                &new_order.currency, &new_order.exchange_rate, &new_order.order_discount, &to_cents(new_order.order_discount),
+                &new_order.tax_rate, &new_order.prices_include_tax, &new_order.created_at, &new_order.updated_at,
+            ],
+        )?;
+
+        // 按行 ID 移动（手工录入的行、同一商品的多行可以分别拆出）。订单项 ID 以「订单ID_」开头，
+        // 移动时换成新订单 ID（保留后面的部分，原订单中的行 ID 互不相同，移过去也不会重名）
+        for item in &new_order.items {
+            let Some(line_id) = item.line_id.as_deref() else { continue };
+            conn.execute(
+                "UPDATE order_items SET id = ?1 || substr(id, length(?2) + 1), order_id = ?1
+                 WHERE id = ?3 AND order_id = ?2",
+                params![&new_order.id, &source.id, line_id],
+            )?;
+        }
+
+        conn.execute(
+            "UPDATE orders SET total_amount = ?1, total_amount_cents = ?2, rounding_adjustment = ?3, rounding_adjustment_cents = ?4, tax_rate = ?5, prices_include_tax = ?6, updated_at = ?7 WHERE id = ?8",
+            params![
+                &source.total_amount, &to_cents(source.total_amount),
+                &source.rounding_adjustment, &to_cents(source.rounding_adjustment),
+                &source.tax_rate, &source.prices_include_tax, &source.updated_at, &source.id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// 记录一次导出：导出次数加 1 并更新最后导出时间
+    pub fn record_export(&self, id: &str, exported_at: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE orders SET export_count = COALESCE(export_count, 0) + 1, last_exported_at = ?1 WHERE id = ?2",
+            params![exported_at, id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    pub fn get_export_info(&self, id: &str) -> Result<OrderExportInfo> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, order_number, COALESCE(export_count, 0), last_exported_at FROM orders WHERE id = ?1",
+            params![id],
+            |row: &rusqlite::Row| {
+                Ok(OrderExportInfo {
+                    order_id: row.get(0)?,
+                    order_number: row.get(1)?,
+                    export_count: row.get(2)?,
+                    last_exported_at: row.get(3)?,
                 })
             },
-        );
+        )
+    }
 
-        match result {
-            Ok(settings) => Ok(Some(settings)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e),
+    /// 更新已有订单及其订单项，并按每个商品数量的变化调整库存，传入调用方的事务：
+    /// 删除的商品退回原数量，新增的商品扣减，数量变化的按差额扣减或退回。返回因扣减降到最低库存及以下的商品。
+    /// 只有占用库存的状态（已确认、已完成）计入库存，例如草稿改为已完成时按全部数量扣减
+    pub fn update_with_items_on(conn: &rusqlite::Connection, order: &Order) -> Result<Vec<LowStockAlert>> {
+        let previous_status: String =
+            conn.query_row("SELECT status FROM orders WHERE id = ?1", params![&order.id], |row| row.get(0))?;
+        let mut deltas: Vec<(String, f64)> = if !holds_stock(&previous_status) {
+            Vec::new()
+        } else {
+            let mut stmt = conn.prepare(
+                "SELECT COALESCE(product_id, ''), SUM(quantity) FROM order_items WHERE order_id = ?1 GROUP BY product_id ORDER BY MIN(sort_value)",
+            )?;
+            let previous = stmt
+                .query_map(params![&order.id], |row: &rusqlite::Row| {
+                    Ok((row.get::<_, String>(0)?, -row.get::<_, f64>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            previous
+        };
+        if holds_stock(&order.status) {
+            for item in &order.items {
+                match deltas.iter_mut().find(|(product_id, _)| *product_id == item.id) {
+                    Some((_, delta)) => *delta += item.quantity,
+                    None => deltas.push((item.id.clone(), item.quantity)),
+                }
+            }
         }
+        deltas.retain(|(_, delta)| delta.abs() > f64::EPSILON);
+
+        update_order_row(conn, order)?;
+        conn.execute("DELETE FROM order_items WHERE order_id = ?1", params![&order.id])?;
+        insert_order_items(conn, order)?;
+        ProductRepository::adjust_stock_batch(conn, &deltas, Some(&order.id), &order.updated_at)
     }
 
-    pub fn save_settings(&self, settings: &AppSettings) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
+    /// 修改订单主表字段（如状态）并按 stock_deltas 调整库存，传入调用方的事务。
+    /// stock_deltas 为 (商品ID, 数量变化)，正数扣减、负数退回。返回因扣减降到最低库存及以下的商品
+    pub fn update_with_stock_on(conn: &rusqlite::Connection, order: &Order, stock_deltas: &[(String, f64)]) -> Result<Vec<LowStockAlert>> {
+        update_order_row(conn, order)?;
+        ProductRepository::adjust_stock_batch(conn, stock_deltas, Some(&order.id), &order.updated_at)
+    }
+
+    /// 删除订单并退回订单项扣减的库存，同时删除该订单的挂账记录与附件记录，全部在同一事务中完成。
+    /// items 为 (商品ID, 数量)；订单不存在时返回 QueryReturnedNoRows 且不改动库存
+    pub fn delete_with_restock(&self, id: &str, items: &[(String, f64)], now: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        if !delete_order_restocking(&tx, id, items, now)? {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        tx.commit()
+    }
+
+    /// 批量删除订单并退回各订单项扣减的库存、删除挂账记录与附件记录，整批在同一事务中完成，任一订单失败则全部回滚。
+    /// 不存在的订单跳过，返回实际删除的订单数
+    pub fn delete_batch_with_restock(&self, ids: &[String], now: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mut deleted = 0;
+        for id in ids {
+            let items = Self::item_quantities_on(&tx, id)?;
+            if delete_order_restocking(&tx, id, &items, now)? {
+                deleted += 1;
+            }
+        }
+
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// 合并订单：primary.items 为合并后的订单项（line_id 指向主订单或 secondary 中的行）。
+    /// 主订单的行按 line_id 更新数量与行金额，secondary 的行移到主订单末尾，未列出的 secondary 行（数量已并入主订单）删除；
+    /// 按 primary 传入的总额与整单优惠更新主订单后删除 secondary。传入调用方的事务，以便与操作记录一起提交，不涉及库存
+    pub fn merge_on(conn: &rusqlite::Connection, primary: &Order, secondary_id: &str) -> Result<()> {
+        let mut next_sort: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(sort_value) + 1, 0) FROM order_items WHERE order_id = ?1",
+            params![&primary.id],
+            |row: &rusqlite::Row| row.get(0),
+        )?;
+        let own_prefix = format!("{}_", primary.id);
+        let secondary_prefix = format!("{}_", secondary_id);
+        for item in &primary.items {
+            let Some(line_id) = item.line_id.as_deref() else { continue };
+            let updated = conn.execute(
+                "UPDATE order_items SET quantity = ?1, line_total = ?2, line_total_cents = ?3 WHERE id = ?4 AND order_id = ?5",
+                params![item.quantity, item.line_total, to_cents(item.line_total), line_id, &primary.id],
+            )?;
+            if updated > 0 {
+                continue;
+            }
+            // 订单项 ID 以「订单ID_」开头，移动时换成主订单 ID（与主订单已有的行重名时加序号），排在主订单原有项之后
+            let base = format!("{}{}", own_prefix, line_id.strip_prefix(&secondary_prefix).unwrap_or(line_id));
+            let mut new_line_id = base.clone();
+            let mut n = 2;
+            while conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM order_items WHERE id = ?1)",
+                params![&new_line_id],
+                |row: &rusqlite::Row| row.get::<_, bool>(0),
+            )? {
+                new_line_id = format!("{}_{}", base, n);
+                n += 1;
+            }
+            conn.execute(
+                "UPDATE order_items SET id = ?1, order_id = ?2, sort_value = ?3, quantity = ?4, line_total = ?5, line_total_cents = ?6
+                 WHERE id = ?7 AND order_id = ?8",
+                params![
+                    &new_line_id, &primary.id, next_sort, item.quantity, item.line_total, to_cents(item.line_total),
+                    line_id, secondary_id,
+                ],
+            )?;
+            next_sort += 1;
+        }
+        conn.execute("DELETE FROM order_items WHERE order_id = ?1", params![secondary_id])?;
 
         conn.execute(
-            "INSERT OR REPLACE INTO app_settings
-             (id, data_directory, output_directory, backup_directory, font_size, theme,
-              remember_window, date_format, excel_date_format, order_number_format, order_number_prefix,
-              order_number_reset_daily, order_number_digits, retain_days, auto_backup, backup_interval,
-              backup_keep_count, default_template_id, default_category_id,
-              excel_filename_format, auto_open_excel, skip_save_dialog,
-              template_validation, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24)",
+            "UPDATE orders SET total_amount = ?1, total_amount_cents = ?2, rounding_adjustment = ?3, rounding_adjustment_cents = ?4, order_discount = ?5, order_discount_cents = ?6, tax_rate = ?7, prices_include_tax = ?8, updated_at = ?9 WHERE id = ?10",
             params![
-                &settings.id,
-                &settings.data_directory,
-                &settings.output_directory,
-                &settings.backup_directory,
-                &settings.font_size,
-                &settings.theme,
-                &settings.remember_window,
-                &settings.date_format,
-                &settings.excel_date_format,
-                &settings.order_number_format,
-                &settings.order_number_prefix,
-                &settings.order_number_reset_daily,
-                &settings.order_number_digits,
-                &settings.retain_days,
-                &settings.auto_backup,
-                &settings.backup_interval,
-                &settings.backup_keep_count,
-                &settings.default_template_id,
-                &settings.default_category_id,
-                &settings.excel_filename_format,
-                &settings.auto_open_excel,
-                &settings.skip_save_dialog,
-                &serde_json::to_string(&settings.template_validation.clone().unwrap_or_default()).unwrap_or_else(|_| "{}".to_string()),
-                &settings.updated_at,
+                &primary.total_amount, &to_cents(primary.total_amount),
+                &primary.rounding_adjustment, &to_cents(primary.rounding_adjustment),
+                &primary.order_discount, &to_cents(primary.order_discount),
+                &primary.tax_rate, &primary.prices_include_tax, &primary.updated_at, &primary.id,
             ],
         )?;
-
+        // 库存流水、往来账、附件与操作记录改挂到主订单
+        conn.execute("UPDATE stock_movements SET order_id = ?1 WHERE order_id = ?2", params![&primary.id, secondary_id])?;
+        conn.execute("UPDATE order_attachments SET order_id = ?1 WHERE order_id = ?2", params![&primary.id, secondary_id])?;
+        conn.execute("UPDATE order_events SET order_id = ?1 WHERE order_id = ?2", params![&primary.id, secondary_id])?;
+        conn.execute("UPDATE customer_transactions SET order_id = ?1 WHERE order_id = ?2", params![&primary.id, secondary_id])?;
+        conn.execute("DELETE FROM orders WHERE id = ?1", params![secondary_id])?;
         Ok(())
     }
+
+    /// 生成订单号。模板配置了独立单号格式时使用模板格式，且序号只在该模板的订单内递增；
+    /// 否则使用全局格式，序号在未配置独立格式的订单之间共享。订单日期无法解析时按 today 计；
+    /// 设置了单号前缀时加在展开后的单号前面
+    pub fn generate_order_number(
+        &self,
+        settings: &AppSettings,
+        order_date: &str,
+        template_id: Option<&str>,
+        today: NaiveDate,
+    ) -> std::result::Result<String, WriteError> {
+        let conn = self.conn.lock().unwrap();
+        generate_order_number_on(&conn, settings, order_date, template_id, today)
+    }
 }
 
-// ========== Order Repository ==========
+// 订单号格式中的序号占位符 {SEQ} 或 {SEQ:位数}
+static SEQ_TOKEN_RE: std::sync::LazyLock<regex::Regex> =
+    std::sync::LazyLock::new(|| regex::Regex::new(r"\{SEQ(?::(\d+))?\}").unwrap());
+
+/// 生成订单号。模板配置了独立单号格式时使用模板格式，且序号只在该模板的订单内递增；
+/// 否则使用全局格式，序号在未配置独立格式的订单之间共享。订单日期无法解析时按 today 计；
+/// 设置了单号前缀时加在展开后的单号前面。传入调用方的事务，以便单号与订单在同一事务中写入
+pub fn generate_order_number_on(
+    conn: &rusqlite::Connection,
+    settings: &AppSettings,
+    order_date: &str,
+    template_id: Option<&str>,
+    today: NaiveDate,
+) -> std::result::Result<String, WriteError> {
+    // 订单日期可能带时间（如 2024-01-05T10:00:00），只取前 10 位；为空或无法解析时回退到当前日期
+    let effective_date = order_date
+        .get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .unwrap_or(today);
+
+    // 查询模板的独立单号格式（空字符串视为未设置）
+    let template_format: Option<(String, String)> = match template_id {
+        Some(tid) if !tid.is_empty() => conn
+            .query_row(
+                "SELECT number_format FROM templates WHERE id = ?1",
+                params![tid],
+                |row: &rusqlite::Row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .flatten()
+            .filter(|f| !f.trim().is_empty())
+            .map(|f| (tid.to_string(), f)),
+        _ => None,
+    };
+
+    let format = template_format
+        .as_ref()
+        .map(|(_, f)| f)
+        .unwrap_or(&settings.order_number_format);
+
+    // 第一步：替换日期变量（优先使用订单日期，解析失败时回退到当前日期）
+    let mut result = format.clone();
+    result = result.replace("{YYYY}", &effective_date.format("%Y").to_string());
+    result = result.replace("{YY}", &effective_date.format("%y").to_string());
+    result = result.replace("{MM}", &effective_date.format("%m").to_string());
+    result = result.replace("{DD}", &effective_date.format("%d").to_string());
+    result = result.replace("{M}", &effective_date.format("%-m").to_string());
+    result = result.replace("{D}", &effective_date.format("%-d").to_string());
+
+    // 第二步：处理序号 {SEQ} 或 {SEQ:N}
+    if let Some(caps) = SEQ_TOKEN_RE.captures(&result) {
+        // 提取序号位数（默认6位）
+        let seq_len = caps
+            .get(1)
+            .and_then(|m| m.as_str().parse::<usize>().ok())
+            .unwrap_or(settings.order_number_digits as usize);
+
+        // 第三步：查询最后的订单号（按订单日期维度，并按模板格式划分序号范围）；创建时间相同时取最后插入的订单
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+        if let Some((tid, _)) = &template_format {
+            // 模板独立格式：只看同模板的订单
+            conditions.push("template_id = ?");
+            values.push(tid.clone());
+        } else {
+            // 全局格式：排除使用独立格式的模板下的订单，避免序号互相干扰
+            conditions.push(
+                "(template_id IS NULL OR template_id NOT IN (SELECT id FROM templates WHERE COALESCE(number_format, '') <> ''))",
+            );
+        }
+        // 按重置周期只看同一天/同月/同年的订单（比较订单日期的前缀，带时间的日期也归入当天）
+        let period_scope = match order_number_reset_period(settings) {
+            "daily" => Some(("substr(date, 1, 10) = ?", "%Y-%m-%d")),
+            "monthly" => Some(("substr(date, 1, 7) = ?", "%Y-%m")),
+            "yearly" => Some(("substr(date, 1, 4) = ?", "%Y")),
+            _ => None,
+        };
+        if let Some((condition, date_format)) = period_scope {
+            conditions.push(condition);
+            values.push(effective_date.format(date_format).to_string());
+        }
+        let sql = format!(
+            "SELECT order_number FROM orders WHERE {} ORDER BY created_at DESC, rowid DESC LIMIT 1",
+            conditions.join(" AND ")
+        );
+        let last_number: Option<String> = conn
+            .query_row(&sql, params_from_iter(values.iter()), |row: &rusqlite::Row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?;
+
+        // 第四步：提取序号。按 {SEQ} 在格式中的位置切出上一个单号中对应的部分（日期已替换，
+        // 前后的字面内容必须一致，单号前缀一并去掉），避免与紧挨着的日期数字混在一起；格式不一致时从 1 开始
+        let seq_token = caps.get(0).unwrap();
+        let prefix = format!("{}{}", settings.order_number_prefix, &result[..seq_token.start()]);
+        let suffix = &result[seq_token.end()..];
+        let last_seq = last_number
+            .as_deref()
+            .and_then(|n| n.strip_prefix(prefix.as_str()))
+            .and_then(|n| n.strip_suffix(suffix))
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()));
+        let next_seq = match last_seq {
+            Some(digits) => digits
+                .parse::<u64>()
+                .ok()
+                .and_then(|seq| seq.checked_add(1))
+                .ok_or_else(|| WriteError::Rejected(format!("单号序号超出范围: {}，请修改单号格式", digits)))?,
+            None => 1,
+        };
+
+        // 第五步：替换 {SEQ:N} 为格式化的序号
+        let seq_str = format!("{:0width$}", next_seq, width = seq_len);
+        let seq_pattern = if let Some(len_match) = caps.get(1) {
+            format!("{{SEQ:{}}}", len_match.as_str())
+        } else {
+            "{SEQ}".to_string()
+        };
+        result = result.replace(&seq_pattern, &seq_str);
+    }
 
-pub struct OrderRepository {
-    pub conn: DbConnection,
+    // 第六步：加上单号前缀（为空时不变）
+    Ok(format!("{}{}", settings.order_number_prefix, result))
 }
 
-impl OrderRepository {
-    pub fn new(conn: DbConnection) -> Self {
-        Self { conn }
-    }
+/// 日期区间内已完成订单的数量与金额，外币订单按记录的汇率折算为本币
+fn query_sales_total(conn: &rusqlite::Connection, from: &str, to: &str, include_archived: bool) -> Result<SalesTotal> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(ROUND(COALESCE(total_amount_cents, ROUND(total_amount * 100)) * COALESCE(exchange_rate, 1))), 0)
+             FROM {}
+             WHERE status = 'completed' AND substr(date, 1, 10) BETWEEN ?1 AND ?2",
+            report_orders_source(include_archived)
+        ),
+        params![from, to],
+        |row: &rusqlite::Row| {
+            Ok(SalesTotal {
+                order_count: row.get::<_, i64>(0)?,
+                total_amount: from_cents(row.get::<_, f64>(1)? as i64),
+            })
+        },
+    )
+}
 
-    pub fn get_order_items(&self, order_id: &str) -> Result<Vec<OrderItem>> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, product_id, name, unit, price, quantity, discount_price, remark, sort_value
-             FROM order_items WHERE order_id = ?1 ORDER BY sort_value",
-        )?;
-        let items = stmt
-            .query_map(params![order_id], |row: &rusqlite::Row| {
-                Ok(OrderItem {
-                    id: row.get::<_, String>(1)?, // 映射数据库中的 product_id 回到结构体的 id
-                    name: row.get::<_, String>(2)?,
-                    unit: row.get::<_, String>(3)?,
-                    price: row.get::<_, f64>(4)?,
-                    quantity: row.get::<_, f64>(5)?,
-                    category: "".to_string(),
-                    discount_price: row.get::<_, Option<f64>>(6)?,
-                    remark: row.get::<_, Option<String>>(7)?,
-                    sort_value: row.get::<_, i64>(8)?,
-                })
+// 分类销售排行中没有分类的销售额（分类 ID 为空）
+const UNCATEGORIZED_NAME: &str = "未分类";
+
+/// 分类销售排行：各分类自身与含全部下级的销售额（已完成订单，外币按汇率折算），按含下级的销售额降序；
+/// 有未分类的销售额时另加一项“未分类”（分类 ID 为空）
+fn query_category_sales(conn: &rusqlite::Connection, from: &str, to: &str, include_archived: bool) -> Result<Vec<CategorySales>> {
+    use std::collections::{HashMap, HashSet};
+
+    let categories: Vec<(String, String, Option<String>, i32)> = {
+        let mut stmt = conn.prepare("SELECT id, name, parent_id, level FROM categories")?;
+        let rows = stmt
+            .query_map([], |row: &rusqlite::Row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
             })?
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(items)
+        rows
+    };
+
+    // 按订单项保存的分类快照归类（旧数据没有快照时取商品当前分类），商品已删除的订单项同样计入；
+    // 没有分类或分类已被删除的销售额归入“未分类”
+    let mut own: HashMap<String, i64> = HashMap::new();
+    let mut uncategorized: i64 = 0;
+    {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT COALESCE(NULLIF(i.category, ''), p.category_id),
+                    CAST(SUM(ROUND(i.line_total_cents * COALESCE(o.exchange_rate, 1))) AS INTEGER)
+             FROM {} i
+             JOIN {} o ON o.id = i.order_id
+             LEFT JOIN products p ON p.id = i.product_id
+             WHERE o.status = 'completed' AND substr(o.date, 1, 10) BETWEEN ?1 AND ?2
+             GROUP BY 1",
+            report_items_source(include_archived),
+            report_orders_source(include_archived),
+        ))?;
+        let rows = stmt.query_map(params![from, to], |row: &rusqlite::Row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<i64>>(1)?.unwrap_or(0)))
+        })?;
+        for row in rows {
+            let (category_id, cents) = row?;
+            match category_id.filter(|id| categories.iter().any(|(c, ..)| c == id)) {
+                Some(id) => *own.entry(id).or_insert(0) += cents,
+                None => uncategorized += cents,
+            }
+        }
     }
 
-    pub fn generate_order_number(
-        &self,
-        settings: &AppSettings,
-        order_date: &str,
-    ) -> Result<String> {
-        let conn = self.conn.lock().unwrap();
-        let effective_date =
-            NaiveDate::parse_from_str(order_date, "%Y-%m-%d").unwrap_or_else(|_| Utc::now().date_naive());
-
-        let format = &settings.order_number_format;
-
-        // 第一步：替换日期变量（优先使用订单日期，解析失败时回退到当前日期）
-        let mut result = format.clone();
-        result = result.replace("{YYYY}", &effective_date.format("%Y").to_string());
-        result = result.replace("{YY}", &effective_date.format("%y").to_string());
-        result = result.replace("{MM}", &effective_date.format("%m").to_string());
-        result = result.replace("{DD}", &effective_date.format("%d").to_string());
-        result = result.replace("{M}", &effective_date.format("%-m").to_string());
-        result = result.replace("{D}", &effective_date.format("%-d").to_string());
-
-        // 第二步：处理序号 {SEQ} 或 {SEQ:N}
-        let seq_re = regex::Regex::new(r"\{SEQ(?::(\d+))?\}").unwrap();
-
-        if let Some(caps) = seq_re.captures(&result) {
-            // 提取序号位数（默认6位）
-            let seq_len = caps
-                .get(1)
-                .and_then(|m| m.as_str().parse::<usize>().ok())
-                .unwrap_or(settings.order_number_digits as usize);
-
-            // 第三步：查询最后的订单号（按订单日期维度）
-            let date_key = effective_date.format("%Y-%m-%d").to_string();
-            let last_number: Option<String> = if settings.order_number_reset_daily {
-                // 每日重置：查询同订单日期的最后订单号
-                conn.query_row(
-                    "SELECT order_number FROM orders WHERE date = ?1 ORDER BY created_at DESC LIMIT 1",
-                    params![date_key],
-                    |row: &rusqlite::Row| row.get::<_, String>(0),
-                ).ok()
-            } else {
-                // 不重置：查询所有订单的最后订单号
-                conn.query_row(
-                    "SELECT order_number FROM orders ORDER BY created_at DESC LIMIT 1",
-                    [],
-                    |row: &rusqlite::Row| row.get::<_, String>(0),
-                ).ok()
-            };
+    // 将每个分类的销售额累加到自身及所有上级（遇到循环或缺失的上级时停止）
+    let parents: HashMap<&str, Option<&str>> = categories
+        .iter()
+        .map(|(id, _, parent_id, _)| (id.as_str(), parent_id.as_deref()))
+        .collect();
+    let mut subtree: HashMap<&str, i64> = HashMap::new();
+    for (id, cents) in &own {
+        let mut visited = HashSet::new();
+        let mut current = Some(id.as_str());
+        while let Some(node) = current {
+            if !parents.contains_key(node) || !visited.insert(node) {
+                break;
+            }
+            *subtree.entry(node).or_insert(0) += cents;
+            current = parents[node];
+        }
+    }
 
-            // 第四步：提取序号（健壮的正则提取）
-            let next_seq = if let Some(last_num) = last_number {
-                // 使用正则提取所有数字序列，取最后一个
-                let num_re = regex::Regex::new(r"(\d+)").unwrap();
-                let numbers: Vec<u32> = num_re
-                    .find_iter(&last_num)
-                    .filter_map(|m| m.as_str().parse::<u32>().ok())
-                    .collect();
-
-                if let Some(&last_seq) = numbers.last() {
-                    last_seq + 1
-                } else {
-                    1
-                }
-            } else {
-                1
-            };
+    let mut ranking: Vec<CategorySales> = categories
+        .iter()
+        .map(|(id, name, parent_id, level)| CategorySales {
+            category_id: id.clone(),
+            name: name.clone(),
+            parent_id: parent_id.clone(),
+            level: *level,
+            own_revenue: from_cents(own.get(id).copied().unwrap_or(0)),
+            subtree_revenue: from_cents(subtree.get(id.as_str()).copied().unwrap_or(0)),
+        })
+        .collect();
+    if uncategorized != 0 {
+        ranking.push(CategorySales {
+            category_id: String::new(),
+            name: UNCATEGORIZED_NAME.to_string(),
+            parent_id: None,
+            level: 0,
+            own_revenue: from_cents(uncategorized),
+            subtree_revenue: from_cents(uncategorized),
+        });
+    }
+    ranking.sort_by(|a, b| {
+        b.subtree_revenue
+            .total_cmp(&a.subtree_revenue)
+            .then_with(|| a.level.cmp(&b.level))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    Ok(ranking)
+}
 
-            // 第五步：替换 {SEQ:N} 为格式化的序号
-            let seq_str = format!("{:0width$}", next_seq, width = seq_len);
-            let seq_pattern = if let Some(len_match) = caps.get(1) {
-                format!("{{SEQ:{}}}", len_match.as_str())
-            } else {
-                "{SEQ}".to_string()
-            };
-            result = result.replace(&seq_pattern, &seq_str);
-        }
+/// 按订单日期逐日汇总已完成订单的数量与金额（外币按汇率折算），只列出有订单的日期，按日期升序
+fn query_daily_sales(conn: &rusqlite::Connection, from: &str, to: &str, include_archived: bool) -> Result<Vec<DailySales>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT substr(date, 1, 10) AS day, COUNT(*),
+                COALESCE(SUM(ROUND(COALESCE(total_amount_cents, ROUND(total_amount * 100)) * COALESCE(exchange_rate, 1))), 0)
+         FROM {}
+         WHERE status = 'completed' AND substr(date, 1, 10) BETWEEN ?1 AND ?2
+         GROUP BY day
+         ORDER BY day",
+        report_orders_source(include_archived)
+    ))?;
+    let rows = stmt
+        .query_map(params![from, to], |row: &rusqlite::Row| {
+            Ok(DailySales {
+                date: row.get::<_, String>(0)?,
+                order_count: row.get::<_, i64>(1)?,
+                total_amount: from_cents(row.get::<_, f64>(2)? as i64),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
 
-        Ok(result)
+/// 销售额最高的商品（已完成订单，外币按汇率折算）。关联商品的订单项按商品汇总并使用商品当前名称，
+/// 手动录入的订单项按名称汇总；销售额相同时按名称排序
+fn query_top_products(
+    conn: &rusqlite::Connection,
+    from: &str,
+    to: &str,
+    include_archived: bool,
+    limit: u32,
+) -> Result<Vec<ProductSales>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT i.product_id,
+                COALESCE(MAX(p.name), MAX(i.name)) AS product_name,
+                SUM(i.quantity),
+                SUM(ROUND(i.line_total_cents * COALESCE(o.exchange_rate, 1))) AS revenue
+         FROM {} i
+         JOIN {} o ON o.id = i.order_id
+         LEFT JOIN products p ON p.id = i.product_id
+         WHERE o.status = 'completed' AND substr(o.date, 1, 10) BETWEEN ?1 AND ?2
+         GROUP BY i.product_id, CASE WHEN i.product_id IS NULL THEN i.name END
+         ORDER BY revenue DESC, product_name
+         LIMIT ?3",
+        report_items_source(include_archived),
+        report_orders_source(include_archived),
+    ))?;
+    let rows = stmt
+        .query_map(params![from, to, limit], |row: &rusqlite::Row| {
+            Ok(ProductSales {
+                product_id: row.get::<_, Option<String>>(0)?,
+                name: row.get::<_, String>(1)?,
+                quantity: row.get::<_, f64>(2)?,
+                revenue: from_cents(row.get::<_, Option<f64>>(3)?.unwrap_or(0.0) as i64),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// 消费金额最高的客户（已完成订单，外币按汇率折算）；客户已删除时名称为空，金额相同时按名称排序
+fn query_top_customers(
+    conn: &rusqlite::Connection,
+    from: &str,
+    to: &str,
+    include_archived: bool,
+    limit: u32,
+) -> Result<Vec<CustomerSales>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT o.customer_id, COALESCE(c.name, '') AS customer_name, COUNT(*),
+                SUM(ROUND(COALESCE(o.total_amount_cents, ROUND(o.total_amount * 100)) * COALESCE(o.exchange_rate, 1))) AS revenue
+         FROM {} o
+         LEFT JOIN customers c ON c.id = o.customer_id
+         WHERE o.status = 'completed' AND substr(o.date, 1, 10) BETWEEN ?1 AND ?2
+         GROUP BY o.customer_id
+         ORDER BY revenue DESC, customer_name
+         LIMIT ?3",
+        report_orders_source(include_archived)
+    ))?;
+    let rows = stmt
+        .query_map(params![from, to, limit], |row: &rusqlite::Row| {
+            Ok(CustomerSales {
+                customer_id: row.get::<_, String>(0)?,
+                name: row.get::<_, String>(1)?,
+                order_count: row.get::<_, i64>(2)?,
+                total_amount: from_cents(row.get::<_, Option<f64>>(3)?.unwrap_or(0.0) as i64),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+/// 报表使用的订单来源（include_archived 为 true 时合并归档订单）
+fn report_orders_source(include_archived: bool) -> &'static str {
+    if include_archived {
+        "(SELECT id, customer_id, status, date, total_amount, total_amount_cents, exchange_rate FROM orders
+          UNION ALL
+          SELECT id, customer_id, status, date, total_amount, total_amount_cents, exchange_rate FROM orders_archive)"
+    } else {
+        "orders"
+    }
+}
+
+/// 报表使用的订单项来源（include_archived 为 true 时合并归档订单项）
+fn report_items_source(include_archived: bool) -> &'static str {
+    if include_archived {
+        "(SELECT order_id, product_id, name, quantity, category, line_total_cents FROM order_items
+          UNION ALL
+          SELECT order_id, product_id, name, quantity, category, line_total_cents FROM order_items_archive)"
+    } else {
+        "order_items"
     }
 }
 
+/// 订单查询的列（表别名为 o），由 order_from_row 读取
+const ORDER_COLUMNS: &str =
+    "o.id, o.order_number, o.date, o.customer_id, COALESCE(o.total_amount_cents / 100.0, o.total_amount), o.remark, o.template_id, o.status, o.created_at, o.updated_at, o.on_account,
+     COALESCE(o.rounding_adjustment_cents / 100.0, o.rounding_adjustment, 0), o.currency, COALESCE(o.exchange_rate, 1),
+     COALESCE(o.order_discount_cents / 100.0, o.order_discount, 0), o.tax_rate, o.prices_include_tax";
+
+/// 订单项查询的列（表别名为 i），第 0 列为所属订单 ID，由 order_item_from_row 读取
+const ORDER_ITEM_COLUMNS: &str =
+    "i.order_id, COALESCE(i.product_id, ''), i.name, i.unit, COALESCE(i.price_cents / 100.0, i.price), i.quantity,
+     COALESCE(i.discount_price_cents / 100.0, i.discount_price), i.remark, i.sort_value,
+     COALESCE(i.category, (SELECT p.category_id FROM products p WHERE p.id = i.product_id), ''),
+     COALESCE(i.line_total_cents / 100.0, i.line_total, 0), i.id";
+
+/// 按 ORDER_ITEM_COLUMNS 的列顺序读取订单项
+fn order_item_from_row(row: &rusqlite::Row) -> Result<OrderItem> {
+    Ok(OrderItem {
+        id: row.get::<_, String>(1)?, // 映射数据库中的 product_id 回到结构体的 id
+        name: row.get::<_, String>(2)?,
+        unit: row.get::<_, String>(3)?,
+        price: row.get::<_, f64>(4)?,
+        quantity: row.get::<_, f64>(5)?,
+        category: row.get::<_, String>(9)?, // 旧数据没有快照时取商品当前分类
+        discount_price: row.get::<_, Option<f64>>(6)?,
+        remark: row.get::<_, Option<String>>(7)?,
+        sort_value: row.get::<_, i64>(8)?,
+        line_total: row.get::<_, f64>(10)?,
+        line_id: Some(row.get::<_, String>(11)?),
+    })
+}
+
+/// 按 ORDER_COLUMNS 的列顺序读取订单行（客户与订单项留空）
+fn order_from_row(row: &rusqlite::Row) -> Result<Order> {
+    Ok(Order {
+        id: row.get::<_, String>(0)?,
+        order_number: row.get::<_, String>(1)?,
+        date: row.get::<_, String>(2)?,
+        customer_id: row.get::<_, String>(3)?,
+        customer: Customer {
+            id: "".to_string(),
+            name: "".to_string(),
+            phone: "".to_string(),
+            license_plate: "".to_string(),
+            address: None,
+            last_purchase_at: None,
+            created_at: "".to_string(),
+            updated_at: "".to_string(),
+        },
+        items: vec![],
+        total_amount: row.get::<_, f64>(4)?,
+        remark: row.get::<_, Option<String>>(5)?,
+        template_id: row.get::<_, Option<String>>(6)?,
+        status: row.get::<_, String>(7)?,
+        on_account: row.get::<_, Option<i32>>(10)?.unwrap_or(0) != 0,
+        rounding_adjustment: row.get::<_, f64>(11)?,
+        currency: row.get::<_, Option<String>>(12)?,
+        exchange_rate: row.get::<_, f64>(13)?,
+        order_discount: row.get::<_, f64>(14)?,
+        tax_rate: row.get::<_, Option<f64>>(15)?,
+        prices_include_tax: row.get::<_, Option<bool>>(16)?,
+        created_at: row.get::<_, String>(8)?,
+        updated_at: row.get::<_, String>(9)?,
+    })
+}
+
 impl Repository<Order> for OrderRepository {
     fn get_all(&self) -> Result<Vec<Order>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, order_number, date, customer_id, total_amount, remark, template_id, status, created_at, updated_at
-             FROM orders ORDER BY created_at DESC"
-        )?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM orders o ORDER BY o.created_at DESC", ORDER_COLUMNS))?;
         let orders = stmt
-            .query_map([], |row: &rusqlite::Row| {
-                Ok(Order {
-                    id: row.get::<_, String>(0)?,
-                    order_number: row.get::<_, String>(1)?,
-                    date: row.get::<_, String>(2)?,
-                    customer_id: row.get::<_, String>(3)?,
-                    customer: Customer {
-                        id: "".to_string(),
-                        name: "".to_string(),
-                        phone: "".to_string(),
-                        license_plate: "".to_string(),
-                        address: None,
-                        last_purchase_at: None,
-                        created_at: "".to_string(),
-                        updated_at: "".to_string(),
-                    },
-                    items: vec![],
-                    total_amount: row.get::<_, f64>(4)?,
-                    remark: row.get::<_, Option<String>>(5)?,
-                    template_id: row.get::<_, Option<String>>(6)?,
-                    status: row.get::<_, String>(7)?,
-                    created_at: row.get::<_, String>(8)?,
-                    updated_at: row.get::<_, String>(9)?,
-                })
-            })?
+            .query_map([], order_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
         Ok(orders)
     }
@@ -994,78 +3884,173 @@ impl Repository<Order> for OrderRepository {
     fn get_by_id(&self, id: &str) -> Result<Order> {
         let conn = self.conn.lock().unwrap();
         conn.query_row(
-            "SELECT id, order_number, date, customer_id, total_amount, remark, template_id, status, created_at, updated_at FROM orders WHERE id = ?1",
+            &format!("SELECT {} FROM orders o WHERE o.id = ?1", ORDER_COLUMNS),
             params![id],
-            |row: &rusqlite::Row| {
-                Ok(Order {
-                    id: row.get::<_, String>(0)?,
-                    order_number: row.get::<_, String>(1)?,
-                    date: row.get::<_, String>(2)?,
-                    customer_id: row.get::<_, String>(3)?,
-                    customer: Customer {
-                        id: "".to_string(),
-                        name: "".to_string(),
-                        phone: "".to_string(),
-                        license_plate: "".to_string(),
-                        address: None,
-                        last_purchase_at: None,
-                        created_at: "".to_string(),
-                        updated_at: "".to_string(),
-                    },
-                    items: vec![],
-                    total_amount: row.get::<_, f64>(4)?,
-                    remark: row.get::<_, Option<String>>(5)?,
-                    template_id: row.get::<_, Option<String>>(6)?,
-                    status: row.get::<_, String>(7)?,
-                    created_at: row.get::<_, String>(8)?,
-                    updated_at: row.get::<_, String>(9)?,
-                })
-            },
+            order_from_row,
         )
     }
 
     fn insert(&self, order: &Order) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO orders (id, order_number, date, customer_id, total_amount, remark, template_id, status, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-            params![
-                &order.id, &order.order_number, &order.date, &order.customer_id,
-                &order.total_amount, &order.remark, &order.template_id, &order.status,
-                &order.created_at, &order.updated_at,
-            ],
-        )?;
-        for item in &order.items {
-            conn.execute(
-                "INSERT INTO order_items (id, order_id, product_id, name, unit, price, quantity, discount_price, remark, sort_value)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-                params![
-                    &format!("{}_{}", order.id, item.id), &order.id, &item.id,
-                    &item.name, &item.unit, &item.price, &item.quantity,
-                    &item.discount_price, &item.remark, &item.sort_value,
-                ],
-            )?;
-        }
-        Ok(())
+        Self::insert_on(&conn, order)
     }
 
     fn update(&self, order: &Order) -> Result<()> {
         let conn = self.conn.lock().unwrap();
+        update_order_row(&conn, order)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        // 订单项与附件记录随外键级联删除，操作记录没有外键需显式删除
+        tx.execute("DELETE FROM order_events WHERE order_id = ?1", params![id])?;
+        tx.execute("DELETE FROM orders WHERE id = ?1", params![id])?;
+        tx.commit()
+    }
+}
+
+/// 订单号序号的重置周期：daily / monthly / yearly / never。未设置或无法识别时沿用已弃用的
+/// order_number_reset_daily（true 为 daily，false 为 never）
+fn order_number_reset_period(settings: &AppSettings) -> &str {
+    match settings.order_number_reset_period.as_str() {
+        period @ ("daily" | "monthly" | "yearly" | "never") => period,
+        _ if settings.order_number_reset_daily => "daily",
+        _ => "never",
+    }
+}
+
+/// 退回订单项扣减的库存（仅占用库存的状态）、删除订单的挂账记录、附件记录与订单（订单项级联删除），items 为 (商品ID, 数量)。
+/// 关联该订单的收款与退款是实际发生的资金往来，保留在客户往来账中，只解除与订单的关联并在备注中注明原订单号。
+/// 附件文件由调用方在事务提交后删除。订单不存在时不做任何改动并返回 false
+fn delete_order_restocking(conn: &rusqlite::Connection, id: &str, items: &[(String, f64)], now: &str) -> Result<bool> {
+    let (status, order_number): (String, String) = match conn.query_row(
+        "SELECT status, order_number FROM orders WHERE id = ?1",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ) {
+        Ok(order) => order,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    if holds_stock(&status) {
+        ProductRepository::restock_batch(conn, items, Some(id), now)?;
+    }
+    conn.execute(
+        "DELETE FROM customer_transactions WHERE order_id = ?1 AND kind = 'charge'",
+        params![id],
+    )?;
+    conn.execute(
+        "UPDATE customer_transactions
+         SET order_id = NULL,
+             remark = CASE WHEN COALESCE(remark, '') = '' THEN ?2 ELSE remark || '；' || ?2 END
+         WHERE order_id = ?1",
+        params![id, format!("原订单 {} 已删除", order_number)],
+    )?;
+    conn.execute("DELETE FROM order_attachments WHERE order_id = ?1", params![id])?;
+    // 操作记录没有外键（归档订单需要保留），删除订单时显式删除
+    conn.execute("DELETE FROM order_events WHERE order_id = ?1", params![id])?;
+    conn.execute("DELETE FROM orders WHERE id = ?1", params![id])?;
+    Ok(true)
+}
+
+/// 商品的分类引用：未分类（空字符串）写入 NULL，满足外键约束
+fn category_ref(category_id: &str) -> Option<&str> {
+    Some(category_id).filter(|id| !id.is_empty())
+}
+
+/// 订单项的商品引用：商品未知（空字符串）写入 NULL
+fn product_ref(product_id: &str) -> Option<&str> {
+    Some(product_id).filter(|id| !id.is_empty())
+}
+
+/// 是否为订单号唯一约束冲突
+pub fn is_order_number_unique_violation(err: &rusqlite::Error) -> bool {
+    err.to_string()
+        .contains("UNIQUE constraint failed: orders.order_number")
+}
+
+/// 写入订单行（不含订单项）
+fn insert_order_row(conn: &rusqlite::Connection, order: &Order) -> Result<()> {
+    conn.execute(
+        "INSERT INTO orders (id, order_number, date, customer_id, total_amount, total_amount_cents, remark, template_id, status, on_account, rounding_adjustment, rounding_adjustment_cents, currency, exchange_rate, order_discount, order_discount_cents, tax_rate, prices_include_tax, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+        params![
+            &order.id, &order.order_number, &order.date, &order.customer_id,
+            &order.total_amount, &to_cents(order.total_amount), &order.remark, &order.template_id, &order.status,
+            &order.on_account, &order.rounding_adjustment, &to_cents(order.rounding_adjustment),
+            &order.currency, &order.exchange_rate, &order.order_discount, &to_cents(order.order_discount),
+            &order.tax_rate, &order.prices_include_tax, &order.created_at, &order.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// 写入订单的全部订单项。沿用属于本订单的 line_id，新行的行 ID 为「订单ID_商品ID」，
+/// 同一商品有多行时依次加上 _2、_3… 后缀
+fn insert_order_items(conn: &rusqlite::Connection, order: &Order) -> Result<()> {
+    use std::collections::HashSet;
+
+    let own_prefix = format!("{}_", order.id);
+    let mut used: HashSet<String> = HashSet::new();
+    let kept: Vec<Option<String>> = order
+        .items
+        .iter()
+        .map(|item| {
+            item.line_id
+                .clone()
+                .filter(|line_id| line_id.starts_with(&own_prefix) && used.insert(line_id.clone()))
+        })
+        .collect();
+    let line_ids: Vec<String> = order
+        .items
+        .iter()
+        .zip(kept)
+        .map(|(item, kept)| match kept {
+            Some(line_id) => line_id,
+            None => {
+                let base = format!("{}{}", own_prefix, item.id);
+                let mut line_id = base.clone();
+                let mut n = 2;
+                while used.contains(&line_id) {
+                    line_id = format!("{}_{}", base, n);
+                    n += 1;
+                }
+                used.insert(line_id.clone());
+                line_id
+            }
+        })
+        .collect();
+
+    for (item, line_id) in order.items.iter().zip(&line_ids) {
         conn.execute(
-            "UPDATE orders SET order_number = ?1, date = ?2, customer_id = ?3, total_amount = ?4, remark = ?5, template_id = ?6, status = ?7, updated_at = ?8 WHERE id = ?9",
+            "INSERT INTO order_items (id, order_id, product_id, name, unit, price, price_cents, quantity, discount_price, discount_price_cents, remark, sort_value, category, line_total, line_total_cents)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
             params![
-                &order.order_number, &order.date, &order.customer_id, &order.total_amount,
-                &order.remark, &order.template_id, &order.status, &order.updated_at, &order.id,
+                line_id, &order.id, product_ref(&item.id),
+                &item.name, &item.unit, &item.price, &to_cents(item.price), &item.quantity,
+                &item.discount_price, &item.discount_price.map(to_cents), &item.remark, &item.sort_value,
+                &item.category, &item.line_total, &to_cents(item.line_total),
             ],
         )?;
-        Ok(())
     }
+    Ok(())
+}
 
-    fn delete(&self, id: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM orders WHERE id = ?1", params![id])?;
-        Ok(())
-    }
+/// 更新订单主表字段（不含订单项）
+fn update_order_row(conn: &rusqlite::Connection, order: &Order) -> Result<()> {
+    conn.execute(
+        "UPDATE orders SET order_number = ?1, date = ?2, customer_id = ?3, total_amount = ?4, total_amount_cents = ?5, remark = ?6, template_id = ?7, status = ?8, on_account = ?9, rounding_adjustment = ?10, rounding_adjustment_cents = ?11, currency = ?12, exchange_rate = ?13, order_discount = ?14, order_discount_cents = ?15, tax_rate = ?16, prices_include_tax = ?17, updated_at = ?18 WHERE id = ?19",
+        params![
+            &order.order_number, &order.date, &order.customer_id, &order.total_amount, &to_cents(order.total_amount),
+            &order.remark, &order.template_id, &order.status, &order.on_account,
+            &order.rounding_adjustment, &to_cents(order.rounding_adjustment),
+            &order.currency, &order.exchange_rate, &order.order_discount, &to_cents(order.order_discount),
+            &order.tax_rate, &order.prices_include_tax, &order.updated_at, &order.id,
+        ],
+    )?;
+    Ok(())
 }
 
 // ========== Remark Preset Repository ==========
@@ -1079,6 +4064,16 @@ impl RemarkPresetRepository {
         Self { conn }
     }
 
+    /// 下一个可用的排序值（当前最大值 + 1，没有数据时为 0）
+    pub fn next_sort_order(&self) -> Result<i32> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COALESCE(MAX(sort_order) + 1, 0) FROM remark_presets",
+            [],
+            |row: &rusqlite::Row| row.get::<_, i32>(0),
+        )
+    }
+
     pub fn get_by_type(&self, preset_type: &str) -> Result<Vec<RemarkPreset>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
@@ -1192,6 +4187,16 @@ impl UnitPresetRepository {
     pub fn new(conn: DbConnection) -> Self {
         Self { conn }
     }
+
+    /// 下一个可用的排序值（当前最大值 + 1，没有数据时为 0）
+    pub fn next_sort_order(&self) -> Result<i32> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COALESCE(MAX(sort_order) + 1, 0) FROM unit_presets",
+            [],
+            |row: &rusqlite::Row| row.get::<_, i32>(0),
+        )
+    }
 }
 
 impl Repository<UnitPreset> for UnitPresetRepository {
@@ -1271,3 +4276,369 @@ impl Repository<UnitPreset> for UnitPresetRepository {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn category_sales_ranking_rolls_up_and_buckets_uncategorized() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "root", None, 0);
+            insert_category(&c, "mid", Some("root"), 1);
+            insert_category(&c, "leaf", Some("mid"), 2);
+            insert_product(&c, "p_root", 10.0, Some("root"));
+            insert_product(&c, "p_leaf", 2.5, Some("leaf"));
+            insert_product(&c, "p_none", 3.0, None);
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_order(&c, "o1", "c1", "2024-01-05", "completed");
+            insert_item(&c, "i1", "o1", "p_root", 10.0, 1.0, Some("root"));
+            insert_item(&c, "i2", "o1", "p_leaf", 2.5, 4.0, Some("leaf"));
+            // 按保存时的分类快照归类：商品之后移到了顶级分类，销售额仍计入 mid
+            insert_item(&c, "i3", "o1", "p_root", 5.0, 2.0, Some("mid"));
+            // 没有快照的旧订单项取商品当前分类
+            insert_item(&c, "i4", "o1", "p_leaf", 1.0, 1.0, None);
+            // 未分类商品、已删除的商品、分类已删除的快照都归入“未分类”
+            insert_item(&c, "i5", "o1", "p_none", 3.0, 1.0, None);
+            insert_item(&c, "i6", "o1", "p_deleted", 4.0, 1.0, None);
+            insert_item(&c, "i7", "o1", "p_deleted", 0.5, 2.0, Some("gone"));
+            // 区间外与未完成的订单不计入
+            insert_order(&c, "o2", "c1", "2024-02-05", "completed");
+            insert_item(&c, "i8", "o2", "p_leaf", 100.0, 1.0, Some("leaf"));
+            insert_order(&c, "o3", "c1", "2024-01-06", "draft");
+            insert_item(&c, "i9", "o3", "p_none", 100.0, 1.0, None);
+        }
+
+        let repo = CategoryRepository::new(conn);
+        let ranking = repo.get_sales_ranking("2024-01-01", "2024-01-31", false).unwrap();
+        // 合并归档订单项时同样按分类快照归类
+        let with_archived = repo.get_sales_ranking("2024-01-01", "2024-01-31", true).unwrap();
+        assert_eq!(
+            with_archived.iter().map(|c| (&c.category_id, c.subtree_revenue)).collect::<Vec<_>>(),
+            ranking.iter().map(|c| (&c.category_id, c.subtree_revenue)).collect::<Vec<_>>()
+        );
+        let revenue = |id: &str| {
+            let entry = ranking.iter().find(|c| c.category_id == id).unwrap();
+            (entry.own_revenue, entry.subtree_revenue)
+        };
+        assert_eq!(revenue("leaf"), (11.0, 11.0));
+        assert_eq!(revenue("mid"), (10.0, 21.0));
+        assert_eq!(revenue("root"), (10.0, 31.0));
+        assert_eq!(revenue(""), (8.0, 8.0));
+        assert_eq!(ranking.iter().find(|c| c.category_id.is_empty()).unwrap().name, "未分类");
+        assert_eq!(ranking[0].category_id, "root");
+    }
+
+    #[test]
+    fn category_sales_ranking_without_sales_has_no_uncategorized_entry() {
+        let conn = memory_db();
+        let ranking = CategoryRepository::new(conn)
+            .get_sales_ranking("2024-01-01", "2024-01-31", false)
+            .unwrap();
+        assert!(!ranking.is_empty());
+        assert!(ranking.iter().all(|c| !c.category_id.is_empty() && c.subtree_revenue == 0.0));
+    }
+
+    #[test]
+    fn archive_then_restore_round_trips_order_and_items() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_order(&c, "old", "c1", "2023-12-30", "completed");
+            insert_item(&c, "i1", "old", "p1", 10.0, 2.0, None);
+            insert_item(&c, "i2", "old", "p1", 2.5, 1.0, None);
+            insert_order(&c, "recent", "c1", "2024-01-02", "completed");
+            insert_item(&c, "i3", "recent", "p1", 10.0, 1.0, None);
+            c.execute("UPDATE orders SET tax_rate = 13, prices_include_tax = 0, remark = '备注' WHERE id = 'old'", [])
+                .unwrap();
+        }
+        let repo = OrderRepository::new(conn.clone());
+        let before = (repo.get_by_id("old").unwrap(), repo.get_order_items("old").unwrap());
+
+        assert_eq!(repo.archive_before("2024-01-01").unwrap(), 1);
+        let count = |sql: &str| conn.lock().unwrap().query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM orders WHERE id = 'old'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items WHERE order_id = 'old'"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items_archive WHERE order_id = 'old'"), 2);
+        let archived = repo.get_archived().unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].items.len(), 2);
+
+        repo.restore_archived("old").unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM orders_archive"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM order_items_archive"), 0);
+        let after = (repo.get_by_id("old").unwrap(), repo.get_order_items("old").unwrap());
+        assert_eq!(after.1.len(), 2);
+        assert_eq!(serde_json::to_value(&after).unwrap(), serde_json::to_value(&before).unwrap());
+        assert!(matches!(repo.restore_archived("old"), Err(rusqlite::Error::QueryReturnedNoRows)));
+    }
+
+    #[test]
+    fn price_anomalies_catch_a_tenfold_price_in_a_small_category() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_category(&c, "filters", None, 0);
+            insert_category(&c, "same", None, 0);
+            insert_product(&c, "f1", 10.0, Some("filters"));
+            insert_product(&c, "f2", 12.0, Some("filters"));
+            insert_product(&c, "f3", 11.0, Some("filters"));
+            insert_product(&c, "f4", 110.0, Some("filters"));
+            // 其余商品价格完全相同
+            insert_product(&c, "s1", 5.0, Some("same"));
+            insert_product(&c, "s2", 5.0, Some("same"));
+            insert_product(&c, "s3", 50.0, Some("same"));
+        }
+
+        let anomalies = ProductRepository::new(conn).find_price_anomalies(3.0).unwrap();
+        let found: Vec<(&str, f64)> = anomalies.iter().map(|a| (a.product.id.as_str(), a.category_mean)).collect();
+        assert_eq!(found, vec![("s3", 5.0), ("f4", 11.0)]);
+        assert!(anomalies.iter().all(|a| a.z_score.is_finite() && a.z_score > 3.0));
+    }
+
+    #[test]
+    fn order_search_returns_customers_and_items_with_each_result() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_customer(&c, "c2", "李四", "13900000000", "B67890");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+            insert_order(&c, "o1", "c1", "2024-03-01", "completed");
+            insert_item(&c, "i1", "o1", "p1", 10.0, 1.0, None);
+            insert_item(&c, "i2", "o1", "p2", 5.0, 2.0, None);
+            insert_order(&c, "o2", "c1", "2024-03-02", "completed");
+            insert_item(&c, "i3", "o2", "p2", 5.0, 1.0, None);
+            insert_order(&c, "o3", "c2", "2024-03-03", "completed");
+            insert_item(&c, "i4", "o3", "p1", 10.0, 1.0, None);
+        }
+        let repo = OrderRepository::new(conn.clone());
+        let summary = |orders: Vec<Order>| {
+            orders
+                .into_iter()
+                .map(|o| (o.id, o.customer.name, o.items.into_iter().map(|i| i.id).collect::<Vec<_>>()))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            ("o2".to_string(), "张三".to_string(), vec!["p2".to_string()]),
+            ("o1".to_string(), "张三".to_string(), vec!["p1".to_string(), "p2".to_string()]),
+        ];
+
+        // 两个字符走 LIKE 搜索，电话号码走全文索引，两者附带的客户与订单项一致
+        assert_eq!(summary(repo.search("张三").unwrap()), expected);
+        assert_eq!(summary(repo.search("13800000000").unwrap()), expected);
+
+        let by_customer = repo.get_by_customer("c1").unwrap();
+        assert_eq!(
+            by_customer.iter().map(|o| (o.id.as_str(), o.items.len())).collect::<Vec<_>>(),
+            vec![("o1", 2), ("o2", 1)]
+        );
+    }
+
+    #[test]
+    fn template_list_omits_files_and_saving_it_back_keeps_them() {
+        let conn = memory_db();
+        let repo = TemplateRepository::new(conn.clone());
+        let file = BASE64.encode(b"PK\x03\x04 xlsx bytes");
+        let template = TemplateConfig {
+            id: "t1".to_string(),
+            name: "送货单".to_string(),
+            template_base64: file.clone(),
+            file_name: "送货单.xlsx".to_string(),
+            filename_pattern: "{orderNo}".to_string(),
+            is_default: false,
+            mappings: TemplateMappings::default(),
+            required_fields: RequiredFields::default(),
+            created_at: "2024-03-10T04:00:00Z".to_string(),
+            updated_at: "2024-03-10T04:00:00Z".to_string(),
+            number_format: None,
+            has_file: false,
+        };
+        repo.insert(&template).unwrap();
+
+        let listed = repo.get_all().unwrap().into_iter().find(|t| t.id == "t1").unwrap();
+        assert!(listed.template_base64.is_empty() && listed.has_file);
+        assert_eq!(repo.get_file_base64("t1").unwrap(), file);
+
+        // 前端用列表中的模板信息保存（不带文件内容），原文件保留
+        repo.update(&TemplateConfig { name: "送货单（新）".to_string(), ..listed.clone() }).unwrap();
+        assert_eq!(repo.get_by_id("t1").unwrap().template_base64, file);
+
+        // 明确清除文件时删除
+        repo.update(&TemplateConfig { has_file: false, ..listed }).unwrap();
+        assert!(repo.get_file_base64("t1").unwrap().is_empty());
+        assert!(!repo.get_all().unwrap().into_iter().find(|t| t.id == "t1").unwrap().has_file);
+    }
+
+    #[test]
+    fn stock_deduction_propagates_query_errors_and_skips_untracked_products() {
+        let conn = memory_db();
+        let c = conn.lock().unwrap();
+        insert_product(&c, "p1", 10.0, None);
+        insert_product(&c, "p2", 10.0, None);
+        c.execute("UPDATE products SET track_stock = 1, stock = 5 WHERE id = 'p1'", []).unwrap();
+
+        // 未开启库存跟踪与不存在的商品不扣减
+        ProductRepository::adjust_stock_batch(&c, &[("p2".to_string(), 1.0), ("missing".to_string(), 1.0)], None, "now").unwrap();
+        let stock = |id: &str| c.query_row("SELECT stock FROM products WHERE id = ?1", params![id], |row| row.get::<_, Option<f64>>(0)).unwrap();
+        assert_eq!((stock("p1"), stock("p2")), (Some(5.0), None));
+
+        // 其余查询错误不能当作“未跟踪”而跳过
+        c.execute_batch("ALTER TABLE products RENAME COLUMN min_stock TO min_stock_before").unwrap();
+        assert!(ProductRepository::adjust_stock_batch(&c, &[("p1".to_string(), 1.0)], None, "now").is_err());
+        assert_eq!(stock("p1"), Some(5.0));
+    }
+
+    #[test]
+    fn restocking_propagates_query_errors_and_skips_untracked_products() {
+        let conn = memory_db();
+        let c = conn.lock().unwrap();
+        insert_product(&c, "p1", 10.0, None);
+        insert_product(&c, "p2", 10.0, None);
+        c.execute("UPDATE products SET track_stock = 1, stock = 5 WHERE id = 'p1'", []).unwrap();
+
+        ProductRepository::restock_batch(&c, &[("p1".to_string(), 2.0), ("p2".to_string(), 1.0), ("missing".to_string(), 1.0)], Some("o1"), "now").unwrap();
+        let stock = |id: &str| c.query_row("SELECT stock FROM products WHERE id = ?1", params![id], |row| row.get::<_, Option<f64>>(0)).unwrap();
+        assert_eq!((stock("p1"), stock("p2")), (Some(7.0), None));
+        let movements: i64 = c.query_row("SELECT COUNT(*) FROM stock_movements WHERE reason = 'restock' AND order_id = 'o1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(movements, 1);
+
+        c.execute_batch("ALTER TABLE products RENAME COLUMN track_stock TO track_stock_before").unwrap();
+        assert!(ProductRepository::restock_batch(&c, &[("p1".to_string(), 1.0)], None, "now").is_err());
+    }
+
+    #[test]
+    fn order_number_sequence_overflow_is_rejected_and_query_errors_propagate() {
+        let conn = memory_db();
+        let mut settings = SettingsRepository::new(conn.clone()).get_settings().unwrap().unwrap();
+        settings.order_number_prefix = String::new();
+        settings.order_number_format = "NO{SEQ:3}".to_string();
+        settings.order_number_reset_period = "never".to_string();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let c = conn.lock().unwrap();
+        insert_customer(&c, "c1", "张三", "", "");
+        insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+
+        c.execute("UPDATE orders SET order_number = 'NO041' WHERE id = 'o1'", []).unwrap();
+        assert_eq!(generate_order_number_on(&c, &settings, "2024-03-10", None, today).unwrap(), "NO042");
+
+        // 创建时间相同的订单按插入顺序取最后一个
+        insert_order(&c, "o2", "c1", "2024-03-10", "draft");
+        c.execute("UPDATE orders SET order_number = 'NO042' WHERE id = 'o2'", []).unwrap();
+        assert_eq!(generate_order_number_on(&c, &settings, "2024-03-10", None, today).unwrap(), "NO043");
+        c.execute("DELETE FROM orders WHERE id = 'o2'", []).unwrap();
+
+        // 上一个序号已是 u64 最大值时不能回绕，返回说明
+        c.execute("UPDATE orders SET order_number = ?1 WHERE id = 'o1'", params![format!("NO{}", u64::MAX)]).unwrap();
+        match generate_order_number_on(&c, &settings, "2024-03-10", None, today) {
+            Err(WriteError::Rejected(message)) => assert!(message.contains(&u64::MAX.to_string()), "{}", message),
+            other => panic!("应拒绝超出范围的序号: {:?}", other),
+        }
+
+        // 模板查询出错时不能当作“没有独立格式”继续生成
+        c.execute_batch("ALTER TABLE templates RENAME COLUMN number_format TO number_format_before").unwrap();
+        assert!(matches!(
+            generate_order_number_on(&c, &settings, "2024-03-10", Some("t1"), today),
+            Err(WriteError::Database(_))
+        ));
+    }
+
+    #[test]
+    fn archived_orders_keep_their_own_items_in_sort_order() {
+        let conn = memory_db();
+        {
+            let c = conn.lock().unwrap();
+            insert_customer(&c, "c1", "张三", "13800000000", "A12345");
+            insert_product(&c, "p1", 10.0, None);
+            insert_product(&c, "p2", 5.0, None);
+            insert_order(&c, "o1", "c1", "2023-12-01", "completed");
+            insert_item(&c, "i1", "o1", "p2", 5.0, 1.0, None);
+            insert_item(&c, "i2", "o1", "p1", 10.0, 1.0, None);
+            insert_order(&c, "o2", "c1", "2023-12-02", "completed");
+            insert_item(&c, "i3", "o2", "p1", 10.0, 3.0, None);
+            insert_order(&c, "o3", "c1", "2023-12-03", "completed");
+            c.execute_batch("UPDATE order_items SET sort_value = 2 WHERE id = 'i1'; UPDATE order_items SET sort_value = 1 WHERE id = 'i2';")
+                .unwrap();
+        }
+        let repo = OrderRepository::new(conn.clone());
+        assert_eq!(repo.archive_before("2024-01-01").unwrap(), 3);
+
+        let archived: Vec<(String, Vec<(String, f64)>)> = repo
+            .get_archived()
+            .unwrap()
+            .into_iter()
+            .map(|o| (o.id, o.items.into_iter().map(|i| (i.id, i.quantity)).collect()))
+            .collect();
+        assert_eq!(
+            archived,
+            vec![
+                ("o3".to_string(), vec![]),
+                ("o2".to_string(), vec![("p1".to_string(), 3.0)]),
+                ("o1".to_string(), vec![("p1".to_string(), 1.0), ("p2".to_string(), 1.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn order_number_sequence_is_read_at_its_position_next_to_date_digits() {
+        let conn = memory_db();
+        let mut settings = SettingsRepository::new(conn.clone()).get_settings().unwrap().unwrap();
+        settings.order_number_prefix = String::new();
+        settings.order_number_reset_period = "daily".to_string();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let c = conn.lock().unwrap();
+        insert_customer(&c, "c1", "张三", "", "");
+        insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+        let next_after = |last: &str, format: &str| {
+            c.execute("UPDATE orders SET order_number = ?1 WHERE id = 'o1'", params![last]).unwrap();
+            let settings = AppSettings { order_number_format: format.to_string(), ..settings.clone() };
+            generate_order_number_on(&c, &settings, "2024-03-10", None, today).unwrap()
+        };
+
+        // 日期与序号之间没有分隔符
+        assert_eq!(next_after("NO202403100009", "NO{YYYY}{MM}{DD}{SEQ:4}"), "NO202403100010");
+        assert_eq!(next_after("NO202403109999", "NO{YYYY}{MM}{DD}{SEQ:4}"), "NO2024031010000");
+        assert_eq!(next_after("NO2024031010000", "NO{YYYY}{MM}{DD}{SEQ:4}"), "NO2024031010001");
+        assert_eq!(next_after("2403100041", "{YY}{MM}{DD}{SEQ:4}"), "2403100042");
+        // 序号在日期前面，或单号里还有其他数字
+        assert_eq!(next_after("0420310", "{SEQ:3}{MM}{DD}"), "0430310");
+        assert_eq!(next_after("A1-20240310-007-B2", "A1-{YYYY}{MM}{DD}-{SEQ:3}-B2"), "A1-20240310-008-B2");
+        // 上一个单号不符合当前格式（例如刚改过格式）时从 1 开始
+        assert_eq!(next_after("NO.000041", "NO{YYYY}{MM}{DD}{SEQ:4}"), "NO202403100001");
+        assert_eq!(next_after("NO20240310AB12", "NO{YYYY}{MM}{DD}{SEQ:4}"), "NO202403100001");
+    }
+
+    #[test]
+    fn order_number_prefix_is_prepended_and_stripped_before_reading_the_sequence() {
+        let conn = memory_db();
+        let mut settings = SettingsRepository::new(conn.clone()).get_settings().unwrap().unwrap();
+        settings.order_number_prefix = "A-".to_string();
+        settings.order_number_format = "{YYYY}{SEQ:4}".to_string();
+        settings.order_number_reset_period = "yearly".to_string();
+        let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let c = conn.lock().unwrap();
+        insert_customer(&c, "c1", "张三", "", "");
+        let generate =
+            |settings: &AppSettings| generate_order_number_on(&c, settings, "2024-03-10", None, today).unwrap();
+
+        assert_eq!(generate(&settings), "A-20240001");
+        insert_order(&c, "o1", "c1", "2024-03-10", "draft");
+        c.execute("UPDATE orders SET order_number = 'A-20240001' WHERE id = 'o1'", []).unwrap();
+        assert_eq!(generate(&settings), "A-20240002");
+        c.execute("UPDATE orders SET order_number = 'A-20240099' WHERE id = 'o1'", []).unwrap();
+        assert_eq!(generate(&settings), "A-20240100");
+
+        // 前缀为空时与原来一致
+        let unprefixed = AppSettings { order_number_prefix: String::new(), ..settings.clone() };
+        c.execute("UPDATE orders SET order_number = '20240041' WHERE id = 'o1'", []).unwrap();
+        assert_eq!(generate(&unprefixed), "20240042");
+        // 设置前缀前生成的单号不带前缀，序号从 1 开始
+        assert_eq!(generate(&settings), "A-20240001");
+    }
+}
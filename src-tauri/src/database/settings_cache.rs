@@ -0,0 +1,40 @@
+use crate::database::schema::SettingsRepository;
+use crate::models::AppSettings;
+use rusqlite::Result;
+use std::sync::RwLock;
+
+/// 系统设置的进程内缓存（由 Tauri 托管），避免热点路径（生成订单号、导出）反复查库。
+/// 通过 save 写入时在持有写锁期间完成落库与缓存更新，读者不会看到旧值；
+/// 其他直接修改 app_settings 的操作需调用 invalidate
+#[derive(Default)]
+pub struct SettingsCache {
+    settings: RwLock<Option<AppSettings>>,
+}
+
+impl SettingsCache {
+    /// 读取设置，缓存为空时从数据库加载
+    pub fn get(&self, repo: &SettingsRepository) -> Result<Option<AppSettings>> {
+        if let Some(settings) = self.settings.read().unwrap().as_ref() {
+            return Ok(Some(settings.clone()));
+        }
+
+        let mut slot = self.settings.write().unwrap();
+        if slot.is_none() {
+            *slot = repo.get_settings()?;
+        }
+        Ok(slot.clone())
+    }
+
+    /// 保存设置并同步更新缓存
+    pub fn save(&self, repo: &SettingsRepository, settings: &AppSettings) -> Result<()> {
+        let mut slot = self.settings.write().unwrap();
+        repo.save_settings(settings)?;
+        *slot = Some(settings.clone());
+        Ok(())
+    }
+
+    /// 清空缓存，下次读取时重新加载
+    pub fn invalidate(&self) {
+        *self.settings.write().unwrap() = None;
+    }
+}
@@ -5,9 +5,31 @@ mod commands;
 mod database;
 mod models;
 mod utils;
+#[cfg(test)]
+mod test_support;
 
-use database::connection::Database;
+use database::{connection::Database, OrderLocks, SettingsCache};
+use database::schema::{OrderRepository, SettingsRepository};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tauri::Manager;
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use utils::clock::{SharedClock, SystemClock};
+use utils::logger;
+
+/// 在指定目录中打开（必要时创建）数据库并写入默认数据，返回数据库及其文件路径
+fn open_database(dir: &Path, clock: &SharedClock) -> Result<(Database, PathBuf), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("无法创建目录 {:?}: {}", dir, e))?;
+
+    let db_path = dir.join("quicksales.db");
+    let now = clock.now_rfc3339();
+    let db = Database::new(&db_path.to_string_lossy(), &now)
+        .map_err(|e| format!("无法打开数据库 {:?}: {:#}", db_path, e))?;
+    db.insert_default_data(&now)
+        .map_err(|e| format!("写入默认数据失败: {:#}", e))?;
+
+    Ok((db, db_path))
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -17,34 +39,105 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
-            // 获取应用数据目录
+            // 获取应用数据目录并打开数据库；失败时退到临时目录，仍失败则提示后退出
             let app_data_dir = app
                 .path()
                 .app_data_dir()
-                .expect("Failed to get app data directory");
+                .map_err(|e| format!("无法获取应用数据目录: {}", e));
 
-            // 确保目录存在
-            std::fs::create_dir_all(&app_data_dir).expect("Failed to create app data directory");
+            // 初始化日志（内存缓冲 + logs/quicksales.log）
+            if let Ok(dir) = &app_data_dir {
+                logger::init(Some(&dir.join("logs")));
+            } else {
+                logger::init(None);
+            }
 
-            // 数据库文件路径
-            let db_path = app_data_dir.join("quicksales.db");
+            // 时钟（测试时可替换为 MockClock）
+            let clock: SharedClock = Arc::new(SystemClock);
 
-            // 初始化数据库
-            let db =
-                Database::new(db_path.to_str().unwrap()).expect("Failed to initialize database");
+            let (db, db_path) = match app_data_dir.and_then(|dir| open_database(&dir, &clock)) {
+                Ok(opened) => opened,
+                Err(primary_err) => {
+                    log::error!("数据目录不可用: {}", primary_err);
 
-            // 插入默认数据
-            db.insert_default_data()
-                .expect("Failed to insert default data");
+                    let fallback_dir = std::env::temp_dir().join("QuickSales");
+                    match open_database(&fallback_dir, &clock) {
+                        Ok(opened) => {
+                            logger::init(Some(&fallback_dir.join("logs")));
+                            log::warn!("⚠️ 已改用临时目录保存数据: {:?}", fallback_dir);
+                            app.dialog()
+                                .message(format!(
+                                    "无法使用应用数据目录：{}\n\n本次已改用临时目录保存数据：\n{}\n\n临时目录中的数据可能被系统清理，请检查数据目录的权限（或以管理员身份运行）后重新启动。",
+                                    primary_err,
+                                    fallback_dir.display()
+                                ))
+                                .title("QuickSales 数据目录不可用")
+                                .kind(MessageDialogKind::Warning)
+                                .show(|_| {});
+                            opened
+                        }
+                        Err(fallback_err) => {
+                            log::error!("临时目录也不可用: {}", fallback_err);
+                            let handle = app.handle().clone();
+                            app.dialog()
+                                .message(format!(
+                                    "无法初始化数据库：{}\n\n备用临时目录同样不可用：{}\n\n请检查磁盘空间与目录权限，或以管理员身份运行 QuickSales。",
+                                    primary_err, fallback_err
+                                ))
+                                .title("QuickSales 无法启动")
+                                .kind(MessageDialogKind::Error)
+                                .show(move |_| handle.exit(1));
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+
+            // 修正指向已删除模板/分类的默认设置
+            match SettingsRepository::new(db.conn.clone()).validate_references(true, &clock.now_rfc3339()) {
+                Ok(check) if check.healed => log::warn!(
+                    "默认设置引用已失效并自动修正: 模板 {:?}, 分类 {:?}",
+                    check.default_template_id,
+                    check.default_category_id
+                ),
+                Ok(_) => {}
+                Err(e) => log::error!("检查设置引用失败: {}", e),
+            }
+
+            // 检查重复订单号（不自动修复，由用户在维护工具中执行修复）
+            match OrderRepository::new(db.conn.clone()).find_duplicate_numbers() {
+                Ok(duplicates) if !duplicates.is_empty() => log::warn!(
+                    "⚠️ 发现 {} 组重复订单号: {}",
+                    duplicates.len(),
+                    duplicates
+                        .iter()
+                        .map(|d| format!("{}（{} 个订单）", d.order_number, d.order_ids.len()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Ok(_) => {}
+                Err(e) => log::error!("检查重复订单号失败: {}", e),
+            }
 
             // 获取连接并管理应用状态
             let conn = db.conn;
 
+            // 按设置调整日志级别
+            if let Ok(Some(settings)) = SettingsRepository::new(conn.clone()).get_settings() {
+                logger::set_level(&settings.log_level);
+            }
+
             // 将数据库连接存储到全局状态中
             app.manage(conn);
+            // 系统设置缓存（首次读取时加载）
+            app.manage(SettingsCache::default());
+            // 订单保存锁（同一订单的保存依次执行）
+            app.manage(OrderLocks::default());
+            // 时钟（打开数据库时已创建）
+            app.manage(clock);
 
-            println!("✅ QuickSales 数据库初始化成功!");
-            println!("📁 数据库位置: {:?}", db_path);
+            log::info!("✅ QuickSales 数据库初始化成功!");
+            log::info!("📁 数据库位置: {:?}", db_path);
 
             Ok(())
         })
@@ -52,50 +145,140 @@ pub fn run() {
             // 商品相关命令
             commands::get_all_products,
             commands::get_product_by_id,
+            commands::get_product_by_barcode,
             commands::search_products,
+            commands::search_products_fts,
+            commands::get_products,
             commands::get_products_by_category,
             commands::save_product,
             commands::delete_product,
             commands::batch_delete_products,
             commands::update_product_price,
+            commands::adjust_prices,
+            commands::find_price_anomalies,
             commands::generate_product_pinyin,
             commands::batch_update_pinyin,
+            commands::export_products_csv,
+            commands::import_products_csv,
             // 客户相关命令
             commands::get_all_customers,
             commands::get_customer_by_id,
             commands::search_customers,
+            commands::get_customers_paged,
             commands::save_customer,
             commands::merge_customers,
+            commands::resolve_customer,
+            commands::import_customers_csv,
+            commands::export_customers_with_orders,
+            commands::import_customers_with_orders,
             commands::delete_customer,
             commands::batch_delete_customers,
+            commands::add_customer_charge,
+            commands::add_customer_payment,
+            commands::add_customer_refund,
+            commands::get_customer_transactions,
+            commands::get_customer_balance,
+            commands::get_outstanding_balances,
             // 分类相关命令
             commands::get_all_categories,
             commands::get_category_by_id,
             commands::get_category_tree,
+            commands::get_category_breadcrumb,
             commands::save_category,
             commands::save_categories_batch,
+            commands::import_categories_csv,
+            commands::get_next_sort_order,
             commands::delete_category,
+            commands::delete_category_subtree,
             // 订单和模板相关命令
             commands::get_all_orders,
+            commands::get_order_by_id,
+            commands::get_all_orders_paged,
+            commands::get_orders_by_date_range,
+            commands::search_orders,
+            commands::rebuild_order_search_index,
+            commands::get_sales_total,
+            commands::archive_orders_before,
+            commands::get_archived_orders,
+            commands::restore_archived_order,
+            commands::get_category_sales_ranking,
+            commands::get_daily_sales,
+            commands::get_top_products,
+            commands::get_top_customers,
+            commands::get_report_bundle,
+            commands::verify_totals,
             commands::save_order,
+            commands::save_order_draft,
+            commands::get_draft_orders,
+            commands::delete_draft_order,
+            commands::get_pending_buffers,
+            commands::clear_buffers,
+            commands::update_order_status,
+            commands::delete_order,
+            commands::batch_delete_orders,
+            commands::split_order,
+            commands::merge_orders,
+            commands::add_order_attachment,
+            commands::get_order_attachments,
+            commands::export_order_to_excel,
+            commands::export_orders_grouped,
+            commands::record_order_export,
+            commands::get_order_export_info,
+            commands::get_order_audit_trail,
             commands::get_all_templates,
+            commands::get_template_file,
             commands::save_template,
             commands::delete_template,
+            commands::delete_template_with_reassign,
             commands::save_settings,
             commands::get_settings,
             commands::update_all_template_filename_patterns,
+            commands::set_all_template_filename_patterns,
+            commands::inspect_template_file,
+            commands::resolve_order_template,
+            commands::preview_export_filenames,
+            commands::set_orders_template,
+            commands::validate_order_against_template,
+            commands::audit_templates,
             // 备注预设相关命令
             commands::get_all_remark_presets,
             commands::get_remark_presets_by_type,
             commands::save_remark_preset,
             commands::delete_remark_preset,
             commands::increment_remark_use_count,
+            commands::increment_presets_batch,
             // 单位预设相关命令
             commands::get_all_unit_presets,
             commands::get_unit_preset_by_id,
             commands::save_unit_preset,
             commands::delete_unit_preset,
             commands::increment_unit_preset_use_count,
+            // 日志相关命令
+            commands::get_recent_logs,
+            // 库存相关命令
+            commands::get_movement_summary,
+            commands::adjust_stock,
+            commands::get_stock_movements,
+            commands::get_low_stock_products,
+            commands::export_low_stock_csv,
+            commands::refresh_product_stats,
+            commands::export_stocktake_sheet,
+            commands::get_dead_stock,
+            commands::get_reorder_suggestions,
+            // 数据维护相关命令
+            commands::normalize_all_order_item_sort,
+            commands::find_orphaned_order_items,
+            commands::purge_orphaned_order_items,
+            commands::export_diagnostic_bundle,
+            commands::validate_settings_references,
+            commands::find_duplicate_order_numbers,
+            commands::dedupe_order_numbers,
+            commands::restore_default_data,
+            // 数据导入导出相关命令
+            commands::export_all_json,
+            commands::import_all_json,
+            commands::backup_database,
+            commands::restore_database,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -19,6 +19,96 @@ pub struct Product {
     pub track_stock: Option<bool>, // 是否跟踪库存
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub barcode: Option<String>, // 商品条码（扫码枪录入），未设置时为空
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductPage {
+    pub items: Vec<Product>,
+    pub total: i64, // 符合筛选条件的商品总数（不受分页影响）
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceAnomaly {
+    pub product: Product,
+    pub price: f64,
+    pub category_mean: f64, // 同分类其他商品的均价
+    pub z_score: f64,       // 偏离其他商品均价的标准差倍数（正数偏高，负数偏低）
+}
+
+// low-stock 事件的内容：扣减后库存降到最低库存及以下的商品
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LowStockAlert {
+    pub product_id: String,
+    pub name: String,
+    pub stock: f64,     // 扣减后的库存
+    pub min_stock: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovementReasonTotal {
+    pub reason: String,
+    pub change: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderSuggestion {
+    pub product: Product,
+    pub units_per_day: f64,            // 缓存的日均销量
+    pub days_of_stock: Option<f64>,    // 按日均销量计算的预计可售天数（无销量时为空）
+    pub suggested_quantity: f64,
+    pub window_days: Option<i64>,      // 日均销量的统计窗口（天），未刷新过时为空
+    pub refreshed_at: Option<String>,  // 缓存最后刷新时间
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadStockItem {
+    pub product: Product,
+    pub last_sold_date: Option<String>, // 最近一次售出的订单日期，从未售出时为空
+    pub tied_up_value: f64,             // 占用资金：库存 × 零售价
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakeItem {
+    pub product_id: String,
+    pub name: String,
+    pub unit: String,
+    pub category_name: String,
+    pub stock: Option<f64>, // 系统库存，为空表示尚未盘点
+}
+
+// 库存流水：reason 为 sale（销售扣减）、restock（订单删除/修改退回）或手工调整时填写的原因
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockMovement {
+    pub id: String,
+    pub product_id: String,
+    pub change: f64,                 // 正数入库，负数出库
+    pub reason: String,
+    pub order_id: Option<String>,    // 订单引起的变动关联的订单 ID
+    pub balance_after: Option<f64>,  // 变动后的库存
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StockMovementSummary {
+    pub product_id: String,
+    pub product_name: String,
+    pub unit: String,
+    pub deducted: f64,     // 期间出库合计（销售等，正数）
+    pub added: f64,        // 期间入库合计（补货/盘点等）
+    pub net_change: f64,
+    pub current_stock: Option<f64>,
+    pub reasons: Vec<MovementReasonTotal>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +126,29 @@ pub struct Category {
     pub updated_at: String,
 }
 
+// delete_category_subtree 的返回值：删除的分类数（含子孙分类）与改到目标分类下的商品数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategorySubtreeDeletion {
+    pub deleted_categories: usize,
+    pub reassigned_products: usize,
+    pub reassigned_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryImportResult {
+    pub inserted: usize,
+    pub warnings: Vec<String>, // 跳过的行、找不到的上级、循环引用等提示
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryCrumb {
+    pub id: String,
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Customer {
@@ -51,6 +164,21 @@ pub struct Customer {
     pub updated_at: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerResolution {
+    pub customer: Option<Customer>, // 当前有效的客户（已删除或不存在时为空）
+    pub is_placeholder: bool,       // 传入的是占位 ID（临时/订单快照/deleted_*）
+    pub merged: bool,               // 传入的客户已被合并到其他客户
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerPage {
+    pub items: Vec<Customer>,
+    pub total: i64, // 符合条件的客户总数（不含临时/快照/已删除占位客户）
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderItem {
@@ -65,6 +193,10 @@ pub struct OrderItem {
     pub remark: Option<String>,
     #[serde(alias = "sort_value")]
     pub sort_value: i64,
+    #[serde(alias = "line_total", default)]
+    pub line_total: f64, // 行金额（实际单价 × 数量），保存订单时由后端计算
+    #[serde(alias = "line_id", default)]
+    pub line_id: Option<String>, // 订单项行 ID（order_items.id，id 为商品 ID），新加的行为空，保存时生成
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,10 +216,212 @@ pub struct Order {
     #[serde(alias = "template_id")]
     pub template_id: Option<String>,
     pub status: String,
+    #[serde(alias = "on_account", default)]
+    pub on_account: bool, // 挂账（未付款，记入客户往来账）
+    #[serde(alias = "rounding_adjustment", default)]
+    pub rounding_adjustment: f64, // 抹零调整额（抹零后总额 - 明细合计）
+    pub currency: Option<String>, // 外币订单的币种，None 表示本币
+    #[serde(alias = "exchange_rate", default = "default_exchange_rate")]
+    pub exchange_rate: f64, // 外币兑本币汇率，本币订单为 1.0
+    #[serde(alias = "order_discount", default)]
+    pub order_discount: f64, // 整单优惠金额（在明细合计上减免，计税与抹零之前）
+    #[serde(alias = "tax_rate", default)]
+    pub tax_rate: Option<f64>, // 保存时使用的税率（百分比），由后端写入；早于计税快照的订单为空
+    #[serde(alias = "prices_include_tax", default)]
+    pub prices_include_tax: Option<bool>, // 保存时价格是否含税，与 tax_rate 一同写入
     pub created_at: String,
     pub updated_at: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPage {
+    pub items: Vec<Order>,
+    pub total: i64, // 订单总数（不受分页影响）
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderEvent {
+    pub id: String,
+    #[serde(alias = "order_id")]
+    pub order_id: String,
+    #[serde(alias = "event_type")]
+    pub event_type: String, // "created" | "updated" | "status_changed" | "exported"
+    pub detail: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAttachment {
+    pub id: String,
+    pub order_id: String,
+    pub file_path: String, // 复制到附件目录后的完整路径
+    pub file_name: String, // 原始文件名
+    pub kind: String,      // "image" | "document"
+    pub size_bytes: i64,
+    pub created_at: String,
+}
+
+fn default_exchange_rate() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveOrderResult {
+    pub order_number: String,
+    pub warnings: Vec<String>, // 超出数量/总额上限等提示（不阻止保存）
+}
+
+// inspect_template_file 的返回值：valid 为 false 时 error 说明原因，其余字段为能读到的部分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateFileInfo {
+    pub valid: bool,
+    pub sheet_names: Vec<String>,
+    pub max_row: u32,
+    pub max_col: usize,
+    pub size_bytes: usize,
+    pub error: Option<String>,
+}
+
+// get_order_export_info 的返回值：订单被导出/打印的次数与最后一次导出时间
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderExportInfo {
+    pub order_id: String,
+    pub order_number: String,
+    pub export_count: i64,
+    pub last_exported_at: Option<String>,
+}
+
+// export_order_to_excel 的返回值：生成的 xlsx 文件内容与建议的文件名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExcelExport {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFilenamePreview {
+    pub order_id: String,
+    pub order_number: String,
+    pub filename: String,
+    pub collision: bool,                 // 与其他订单生成的文件名相同（不区分大小写）
+    pub collision_group: Option<usize>,  // 重名分组序号，同组订单文件名相同
+}
+
+// get_pending_buffers 的返回值：当前暂存的未保存数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingBuffers {
+    pub draft_count: usize,
+    pub oldest_draft_at: Option<String>,
+    pub newest_draft_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitOrderResult {
+    pub original_order_id: String,
+    pub new_order_id: String,
+    pub new_order_number: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderTotalMismatch {
+    pub order_id: String,
+    pub order_number: String,
+    pub stored_total: f64,
+    pub expected_total: f64, // 按明细、整单优惠、税率与抹零重新计算的总额
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SalesTotal {
+    pub order_count: i64,
+    pub total_amount: f64, // 折算为本币后的合计
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailySales {
+    pub date: String, // YYYY-MM-DD
+    pub order_count: i64,
+    pub total_amount: f64, // 折算为本币后的合计
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductSales {
+    pub product_id: Option<String>, // 手动录入（未关联商品）的订单项按名称汇总，此项为空
+    pub name: String,
+    pub quantity: f64,
+    pub revenue: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerSales {
+    pub customer_id: String,
+    pub name: String,
+    pub order_count: i64,
+    pub total_amount: f64,
+}
+
+/// 报表页所需的全部统计（同一次加锁内计算，各项与对应的单独命令结果一致）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportBundle {
+    pub from: String,
+    pub to: String,
+    pub sales_total: SalesTotal,
+    pub daily_sales: Vec<DailySales>,
+    pub top_products: Vec<ProductSales>,
+    pub top_customers: Vec<CustomerSales>,
+    pub category_sales: Vec<CategorySales>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategorySales {
+    pub category_id: String,  // “未分类”为空字符串
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub level: i32,
+    pub own_revenue: f64,     // 直接归属该分类的商品销售额
+    pub subtree_revenue: f64, // 含全部下级分类的销售额
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTransaction {
+    pub id: String,
+    #[serde(alias = "customer_id")]
+    pub customer_id: String,
+    pub amount: f64,
+    pub kind: String, // "charge" | "payment" | "refund"
+    #[serde(alias = "order_id")]
+    pub order_id: Option<String>,
+    pub remark: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerBalance {
+    pub customer_id: String,
+    pub customer_name: String,
+    pub charged: f64,
+    pub paid: f64,
+    pub balance: f64, // 正数表示客户欠款
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TemplateConfig {
@@ -107,6 +441,29 @@ pub struct TemplateConfig {
     pub required_fields: RequiredFields,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(alias = "number_format", default)]
+    pub number_format: Option<String>, // 模板独立的单号格式，为空时使用全局设置
+    // 是否已上传模板文件；模板列表不带文件内容，保存时 template_base64 为空且 has_file 为 true 表示保留原文件
+    #[serde(alias = "has_file", default)]
+    pub has_file: bool,
+}
+
+// resolve_order_template 的返回值：订单导出时实际使用的模板（不含文件内容），
+// capacity 为模板的商品行数（起始行到结束行），未配置商品行范围时为空（不限制）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedTemplate {
+    #[serde(flatten)]
+    pub template: TemplateConfig,
+    pub capacity: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateAuditIssue {
+    pub template_id: String,
+    pub template_name: String,
+    pub unmapped_fields: Vec<String>, // 设为必填但没有配置单元格/列的字段
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -138,6 +495,9 @@ pub struct RequiredFields {
     pub require_item_total: bool,
     #[serde(alias = "item_remark", alias = "itemRemark", default)]
     pub require_item_remark: bool,
+    // 最少商品行数（正式表单要求至少填写 N 行），0 表示不限制
+    #[serde(alias = "min_items", default)]
+    pub min_items: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -157,17 +517,43 @@ pub struct TemplateMappings {
     pub order_remark: String,
     #[serde(alias = "total_amount", default)]
     pub total_amount: String,
+    #[serde(default)]
+    pub subtotal: String, // 税前小计单元格（可选）
+    #[serde(alias = "tax_amount", default)]
+    pub tax_amount: String, // 税额单元格（可选）
     #[serde(alias = "item_start_row", default)]
     pub item_start_row: i32,
     #[serde(alias = "item_end_row", default)]
     pub item_end_row: i32,
     #[serde(default)]
     pub columns: TemplateColumns,
+    #[serde(alias = "number_formats", default)]
+    pub number_formats: TemplateNumberFormats,
+}
+
+/// 写入数值单元格时使用的数字格式（如 "#,##0.00"），为空时保留模板单元格原有格式
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateNumberFormats {
+    #[serde(default)]
+    pub quantity: String,
+    #[serde(default)]
+    pub price: String,
+    #[serde(default)]
+    pub total: String,
+    #[serde(alias = "total_amount", default)]
+    pub total_amount: String,
+    #[serde(default)]
+    pub subtotal: String,
+    #[serde(alias = "tax_amount", default)]
+    pub tax_amount: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct TemplateColumns {
+    #[serde(default)]
+    pub index: String, // 序号列（可选，为空时不写入）
     #[serde(default)]
     pub name: String,
     #[serde(default)]
@@ -233,7 +619,9 @@ pub struct AppSettings {
     #[serde(alias = "order_number_prefix")]
     pub order_number_prefix: String,
     #[serde(alias = "order_number_reset_daily")]
-    pub order_number_reset_daily: bool,
+    pub order_number_reset_daily: bool, // 已弃用：未设置 order_number_reset_period 时，true 等同 daily，false 等同 never
+    #[serde(alias = "order_number_reset_period", default)]
+    pub order_number_reset_period: String, // "daily" | "monthly" | "yearly" | "never"（序号重新从 1 开始的周期）
     #[serde(alias = "order_number_digits")]
     pub order_number_digits: i32,
     #[serde(alias = "retain_days")]
@@ -256,5 +644,175 @@ pub struct AppSettings {
     pub skip_save_dialog: bool,
     #[serde(alias = "template_validation")]
     pub template_validation: Option<RequiredFields>,
+    #[serde(alias = "log_level", default = "default_log_level")]
+    pub log_level: String, // "error" | "warn" | "info" | "debug" | "trace"
+    #[serde(alias = "total_rounding", default = "default_total_rounding")]
+    pub total_rounding: String, // "none" | "round" | "floor"（订单总额抹零到元）
+    #[serde(alias = "tax_rate", default)]
+    pub tax_rate: f64, // 税率（百分比，0 表示不计税）
+    #[serde(alias = "prices_include_tax", default = "default_prices_include_tax")]
+    pub prices_include_tax: bool, // 价格是否含税
+    #[serde(alias = "max_item_quantity", default)]
+    pub max_item_quantity: f64, // 单行数量上限，0 表示不限制
+    #[serde(alias = "max_order_total", default)]
+    pub max_order_total: f64, // 订单总额上限，0 表示不限制
+    #[serde(alias = "block_on_order_limits", default)]
+    pub block_on_order_limits: bool, // 超出上限时禁止保存，否则只提示
+    #[serde(alias = "export_item_sort", default = "default_export_item_sort")]
+    pub export_item_sort: String, // "entry" | "category" | "amount_desc"（导出时订单项的排列方式）
+    #[serde(alias = "default_track_stock", default)]
+    pub default_track_stock: bool, // 新建商品未指定时是否默认跟踪库存
+    #[serde(alias = "default_min_stock", default)]
+    pub default_min_stock: f64, // 新建跟踪库存商品的默认最低库存，0 表示不设置
+    #[serde(alias = "allow_zero_price", default = "default_allow_zero_price")]
+    pub allow_zero_price: bool, // 是否允许零售价为 0 的商品（服务类商品可能为 0）
+    #[serde(alias = "strict_stock", default)]
+    pub strict_stock: bool, // 库存不足时禁止保存订单，否则扣减到 0 为止
     pub updated_at: String,
 }
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_total_rounding() -> String {
+    "none".to_string()
+}
+
+fn default_prices_include_tax() -> bool {
+    true
+}
+
+fn default_export_item_sort() -> String {
+    "entry".to_string()
+}
+
+fn default_allow_zero_price() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedOrderItems {
+    pub count: usize,
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoredDefaults {
+    pub categories_added: Vec<String>, // 重新插入的默认分类名称
+    pub units_added: Vec<String>,      // 重新插入的单位预设名称
+    pub template_added: bool,          // 是否重新插入了默认模板
+    pub overwritten: usize,            // overwrite 时恢复为默认配置的已有项数量
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateOrderNumber {
+    pub order_number: String,
+    pub order_ids: Vec<String>, // 按创建时间从早到晚排列，第一个为保留原单号的订单
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableImportCount {
+    pub table: String,
+    pub rows: usize,
+    pub skipped: Vec<String>, // 与本机已有记录冲突（或引用了被跳过的记录）而未导入的记录主键
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsReferenceCheck {
+    pub template_exists: bool,       // 默认模板是否存在（未设置视为存在）
+    pub category_exists: bool,       // 默认分类是否存在（未设置视为存在）
+    pub default_template_id: String, // 检查/修正后的默认模板 ID
+    pub default_category_id: String, // 检查/修正后的默认分类 ID
+    pub healed: bool,                // 是否已自动修正
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportRow {
+    pub line: usize,            // 文件中的行号（含表头）
+    pub name: String,
+    pub action: String,         // insert | update | skip
+    pub reason: Option<String>, // 跳过或更新的原因
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportReport {
+    pub rows: Vec<CsvImportRow>,
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub dry_run: bool, // 为 true 时仅预览，未写入数据库
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTransferEntry {
+    pub customer: Customer,
+    pub orders: Vec<Order>, // 客户的订单（含订单项）
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTransferBundle {
+    pub version: u32,
+    pub exported_at: String,
+    pub customers: Vec<CustomerTransferEntry>,
+    #[serde(default)]
+    pub products: Vec<CustomerTransferProduct>, // 订单项引用的商品，导入时按条码或名称对应到本机商品；旧文件没有
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTransferProduct {
+    pub id: String,
+    pub name: String,
+    pub barcode: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerIdMapping {
+    pub source_id: String, // 导出文件中的客户 ID
+    pub target_id: String, // 本机对应的客户 ID
+    pub name: String,
+    pub action: String,    // matched（按电话/车牌匹配到已有客户）| created
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderIdMapping {
+    pub source_id: String,
+    pub target_id: String,
+    pub source_number: String,
+    pub target_number: String,  // 单号在本机已被其他订单占用时追加后缀
+    pub customer_id: String,    // 关联到的本机客户 ID
+    pub action: String,         // imported | skipped
+    pub reason: Option<String>, // 跳过原因
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTransferReport {
+    pub customers: Vec<CustomerIdMapping>,
+    pub orders: Vec<OrderIdMapping>,
+    pub customers_matched: usize,
+    pub customers_created: usize,
+    pub orders_imported: usize,
+    pub orders_skipped: usize,
+}
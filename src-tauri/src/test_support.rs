@@ -0,0 +1,142 @@
+// 单元测试共用的数据库、托管状态与测试数据
+use crate::database::connection::{Database, DbConnection};
+use crate::database::{OrderLocks, SettingsCache};
+use crate::models::{Customer, Order, OrderItem};
+use crate::utils::clock::{MockClock, SharedClock};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::sync::Arc;
+use tauri::test::{mock_app, MockRuntime};
+use tauri::{App, Manager};
+
+/// 建好全部表并插入默认数据（分类、单位、模板、设置）的内存数据库
+pub fn memory_db() -> DbConnection {
+    let now = "2024-01-01T00:00:00+00:00";
+    let db = Database::new(":memory:", now).expect("打开内存数据库失败");
+    db.insert_default_data(now).expect("插入默认数据失败");
+    db.conn
+}
+
+/// 插入分类（parent_id 为空表示顶级分类）
+pub fn insert_category(conn: &Connection, id: &str, parent_id: Option<&str>, level: i32) {
+    conn.execute(
+        "INSERT INTO categories (id, name, parent_id, level, path, sort_order, created_at, updated_at)
+         VALUES (?1, ?1, ?2, ?3, ?1, 0, 'now', 'now')",
+        params![id, parent_id, level],
+    )
+    .unwrap();
+}
+
+/// 插入商品（category_id 为空表示未分类）
+pub fn insert_product(conn: &Connection, id: &str, price: f64, category_id: Option<&str>) {
+    conn.execute(
+        "INSERT INTO products (id, name, unit, price, price_cents, category_id, created_at, updated_at)
+         VALUES (?1, ?1, '个', ?2, ?3, ?4, 'now', 'now')",
+        params![id, price, (price * 100.0).round() as i64, category_id],
+    )
+    .unwrap();
+}
+
+/// 插入客户
+pub fn insert_customer(conn: &Connection, id: &str, name: &str, phone: &str, license_plate: &str) {
+    conn.execute(
+        "INSERT INTO customers (id, name, phone, license_plate, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, 'now', 'now')",
+        params![id, name, phone, license_plate],
+    )
+    .unwrap();
+}
+
+/// 插入订单（总额为 0，需要时由调用方更新）
+pub fn insert_order(conn: &Connection, id: &str, customer_id: &str, date: &str, status: &str) {
+    conn.execute(
+        "INSERT INTO orders (id, order_number, date, customer_id, total_amount, total_amount_cents, status, created_at, updated_at)
+         VALUES (?1, ?1, ?2, ?3, 0, 0, ?4, ?2, ?2)",
+        params![id, date, customer_id, status],
+    )
+    .unwrap();
+}
+
+/// 插入订单项（行金额按单价 × 数量计算，分类为空时不写分类快照）
+pub fn insert_item(conn: &Connection, id: &str, order_id: &str, product_id: &str, price: f64, quantity: f64, category: Option<&str>) {
+    let line_total = price * quantity;
+    conn.execute(
+        "INSERT INTO order_items (id, order_id, product_id, name, unit, price, price_cents, quantity, category, line_total, line_total_cents)
+         VALUES (?1, ?2, ?3, ?3, '个', ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id,
+            order_id,
+            product_id,
+            price,
+            (price * 100.0).round() as i64,
+            quantity,
+            category,
+            line_total,
+            (line_total * 100.0).round() as i64
+        ],
+    )
+    .unwrap();
+}
+
+/// 托管了数据库、设置缓存、订单锁与 MockClock 的测试应用，命令参数通过 app.state() 取得
+pub fn test_app(conn: DbConnection, now: &str) -> (App<MockRuntime>, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(
+        DateTime::parse_from_rfc3339(now).expect("时间格式错误").with_timezone(&Utc),
+    ));
+    let app = mock_app();
+    app.manage(conn);
+    app.manage(SettingsCache::default());
+    app.manage(OrderLocks::default());
+    app.manage(clock.clone() as SharedClock);
+    (app, clock)
+}
+
+/// 待保存的订单：订单号为空（由后端生成），items 为 (商品ID, 单价, 数量)
+pub fn new_order(id: &str, customer_id: &str, status: &str, items: &[(&str, f64, f64)]) -> Order {
+    Order {
+        id: id.to_string(),
+        order_number: String::new(),
+        date: String::new(),
+        customer_id: customer_id.to_string(),
+        customer: Customer {
+            id: customer_id.to_string(),
+            name: "测试客户".to_string(),
+            phone: String::new(),
+            license_plate: String::new(),
+            address: None,
+            last_purchase_at: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+        },
+        items: items
+            .iter()
+            .enumerate()
+            .map(|(index, (product_id, price, quantity))| OrderItem {
+                id: product_id.to_string(),
+                name: product_id.to_string(),
+                unit: "个".to_string(),
+                price: *price,
+                quantity: *quantity,
+                category: String::new(),
+                discount_price: None,
+                remark: None,
+                sort_value: index as i64,
+                line_total: 0.0,
+                line_id: None,
+            })
+            .collect(),
+        total_amount: 0.0,
+        remark: None,
+        template_id: None,
+        status: status.to_string(),
+        on_account: false,
+        rounding_adjustment: 0.0,
+        currency: None,
+        exchange_rate: 1.0,
+        order_discount: 0.0,
+        tax_rate: None,
+        prices_include_tax: None,
+        created_at: String::new(),
+        updated_at: String::new(),
+    }
+}
@@ -0,0 +1,60 @@
+// 时钟抽象：订单号每日重置、销量统计窗口等依赖“当前时间”的逻辑通过 State 中的时钟取时间，
+// 测试时换成 MockClock 即可模拟跨天
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::sync::Arc;
+
+pub trait Clock: Send + Sync {
+    /// 当前时间（UTC）
+    fn now(&self) -> DateTime<Utc>;
+
+    /// 当前本地日期（订单日期、统计窗口均按本地日期计算）
+    fn today(&self) -> NaiveDate {
+        self.now().with_timezone(&Local).date_naive()
+    }
+
+    /// 当前时间的 RFC 3339 字符串（用于 created_at / updated_at）
+    fn now_rfc3339(&self) -> String {
+        self.now().to_rfc3339()
+    }
+}
+
+/// 由 Tauri 托管的时钟
+pub type SharedClock = Arc<dyn Clock>;
+
+/// 系统时钟
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// 可手动设置与拨动的时钟（测试用）
+#[cfg(test)]
+pub struct MockClock {
+    now: std::sync::Mutex<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: std::sync::Mutex::new(now) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,101 @@
+// CSV 导入导出辅助：导出带 BOM 的 UTF-8 便于 Excel 直接打开中文内容
+
+use crate::models::{CsvImportReport, CsvImportRow};
+
+/// UTF-8 BOM，写在文件开头让 Excel 按 UTF-8 识别
+pub const BOM: &str = "\u{feff}";
+
+/// 写出带 BOM 的 CSV 文本：首行为表头，字段包含逗号、引号或换行时加引号（内部引号双写），行以 \r\n 结尾
+pub fn write_rows<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) -> Result<String, String> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .terminator(::csv::Terminator::CRLF)
+        .from_writer(BOM.as_bytes().to_vec());
+    writer.write_record(header).map_err(|e| e.to_string())?;
+    for row in rows {
+        writer
+            .write_record(row.iter().map(|field| field.as_ref()))
+            .map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// 解析 CSV 文本为行列表：支持引号字段、双写引号与字段内换行，各行列数可以不同，去掉开头的 BOM，跳过空行
+pub fn parse(content: &str) -> Result<Vec<Vec<String>>, String> {
+    let content = content.strip_prefix(BOM).unwrap_or(content);
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("CSV 格式错误: {}", e))?;
+        if record.iter().any(|field| !field.trim().is_empty()) {
+            rows.push(record.iter().map(str::to_string).collect());
+        }
+    }
+    Ok(rows)
+}
+
+/// 首行首列为表头名称（忽略大小写）时移除该行，返回是否移除
+pub fn strip_header(records: &mut Vec<Vec<String>>, names: &[&str]) -> bool {
+    let is_header = records
+        .first()
+        .and_then(|r| r.first())
+        .map(|first| {
+            let first = first.trim().to_lowercase();
+            names.iter().any(|name| first == *name)
+        })
+        .unwrap_or(false);
+    if is_header {
+        records.remove(0);
+    }
+    is_header
+}
+
+/// 汇总逐行导入结果（预览与实际导入共用）
+pub fn import_report(rows: Vec<CsvImportRow>, dry_run: bool) -> CsvImportReport {
+    let count = |action: &str| rows.iter().filter(|r| r.action == action).count();
+    CsvImportReport {
+        inserted: count("insert"),
+        updated: count("update"),
+        skipped: count("skip"),
+        dry_run,
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_with_commas_quotes_and_newlines_survive_a_round_trip() {
+        let rows = vec![
+            vec!["机油, 5W-30".to_string(), "瓶".to_string(), "45".to_string()],
+            vec!["12\"轮毂".to_string(), "只".to_string(), "".to_string()],
+            vec!["第一行\r\n第二行\n第三行".to_string(), "\"套\"".to_string(), "1.5".to_string()],
+        ];
+        let content = write_rows(&["商品名称", "单位", "零售价"], &rows).unwrap();
+        assert!(content.starts_with(BOM));
+        assert!(content.contains("\"机油, 5W-30\",瓶,45\r\n"));
+
+        let mut parsed = parse(&content).unwrap();
+        assert!(strip_header(&mut parsed, &["商品名称"]));
+        assert_eq!(parsed, rows);
+    }
+
+    #[test]
+    fn quotes_inside_an_unquoted_field_are_kept_as_text() {
+        let parsed = parse("名称,备注\r\n12\"轮毂,a\"b\"c\r\n\r\n ,  \r\n尾行,\"含,逗号\"").unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                vec!["名称".to_string(), "备注".to_string()],
+                vec!["12\"轮毂".to_string(), "a\"b\"c".to_string()],
+                vec!["尾行".to_string(), "含,逗号".to_string()],
+            ]
+        );
+    }
+}
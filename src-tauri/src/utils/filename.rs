@@ -0,0 +1,147 @@
+// 导出文件名模板：如 {date}_{customerName}_{orderNumber}
+
+use crate::models::{AppSettings, Order};
+use crate::utils::money::{from_cents, to_cents};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// 设置与模板都没有配置文件名格式时使用的默认格式
+pub const DEFAULT_FILENAME_PATTERN: &str = "{date}_{customerName}_{orderNumber}";
+
+/// 文件名模板支持的占位符
+pub const FILENAME_TOKENS: &[&str] = &[
+    "date",
+    "customerName",
+    "customer",
+    "orderNumber",
+    "orderNo",
+    "licensePlate",
+    "customerPlate",
+    "totalAmount",
+];
+
+/// 文件名模板中的占位符 {name}
+static FILENAME_TOKEN_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([^{}]*)\}").unwrap());
+
+/// 占位符值为空时，与之相邻的这些分隔符会被合并，避免出现 "__NO.000001" 这样的文件名
+const FILENAME_SEPARATORS: &[char] = &['_', '-', ' ', '.'];
+
+/// Windows 文件名中不允许出现的字符
+pub const ILLEGAL_FILENAME_CHARS: &[char] = &['\\', '/', ':', '*', '?', '"', '<', '>', '|'];
+
+/// 校验文件名模板：只允许已知占位符，且不包含非法文件名字符
+pub fn validate_filename_pattern(pattern: &str) -> Result<(), String> {
+    if pattern.trim().is_empty() {
+        return Err("文件名格式不能为空".to_string());
+    }
+
+    let unknown: Vec<String> = FILENAME_TOKEN_RE
+        .captures_iter(pattern)
+        .filter_map(|caps| {
+            let token = caps.get(1).map_or("", |m| m.as_str());
+            (!FILENAME_TOKENS.contains(&token)).then(|| format!("{{{}}}", token))
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "不支持的占位符: {}（可用: {}）",
+            unknown.join(", "),
+            FILENAME_TOKENS
+                .iter()
+                .map(|t| format!("{{{}}}", t))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let literal = FILENAME_TOKEN_RE.replace_all(pattern, "");
+    if literal.contains('{') || literal.contains('}') {
+        return Err("文件名格式中的花括号不完整".to_string());
+    }
+    if let Some(c) = literal
+        .chars()
+        .find(|c| ILLEGAL_FILENAME_CHARS.contains(c) || c.is_control())
+    {
+        return Err(format!("文件名格式包含非法字符: {:?}", c));
+    }
+
+    Ok(())
+}
+
+/// 占位符对应的订单字段值（日期去掉连字符，金额保留两位小数），未知占位符返回 None
+fn token_value(token: &str, order: &Order) -> Option<String> {
+    let value = match token {
+        "date" => order.date.replace('-', ""),
+        "customerName" | "customer" => order.customer.name.clone(),
+        "orderNumber" | "orderNo" => order.order_number.clone(),
+        "licensePlate" | "customerPlate" => order.customer.license_plate.clone(),
+        "totalAmount" => format!("{:.2}", from_cents(to_cents(order.total_amount))),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// 追加模板中的字面文本；前一个占位符为空时去掉与已有分隔符重复的开头分隔符
+fn push_literal(expanded: &mut String, pending_gap: &mut bool, literal: &str) {
+    let literal = if *pending_gap && (expanded.is_empty() || expanded.ends_with(FILENAME_SEPARATORS)) {
+        literal.trim_start_matches(FILENAME_SEPARATORS)
+    } else {
+        literal
+    };
+    if !literal.is_empty() {
+        expanded.push_str(literal);
+        *pending_gap = false;
+    }
+}
+
+/// 按模板展开订单的导出文件名，规则与前端 generateFileName 一致：
+/// 日期去掉连字符，非法字符与空白替换为下划线并合并连续下划线，补全 .xlsx 后缀。
+/// 占位符的值为空时去掉它两侧多余的分隔符
+pub fn expand_filename_pattern(pattern: &str, order: &Order) -> String {
+    let mut expanded = String::with_capacity(pattern.len());
+    // 上一个占位符为空时，后面紧接的分隔符需要与已有的分隔符合并
+    let mut pending_gap = false;
+    let mut last = 0;
+    for caps in FILENAME_TOKEN_RE.captures_iter(pattern) {
+        let whole = caps.get(0).unwrap();
+        push_literal(&mut expanded, &mut pending_gap, &pattern[last..whole.start()]);
+        last = whole.end();
+        match token_value(&caps[1], order) {
+            Some(value) if value.trim().is_empty() => pending_gap = true,
+            Some(value) => {
+                expanded.push_str(&value);
+                pending_gap = false;
+            }
+            // 未知占位符原样保留（与前端一致）
+            None => push_literal(&mut expanded, &mut pending_gap, whole.as_str()),
+        }
+    }
+    push_literal(&mut expanded, &mut pending_gap, &pattern[last..]);
+    if pending_gap {
+        let trimmed = expanded.trim_end_matches(FILENAME_SEPARATORS).len();
+        expanded.truncate(trimmed);
+    }
+
+    let mut filename = String::with_capacity(expanded.len());
+    for c in expanded.chars() {
+        let c = if ILLEGAL_FILENAME_CHARS.contains(&c) || c.is_whitespace() { '_' } else { c };
+        if !(c == '_' && filename.ends_with('_')) {
+            filename.push(c);
+        }
+    }
+
+    if !filename.ends_with(".xlsx") {
+        filename.push_str(".xlsx");
+    }
+    filename
+}
+
+/// 生成订单的导出文件名，与前端一致：优先使用设置中的 excel_filename_format，
+/// 其次为 pattern（模板的文件名格式），都为空时使用默认格式
+pub fn build_output_filename(pattern: &str, order: &Order, settings: &AppSettings) -> String {
+    let pattern = [settings.excel_filename_format.as_str(), pattern]
+        .into_iter()
+        .find(|p| !p.trim().is_empty())
+        .unwrap_or(DEFAULT_FILENAME_PATTERN);
+    expand_filename_pattern(pattern, order)
+}
@@ -0,0 +1,130 @@
+use crate::models::LogEntry;
+use chrono::Utc;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+// 内存中保留的最近日志条数
+const MAX_BUFFERED_ENTRIES: usize = 1000;
+// 日志文件超过该大小时在启动时滚动
+const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+struct AppLogger {
+    buffer: Mutex<VecDeque<LogEntry>>,
+    file: Mutex<Option<File>>,
+}
+
+static LOGGER: OnceLock<AppLogger> = OnceLock::new();
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let entry = LogEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        let line = format!(
+            "{} [{}] {}: {}",
+            entry.timestamp, entry.level, entry.target, entry.message
+        );
+
+        // 仅调试构建同时输出到终端，发布版只写日志文件
+        #[cfg(debug_assertions)]
+        println!("{}", line);
+
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+
+        if let Ok(mut buffer) = self.buffer.lock() {
+            if buffer.len() >= MAX_BUFFERED_ENTRIES {
+                buffer.pop_front();
+            }
+            buffer.push_back(entry);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// 初始化日志系统（内存环形缓冲 + 日志文件），重复调用无副作用
+pub fn init(log_dir: Option<&Path>) {
+    let logger = LOGGER.get_or_init(|| AppLogger {
+        buffer: Mutex::new(VecDeque::with_capacity(MAX_BUFFERED_ENTRIES)),
+        file: Mutex::new(None),
+    });
+
+    if let Some(dir) = log_dir {
+        if let Some(file) = open_log_file(dir) {
+            if let Ok(mut slot) = logger.file.lock() {
+                *slot = Some(file);
+            }
+        }
+    }
+
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+fn open_log_file(dir: &Path) -> Option<File> {
+    fs::create_dir_all(dir).ok()?;
+    let path = dir.join("quicksales.log");
+
+    // 超过上限时滚动为 quicksales.log.1（只保留一份历史）
+    if let Ok(meta) = fs::metadata(&path) {
+        if meta.len() > MAX_LOG_FILE_BYTES {
+            let _ = fs::rename(&path, dir.join("quicksales.log.1"));
+        }
+    }
+
+    OpenOptions::new().create(true).append(true).open(path).ok()
+}
+
+/// 解析日志级别设置，无法识别时回退到 info
+pub fn parse_level(level: &str) -> LevelFilter {
+    match level.trim().to_lowercase().as_str() {
+        "off" => LevelFilter::Off,
+        "error" => LevelFilter::Error,
+        "warn" | "warning" => LevelFilter::Warn,
+        "debug" => LevelFilter::Debug,
+        "trace" => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    }
+}
+
+/// 按设置调整日志级别
+pub fn set_level(level: &str) {
+    log::set_max_level(parse_level(level));
+}
+
+/// 获取最近的日志（按时间顺序，最多 limit 条）
+pub fn recent_logs(limit: usize) -> Vec<LogEntry> {
+    let Some(logger) = LOGGER.get() else {
+        return vec![];
+    };
+
+    let buffer = logger.buffer.lock().unwrap();
+    let skip = buffer.len().saturating_sub(limit);
+    buffer.iter().skip(skip).cloned().collect()
+}
@@ -1,6 +1,9 @@
-// Utility function for generating unique IDs
-// Currently unused but kept for future use
-#[allow(dead_code)]
-pub fn generate_id() -> String {
-    uuid::Uuid::new_v4().to_string()
-}
+pub mod clock;
+pub mod csv;
+pub mod filename;
+pub mod logger;
+pub mod money;
+pub mod order_status;
+pub mod validation;
+pub mod xlsx;
+pub mod xlsx_template;
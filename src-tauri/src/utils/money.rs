@@ -0,0 +1,47 @@
+// 金额统一以“分”为单位的整数存储，避免 REAL 列长期累积的浮点误差。
+// REAL 列仍然保留写入以兼容旧版本，读取、报表与导出均优先使用 *_cents 列。
+
+/// 金额（元）转换为整数分，四舍五入
+pub fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+/// 整数分转换为金额（元）
+pub fn from_cents(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+/// 订单项行金额（分）：实际单价（有折扣价时取折扣价）× 数量。保存订单时写入 order_items.line_total_cents，
+/// 订单明细合计即各行金额之和
+pub fn line_total_cents(price: f64, discount_price: Option<f64>, quantity: f64) -> i64 {
+    to_cents(discount_price.unwrap_or(price) * quantity)
+}
+
+/// 订单总额计算流程（单位：分）：明细合计 → 减整单优惠 → 价格不含税时加税 → 抹零，
+/// 返回 (总额, 抹零调整额)。保存订单与总额核对共用此流程，保证两者结果一致
+pub fn order_total_cents(
+    lines_cents: i64,
+    discount_cents: i64,
+    tax_rate: f64,
+    prices_include_tax: bool,
+    rounding: &str,
+) -> (i64, i64) {
+    let after_discount = (lines_cents - discount_cents).max(0);
+    let tax_cents = if !prices_include_tax && tax_rate > 0.0 {
+        (after_discount as f64 * tax_rate / 100.0).round() as i64
+    } else {
+        0
+    };
+    let before_rounding = after_discount + tax_cents;
+    let total = round_total_cents(before_rounding, rounding);
+    (total, total - before_rounding)
+}
+
+/// 按抹零方式将总额（分）调整到整元："round" 四舍五入，"floor" 舍去角分，其余不处理
+pub fn round_total_cents(cents: i64, mode: &str) -> i64 {
+    match mode {
+        "round" => (cents as f64 / 100.0).round() as i64 * 100,
+        "floor" => cents.div_euclid(100) * 100,
+        _ => cents,
+    }
+}
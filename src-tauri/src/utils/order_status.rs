@@ -0,0 +1,68 @@
+// 订单状态流转：草稿 → 已确认 → 已完成，未完成前可取消，已完成的订单也可取消（退货）。
+// 已确认与已完成的订单占用库存：进入这两种状态时扣减库存，离开时退回库存。
+
+pub const DRAFT: &str = "draft";
+pub const CONFIRMED: &str = "confirmed";
+pub const COMPLETED: &str = "completed";
+pub const CANCELLED: &str = "cancelled";
+
+/// 全部合法的订单状态
+pub const ORDER_STATUSES: [&str; 4] = [DRAFT, CONFIRMED, COMPLETED, CANCELLED];
+
+/// 是否为合法的订单状态
+pub fn is_valid_status(status: &str) -> bool {
+    ORDER_STATUSES.contains(&status)
+}
+
+/// 校验订单状态，未知状态返回列出可选状态的错误
+pub fn validate_status(status: &str) -> Result<(), String> {
+    if is_valid_status(status) {
+        Ok(())
+    } else {
+        Err(format!("未知的订单状态: {}（可选：{}）", status, ORDER_STATUSES.join("、")))
+    }
+}
+
+/// 订单状态的中文名称（用于错误提示），未知状态原样返回
+pub fn status_label(status: &str) -> &str {
+    match status {
+        DRAFT => "草稿",
+        CONFIRMED => "已确认",
+        COMPLETED => "已完成",
+        CANCELLED => "已取消",
+        other => other,
+    }
+}
+
+/// 是否允许从 from 变更为 to（状态不变视为允许）：
+/// - 草稿 → 已确认、已完成、已取消
+/// - 已确认 → 草稿、已完成、已取消
+/// - 已完成 → 已取消
+/// - 已取消为终态
+pub fn can_transition(from: &str, to: &str) -> bool {
+    if from == to {
+        return is_valid_status(from);
+    }
+    matches!(
+        (from, to),
+        (DRAFT, CONFIRMED | COMPLETED | CANCELLED) | (CONFIRMED, DRAFT | COMPLETED | CANCELLED) | (COMPLETED, CANCELLED)
+    )
+}
+
+/// 校验状态变更，不允许时返回说明原因的错误
+pub fn validate_transition(from: &str, to: &str) -> Result<(), String> {
+    validate_status(to)?;
+    if !can_transition(from, to) {
+        return Err(format!(
+            "订单状态不能从「{}」变更为「{}」",
+            status_label(from),
+            status_label(to)
+        ));
+    }
+    Ok(())
+}
+
+/// 该状态的订单是否占用库存（已确认、已完成）
+pub fn holds_stock(status: &str) -> bool {
+    status == CONFIRMED || status == COMPLETED
+}
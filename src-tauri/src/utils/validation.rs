@@ -0,0 +1,149 @@
+// 导出前按模板的必填设置检查订单
+
+use crate::models::{Order, OrderItem, RequiredFields};
+use crate::utils::money::line_total_cents;
+
+/// 导出命令因必填项缺失而失败时，错误信息以此开头（界面据此区分并高亮缺失项）
+pub const MISSING_FIELDS_ERROR: &str = "缺少必填项";
+
+/// 商品行字段检查：(是否必填, 字段名, 该行是否缺失)
+type ItemCheck = (bool, &'static str, fn(&OrderItem) -> bool);
+
+fn is_blank(value: &str) -> bool {
+    value.trim().is_empty()
+}
+
+/// 按模板的必填设置检查订单，返回缺失项说明列表；商品行的问题按字段汇总行号（行号从 1 开始）
+pub fn validate_order_for_template(order: &Order, fields: &RequiredFields) -> Result<(), Vec<String>> {
+    let mut missing = Vec::new();
+
+    if fields.require_customer_name && is_blank(&order.customer.name) {
+        missing.push("客户姓名".to_string());
+    }
+    if fields.require_customer_phone && is_blank(&order.customer.phone) {
+        missing.push("联系电话".to_string());
+    }
+    if fields.require_customer_plate && is_blank(&order.customer.license_plate) {
+        missing.push("车牌号".to_string());
+    }
+    if fields.require_date && is_blank(&order.date) {
+        missing.push("日期".to_string());
+    }
+    if fields.require_order_number && is_blank(&order.order_number) {
+        missing.push("订单号".to_string());
+    }
+    if fields.require_order_remark && order.remark.as_deref().is_none_or(is_blank) {
+        missing.push("订单备注".to_string());
+    }
+    if fields.require_total_amount && order.total_amount <= 0.0 {
+        missing.push("订单总额".to_string());
+    }
+
+    let min_items = fields.min_items as usize;
+    if order.items.len() < min_items {
+        missing.push(format!("至少需要 {} 行商品，当前只有 {} 行", min_items, order.items.len()));
+    }
+
+    let item_checks: [ItemCheck; 6] = [
+        (fields.require_item_name, "名称", |item| is_blank(&item.name)),
+        (fields.require_item_unit, "单位", |item| is_blank(&item.unit)),
+        (fields.require_item_quantity, "数量", |item| item.quantity <= 0.0),
+        (fields.require_item_price, "单价", |item| item.price <= 0.0),
+        (fields.require_item_total, "金额", |item| {
+            line_total_cents(item.price, item.discount_price, item.quantity) <= 0
+        }),
+        (fields.require_item_remark, "备注", |item| item.remark.as_deref().is_none_or(is_blank)),
+    ];
+    for (required, label, is_missing) in item_checks {
+        if !required {
+            continue;
+        }
+        let rows: Vec<String> = order
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| is_missing(item))
+            .map(|(index, _)| (index + 1).to_string())
+            .collect();
+        if !rows.is_empty() {
+            missing.push(format!("第 {} 行商品的{}", rows.join("、"), label));
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::new_order;
+
+    /// 各项内容都已填写、只要求一行商品时能通过的订单
+    fn complete_order() -> Order {
+        let mut order = new_order("o1", "c1", "draft", &[("p1", 10.0, 2.0), ("p2", 5.0, 1.0)]);
+        order.customer.phone = "13800000000".to_string();
+        order.customer.license_plate = "A12345".to_string();
+        order.date = "2024-03-10".to_string();
+        order.order_number = "NO.000001".to_string();
+        order.remark = Some("加急".to_string());
+        order.total_amount = 25.0;
+        for item in &mut order.items {
+            item.remark = Some("已检查".to_string());
+        }
+        order
+    }
+
+    #[test]
+    fn each_rule_reports_only_its_own_missing_field() {
+        type Rule = (fn(&mut RequiredFields), fn(&mut Order), &'static str);
+        let rules: [Rule; 14] = [
+            (|f| f.require_customer_name = true, |o| o.customer.name = " ".to_string(), "客户姓名"),
+            (|f| f.require_customer_phone = true, |o| o.customer.phone.clear(), "联系电话"),
+            (|f| f.require_customer_plate = true, |o| o.customer.license_plate.clear(), "车牌号"),
+            (|f| f.require_date = true, |o| o.date.clear(), "日期"),
+            (|f| f.require_order_number = true, |o| o.order_number.clear(), "订单号"),
+            (|f| f.require_order_remark = true, |o| o.remark = None, "订单备注"),
+            (|f| f.require_total_amount = true, |o| o.total_amount = 0.0, "订单总额"),
+            (|f| f.min_items = 2, |o| o.items.truncate(1), "至少需要 2 行商品，当前只有 1 行"),
+            (|f| f.require_item_name = true, |o| o.items[1].name = "".to_string(), "第 2 行商品的名称"),
+            (|f| f.require_item_unit = true, |o| o.items[0].unit = " ".to_string(), "第 1 行商品的单位"),
+            (|f| f.require_item_quantity = true, |o| o.items[1].quantity = 0.0, "第 2 行商品的数量"),
+            (|f| f.require_item_price = true, |o| o.items[0].price = 0.0, "第 1 行商品的单价"),
+            (|f| f.require_item_total = true, |o| o.items[1].discount_price = Some(0.0), "第 2 行商品的金额"),
+            (|f| f.require_item_remark = true, |o| o.items[0].remark = None, "第 1 行商品的备注"),
+        ];
+
+        for (require, clear, expected) in rules {
+            let mut fields = RequiredFields::default();
+            require(&mut fields);
+            assert!(validate_order_for_template(&complete_order(), &fields).is_ok(), "{}", expected);
+
+            let mut order = complete_order();
+            clear(&mut order);
+            assert_eq!(validate_order_for_template(&order, &fields), Err(vec![expected.to_string()]));
+            // 不要求的字段缺失时不报告
+            assert!(validate_order_for_template(&order, &RequiredFields::default()).is_ok(), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn missing_item_fields_are_grouped_by_field_with_row_numbers() {
+        let fields = RequiredFields { require_item_unit: true, require_item_remark: true, ..RequiredFields::default() };
+        let mut order = complete_order();
+        order.items.push(order.items[0].clone());
+        for item in &mut order.items {
+            item.remark = None;
+        }
+        order.items[0].unit.clear();
+        order.items[2].unit.clear();
+
+        assert_eq!(
+            validate_order_for_template(&order, &fields),
+            Err(vec!["第 1、3 行商品的单位".to_string(), "第 1、2、3 行商品的备注".to_string()])
+        );
+    }
+}
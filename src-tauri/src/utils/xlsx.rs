@@ -0,0 +1,194 @@
+// 简单 xlsx 生成：后端直接生成的报表（如盘点表）不依赖模板，只需表头与数据行。
+// 工作簿由 umya-spreadsheet 生成，这里只负责表头、边框、列宽与打印设置
+
+use std::io::{Cursor, Error, ErrorKind};
+use umya_spreadsheet::{
+    writer, Border, OrientationValues, Pane, PaneStateValues, PaneValues, SheetView, SheetViews, Spreadsheet, Style,
+};
+
+/// 单元格内容
+pub enum Cell {
+    Empty,
+    Text(String),
+    Number(f64),
+}
+
+/// 工作表列：表头文字与列宽（字符数）
+pub struct Column {
+    pub title: String,
+    pub width: f64,
+}
+
+impl Column {
+    pub fn new(title: &str, width: f64) -> Self {
+        Self { title: title.to_string(), width }
+    }
+}
+
+/// 工作表：第一行为表头（加粗、打印时每页重复），数据行带边框便于打印后手写
+pub struct Sheet {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+/// A4 纸张（OOXML paperSize 编号）
+const PAPER_A4: u32 = 9;
+
+/// 列序号（从 0 开始）转为列名：0 → A，26 → AA
+pub fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// 宋体 11 号、四周细边框的单元格样式；表头另加粗并填充浅灰底色
+pub fn cell_style(header: bool) -> Style {
+    let mut style = Style::default();
+    style.get_font_mut().set_name("宋体").set_size(11.0).set_bold(header);
+    if header {
+        style.set_background_color("FFE7E6E6");
+    }
+    let borders = style.get_borders_mut();
+    borders.get_left_mut().set_border_style(Border::BORDER_THIN);
+    borders.get_right_mut().set_border_style(Border::BORDER_THIN);
+    borders.get_top_mut().set_border_style(Border::BORDER_THIN);
+    borders.get_bottom_mut().set_border_style(Border::BORDER_THIN);
+    style
+}
+
+/// 冻结首行的工作表视图
+pub fn frozen_header_view(selected: bool) -> SheetViews {
+    let mut pane = Pane::default();
+    pane.set_vertical_split(1.0)
+        .set_active_pane(PaneValues::BottomLeft)
+        .set_state(PaneStateValues::Frozen);
+    pane.get_top_left_cell_mut().set_coordinate("A2");
+
+    let mut view = SheetView::default();
+    view.set_workbook_view_id(0).set_tab_selected(selected).set_pane(pane);
+    let mut views = SheetViews::default();
+    views.add_sheet_view_list_mut(view);
+    views
+}
+
+fn build_workbook(sheets: &[Sheet]) -> std::io::Result<Spreadsheet> {
+    let mut book = umya_spreadsheet::new_file_empty_worksheet();
+    let (header_style, data_style) = (cell_style(true), cell_style(false));
+
+    for (index, sheet) in sheets.iter().enumerate() {
+        let worksheet = book
+            .new_sheet(sheet.name.as_str())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("工作表名称「{}」无效: {}", sheet.name, e)))?;
+
+        for (c, column) in sheet.columns.iter().enumerate() {
+            worksheet.get_column_dimension_mut(&column_name(c)).set_width(column.width);
+            worksheet
+                .get_cell_mut((c as u32 + 1, 1))
+                .set_value_string(column.title.clone())
+                .set_style(header_style.clone());
+        }
+
+        // 数据行补齐到表头列数，空白列也带边框
+        let width = sheet.columns.len();
+        for (r, row) in sheet.rows.iter().enumerate() {
+            let row_number = r as u32 + 2;
+            for c in 0..width.max(row.len()) {
+                let cell = worksheet.get_cell_mut((c as u32 + 1, row_number));
+                match row.get(c) {
+                    Some(Cell::Text(text)) => {
+                        cell.set_value_string(text.clone());
+                    }
+                    Some(Cell::Number(value)) if value.is_finite() => {
+                        cell.set_value_number(*value);
+                    }
+                    _ => {}
+                }
+                cell.set_style(data_style.clone());
+            }
+        }
+
+        worksheet.set_sheets_views(frozen_header_view(index == 0));
+        worksheet
+            .get_page_setup_mut()
+            .set_paper_size(PAPER_A4)
+            .set_orientation(OrientationValues::Portrait);
+        worksheet
+            .get_page_margins_mut()
+            .set_left(0.5)
+            .set_right(0.5)
+            .set_top(0.75)
+            .set_bottom(0.75)
+            .set_header(0.3)
+            .set_footer(0.3);
+
+        // 打印时每页重复表头
+        let titles = format!("'{}'!$1:$1", sheet.name.replace('\'', "''"));
+        if worksheet.add_defined_name("_xlnm.Print_Titles", titles.as_str()).is_ok() {
+            if let Some(name) = worksheet.get_defined_names_mut().last_mut() {
+                name.set_local_sheet_id(index as u32);
+            }
+        }
+    }
+    Ok(book)
+}
+
+/// 生成 xlsx 文件内容
+pub fn write_workbook(sheets: &[Sheet]) -> std::io::Result<Vec<u8>> {
+    let book = build_workbook(sheets)?;
+    let mut output = Cursor::new(Vec::new());
+    writer::xlsx::write_writer(&book, &mut output).map_err(|e| Error::other(e.to_string()))?;
+    Ok(output.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use umya_spreadsheet::reader;
+
+    #[test]
+    fn written_workbook_reads_back_with_header_borders_and_print_titles() {
+        let sheet = Sheet {
+            name: "盘点表".to_string(),
+            columns: vec![Column::new("商品名称", 30.0), Column::new("系统库存", 12.0), Column::new("实盘数量", 12.0)],
+            rows: vec![
+                vec![Cell::Text("机油 & 滤芯".to_string()), Cell::Number(12.5), Cell::Empty],
+                vec![Cell::Text("雨刮".to_string()), Cell::Number(f64::NAN)],
+            ],
+        };
+        let bytes = write_workbook(&[sheet]).unwrap();
+
+        let book = reader::xlsx::read_reader(Cursor::new(bytes), true).unwrap();
+        assert_eq!(book.get_sheet_count(), 1);
+        let sheet = book.get_sheet(&0).unwrap();
+        assert_eq!(sheet.get_name(), "盘点表");
+        assert_eq!(sheet.get_value("A1"), "商品名称");
+        assert!(*sheet.get_style("A1").get_font().unwrap().get_bold());
+        assert_eq!(sheet.get_value("A2"), "机油 & 滤芯");
+        assert_eq!(sheet.get_value("B2"), "12.5");
+        assert_eq!(sheet.get_value("B3"), "");
+        assert_eq!(*sheet.get_column_dimension("A").unwrap().get_width(), 30.0);
+
+        // 数据行补齐到表头列数，空白单元格也有边框
+        let bottom = sheet.get_style("C3").get_borders().unwrap().get_bottom().get_border_style().to_string();
+        assert_eq!(bottom, Border::BORDER_THIN);
+        assert!(!*sheet.get_style("C3").get_font().unwrap().get_bold());
+
+        assert_eq!(*sheet.get_page_setup().get_paper_size(), PAPER_A4);
+        let titles = &sheet.get_defined_names()[0];
+        assert_eq!((titles.get_name(), titles.get_address()), ("_xlnm.Print_Titles", "'盘点表'!$1:$1".to_string()));
+    }
+
+    #[test]
+    fn duplicate_sheet_names_are_rejected() {
+        let sheet = || Sheet { name: "盘点表".to_string(), columns: Vec::new(), rows: Vec::new() };
+        assert!(write_workbook(&[sheet(), sheet()]).is_err());
+    }
+}
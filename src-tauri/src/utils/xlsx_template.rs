@@ -0,0 +1,428 @@
+// 按模板填充 xlsx：用 umya-spreadsheet 读取模板，在第一个工作表中改写指定单元格后重新保存，
+// 其余内容（合并单元格、页面设置、图片等）由库原样保留。写入只替换单元格的值，样式沿用模板单元格，
+// 配置了数字格式时只替换数字格式。商品行超出模板范围时可先插入空行，插入位置以下的内容与引用整体下移
+
+use super::xlsx::Cell;
+use std::io::{Cursor, Error, ErrorKind};
+use umya_spreadsheet::{reader, writer, Spreadsheet, Worksheet};
+
+/// 解析单元格地址：C3 → (2, 3)，列从 0 开始、行从 1 开始；地址无效时返回 None
+pub fn parse_cell_ref(reference: &str) -> Option<(usize, u32)> {
+    let reference = reference.trim().replace('$', "").to_uppercase();
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || letters.len() > 3 || !letters.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let row: u32 = digits.parse().ok().filter(|&r| r > 0)?;
+    let column = letters
+        .bytes()
+        .fold(0usize, |acc, b| acc * 26 + (b - b'A' + 1) as usize);
+    Some((column - 1, row))
+}
+
+/// 写入单元格前在工作表中插入空行：在第 at 行之前插入 count 行，新行沿用第 at 行的格式
+#[derive(Debug, Clone, Copy)]
+pub struct RowInsertion {
+    pub at: u32,
+    pub count: u32,
+}
+
+/// 将公式或区域列表中行号不小于 at 的单元格引用下移 count 行。
+/// 没有工作表前缀的引用视为指向 sheet 本身；带前缀（Sheet2!A10）的只在前缀为 sheet 时下移，sheet 为 None 时不下移。
+/// 引号内的文本与函数名（如 LOG10）保持不变
+pub fn shift_refs(text: &str, sheet: Option<&str>, at: u32, count: u32) -> String {
+    shift_refs_with(text, sheet, true, at, count)
+}
+
+/// 只下移带 sheet 前缀的引用，用于其他工作表中指向 sheet 的公式（没有前缀的引用指向公式所在的工作表）
+fn shift_sheet_refs(text: &str, sheet: &str, at: u32, count: u32) -> String {
+    shift_refs_with(text, Some(sheet), false, at, count)
+}
+
+fn shift_refs_with(text: &str, sheet: Option<&str>, unprefixed: bool, at: u32, count: u32) -> String {
+    let bytes = text.as_bytes();
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    let mut in_string = false;
+    // 区域（A1:B10）的后半部分沿用前半部分的判断
+    let mut range_shift: Option<bool> = None;
+    while pos < bytes.len() {
+        let rest = &text[pos..];
+        if rest.starts_with('"') || rest.starts_with("&quot;") {
+            let quote = if rest.starts_with('"') { 1 } else { "&quot;".len() };
+            in_string = !in_string;
+            result.push_str(&rest[..quote]);
+            pos += quote;
+            continue;
+        }
+        let boundary = pos == 0 || !matches!(bytes[pos - 1], b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'.' | b'$');
+        if !in_string && boundary {
+            if let Some((len, row)) = cell_token(rest) {
+                let token = &rest[..len];
+                let shift = range_shift.take().unwrap_or_else(|| {
+                    if pos > 0 && bytes[pos - 1] == b'!' {
+                        sheet.is_some_and(|sheet| sheet_before(&text[..pos - 1]) == sheet)
+                    } else {
+                        unprefixed
+                    }
+                });
+                if shift && row >= at {
+                    // 保留 $ 绝对引用标记
+                    let row_start = token.rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+                    result.push_str(&token[..row_start]);
+                    result.push_str(&(row + count).to_string());
+                } else {
+                    result.push_str(token);
+                }
+                if rest[len..].starts_with(':') {
+                    range_shift = Some(shift);
+                }
+                pos += len;
+                continue;
+            }
+        }
+        if !rest.starts_with(':') {
+            range_shift = None;
+        }
+        let ch = rest.chars().next().unwrap_or_default();
+        result.push(ch);
+        pos += ch.len_utf8();
+    }
+    result
+}
+
+/// 取 ! 之前的工作表名（去掉引号）
+fn sheet_before(text: &str) -> &str {
+    if let Some(quoted) = text.strip_suffix('\'') {
+        return quoted.rfind('\'').map_or(quoted, |i| &quoted[i + 1..]);
+    }
+    let start = text
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == '.'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    &text[start..]
+}
+
+/// 识别文本开头的单元格引用（如 A10、$B$3），返回（长度, 行号）
+fn cell_token(text: &str) -> Option<(usize, u32)> {
+    let bytes = text.as_bytes();
+    let mut len = 0;
+    if bytes.first() == Some(&b'$') {
+        len += 1;
+    }
+    let letters_start = len;
+    while len < bytes.len() && bytes[len].is_ascii_uppercase() {
+        len += 1;
+    }
+    if len == letters_start || len - letters_start > 3 {
+        return None;
+    }
+    if bytes.get(len) == Some(&b'$') {
+        len += 1;
+    }
+    let digits_start = len;
+    while len < bytes.len() && bytes[len].is_ascii_digit() {
+        len += 1;
+    }
+    if len == digits_start {
+        return None;
+    }
+    // 后面紧跟字母、数字或括号的不是单元格引用（如函数名 LOG10( 、名称 ABC1_X）
+    if matches!(bytes.get(len), Some(b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'(')) {
+        return None;
+    }
+    let (_, row) = parse_cell_ref(&text[..len])?;
+    Some((len, row))
+}
+
+
+/// 要写入模板的单元格：地址、值，以及数字格式（为空时保留模板单元格原有的数字格式）
+pub struct TemplateCell {
+    pub address: String,
+    pub value: Cell,
+    pub number_format: Option<String>,
+}
+
+fn read_workbook(bytes: &[u8]) -> std::io::Result<Spreadsheet> {
+    reader::xlsx::read_reader(Cursor::new(bytes), true)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("不是有效的 xlsx 文件: {}", e)))
+}
+
+fn write_workbook(book: &Spreadsheet) -> std::io::Result<Vec<u8>> {
+    let mut output = Cursor::new(Vec::new());
+    writer::xlsx::write_writer(book, &mut output).map_err(|e| Error::other(e.to_string()))?;
+    Ok(output.into_inner())
+}
+
+/// 在第一个工作表的第 at 行之前插入 count 个空行：新行复制第 at 行的行高、行样式、单元格样式与单行合并单元格（不含值与公式）。
+/// 原第 at 行及以下的单元格、公式引用、合并单元格、条件格式、图片与指向本表的定义名称由库一并下移；
+/// 数据验证的区域与其他工作表中指向本表的公式库不处理，这里单独下移
+fn insert_rows(book: &mut Spreadsheet, insertion: RowInsertion) {
+    let RowInsertion { at, count } = insertion;
+    let Some(sheet) = book.get_sheet_mut(&0) else {
+        return;
+    };
+    let sheet_name = sheet.get_name().to_string();
+    let row_merges: Vec<(usize, usize)> = sheet
+        .get_merge_cells()
+        .iter()
+        .filter_map(|range| {
+            let range = range.get_range();
+            let (first, last) = range.split_once(':')?;
+            let ((first_column, first_row), (last_column, last_row)) = (parse_cell_ref(first)?, parse_cell_ref(last)?);
+            (first_row == at && last_row == at).then_some((first_column, last_column))
+        })
+        .collect();
+
+    sheet.insert_new_row(&at, &count);
+
+    let source = at + count;
+    let height = sheet
+        .get_row_dimension(&source)
+        .filter(|row| *row.get_custom_height())
+        .map(|row| *row.get_height());
+    for number in at..source {
+        sheet.copy_row_styling(&source, &number, None, None);
+        if let Some(height) = height {
+            sheet.get_row_dimension_mut(&number).set_height(height).set_custom_height(true);
+        }
+        for (first, last) in &row_merges {
+            sheet.add_merge_cells(format!(
+                "{}{}:{}{}",
+                super::xlsx::column_name(*first),
+                number,
+                super::xlsx::column_name(*last),
+                number
+            ));
+        }
+    }
+
+    if let Some(validations) = sheet.get_data_validations_mut() {
+        for validation in validations.get_data_validation_list_mut().iter_mut() {
+            let references = validation.get_sequence_of_references_mut();
+            let shifted = shift_refs(&references.get_sqref(), Some(&sheet_name), at, count);
+            references.remove_range_collection().set_sqref(shifted);
+        }
+    }
+
+    for other in book.get_sheet_collection_mut().iter_mut().skip(1) {
+        for cell in other.get_cell_collection_mut() {
+            let formula = cell.get_formula();
+            if formula.is_empty() {
+                continue;
+            }
+            let shifted = shift_sheet_refs(formula, &sheet_name, at, count);
+            if shifted != formula {
+                cell.set_formula(shifted);
+            }
+        }
+    }
+}
+
+/// 在工作表中写入单元格（地址无效的跳过）。已有单元格沿用原样式，新单元格沿用所在行的默认样式（如有）
+fn write_cells(sheet: &mut Worksheet, cells: &[TemplateCell]) {
+    for cell in cells {
+        let Some((column, row)) = parse_cell_ref(&cell.address) else {
+            continue;
+        };
+        let coordinate = (column as u32 + 1, row);
+        let row_style = sheet
+            .get_cell(coordinate)
+            .is_none()
+            .then(|| sheet.get_row_dimension(&row).map(|r| r.get_style().clone()))
+            .flatten();
+
+        let target = sheet.get_cell_mut(coordinate);
+        if let Some(style) = row_style {
+            target.set_style(style);
+        }
+        match &cell.value {
+            Cell::Text(text) => target.set_value_string(text.clone()),
+            Cell::Number(number) => target.set_value_number(*number),
+            Cell::Empty => target.set_blank(),
+        };
+        if let Some(format) = cell.number_format.as_deref().map(str::trim).filter(|f| !f.is_empty()) {
+            target.get_style_mut().get_number_format_mut().set_format_code(format);
+        }
+    }
+}
+
+/// 清空所有公式的缓存结果，使打开文件时重新计算，显示写入后的结果
+fn clear_formula_results(book: &mut Spreadsheet) {
+    for sheet in book.get_sheet_collection_mut().iter_mut() {
+        for cell in sheet.get_cell_collection_mut() {
+            let value = cell.get_cell_value_mut();
+            if let Some(formula) = value.get_formula_obj().cloned() {
+                value.set_blank().set_formula_obj(formula);
+            }
+        }
+    }
+}
+
+/// 将单元格写入模板的第一个工作表，返回新的 xlsx 内容；insertion 不为空时先插入空行（单元格地址按插入后的位置给出）
+pub fn fill_template(
+    template: &[u8],
+    cells: &[TemplateCell],
+    insertion: Option<RowInsertion>,
+) -> std::io::Result<Vec<u8>> {
+    let mut book = read_workbook(template)?;
+    if book.get_sheet(&0).is_none() {
+        return Err(Error::new(ErrorKind::InvalidData, "模板中没有工作表"));
+    }
+    if let Some(insertion) = insertion.filter(|i| i.at > 0 && i.count > 0) {
+        insert_rows(&mut book, insertion);
+    }
+    if let Some(sheet) = book.get_sheet_mut(&0) {
+        write_cells(sheet, cells);
+    }
+    clear_formula_results(&mut book);
+    write_workbook(&book)
+}
+
+/// 模板文件概况：全部工作表名称，以及第一个工作表（导出时写入的工作表）中已使用的最大行号与列数
+#[derive(Debug, Clone)]
+pub struct WorkbookOutline {
+    pub sheet_names: Vec<String>,
+    pub max_row: u32,
+    pub max_col: usize,
+}
+
+/// 打开 xlsx 并读取工作表名称与第一个工作表的使用范围（按实际出现的单元格计算，不依赖 dimension 元素）
+pub fn inspect_workbook(bytes: &[u8]) -> std::io::Result<WorkbookOutline> {
+    let book = read_workbook(bytes)?;
+    let sheet_names = book
+        .get_sheet_collection()
+        .iter()
+        .map(|sheet| sheet.get_name().to_string())
+        .collect();
+    let sheet = book
+        .get_sheet(&0)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "模板中没有工作表"))?;
+    Ok(WorkbookOutline {
+        sheet_names,
+        max_row: sheet.get_highest_row(),
+        max_col: sheet.get_highest_column() as usize,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use umya_spreadsheet::drawing::spreadsheet::MarkerType;
+    use umya_spreadsheet::{
+        ConditionalFormatValues, ConditionalFormatting, ConditionalFormattingRule, DataValidation, DataValidationValues,
+        DataValidations, Formula, Image,
+    };
+
+    // 1×1 像素的 PNG
+    const PIXEL_PNG: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, 0x49, 0x48, 0x44, 0x52, 0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0,
+        0, 0, 0x1F, 0x15, 0xC4, 0x89, 0, 0, 0, 0x0A, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0, 1, 0, 0, 5, 0, 1, 0x0D, 0x0A,
+        0x2D, 0xB4, 0, 0, 0, 0, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    /// 商品行为第 5~7 行、第 8 行合计的报价单模板，另有一个引用报价单合计的汇总表
+    fn quote_template() -> Vec<u8> {
+        let mut book = umya_spreadsheet::new_file();
+        let sheet = book.get_sheet_mut(&0).unwrap();
+        sheet.set_name("报价单");
+        for row in 5..=7u32 {
+            sheet.get_style_mut((5, row)).get_number_format_mut().set_format_code("0.00");
+        }
+        sheet.get_style_mut("B7").get_font_mut().set_bold(true);
+        sheet.get_cell_mut("E8").set_formula("SUM(E5:E7)");
+        sheet.add_merge_cells("B7:D7");
+        sheet.add_merge_cells("A9:F9");
+
+        let mut validation = DataValidation::default();
+        validation.set_type(DataValidationValues::Decimal);
+        validation.get_sequence_of_references_mut().set_sqref("E5:E7");
+        let mut validations = DataValidations::default();
+        validations.add_data_validation_list(validation);
+        sheet.set_data_validations(validations);
+
+        let mut formatting = ConditionalFormatting::default();
+        formatting.get_sequence_of_references_mut().set_sqref("E5:E7");
+        let mut rule = ConditionalFormattingRule::default();
+        rule.set_type(ConditionalFormatValues::Expression);
+        let mut formula = Formula::default();
+        formula.set_string_value("E5>100");
+        rule.set_formula(formula);
+        formatting.add_conditional_collection(rule);
+        sheet.add_conditional_formatting_collection(formatting);
+
+        sheet.add_defined_name("_xlnm.Print_Area", "'报价单'!$A$1:$F$10").unwrap();
+        let mut marker = MarkerType::default();
+        marker.set_coordinate("B10");
+        let mut image = Image::default();
+        image.new_image_with_dimensions(1, 1, "stamp.png", PIXEL_PNG, marker);
+        sheet.add_image(image);
+
+        let summary = book.new_sheet("汇总").unwrap();
+        summary.get_cell_mut("A1").set_formula("'报价单'!E8+B9");
+        summary.get_cell_mut("B9").set_value_number(1);
+
+        let mut output = Cursor::new(Vec::new());
+        writer::xlsx::write_writer(&book, &mut output).unwrap();
+        output.into_inner()
+    }
+
+    fn fill(cells: Vec<TemplateCell>, insertion: Option<RowInsertion>) -> Spreadsheet {
+        let output = fill_template(&quote_template(), &cells, insertion).unwrap();
+        read_workbook(&output).unwrap()
+    }
+
+    #[test]
+    fn shift_sheet_refs_only_moves_references_to_the_named_sheet() {
+        assert_eq!(shift_sheet_refs("'报价单'!E8+B9+Other!E8", "报价单", 7, 2), "'报价单'!E10+B9+Other!E8");
+        assert_eq!(shift_refs("'报价单'!E8+B9+Other!E8", Some("报价单"), 7, 2), "'报价单'!E10+B11+Other!E8");
+    }
+
+    #[test]
+    fn inserted_rows_shift_everything_below_and_copy_the_last_item_row() {
+        let cells = vec![TemplateCell { address: "B9".to_string(), value: Cell::Text("新增行".to_string()), number_format: None }];
+        let book = fill(cells, Some(RowInsertion { at: 7, count: 2 }));
+        let sheet = book.get_sheet(&0).unwrap();
+
+        // 公式、合并单元格、条件格式、数据验证、打印区域与图片下移
+        assert_eq!(sheet.get_cell("E10").unwrap().get_formula(), "SUM(E5:E9)");
+        let mut merges: Vec<String> = sheet.get_merge_cells().iter().map(|m| m.get_range()).collect();
+        merges.sort();
+        assert_eq!(merges, vec!["A11:F11", "B7:D7", "B8:D8", "B9:D9"]);
+        assert_eq!(sheet.get_conditional_formatting_collection()[0].get_sequence_of_references().get_sqref(), "E5:E9");
+        let validation = &sheet.get_data_validations().unwrap().get_data_validation_list()[0];
+        assert_eq!(validation.get_sequence_of_references().get_sqref(), "E5:E9");
+        assert_eq!(sheet.get_defined_names()[0].get_address(), "'报价单'!$A$1:$F$12");
+        assert_eq!(sheet.get_image_collection()[0].get_coordinate(), "B12");
+
+        // 新行沿用原最后一个商品行的格式，写入的值按插入后的位置
+        assert!(*sheet.get_style("B8").get_font().unwrap().get_bold());
+        assert_eq!(sheet.get_style("E8").get_number_format().unwrap().get_format_code(), "0.00");
+        assert_eq!(sheet.get_value("B9"), "新增行");
+
+        // 其他工作表中指向本表的引用下移，指向自身的引用不变
+        let summary = book.get_sheet(&1).unwrap();
+        assert_eq!(summary.get_cell("A1").unwrap().get_formula(), "'报价单'!E10+B9");
+        assert_eq!(summary.get_value("B9"), "1");
+    }
+
+    #[test]
+    fn without_insertion_the_template_layout_is_unchanged() {
+        let book = fill(Vec::new(), None);
+        let sheet = book.get_sheet(&0).unwrap();
+        assert_eq!(sheet.get_cell("E8").unwrap().get_formula(), "SUM(E5:E7)");
+        assert_eq!(sheet.get_defined_names()[0].get_address(), "'报价单'!$A$1:$F$10");
+        assert_eq!(sheet.get_image_collection()[0].get_coordinate(), "B10");
+        assert_eq!(book.get_sheet(&1).unwrap().get_cell("A1").unwrap().get_formula(), "'报价单'!E8+B9");
+    }
+
+    #[test]
+    fn inspect_workbook_lists_sheets_and_the_used_range() {
+        let outline = inspect_workbook(&quote_template()).unwrap();
+        assert_eq!(outline.sheet_names, vec!["报价单", "汇总"]);
+        assert_eq!((outline.max_row, outline.max_col), (8, 5));
+        assert!(inspect_workbook(b"not a zip").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+import React from 'react'
+import { Modal, Button } from './ui'
+import type { CsvImportReport } from '../types'
+
+interface ImportPreviewModalProps {
+  report: CsvImportReport | null
+  importing: boolean
+  onClose: () => void
+  onConfirm: () => void
+}
+
+const ACTION_LABELS: Record<string, { text: string; className: string }> = {
+  insert: { text: '新增', className: 'text-success' },
+  update: { text: '更新', className: 'text-primary' },
+  skip: { text: '跳过', className: 'text-muted-foreground' },
+}
+
+// CSV 导入预览：逐行展示新增/更新/跳过及原因，确认后再实际导入
+export const ImportPreviewModal: React.FC<ImportPreviewModalProps> = ({
+  report,
+  importing,
+  onClose,
+  onConfirm,
+}) => {
+  if (!report) return null
+
+  const writable = report.inserted + report.updated
+
+  return (
+    <Modal isOpen={!!report} onClose={onClose} title="导入预览" size="xl">
+      <div className="space-y-4">
+        <div className="flex gap-4 text-sm">
+          <span>新增 <strong className="text-success">{report.inserted}</strong></span>
+          <span>更新 <strong className="text-primary">{report.updated}</strong></span>
+          <span>跳过 <strong className="text-muted-foreground">{report.skipped}</strong></span>
+        </div>
+
+        <div className="max-h-[50vh] overflow-auto border border-border rounded">
+          <table className="w-full text-sm">
+            <thead className="bg-muted sticky top-0">
+              <tr>
+                <th className="px-3 py-2 text-left w-16">行号</th>
+                <th className="px-3 py-2 text-left">名称</th>
+                <th className="px-3 py-2 text-left w-16">操作</th>
+                <th className="px-3 py-2 text-left">说明</th>
+              </tr>
+            </thead>
+            <tbody>
+              {report.rows.map((row) => {
+                const label = ACTION_LABELS[row.action] ?? ACTION_LABELS.skip
+                return (
+                  <tr key={row.line} className="border-t border-border">
+                    <td className="px-3 py-1.5 text-muted-foreground">{row.line}</td>
+                    <td className="px-3 py-1.5">{row.name || '-'}</td>
+                    <td className={`px-3 py-1.5 font-medium ${label.className}`}>{label.text}</td>
+                    <td className="px-3 py-1.5 text-muted-foreground">{row.reason || ''}</td>
+                  </tr>
+                )
+              })}
+            </tbody>
+          </table>
+        </div>
+
+        <div className="flex justify-end gap-2">
+          <Button variant="outline" onClick={onClose} disabled={importing}>
+            取消
+          </Button>
+          <Button onClick={onConfirm} disabled={importing || writable === 0}>
+            {importing ? '导入中...' : `确认导入 (${writable})`}
+          </Button>
+        </div>
+      </div>
+    </Modal>
+  )
+}
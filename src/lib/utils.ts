@@ -9,6 +9,11 @@ export function formatCurrency(amount: number): string {
   return `¥${amount.toFixed(2)}`
 }
 
+// 订单金额折算为本币（外币订单按下单时记录的汇率折算）
+export function toBaseAmount(order: { totalAmount: number; exchangeRate?: number }): number {
+  return Number(order.totalAmount || 0) * Number(order.exchangeRate || 1)
+}
+
 export function formatDate(date: string | Date, format: string = "YYYY-MM-DD"): string {
   const d = typeof date === 'string' ? new Date(date) : date
 
@@ -1,10 +1,12 @@
-import React, { useState, useRef, useCallback, useMemo, useEffect } from 'react'
+import React, { useState, useCallback, useMemo, useEffect } from 'react'
 import { Card, Button, Input, Label, Modal } from '../components/ui'
+import { ImportPreviewModal } from '../components/ImportPreviewModal'
+import { customerService } from '../services/api'
 import { Search, Plus, Edit, Trash2, User, Phone, Car, Users, Download, Upload, CheckSquare, Square } from 'lucide-react'
 import { useStore } from '../stores/useStore'
-import type { Customer } from '../types'
+import type { Customer, CsvImportReport } from '../types'
 import { invoke } from '@tauri-apps/api/core'
-import { save } from '@tauri-apps/plugin-dialog'
+import { open, save } from '@tauri-apps/plugin-dialog'
 import { writeTextFile } from '@tauri-apps/plugin-fs'
 
 export const CustomerManagement: React.FC = () => {
@@ -16,7 +18,11 @@ export const CustomerManagement: React.FC = () => {
   const [mergeModalOpen, setMergeModalOpen] = useState(false)
   const [mergeSourceId, setMergeSourceId] = useState('')
   const [mergeTargetId, setMergeTargetId] = useState('')
-  const fileInputRef = useRef<HTMLInputElement>(null)
+
+  // CSV 导入预览
+  const [importPreview, setImportPreview] = useState<CsvImportReport | null>(null)
+  const [importPath, setImportPath] = useState<string | null>(null)
+  const [importing, setImporting] = useState(false)
 
   // 批量选择
   const [selectedCustomers, setSelectedCustomers] = useState<Set<string>>(new Set())
@@ -248,85 +254,39 @@ export const CustomerManagement: React.FC = () => {
     }
   }
 
-  // 导入CSV
-  const handleImportCSV = (event: React.ChangeEvent<HTMLInputElement>) => {
-    const file = event.target.files?.[0]
-    if (!file) return
-
-    const reader = new FileReader()
-    reader.onload = async (e) => {
-      try {
-        const text = e.target?.result as string
-        const lines = text.split('\n').filter(line => line.trim())
-        const dataLines = lines.slice(1) // 跳过表头
-
-        const newCustomers: Customer[] = []
-        const errors: string[] = []
-
-        for (let i = 0; i < dataLines.length; i++) {
-          const line = dataLines[i]
-          const values = line.match(/(".*?"|[^",\s]+)(?=\s*,|\s*$)/g)?.map(v =>
-            v.replace(/^"|"$/g, '').trim()
-          ) || []
-
-          if (values.length < 1) {
-            errors.push(`第 ${i + 2} 行: 格式错误`)
-            continue
-          }
-
-          const [name, phone = '', licensePlate = '', address = '', remark = ''] = values
-
-          if (!name) {
-            errors.push(`第 ${i + 2} 行: 姓名为空`)
-            continue
-          }
-
-          newCustomers.push({
-            id: Date.now().toString() + '_' + i,
-            name,
-            phone,
-            licensePlate,
-            address,
-            remark, // Added remark field
-            createdAt: new Date().toISOString(),
-            updatedAt: new Date().toISOString(),
-          })
-        }
-
-        if (errors.length > 0) {
-          const proceed = confirm(
-            `导入过程中有 ${errors.length} 个错误:\n${errors.slice(0, 5).join('\n')}${errors.length > 5 ? '\n...' : ''}\n\n是否继续导入其他 ${newCustomers.length} 个客户？`
-          )
-          if (!proceed) return
-        }
-
-        if (newCustomers.length === 0) {
-          alert('没有可导入的客户')
-          return
-        }
-
-        let successCount = 0
-        for (const customer of newCustomers) {
-          try {
-            await invoke('save_customer', { customer })
-            successCount++
-          } catch (err) {
-            console.error('保存客户失败:', customer.name, err)
-          }
-        }
-
-        await loadCustomers()
-        alert(`成功导入 ${successCount} 个客户！`)
-      } catch (error) {
-        console.error('导入失败:', error)
-        alert('导入失败: ' + error)
-      }
+  // 导入CSV：先预览逐行结果，确认后再实际导入
+  const handleImportCSV = async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        filters: [{ name: 'CSV文件', extensions: ['csv'] }],
+        title: '选择客户CSV文件'
+      })
+      if (!selected) return
+
+      const report = await customerService.importCsv(selected as string, true)
+      setImportPath(selected as string)
+      setImportPreview(report)
+    } catch (error) {
+      console.error('读取导入文件失败:', error)
+      alert('读取导入文件失败: ' + error)
     }
+  }
 
-    reader.readAsText(file, 'UTF-8')
+  const handleConfirmImport = async () => {
+    if (!importPath) return
 
-    if (fileInputRef.current) {
-      fileInputRef.current.value = ''
+    try {
+      setImporting(true)
+      const report = await customerService.importCsv(importPath, false)
+      await loadCustomers()
+      setImportPreview(null)
+      alert(`导入完成：新增 ${report.inserted} 个，更新 ${report.updated} 个，跳过 ${report.skipped} 行`)
+    } catch (error) {
+      console.error('导入失败:', error)
+      alert('导入失败: ' + error)
+    } finally {
+      setImporting(false)
     }
   }
 
@@ -340,14 +300,7 @@ export const CustomerManagement: React.FC = () => {
             <p className="page-description">管理客户信息，支持批量导入导出</p>
           </div>
           <div className="flex gap-2">
-            <input
-              type="file"
-              ref={fileInputRef}
-              accept=".csv"
-              onChange={handleImportCSV}
-              className="hidden"
-            />
-            <Button variant="outline" onClick={() => fileInputRef.current?.click()}>
+            <Button variant="outline" onClick={handleImportCSV}>
               <Upload size={16} />
               导入CSV
             </Button>
@@ -655,6 +608,13 @@ export const CustomerManagement: React.FC = () => {
           </div>
         </div>
       </Modal>
+
+      <ImportPreviewModal
+        report={importPreview}
+        importing={importing}
+        onClose={() => setImportPreview(null)}
+        onConfirm={handleConfirmImport}
+      />
     </div>
   )
 }
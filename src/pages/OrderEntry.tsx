@@ -9,7 +9,7 @@ import { ProductSelection } from '../components/order/ProductSelection'
 import { CartItemList } from '../components/order/CartItemList'
 import { formatCurrency } from '../lib/utils'
 import { exportOrderWithTemplate } from '../services/excelService'
-import type { Product, Customer, OrderItem, TemplateConfig, Order } from '../types'
+import type { Product, Customer, OrderItem, TemplateConfig, Order, SaveOrderResult } from '../types'
 import { invoke } from '@tauri-apps/api/core'
 import { openPath } from '@tauri-apps/plugin-opener'
 
@@ -293,11 +293,25 @@ export const OrderEntry: React.FC = () => {
   }
 
   // 计算总金额
-  const totalAmount = draftOrder.cart.reduce((sum, item) => {
+  const rawTotalCents = draftOrder.cart.reduce((sum, item) => {
     const price = item.discountPrice ?? item.price
-    return sum + (price * item.quantity)
+    return sum + Math.round(price * item.quantity * 100)
   }, 0)
 
+  // 价格不含税时在合计上加税，再按设置抹零（与后端 recalculate_order_total 保持一致）
+  const taxCents = settings.pricesIncludeTax === false && (settings.taxRate || 0) > 0
+    ? Math.round(rawTotalCents * (settings.taxRate || 0) / 100)
+    : 0
+  const taxedTotalCents = rawTotalCents + taxCents
+  const roundedTotalCents = settings.totalRounding === 'round'
+    ? Math.round(taxedTotalCents / 100) * 100
+    : settings.totalRounding === 'floor'
+      ? Math.floor(taxedTotalCents / 100) * 100
+      : taxedTotalCents
+  const totalAmount = roundedTotalCents / 100
+  const taxAmount = taxCents / 100
+  const roundingAdjustment = (roundedTotalCents - taxedTotalCents) / 100
+
   // 检查库存不足的商品
   const stockWarnings = useMemo(() => {
     const warnings: { itemId: string; productName: string; requested: number; available: number }[] = []
@@ -389,8 +403,11 @@ export const OrderEntry: React.FC = () => {
         updatedAt: new Date().toISOString(),
       }
 
-      const orderNumber = await invoke('save_order', { order }) as string
+      const { orderNumber, warnings } = await invoke('save_order', { order }) as SaveOrderResult
       order.orderNumber = orderNumber
+      if (warnings.length > 0) {
+        alert(`订单已保存，但请核对：\n${warnings.join('\n')}`)
+      }
 
       // 刷新商品列表（库存可能已扣减）
       await loadProducts()
@@ -406,20 +423,36 @@ export const OrderEntry: React.FC = () => {
         }
 
         // 检查模板是否有上传的文件
-        if (!selectedTemplate.templateBase64) {
+        if (!selectedTemplate.hasFile) {
           alert(`模板 "${selectedTemplate.name}" 没有上传Excel文件，请先在设置中上传模板文件！`)
           return
         }
 
+        // 检查订单是否满足模板要求（如最少商品行数）
+        const problems = await invoke('validate_order_against_template', {
+          order,
+          templateId: selectedTemplate.id,
+        }) as string[]
+        if (problems.length > 0) {
+          alert(`订单不满足模板要求：\n${problems.join('\n')}`)
+          return
+        }
+
         try {
           // 使用模板导出
           const filePath = await exportOrderWithTemplate(order, selectedTemplate, {
             outputDirectory: settings.outputDirectory,
             filenameFormat: settings.excelFilenameFormat,
             skipDialog: settings.skipSaveDialog,
+            taxRate: settings.taxRate,
+            itemSort: settings.exportItemSort,
           })
 
           if (filePath) {
+            await invoke('record_order_export', { orderId: order.id, filePath }).catch(e => {
+              console.error('记录导出失败:', e)
+            })
+
             // 根据设置决定是否自动打开文件
             if (settings.autoOpenExcel) {
               try {
@@ -552,7 +585,7 @@ export const OrderEntry: React.FC = () => {
                 {templates.map((template: TemplateConfig) => (
                   <option key={template.id} value={template.id}>
                     {template.name}
-                    {!template.templateBase64 && ' (未上传文件)'}
+                    {!template.hasFile && ' (未上传文件)'}
                   </option>
                 ))}
               </select>
@@ -658,6 +691,18 @@ export const OrderEntry: React.FC = () => {
                   <span className="text-muted-foreground">合计</span>
                   <span className="text-2xl font-bold text-primary">{formatCurrency(totalAmount)}</span>
                 </div>
+                {taxAmount !== 0 && (
+                  <div className="flex items-center justify-between mb-4 -mt-3 text-sm text-muted-foreground">
+                    <span>税额</span>
+                    <span>{formatCurrency(taxAmount)}</span>
+                  </div>
+                )}
+                {roundingAdjustment !== 0 && (
+                  <div className="flex items-center justify-between mb-4 -mt-3 text-sm text-muted-foreground">
+                    <span>抹零</span>
+                    <span>{formatCurrency(roundingAdjustment)}</span>
+                  </div>
+                )}
                 <div className="grid grid-cols-2 gap-3">
                   <Button
                     onClick={() => saveOrder(false)}
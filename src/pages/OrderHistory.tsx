@@ -1,13 +1,13 @@
-import React, { useState, useEffect, useCallback, useMemo } from 'react'
+import React, { useState, useEffect, useCallback, useMemo, useRef } from 'react'
 import { Card, Button, Input, Modal } from '../components/ui'
 import {
   Search, FileDown, Eye, Calendar, User, Package, Upload,
   ChevronLeft, ChevronRight, Filter, FileText, CheckSquare, Square
 } from 'lucide-react'
-import type { Order } from '../types'
+import type { Order, OrderPage } from '../types'
 import { invoke } from '@tauri-apps/api/core'
-import { formatCurrency } from '../lib/utils'
-import { exportOrderToExcel, exportOrdersToExcel } from '../services/excelService'
+import { formatCurrency, toBaseAmount } from '../lib/utils'
+import { exportOrderToExcel, exportOrdersToExcel, exportOrdersGroupedWorkbook, type ItemSortMode } from '../services/excelService'
 import { useStore } from '../stores/useStore'
 import { save } from '@tauri-apps/plugin-dialog'
 import { writeTextFile } from '@tauri-apps/plugin-fs'
@@ -18,9 +18,10 @@ interface OrderDetailModalProps {
   isOpen: boolean
   onClose: () => void
   outputDirectory?: string
+  itemSort?: ItemSortMode
 }
 
-const OrderDetailModal: React.FC<OrderDetailModalProps> = ({ order, isOpen, onClose, outputDirectory }) => {
+const OrderDetailModal: React.FC<OrderDetailModalProps> = ({ order, isOpen, onClose, outputDirectory, itemSort }) => {
   if (!order) return null
 
   return (
@@ -114,7 +115,7 @@ const OrderDetailModal: React.FC<OrderDetailModalProps> = ({ order, isOpen, onCl
           </Button>
           <Button onClick={async () => {
             try {
-              const filePath = await exportOrderToExcel(order, { outputDirectory })
+              const filePath = await exportOrderToExcel(order, { outputDirectory, itemSort })
               if (filePath) {
                 alert(`导出成功！\n\n文件已保存到：${filePath}`)
               }
@@ -147,23 +148,39 @@ export const OrderHistory: React.FC = () => {
 
   // 分页
   const [currentPage, setCurrentPage] = useState(1)
+  const [serverTotal, setServerTotal] = useState(0)
   const pageSize = 20
+  // 已加载过的订单（按 ID），跨页选择后批量导出时从这里取订单
+  const loadedOrdersRef = useRef(new Map<string, Order>())
+
+  // 没有搜索和日期筛选时按页从后端读取；有筛选时读取全部订单在前端过滤
+  const isFiltered = debouncedSearch !== '' || dateFilter !== 'all'
 
   // 加载订单列表
   const loadOrders = useCallback(async () => {
     try {
       setLoading(true)
-      // TODO: 调用后端获取订单列表
-      const data = await invoke('get_all_orders').catch(() => []) as Order[]
+      let data: Order[]
+      if (isFiltered) {
+        data = await invoke('get_all_orders') as Order[]
+      } else {
+        const page = await invoke('get_all_orders_paged', {
+          limit: pageSize,
+          offset: (currentPage - 1) * pageSize,
+        }) as OrderPage
+        data = page.items
+        setServerTotal(page.total)
+      }
+      data.forEach(order => loadedOrdersRef.current.set(order.id, order))
       setOrders(data)
     } catch (error) {
       console.error('加载订单列表失败:', error)
-      // 使用模拟数据展示
       setOrders([])
+      setServerTotal(0)
     } finally {
       setLoading(false)
     }
-  }, [])
+  }, [isFiltered, currentPage])
 
   useEffect(() => {
     loadOrders()
@@ -176,6 +193,11 @@ export const OrderHistory: React.FC = () => {
     return () => window.clearTimeout(timer)
   }, [search])
 
+  // 筛选条件变化时回到第一页
+  useEffect(() => {
+    setCurrentPage(1)
+  }, [debouncedSearch, dateFilter])
+
   // 日期过滤
   const getDateRange = (): { start: Date; end: Date } | null => {
     const now = new Date()
@@ -217,12 +239,12 @@ export const OrderHistory: React.FC = () => {
     return matchSearch && matchDate
   }), [orders, debouncedSearch, dateFilter])
 
-  // 分页数据
-  const totalPages = Math.ceil(filteredOrders.length / pageSize)
-  const paginatedOrders = filteredOrders.slice(
-    (currentPage - 1) * pageSize,
-    currentPage * pageSize
-  )
+  // 分页数据（未筛选时 orders 即为当前页）
+  const totalCount = isFiltered ? filteredOrders.length : serverTotal
+  const totalPages = Math.ceil(totalCount / pageSize)
+  const paginatedOrders = isFiltered
+    ? filteredOrders.slice((currentPage - 1) * pageSize, currentPage * pageSize)
+    : filteredOrders
 
   // 查看订单详情
   const handleViewOrder = (order: Order) => {
@@ -266,11 +288,13 @@ export const OrderHistory: React.FC = () => {
       return
     }
 
-    const selectedOrders = orders.filter(o => selectedOrderIds.has(o.id))
+    const selectedOrders = Array.from(selectedOrderIds)
+      .map(id => loadedOrdersRef.current.get(id))
+      .filter((o): o is Order => o !== undefined)
 
     try {
       setExporting(true)
-      const filePath = await exportOrdersToExcel(selectedOrders, { outputDirectory: settings.outputDirectory })
+      const filePath = await exportOrdersToExcel(selectedOrders, { outputDirectory: settings.outputDirectory, itemSort: settings.exportItemSort })
       if (filePath) {
         alert(`成功导出 ${selectedOrders.length} 个订单！\n\n文件已保存到：${filePath}`)
         setSelectedOrderIds(new Set()) // 清空选择
@@ -283,8 +307,37 @@ export const OrderHistory: React.FC = () => {
     }
   }
 
+  // 合并明细导出：选中订单（未选择时为当前筛选结果）的明细按订单分组写入同一张表
+  const handleGroupedExport = async () => {
+    const targetOrders = selectedOrderIds.size > 0
+      ? Array.from(selectedOrderIds)
+        .map(id => loadedOrdersRef.current.get(id))
+        .filter((o): o is Order => o !== undefined)
+      : filteredOrders
+
+    if (targetOrders.length === 0) {
+      alert('没有可导出的订单')
+      return
+    }
+
+    try {
+      setExporting(true)
+      const filePath = await exportOrdersGroupedWorkbook(targetOrders, { outputDirectory: settings.outputDirectory })
+      if (filePath) {
+        alert(`成功导出 ${targetOrders.length} 个订单的明细！\n\n文件已保存到：${filePath}`)
+      }
+    } catch (error) {
+      console.error('合并明细导出失败:', error)
+      alert('合并明细导出失败: ' + error)
+    } finally {
+      setExporting(false)
+    }
+  }
+
   const handleExportJson = async () => {
     try {
+      // 导出全部订单，不受当前分页影响
+      const orders = await invoke('get_all_orders') as Order[]
       const exportData = {
         version: '1.0',
         exportedAt: new Date().toISOString(),
@@ -341,8 +394,8 @@ export const OrderHistory: React.FC = () => {
   }
 
   // 统计数据
-  const totalAmount = filteredOrders.reduce((sum, o) => sum + o.totalAmount, 0)
-  const orderCount = filteredOrders.length
+  const totalAmount = filteredOrders.reduce((sum, o) => sum + toBaseAmount(o), 0)
+  const orderCount = totalCount
   const isAllSelected = paginatedOrders.length > 0 && paginatedOrders.every(o => selectedOrderIds.has(o.id))
 
   return (
@@ -374,6 +427,10 @@ export const OrderHistory: React.FC = () => {
               <FileDown size={16} />
               导出JSON
             </Button>
+            <Button variant="outline" onClick={handleGroupedExport} disabled={exporting}>
+              <FileDown size={16} />
+              合并明细导出
+            </Button>
             {selectedOrderIds.size > 0 && (
               <Button
                 onClick={handleBatchExport}
@@ -413,7 +470,7 @@ export const OrderHistory: React.FC = () => {
               <Package size={20} className="text-success" />
             </div>
             <div>
-              <p className="text-sm text-muted-foreground">商品总数</p>
+              <p className="text-sm text-muted-foreground">商品总数{isFiltered ? '' : '（本页）'}</p>
               <p className="text-2xl font-bold text-foreground">
                 {filteredOrders.reduce((sum, o) => sum + o.items.reduce((s, i) => s + i.quantity, 0), 0)}
               </p>
@@ -426,7 +483,7 @@ export const OrderHistory: React.FC = () => {
               <span className="text-warning text-lg font-bold">¥</span>
             </div>
             <div>
-              <p className="text-sm text-muted-foreground">总金额</p>
+              <p className="text-sm text-muted-foreground">总金额{isFiltered ? '' : '（本页）'}</p>
               <p className="text-2xl font-bold text-primary">{formatCurrency(totalAmount)}</p>
             </div>
           </div>
@@ -569,7 +626,7 @@ export const OrderHistory: React.FC = () => {
         {totalPages > 1 && (
           <div className="px-4 py-3 border-t border-border bg-muted/30 flex items-center justify-between">
             <span className="text-sm text-muted-foreground">
-              显示 {(currentPage - 1) * pageSize + 1} - {Math.min(currentPage * pageSize, filteredOrders.length)} / 共 {filteredOrders.length} 条
+              显示 {(currentPage - 1) * pageSize + 1} - {Math.min(currentPage * pageSize, totalCount)} / 共 {totalCount} 条
             </span>
             <div className="flex items-center gap-2">
               <button
@@ -603,6 +660,7 @@ export const OrderHistory: React.FC = () => {
           setSelectedOrder(null)
         }}
         outputDirectory={settings.outputDirectory}
+        itemSort={settings.exportItemSort}
       />
     </div>
   )
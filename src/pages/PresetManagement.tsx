@@ -50,14 +50,14 @@ export const PresetManagement: React.FC = () => {
             setRemarkForm({
                 content: remark?.content || '',
                 type: remark?.type || 'item',
-                sortOrder: remark?.sortOrder ?? remarkPresets.length,
+                sortOrder: remark?.sortOrder ?? 0, // 新建时为 0，保存时由后端排到最后
             })
         } else {
             const unit = item as UnitPreset | undefined
             setEditingItem(unit || null)
             setUnitForm({
                 name: unit?.name || '',
-                sortOrder: unit?.sortOrder ?? unitPresets.length,
+                sortOrder: unit?.sortOrder ?? 0, // 新建时为 0，保存时由后端排到最后
             })
         }
         setModalOpen(true)
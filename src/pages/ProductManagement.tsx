@@ -1,17 +1,18 @@
-import React, { useState, useRef, useCallback, useMemo, useEffect } from 'react'
+import React, { useState, useCallback, useMemo, useEffect } from 'react'
 import { Card, Button, Input, Label } from '../components/ui'
 import { UnitPresetSelector } from '../components/UnitPresetSelector'
-import { Search, Plus, Edit, Trash2, Upload, Download, Package, Filter, AlertTriangle, Folder, X, RefreshCw, GripVertical, CheckSquare, Square, ChevronUp, ChevronDown } from 'lucide-react'
+import { ImportPreviewModal } from '../components/ImportPreviewModal'
+import { Search, Plus, Edit, Trash2, Upload, Download, Package, Filter, AlertTriangle, Folder, X, RefreshCw, GripVertical, CheckSquare, Square, ChevronUp, ChevronDown, ClipboardList } from 'lucide-react'
 import { productService, categoryService } from '../services/api'
 import { useStore } from '../stores/useStore'
-import type { Product, Category } from '../types'
+import type { Product, Category, CsvImportReport } from '../types'
 import { formatCurrency } from '../lib/utils'
-import { save } from '@tauri-apps/plugin-dialog'
+import { open, save } from '@tauri-apps/plugin-dialog'
 import { writeTextFile } from '@tauri-apps/plugin-fs'
 import { invoke } from '@tauri-apps/api/core'
 
 export const ProductManagement: React.FC = () => {
-  const { products, setProducts, categories, setCategories } = useStore()
+  const { products, setProducts, categories, setCategories, settings } = useStore()
   const [search, setSearch] = useState('')
   const [debouncedSearch, setDebouncedSearch] = useState('')
   const [selectedCategory, setSelectedCategory] = useState<string | null>(null)
@@ -19,7 +20,11 @@ export const ProductManagement: React.FC = () => {
   const [showLowStock, setShowLowStock] = useState(false)
   const [showCategoryPanel, setShowCategoryPanel] = useState(false)
   const [editingCategory, setEditingCategory] = useState<Category | null>(null)
-  const fileInputRef = useRef<HTMLInputElement>(null)
+
+  // CSV 导入预览
+  const [importPreview, setImportPreview] = useState<CsvImportReport | null>(null)
+  const [importPath, setImportPath] = useState<string | null>(null)
+  const [importing, setImporting] = useState(false)
 
   // 批量选择
   const [selectedProducts, setSelectedProducts] = useState<Set<string>>(new Set())
@@ -133,6 +138,7 @@ export const ProductManagement: React.FC = () => {
     const matchSearch =
       normalizedSearch.length === 0 ||
       p.name.toLowerCase().includes(normalizedSearch) ||
+      (p.barcode || '').toLowerCase().includes(normalizedSearch) ||
       pinyinTokens.some((token: string) => token.includes(normalizedSearch) || token.startsWith(normalizedSearch)) ||
       pinyinJoined.includes(normalizedSearch) ||
       pinyinJoined.startsWith(normalizedSearch)
@@ -359,33 +365,18 @@ export const ProductManagement: React.FC = () => {
       categoryId: categories[0].id,
       pinyin: '',
       stock: undefined,
-      minStock: undefined,
-      trackStock: false,
+      minStock: settings.defaultTrackStock && settings.defaultMinStock ? settings.defaultMinStock : undefined,
+      trackStock: settings.defaultTrackStock ?? false,
       createdAt: new Date().toISOString(),
       updatedAt: new Date().toISOString(),
     }
     setEditingProduct(newProduct)
   }
 
-  // 构建导出数据
+  // 导出全部商品（CSV 由后端生成，可直接用于导入）
   const handleExportCSV = async () => {
     try {
-      const header = '商品名称,单位,零售价,分类,拼音码,最小库存,启用库存,当前库存\n'
-      const rows = filteredProducts.map((p: any) => {
-        const cat = categories.find((c: any) => c.id === p.categoryId)
-        return [
-          p.name,
-          p.unit,
-          p.price,
-          cat ? cat.name : '',
-          p.pinyin || '',
-          p.minStock || 0,
-          p.trackStock ? 'true' : 'false',
-          p.stock || 0
-        ].join(',')
-      }).join('\n')
-
-      const csvContent = header + rows
+      const csvContent = await productService.exportCsv()
 
       const filePath = await save({
         defaultPath: `商品列表_${new Date().toISOString().slice(0, 10)}.csv`,
@@ -393,8 +384,7 @@ export const ProductManagement: React.FC = () => {
       })
 
       if (filePath) {
-        const bom = '\uFEFF'
-        await writeTextFile(filePath, bom + csvContent)
+        await writeTextFile(filePath, csvContent)
         alert('导出成功！')
       }
     } catch (error) {
@@ -403,101 +393,81 @@ export const ProductManagement: React.FC = () => {
     }
   }
 
+  // 导出补货清单（缺货及低于最低库存的商品），用于发给供应商
+  const handleExportLowStock = async () => {
+    try {
+      const filePath = await save({
+        defaultPath: `补货清单_${new Date().toISOString().slice(0, 10)}.csv`,
+        filters: [{ name: 'CSV文件', extensions: ['csv'] }]
+      })
 
-  const getCategoryProductCount = useCallback((categoryId: string) => {
-    return categoryTotalCountMap[categoryId] || 0
-  }, [categoryTotalCountMap])
-
-  // 导入CSV
-  const handleImportCSV = (event: React.ChangeEvent<HTMLInputElement>) => {
-    const file = event.target.files?.[0]
-    if (!file) return
-
-    const reader = new FileReader()
-    reader.onload = async (e: ProgressEvent<FileReader>) => {
-      const text = e.target?.result as string
-      const lines = text.split('\n')
-      const newProducts: Product[] = []
-      const errors: string[] = []
-
-      for (let i = 1; i < lines.length; i++) {
-        const line = lines[i].trim()
-        if (!line) continue
-
-        const values = line.split(',')
-        if (values.length < 3) continue
-
-        const [name, unit, priceStr, categoryName, pinyin, minStockStr, trackStockStr, stockStr] = values
-        const price = parseFloat(priceStr) || 0
-        const minStock = parseFloat(minStockStr) || 0
-        const trackStock = trackStockStr === 'true'
-        const stock = parseFloat(stockStr) || 0
-
-        if (!name) {
-          errors.push(`第 ${i + 1} 行: 商品名称为空`)
-          continue
-        }
-
-        // 查找或创建分类
-        let categoryId = ''
-        if (categoryName) {
-          const cat = categories.find((c: Category) => c.name === categoryName)
-          if (cat) {
-            categoryId = cat.id
-          } else {
-            // 这里可以处理新增分类逻辑
-          }
-        }
-
-        newProducts.push({
-          id: Date.now().toString() + '_' + i,
-          name,
-          price,
-          unit,
-          categoryId,
-          pinyin,
-          stock,
-          minStock,
-          trackStock,
-          createdAt: new Date().toISOString(),
-          updatedAt: new Date().toISOString(),
-        })
+      if (filePath) {
+        const count = await invoke<number>('export_low_stock_csv', { path: filePath })
+        alert(`补货清单导出成功，共 ${count} 个商品`)
       }
+    } catch (error) {
+      console.error('导出补货清单失败:', error)
+      alert('导出补货清单失败: ' + error)
+    }
+  }
 
-      if (errors.length > 0) {
-        const proceed = confirm(
-          `导入过程中有 ${errors.length} 个错误:\n${errors.slice(0, 5).join('\n')}${errors.length > 5 ? '\n...' : ''}\n\n是否继续导入其他 ${newProducts.length} 个商品？`
-        )
-        if (!proceed) return
-      }
+  // 导出盘点表（当前选中分类及其子分类，未选中时为全部跟踪库存的商品）
+  const handleExportStocktake = async () => {
+    try {
+      const filePath = await save({
+        defaultPath: `盘点表_${new Date().toISOString().slice(0, 10)}.xlsx`,
+        filters: [{ name: 'Excel文件', extensions: ['xlsx'] }]
+      })
 
-      if (newProducts.length === 0) {
-        alert('没有可导入的商品')
-        return
+      if (filePath) {
+        const count = await invoke<number>('export_stocktake_sheet', { categoryId: selectedCategory, path: filePath })
+        alert(`盘点表导出成功，共 ${count} 个商品`)
       }
+    } catch (error) {
+      console.error('导出盘点表失败:', error)
+      alert('导出盘点表失败: ' + error)
+    }
+  }
 
-      let successCount = 0
-      for (const product of newProducts) {
-        try {
-          await productService.save(product)
-          successCount++
-        } catch (err) {
-          console.error('保存商品失败:', product.name, err)
-        }
-      }
 
-      const updated = await productService.getAll()
-      setProducts(updated)
+  const getCategoryProductCount = useCallback((categoryId: string) => {
+    return categoryTotalCountMap[categoryId] || 0
+  }, [categoryTotalCountMap])
 
-      alert(`成功导入 ${successCount} 个商品！`)
-    }
-    reader.onerror = () => {
-      alert('读取文件发生错误')
+  // 导入CSV：先预览逐行结果，确认后再实际导入
+  const handleImportCSV = async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        filters: [{ name: 'CSV文件', extensions: ['csv'] }],
+        title: '选择商品CSV文件'
+      })
+      if (!selected) return
+
+      const report = await productService.importCsv(selected as string, true)
+      setImportPath(selected as string)
+      setImportPreview(report)
+    } catch (error) {
+      console.error('读取导入文件失败:', error)
+      alert('读取导入文件失败: ' + error)
     }
-    reader.readAsText(file, 'UTF-8')
+  }
 
-    if (fileInputRef.current) {
-      fileInputRef.current.value = ''
+  const handleConfirmImport = async () => {
+    if (!importPath) return
+
+    try {
+      setImporting(true)
+      const report = await productService.importCsv(importPath, false)
+      const updated = await productService.getAll()
+      setProducts(updated)
+      setImportPreview(null)
+      alert(`导入完成：新增 ${report.inserted} 个，更新 ${report.updated} 个，跳过 ${report.skipped} 行`)
+    } catch (error) {
+      console.error('导入商品失败:', error)
+      alert('导入商品失败: ' + error)
+    } finally {
+      setImporting(false)
     }
   }
 
@@ -527,6 +497,26 @@ export const ProductManagement: React.FC = () => {
     }
   }
 
+  // 从 CSV 导入分类树
+  const handleImportCategories = async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        filters: [{ name: 'CSV文件', extensions: ['csv'] }],
+        title: '选择分类CSV文件（名称,上级名称,排序）'
+      })
+      if (!selected) return
+
+      const result = await categoryService.importCsv(selected as string)
+      await handleRefreshCategories()
+      const warnings = result.warnings.length > 0 ? `\n\n提示：\n${result.warnings.join('\n')}` : ''
+      alert(`成功导入 ${result.inserted} 个分类${warnings}`)
+    } catch (error) {
+      console.error('导入分类失败:', error)
+      alert('导入分类失败: ' + error)
+    }
+  }
+
   // 保存分类
   const handleSaveCategory = async (category: Category) => {
     try {
@@ -569,14 +559,7 @@ export const ProductManagement: React.FC = () => {
             <p className="page-description">管理商品信息和库存，支持CSV批量导入导出</p>
           </div>
           <div className="flex gap-2 flex-wrap items-center">
-            <input
-              type="file"
-              ref={fileInputRef}
-              accept=".csv"
-              onChange={handleImportCSV}
-              className="hidden"
-            />
-            <Button variant="outline" onClick={() => fileInputRef.current?.click()}>
+            <Button variant="outline" onClick={handleImportCSV}>
               <Upload size={16} />
               导入CSV
             </Button>
@@ -584,6 +567,14 @@ export const ProductManagement: React.FC = () => {
               <Download size={16} />
               导出CSV
             </Button>
+            <Button variant="outline" onClick={handleExportLowStock}>
+              <AlertTriangle size={16} />
+              补货清单
+            </Button>
+            <Button variant="outline" onClick={handleExportStocktake}>
+              <ClipboardList size={16} />
+              盘点表
+            </Button>
             <Button variant="outline" onClick={handleBatchGeneratePinyin}>
               <RefreshCw size={16} />
               生成拼音
@@ -695,7 +686,7 @@ export const ProductManagement: React.FC = () => {
                                 parentId: category.id,
                                 level: category.level + 1,
                                 path: `${category.path}/${category.id}`,
-                                sortOrder: 0, // Simplified
+                                sortOrder: 0, // 保存时由后端排到同级最后
                                 createdAt: new Date().toISOString(),
                                 updatedAt: new Date().toISOString(),
                               })}
@@ -749,7 +740,7 @@ export const ProductManagement: React.FC = () => {
                   name: '',
                   level: 0,
                   path: '',
-                  sortOrder: categories.length,
+                  sortOrder: 0, // 保存时由后端排到同级最后
                   createdAt: new Date().toISOString(),
                   updatedAt: new Date().toISOString(),
                 })}
@@ -759,6 +750,15 @@ export const ProductManagement: React.FC = () => {
                 <Plus size={14} />
                 新建分类
               </Button>
+              <Button
+                variant="outline"
+                onClick={handleImportCategories}
+                className="w-full mt-2"
+                size="sm"
+              >
+                <Upload size={14} />
+                导入分类CSV
+              </Button>
             </div>
           </Card>
         )}
@@ -983,6 +983,13 @@ export const ProductManagement: React.FC = () => {
           onClose={() => setEditingCategory(null)}
         />
       )}
+
+      <ImportPreviewModal
+        report={importPreview}
+        importing={importing}
+        onClose={() => setImportPreview(null)}
+        onConfirm={handleConfirmImport}
+      />
     </div>
   )
 }
@@ -1080,6 +1087,14 @@ const ProductEditModal: React.FC<{
               placeholder="用于快速搜索，如 pgz"
             />
           </div>
+          <div>
+            <Label>条码</Label>
+            <Input
+              value={formData.barcode || ''}
+              onChange={e => setFormData({ ...formData, barcode: e.target.value })}
+              placeholder="可用扫码枪录入，留空表示无条码"
+            />
+          </div>
 
           {/* 库存管理 */}
           <div className="border-t border-border pt-4 mt-4">
@@ -36,7 +36,7 @@ export const RemarkPresets: React.FC = () => {
     setFormData({
       content: '',
       type: 'item',
-      sortOrder: presets.length,
+      sortOrder: 0, // 保存时由后端排到最后
     })
     setModalOpen(true)
   }
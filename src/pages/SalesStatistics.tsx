@@ -16,7 +16,7 @@ import {
   ChevronDown,
 } from 'lucide-react'
 import { useStore } from '../stores/useStore'
-import { formatCurrency } from '../lib/utils'
+import { formatCurrency, toBaseAmount } from '../lib/utils'
 import { invoke } from '@tauri-apps/api/core'
 import { save } from '@tauri-apps/plugin-dialog'
 import { writeTextFile } from '@tauri-apps/plugin-fs'
@@ -156,7 +156,7 @@ const buildTrendData = (
     const current = trendMap.get(key)
     if (!current) return
 
-    current.amount += Number(o.totalAmount || 0)
+    current.amount += toBaseAmount(o)
     current.count += 1
   })
 
@@ -376,7 +376,7 @@ export const SalesStatistics: React.FC = () => {
   }, [orders, prevPeriod, applyNonDateFilters])
 
   const stats = useMemo(() => {
-    const totalSales = filteredOrders.reduce((sum, order) => sum + Number(order.totalAmount || 0), 0)
+    const totalSales = filteredOrders.reduce((sum, order) => sum + toBaseAmount(order), 0)
     const orderCount = filteredOrders.length
     const avgOrderValue = orderCount > 0 ? totalSales / orderCount : 0
 
@@ -397,7 +397,7 @@ export const SalesStatistics: React.FC = () => {
         }
       }
       activeCustomerIds.add(customerKey)
-      customerSales[customerKey].amount += Number(order.totalAmount || 0)
+      customerSales[customerKey].amount += toBaseAmount(order)
       customerSales[customerKey].count += 1
 
       order.items.forEach((item: any) => {
@@ -407,7 +407,7 @@ export const SalesStatistics: React.FC = () => {
         }
         const price = Number(item.discountPrice ?? item.price ?? 0)
         const quantity = Number(item.quantity ?? 0)
-        const amount = price * quantity
+        const amount = price * quantity * Number(order.exchangeRate || 1)
 
         productSales[pId].amount += amount
         productSales[pId].quantity += quantity
@@ -430,7 +430,7 @@ export const SalesStatistics: React.FC = () => {
 
     const { trendData, maxTrendAmount, granularity } = buildTrendData(filteredOrders, safeStartDate, safeEndDate)
 
-    const prevTotalSales = prevOrders.reduce((sum, o) => sum + Number(o.totalAmount || 0), 0)
+    const prevTotalSales = prevOrders.reduce((sum, o) => sum + toBaseAmount(o), 0)
     const prevOrderCount = prevOrders.length
 
     return {
@@ -1,8 +1,8 @@
 import React, { useState, useEffect, useMemo } from 'react'
-import { Save, FolderOpen, Plus, Upload, Download, CheckSquare, Square } from 'lucide-react'
+import { Save, FolderOpen, Plus, Upload, Download, CheckSquare, Square, RotateCcw } from 'lucide-react'
 import { Card, Button, Input, Label } from '../components/ui'
 import { useStore } from '../stores/useStore'
-import type { TemplateConfig } from '../types'
+import type { RestoredDefaults, TemplateConfig } from '../types'
 import { invoke } from '@tauri-apps/api/core'
 import { open, save } from '@tauri-apps/plugin-dialog'
 import { writeTextFile } from '@tauri-apps/plugin-fs'
@@ -60,12 +60,12 @@ export const Settings: React.FC = () => {
     }
   }
 
-  const selectDirectory = async (type: 'data' | 'output') => {
+  const selectDirectory = async (type: 'data' | 'output' | 'backup') => {
     try {
       const selected = await open({
         directory: true,
         multiple: false,
-        title: type === 'data' ? '选择数据存储目录' : '选择Excel输出目录'
+        title: type === 'data' ? '选择数据存储目录' : type === 'backup' ? '选择数据库备份目录' : '选择Excel输出目录'
       })
 
       // 用户取消了选择
@@ -78,6 +78,8 @@ export const Settings: React.FC = () => {
       if (path) {
         if (type === 'data') {
           setSettings({ ...settings, dataDirectory: path })
+        } else if (type === 'backup') {
+          setSettings({ ...settings, backupDirectory: path })
         } else {
           setSettings({ ...settings, outputDirectory: path })
         }
@@ -142,7 +144,7 @@ export const Settings: React.FC = () => {
     }
 
     // 验证Excel文件
-    if (!editingTemplate.templateBase64) {
+    if (!editingTemplate.templateBase64 && !editingTemplate.hasFile) {
       alert('❌ 请上传Excel模板文件！')
       return
     }
@@ -255,6 +257,7 @@ export const Settings: React.FC = () => {
           ...editingTemplate,
           templateBase64: base64,
           fileName: file.name,
+          hasFile: true,
         })
       }
     }
@@ -263,9 +266,14 @@ export const Settings: React.FC = () => {
 
   const handleExportSettings = async () => {
     try {
+      // 模板列表不含文件内容，导出前逐个读取模板文件
+      const templatesWithFiles = await Promise.all(templates.map(async (template) => ({
+        ...template,
+        templateBase64: template.hasFile ? await invoke<string>('get_template_file', { id: template.id }) : '',
+      })))
       const settingsToExport = {
         settings,
-        templates,
+        templates: templatesWithFiles,
         exportDate: new Date().toISOString(),
         version: '1.0'
       }
@@ -325,6 +333,58 @@ export const Settings: React.FC = () => {
     event.target.value = ''
   }
 
+  const handleRestoreDefaults = async () => {
+    const overwrite = window.confirm('将重新添加缺失的默认分类、单位和默认模板。\n\n是否同时把已存在的默认项恢复为默认配置？\n（确定 = 同时恢复，取消 = 只添加缺失项）')
+    try {
+      const result = await invoke<RestoredDefaults>('restore_default_data', { overwrite })
+      const added = [
+        result.categoriesAdded.length > 0 ? `分类：${result.categoriesAdded.join('、')}` : '',
+        result.unitsAdded.length > 0 ? `单位：${result.unitsAdded.join('、')}` : '',
+        result.templateAdded ? '默认模板' : '',
+      ].filter(Boolean)
+      await loadTemplates()
+      alert(added.length > 0 || result.overwritten > 0
+        ? `默认数据已恢复！\n\n${added.join('\n')}${result.overwritten > 0 ? `\n已恢复 ${result.overwritten} 项默认配置` : ''}`
+        : '默认数据完整，无需恢复')
+    } catch (error) {
+      console.error('恢复默认数据失败:', error)
+      alert('恢复失败: ' + error)
+    }
+  }
+
+  // 备份目录取已保存的设置，修改目录后需先保存设置
+  const handleBackupDatabase = async () => {
+    try {
+      const path = await invoke<string>('backup_database')
+      alert('数据库备份成功！\n\n' + path)
+    } catch (error) {
+      console.error('备份数据库失败:', error)
+      alert('备份失败: ' + error)
+    }
+  }
+
+  // 恢复会覆盖当前全部数据，完成后重新加载界面以读取恢复后的数据
+  const handleRestoreDatabase = async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        defaultPath: settings.backupDirectory || undefined,
+        filters: [{ name: '数据库备份', extensions: ['db'] }]
+      })
+      if (!selected || typeof selected !== 'string') return
+
+      const confirmed = window.confirm(`将用以下备份覆盖当前全部数据（商品、客户、订单、模板和设置）。此操作不可撤销，建议先备份当前数据。确定要继续吗？\n\n${selected}`)
+      if (!confirmed) return
+
+      await invoke('restore_database', { backupPath: selected })
+      alert('数据库恢复成功！界面将重新加载。')
+      window.location.reload()
+    } catch (error) {
+      console.error('恢复数据库失败:', error)
+      alert('恢复失败: ' + error)
+    }
+  }
+
   return (
     <div className="p-6 h-full overflow-auto">
       <h1 className="text-2xl font-bold text-foreground mb-6">系统设置</h1>
@@ -354,6 +414,10 @@ export const Settings: React.FC = () => {
               <Download size={18} className="mr-2" />
               导出设置
             </Button>
+            <Button variant="secondary" onClick={handleRestoreDefaults}>
+              <RotateCcw size={18} className="mr-2" />
+              恢复默认数据
+            </Button>
           </div>
         </Card>
 
@@ -391,6 +455,29 @@ export const Settings: React.FC = () => {
                 </Button>
               </div>
             </div>
+            <div>
+              <Label>数据库备份目录</Label>
+              <div className="flex gap-2">
+                <Input
+                  value={settings.backupDirectory}
+                  onChange={e => setSettings({ ...settings, backupDirectory: e.target.value })}
+                  placeholder="默认: 数据目录下的 backups"
+                  className="flex-1"
+                />
+                <Button variant="secondary" onClick={() => selectDirectory('backup')}>
+                  <FolderOpen size={18} className="mr-2" />
+                  浏览
+                </Button>
+                <Button variant="secondary" onClick={handleBackupDatabase}>
+                  <Download size={18} className="mr-2" />
+                  立即备份
+                </Button>
+                <Button variant="secondary" onClick={handleRestoreDatabase}>
+                  <RotateCcw size={18} className="mr-2" />
+                  从备份恢复
+                </Button>
+              </div>
+            </div>
           </div>
         </Card>
 
@@ -456,16 +543,33 @@ export const Settings: React.FC = () => {
                 变量: {`{SEQ}`} - 序号, {`{SEQ:n}`} - n位序号, {`{YYYY}`} - 年, {`{MM}`} - 月, {`{DD}`} - 日
               </p>
             </div>
+            <div>
+              <Label>订单号前缀</Label>
+              <Input
+                value={settings.orderNumberPrefix}
+                onChange={e => setSettings({ ...settings, orderNumberPrefix: e.target.value })}
+                placeholder="例如 A-（可留空）"
+                className="font-mono text-sm"
+              />
+              <p className="text-xs text-muted-foreground mt-1">
+                加在按格式生成的订单号前面
+              </p>
+            </div>
             <div className="grid grid-cols-2 gap-4">
               <div>
-                <Label>每天重新从1开始</Label>
+                <Label>序号重新从1开始</Label>
                 <select
                   className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
-                  value={settings.orderNumberResetDaily ? 'true' : 'false'}
-                  onChange={e => setSettings({ ...settings, orderNumberResetDaily: e.target.value === 'true' })}
+                  value={settings.orderNumberResetPeriod || (settings.orderNumberResetDaily ? 'daily' : 'never')}
+                  onChange={e => {
+                    const period = e.target.value as 'daily' | 'monthly' | 'yearly' | 'never'
+                    setSettings({ ...settings, orderNumberResetPeriod: period, orderNumberResetDaily: period === 'daily' })
+                  }}
                 >
-                  <option value="true">是</option>
-                  <option value="false">否</option>
+                  <option value="daily">每天</option>
+                  <option value="monthly">每月</option>
+                  <option value="yearly">每年</option>
+                  <option value="never">不重置</option>
                 </select>
               </div>
               <div>
@@ -479,6 +583,151 @@ export const Settings: React.FC = () => {
                 />
               </div>
             </div>
+            <div>
+              <Label>订单总额抹零</Label>
+              <select
+                className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                value={settings.totalRounding || 'none'}
+                onChange={e => setSettings({ ...settings, totalRounding: e.target.value as 'none' | 'round' | 'floor' })}
+              >
+                <option value="none">不抹零</option>
+                <option value="round">四舍五入到元</option>
+                <option value="floor">舍去角分</option>
+              </select>
+              <p className="text-xs text-muted-foreground mt-1">
+                仅调整订单总额，商品明细金额保持不变
+              </p>
+            </div>
+            <div>
+              <Label>导出商品排列方式</Label>
+              <select
+                className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                value={settings.exportItemSort || 'entry'}
+                onChange={e => setSettings({ ...settings, exportItemSort: e.target.value as 'entry' | 'category' | 'amount_desc' })}
+              >
+                <option value="entry">按录入顺序</option>
+                <option value="category">按分类分组</option>
+                <option value="amount_desc">按金额从高到低</option>
+              </select>
+              <p className="text-xs text-muted-foreground mt-1">
+                只影响导出的 Excel，不改变订单中保存的商品顺序
+              </p>
+            </div>
+            <div className="grid grid-cols-2 gap-4">
+              <div>
+                <Label>税率（%）</Label>
+                <Input
+                  type="number"
+                  min={0}
+                  max={100}
+                  step={0.01}
+                  value={settings.taxRate ?? 0}
+                  onChange={e => setSettings({ ...settings, taxRate: Number(e.target.value) })}
+                  placeholder="0 表示不计税"
+                />
+              </div>
+              <div>
+                <Label>商品价格是否含税</Label>
+                <select
+                  className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                  value={settings.pricesIncludeTax === false ? 'false' : 'true'}
+                  onChange={e => setSettings({ ...settings, pricesIncludeTax: e.target.value === 'true' })}
+                >
+                  <option value="true">含税（从总额倒算税额）</option>
+                  <option value="false">不含税（在小计上加税）</option>
+                </select>
+              </div>
+            </div>
+            <div className="grid grid-cols-3 gap-4">
+              <div>
+                <Label>单行数量上限</Label>
+                <Input
+                  type="number"
+                  min={0}
+                  value={settings.maxItemQuantity ?? 0}
+                  onChange={e => setSettings({ ...settings, maxItemQuantity: Number(e.target.value) })}
+                  placeholder="0 表示不限制"
+                />
+              </div>
+              <div>
+                <Label>订单总额上限</Label>
+                <Input
+                  type="number"
+                  min={0}
+                  step={0.01}
+                  value={settings.maxOrderTotal ?? 0}
+                  onChange={e => setSettings({ ...settings, maxOrderTotal: Number(e.target.value) })}
+                  placeholder="0 表示不限制"
+                />
+              </div>
+              <div>
+                <Label>超出上限时</Label>
+                <select
+                  className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                  value={settings.blockOnOrderLimits ? 'true' : 'false'}
+                  onChange={e => setSettings({ ...settings, blockOnOrderLimits: e.target.value === 'true' })}
+                >
+                  <option value="false">仅提示</option>
+                  <option value="true">禁止保存</option>
+                </select>
+              </div>
+            </div>
+            <p className="text-xs text-muted-foreground -mt-2">
+              用于发现录入错误（如数量多输了一个 0），上限为 0 时不检查
+            </p>
+            <div>
+              <Label>库存不足时</Label>
+              <select
+                className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                value={settings.strictStock ? 'true' : 'false'}
+                onChange={e => setSettings({ ...settings, strictStock: e.target.value === 'true' })}
+              >
+                <option value="false">照常保存，库存扣减到 0 为止</option>
+                <option value="true">禁止保存，并列出库存不足的商品</option>
+              </select>
+            </div>
+          </div>
+        </Card>
+
+        {/* 新建商品默认值 */}
+        <Card className="p-6">
+          <h2 className="text-lg font-semibold text-foreground mb-4">新建商品默认值</h2>
+          <div className="grid grid-cols-2 gap-4">
+            <div>
+              <Label>库存跟踪</Label>
+              <select
+                className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+                value={settings.defaultTrackStock ? 'true' : 'false'}
+                onChange={e => setSettings({ ...settings, defaultTrackStock: e.target.value === 'true' })}
+              >
+                <option value="false">默认不跟踪</option>
+                <option value="true">默认跟踪</option>
+              </select>
+            </div>
+            <div>
+              <Label>最低库存</Label>
+              <Input
+                type="number"
+                min={0}
+                value={settings.defaultMinStock ?? 0}
+                onChange={e => setSettings({ ...settings, defaultMinStock: Number(e.target.value) })}
+                placeholder="0 表示不设置"
+              />
+            </div>
+          </div>
+          <p className="text-xs text-muted-foreground mt-2">
+            新增商品及导入时未填写「启用库存」「最小库存」的商品使用这里的默认值；库存数量留空，表示尚未盘点
+          </p>
+          <div className="mt-4">
+            <Label>零价商品</Label>
+            <select
+              className="w-full h-10 rounded-md border border-input bg-background text-foreground px-3 focus:outline-none focus:ring-2 focus:ring-ring"
+              value={(settings.allowZeroPrice ?? true) ? 'true' : 'false'}
+              onChange={e => setSettings({ ...settings, allowZeroPrice: e.target.value === 'true' })}
+            >
+              <option value="true">允许零售价为 0（如免费服务）</option>
+              <option value="false">不允许，保存/导入时拒绝零价或未填价格的商品</option>
+            </select>
           </div>
         </Card>
 
@@ -495,7 +744,7 @@ export const Settings: React.FC = () => {
                 className="font-mono text-sm"
               />
               <p className="text-xs text-muted-foreground mt-1">
-                变量: {`{date}`} - 日期, {`{customerName}`} - 客户姓名, {`{customer}`} - 客户姓名(简写), {`{orderNumber}`} - 订单号, {`{orderNo}`} - 订单号(简写), {`{licensePlate}`} - 车牌号
+                变量: {`{date}`} - 日期, {`{customerName}`} - 客户姓名, {`{customer}`} - 客户姓名(简写), {`{orderNumber}`} - 订单号, {`{orderNo}`} - 订单号(简写), {`{licensePlate}`} / {`{customerPlate}`} - 车牌号, {`{totalAmount}`} - 订单总额
               </p>
               <p className="text-xs text-muted-foreground mt-1">
                 示例: {`{date}_{customerName}_{orderNumber}`} → 2026-01-09_张三_NO000001.xlsx
@@ -561,7 +810,7 @@ export const Settings: React.FC = () => {
                           默认模板
                         </span>
                       )}
-                      {template.templateBase64 && (
+                      {template.hasFile && (
                         <span className="inline-block px-2 py-1 bg-green-100 dark:bg-green-900/30 text-green-600 dark:text-green-400 text-xs rounded">
                           已上传
                         </span>
@@ -688,6 +937,15 @@ export const Settings: React.FC = () => {
                     placeholder="订单_{orderNo}_{customer}"
                   />
                 </div>
+                <div className="col-span-2">
+                  <Label>独立单号格式（可选）</Label>
+                  <Input
+                    value={editingTemplate.numberFormat || ''}
+                    onChange={e => setEditingTemplate({ ...editingTemplate, numberFormat: e.target.value || undefined })}
+                    placeholder="留空使用全局单号格式，如 QT{YYYY}{MM}{DD}{SEQ:3}"
+                  />
+                  <p className="text-xs text-muted-foreground mt-1">设置后该模板的订单单独编号，序号只在该模板的订单内递增</p>
+                </div>
               </div>
 
               {/* 上传Excel模板 */}
@@ -716,7 +974,7 @@ export const Settings: React.FC = () => {
                       <span className="text-sm text-foreground font-medium">
                         📄 {editingTemplate.fileName}
                       </span>
-                      {editingTemplate.templateBase64 && (
+                      {(editingTemplate.templateBase64 || editingTemplate.hasFile) && (
                         <span className="text-xs text-green-600 dark:text-green-400 bg-green-100 dark:bg-green-900/30 px-2 py-1 rounded">
                           已上传
                         </span>
@@ -829,13 +1087,35 @@ export const Settings: React.FC = () => {
                       placeholder="F6"
                     />
                   </div>
+                  <div>
+                    <Label>税前小计</Label>
+                    <Input
+                      value={editingTemplate.mappings.subtotal || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: { ...editingTemplate.mappings, subtotal: e.target.value }
+                      })}
+                      placeholder="可选"
+                    />
+                  </div>
+                  <div>
+                    <Label>税额</Label>
+                    <Input
+                      value={editingTemplate.mappings.taxAmount || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: { ...editingTemplate.mappings, taxAmount: e.target.value }
+                      })}
+                      placeholder="可选"
+                    />
+                  </div>
                 </div>
               </div>
 
               {/* 商品列表映射 */}
               <div>
                 <h4 className="font-semibold text-foreground mb-3">商品列表映射</h4>
-                <div className="grid grid-cols-2 gap-3">
+                <div className="grid grid-cols-3 gap-3">
                   <div>
                     <Label>起始行号</Label>
                     <Input
@@ -861,8 +1141,35 @@ export const Settings: React.FC = () => {
                       placeholder="0 表示不限制"
                     />
                   </div>
+                  <div>
+                    <Label>最少商品行数</Label>
+                    <Input
+                      type="number"
+                      min={0}
+                      value={editingTemplate.requiredFields?.minItems || 0}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        requiredFields: { ...editingTemplate.requiredFields, minItems: Number(e.target.value) }
+                      })}
+                      placeholder="0 表示不限制"
+                    />
+                  </div>
                 </div>
-                <div className="grid grid-cols-6 gap-3 mt-3">
+                <div className="grid grid-cols-7 gap-3 mt-3">
+                  <div>
+                    <Label>序号列</Label>
+                    <Input
+                      value={editingTemplate.mappings.columns.index || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          columns: { ...editingTemplate.mappings.columns, index: e.target.value }
+                        }
+                      })}
+                      placeholder="可选"
+                    />
+                  </div>
                   <div>
                     <Label>名称列</Label>
                     <Input
@@ -948,6 +1255,95 @@ export const Settings: React.FC = () => {
                     />
                   </div>
                 </div>
+                <p className="text-xs text-muted-foreground mt-3">
+                  数字格式（可选，如 #,##0.00 或 ¥#,##0.00），留空则保留模板单元格原有格式
+                </p>
+                <div className="grid grid-cols-6 gap-3 mt-2">
+                  <div>
+                    <Label>数量格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.quantity || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, quantity: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                  <div>
+                    <Label>单价格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.price || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, price: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                  <div>
+                    <Label>总价格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.total || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, total: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                  <div>
+                    <Label>小计格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.subtotal || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, subtotal: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                  <div>
+                    <Label>税额格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.taxAmount || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, taxAmount: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                  <div>
+                    <Label>合计格式</Label>
+                    <Input
+                      value={editingTemplate.mappings.numberFormats?.totalAmount || ''}
+                      onChange={e => setEditingTemplate({
+                        ...editingTemplate,
+                        mappings: {
+                          ...editingTemplate.mappings,
+                          numberFormats: { ...editingTemplate.mappings.numberFormats, totalAmount: e.target.value }
+                        }
+                      })}
+                      placeholder="保留原格式"
+                    />
+                  </div>
+                </div>
               </div>
 
               <div className="flex gap-3 pt-4 border-t border-border">
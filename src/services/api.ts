@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api/core'
-import type { Product, Customer, Category } from '../types'
+import type { Product, Customer, Category, CategorySubtreeDeletion, CsvImportReport, CustomerTransferReport } from '../types'
 
 // ========== 商品服务 ==========
 
@@ -14,9 +14,19 @@ export const productService = {
     return invoke('get_product_by_id', { id })
   },
 
-  // 搜索商品
-  search: async (query: string): Promise<Product[]> => {
-    return invoke('search_products', { query })
+  // 按条码查找商品（扫码录入），找不到时抛出错误
+  getByBarcode: async (code: string): Promise<Product> => {
+    return invoke('get_product_by_barcode', { code })
+  },
+
+  // 搜索商品（scope：name 仅名称 / pinyin 仅拼音 / all 名称+拼音+条码+分类，默认 all）
+  search: async (query: string, scope?: 'name' | 'pinyin' | 'all'): Promise<Product[]> => {
+    return invoke('search_products', { query, scope })
+  },
+
+  // 全文索引搜索商品（名称、拼音码前缀匹配，按相关度排序），商品较多时比 search 快
+  searchFts: async (query: string): Promise<Product[]> => {
+    return invoke('search_products_fts', { query })
   },
 
   // 根据分类获取商品
@@ -35,9 +45,19 @@ export const productService = {
   },
 
   // 批量删除商品
-  batchDelete: async (ids: string[]): Promise<void> => {
+  batchDelete: async (ids: string[]): Promise<number> => {
     return invoke('batch_delete_products', { ids })
   },
+
+  // 从 CSV 导入商品；validate 为 true 时只预览不写入
+  importCsv: async (path: string, validate: boolean): Promise<CsvImportReport> => {
+    return invoke('import_products_csv', { path, validate })
+  },
+
+  // 导出全部商品为 CSV 文本（已带 BOM，格式与导入一致）
+  exportCsv: async (): Promise<string> => {
+    return invoke('export_products_csv')
+  },
 }
 
 // ========== 客户服务 ==========
@@ -69,9 +89,24 @@ export const customerService = {
   },
 
   // 批量删除客户
-  batchDelete: async (ids: string[]): Promise<void> => {
+  batchDelete: async (ids: string[]): Promise<number> => {
     return invoke('batch_delete_customers', { ids })
   },
+
+  // 从 CSV 导入客户；validate 为 true 时只预览不写入
+  importCsv: async (path: string, validate: boolean): Promise<CsvImportReport> => {
+    return invoke('import_customers_csv', { path, validate })
+  },
+
+  // 导出客户及其订单（用于迁移到另一台电脑），返回导出的客户数
+  exportWithOrders: async (customerIds: string[], path: string): Promise<number> => {
+    return invoke('export_customers_with_orders', { customerIds, path })
+  },
+
+  // 导入客户及其订单：按电话/车牌匹配客户，返回新旧 ID 对照报告
+  importWithOrders: async (path: string): Promise<CustomerTransferReport> => {
+    return invoke('import_customers_with_orders', { path })
+  },
 }
 
 // ========== 分类服务 ==========
@@ -101,4 +136,42 @@ export const categoryService = {
   delete: async (id: string): Promise<void> => {
     return invoke('delete_category', { id })
   },
+
+  // 删除分类及其全部子分类，其中的商品改到指定分类（未指定时为默认分类）
+  deleteSubtree: async (id: string, reassignProductsTo?: string): Promise<CategorySubtreeDeletion> => {
+    return invoke('delete_category_subtree', { id, reassignProductsTo })
+  },
+
+  // 从 CSV 导入分类树（名称,上级名称,排序）
+  importCsv: async (path: string): Promise<{ inserted: number; warnings: string[] }> => {
+    return invoke('import_categories_csv', { path })
+  },
+}
+
+// ========== 数据导入导出服务 ==========
+
+export const dataService = {
+  // 导出全部数据为 JSON 数据包，返回其内容（订单内嵌订单项与客户信息，可由 importAll 导入）
+  exportAll: async (): Promise<string> => {
+    return invoke('export_all_json')
+  },
+
+  // 立即备份数据库到备份目录（未设置时为数据目录下的 backups），返回备份文件路径
+  backupDatabase: async (): Promise<string> => {
+    return invoke('backup_database')
+  },
+
+  // 用备份文件覆盖当前数据库（文件须为 QuickSales 的数据库备份，附件文件不随之恢复），完成后需重新加载数据
+  restoreDatabase: async (backupPath: string): Promise<void> => {
+    return invoke('restore_database', { backupPath })
+  },
+
+  // 从 JSON 数据包导入；tables 为空时导入全部表，否则只导入指定的表。
+  // 与本机已有记录冲突的记录不覆盖，其主键在 skipped 中
+  importAll: async (
+    path: string,
+    tables?: string[]
+  ): Promise<{ table: string; rows: number; skipped: string[] }[]> => {
+    return invoke('import_all_json', { path, tables: tables ?? null })
+  },
 }
@@ -1,11 +1,71 @@
 import ExcelJS from 'exceljs'
 import { save } from '@tauri-apps/plugin-dialog'
 import { writeFile } from '@tauri-apps/plugin-fs'
-import type { Order, TemplateConfig } from '../types'
+import { invoke } from '@tauri-apps/api/core'
+import type { Order, OrderItem, TemplateConfig } from '../types'
+
+export type ItemSortMode = 'entry' | 'category' | 'amount_desc'
 
 export interface ExcelExportOptions {
   template?: TemplateConfig | null
   outputDirectory?: string
+  itemSort?: ItemSortMode
+}
+
+/**
+ * 按导出排序设置排列订单项（不修改订单本身的顺序与 sortValue）
+ * - entry：录入顺序（保持订单中的原有顺序）
+ * - category：按分类快照分组，分组按首次出现的顺序，组内保持录入顺序
+ * - amount_desc：按行金额从高到低，金额相同保持录入顺序
+ */
+export function sortItemsForExport(items: OrderItem[], mode: ItemSortMode = 'entry'): OrderItem[] {
+  if (mode === 'category') {
+    const groups = new Map<string, OrderItem[]>()
+    for (const item of items) {
+      const key = item.category || ''
+      groups.set(key, [...(groups.get(key) ?? []), item])
+    }
+    return Array.from(groups.values()).flat()
+  }
+
+  if (mode === 'amount_desc') {
+    const amount = (item: OrderItem) => Math.round((item.discountPrice ?? item.price) * item.quantity * 100)
+    return [...items].sort((a, b) => amount(b) - amount(a))
+  }
+
+  return [...items]
+}
+
+export interface TaxBreakdown {
+  subtotal: number  // 税前小计
+  tax: number       // 税额
+  total: number     // 含税总额
+}
+
+/**
+ * 按税率拆分订单金额（按分计算避免浮点误差）
+ * - 含税价：总额不变，从总额倒算税额
+ * - 不含税价：订单金额为小计，在其上加税得到总额
+ */
+export function computeTaxBreakdown(amount: number, taxRate: number, pricesIncludeTax: boolean): TaxBreakdown {
+  const amountCents = Math.round(amount * 100)
+  const rate = taxRate > 0 ? taxRate / 100 : 0
+
+  if (pricesIncludeTax) {
+    const subtotalCents = Math.round(amountCents / (1 + rate))
+    return {
+      subtotal: subtotalCents / 100,
+      tax: (amountCents - subtotalCents) / 100,
+      total: amountCents / 100,
+    }
+  }
+
+  const taxCents = Math.round(amountCents * rate)
+  return {
+    subtotal: amountCents / 100,
+    tax: taxCents / 100,
+    total: (amountCents + taxCents) / 100,
+  }
 }
 
 // 默认模板配置
@@ -26,6 +86,7 @@ export async function exportOrderToExcel(
   options: ExcelExportOptions = {}
 ): Promise<string | null> {
   const { template } = options
+  const items = sortItemsForExport(order.items, options.itemSort)
 
   // 创建工作簿
   const workbook = new ExcelJS.Workbook()
@@ -94,7 +155,7 @@ export async function exportOrderToExcel(
   })
 
   // 商品明细
-  order.items.forEach((item) => {
+  items.forEach((item) => {
     const price = item.discountPrice ?? item.price
     const subtotal = price * item.quantity
 
@@ -260,7 +321,7 @@ export async function exportOrdersToExcel(
     })
 
     // 商品明细
-    order.items.forEach((item) => {
+    sortItemsForExport(order.items, _options.itemSort).forEach((item) => {
       const price = item.discountPrice ?? item.price
       const subtotal = price * item.quantity
 
@@ -415,6 +476,38 @@ export async function exportOrdersToExcel(
   return filePath
 }
 
+/**
+ * 导出合并明细工作簿（所有订单明细在同一张表中按订单分组，附汇总表），由后端生成；
+ * 明细按设置中的导出排序排列
+ */
+export async function exportOrdersGroupedWorkbook(
+  orders: Order[],
+  options: ExcelExportOptions = {}
+): Promise<string | null> {
+  if (orders.length === 0) {
+    throw new Error('没有可导出的订单')
+  }
+
+  const dates = orders.map((o) => o.date.slice(0, 10)).sort()
+  const range = dates[0] === dates[dates.length - 1] ? dates[0] : `${dates[0]}_${dates[dates.length - 1]}`
+  const defaultFileName = `订单明细_${range}_${orders.length}条.xlsx`
+  const defaultPath = options.outputDirectory
+    ? `${options.outputDirectory}/${defaultFileName}`
+    : defaultFileName
+
+  const filePath = await save({
+    defaultPath,
+    filters: [{ name: 'Excel文件', extensions: ['xlsx'] }],
+  })
+
+  if (!filePath) {
+    return null
+  }
+
+  await invoke('export_orders_grouped', { orderIds: orders.map((o) => o.id), path: filePath })
+  return filePath
+}
+
 /**
  * 生成文件名
  */
@@ -435,6 +528,8 @@ function generateFileName(order: Order, template?: TemplateConfig | null, filena
     .replace(/{orderNumber}/g, order.orderNumber)
     .replace(/{orderNo}/g, order.orderNumber)
     .replace(/{licensePlate}/g, order.customer.licensePlate || '')
+    .replace(/{customerPlate}/g, order.customer.licensePlate || '')
+    .replace(/{totalAmount}/g, order.totalAmount.toFixed(2))
 
   console.log('generateFileName - 替换后:', fileName)
 
@@ -461,21 +556,27 @@ function generateFileName(order: Order, template?: TemplateConfig | null, filena
 export async function exportOrderWithTemplate(
   order: Order,
   template: TemplateConfig,
-  options: ExcelExportOptions & { filenameFormat?: string; skipDialog?: boolean } = {}
+  options: ExcelExportOptions & {
+    filenameFormat?: string
+    skipDialog?: boolean
+    taxRate?: number
+  } = {}
 ): Promise<string | null> {
   console.log('exportOrderWithTemplate 开始，模板名称:', template.name)
-  console.log('模板是否有 base64 数据:', !!template.templateBase64)
-  console.log('模板 base64 数据长度:', template.templateBase64?.length || 0)
+
+  // 模板列表不含文件内容，导出时再按需读取
+  const templateBase64 = template.templateBase64 || await invoke<string>('get_template_file', { id: template.id })
+  console.log('模板 base64 数据长度:', templateBase64.length)
 
   // 必须有 base64 数据
-  if (!template.templateBase64) {
+  if (!templateBase64) {
     throw new Error('模板没有上传Excel文件，请先在设置中上传模板文件')
   }
 
   try {
     console.log('开始加载模板...')
     const workbook = new ExcelJS.Workbook()
-    const templateBuffer = base64ToArrayBuffer(template.templateBase64)
+    const templateBuffer = base64ToArrayBuffer(templateBase64)
     console.log('模板 buffer 大小:', templateBuffer.byteLength)
 
     await workbook.xlsx.load(templateBuffer)
@@ -490,6 +591,7 @@ export async function exportOrderWithTemplate(
 
     // 根据映射填充数据
     const mappings = template.mappings
+    const formats = mappings.numberFormats || {}
     console.log('模板映射配置:', mappings)
 
     // 填充客户信息
@@ -519,9 +621,17 @@ export async function exportOrderWithTemplate(
       setCellValue(worksheet, mappings.orderRemark, order.remark || '')
       console.log('设置订单备注:', mappings.orderRemark, '=', order.remark)
     }
+    // 保存的订单总额已包含税额（价格不含税时保存前已加税），这里从总额倒算小计与税额
+    const amounts = computeTaxBreakdown(order.totalAmount, options.taxRate || 0, true)
+    if (mappings.subtotal) {
+      setCellValue(worksheet, mappings.subtotal, amounts.subtotal, formats.subtotal)
+    }
+    if (mappings.taxAmount) {
+      setCellValue(worksheet, mappings.taxAmount, amounts.tax, formats.taxAmount)
+    }
     if (mappings.totalAmount) {
-      setCellValue(worksheet, mappings.totalAmount, order.totalAmount)
-      console.log('设置总金额:', mappings.totalAmount, '=', order.totalAmount)
+      setCellValue(worksheet, mappings.totalAmount, amounts.total, formats.totalAmount)
+      console.log('设置总金额:', mappings.totalAmount, '=', amounts.total)
     }
 
     // 填充商品列表
@@ -538,24 +648,27 @@ export async function exportOrderWithTemplate(
         throw new Error(`当前订单有 ${order.items.length} 个商品，但模板只支持 ${maxItems} 个商品（起始行${startRow}到结束行${endRow}）。请修改模板配置或减少商品数量后重试。`)
       }
 
-      order.items.forEach((item, index) => {
+      sortItemsForExport(order.items, options.itemSort).forEach((item, index) => {
         const rowNumber = startRow + index
 
+        if (cols.index) {
+          setCellValueByCol(worksheet, rowNumber, cols.index, index + 1)
+        }
         if (cols.name) {
           setCellValueByCol(worksheet, rowNumber, cols.name, item.name)
         }
         if (cols.quantity) {
-          setCellValueByCol(worksheet, rowNumber, cols.quantity, item.quantity)
+          setCellValueByCol(worksheet, rowNumber, cols.quantity, item.quantity, formats.quantity)
         }
         if (cols.unit) {
           setCellValueByCol(worksheet, rowNumber, cols.unit, item.unit)
         }
         if (cols.price) {
-          setCellValueByCol(worksheet, rowNumber, cols.price, item.discountPrice ?? item.price)
+          setCellValueByCol(worksheet, rowNumber, cols.price, item.discountPrice ?? item.price, formats.price)
         }
         if (cols.total) {
           const price = item.discountPrice ?? item.price
-          setCellValueByCol(worksheet, rowNumber, cols.total, price * item.quantity)
+          setCellValueByCol(worksheet, rowNumber, cols.total, price * item.quantity, formats.total)
         }
         if (cols.remark) {
           setCellValueByCol(worksheet, rowNumber, cols.remark, item.remark || '')
@@ -620,13 +733,16 @@ function base64ToArrayBuffer(base64: string): ArrayBuffer {
 }
 
 // 辅助函数：设置单元格值（通过单元格地址，如 "A1"）
-function setCellValue(worksheet: ExcelJS.Worksheet, cellAddress: string, value: string | number) {
+// numFmt 为空时只写入值，单元格保留模板中原有的数字格式
+function setCellValue(worksheet: ExcelJS.Worksheet, cellAddress: string, value: string | number, numFmt?: string) {
   const cell = worksheet.getCell(cellAddress)
   cell.value = value
+  if (numFmt && numFmt.trim()) {
+    cell.numFmt = numFmt.trim()
+  }
 }
 
 // 辅助函数：设置单元格值（通过行号和列字母）
-function setCellValueByCol(worksheet: ExcelJS.Worksheet, row: number, col: string, value: string | number) {
-  const cell = worksheet.getCell(`${col}${row}`)
-  cell.value = value
+function setCellValueByCol(worksheet: ExcelJS.Worksheet, row: number, col: string, value: string | number, numFmt?: string) {
+  setCellValue(worksheet, `${col}${row}`, value, numFmt)
 }
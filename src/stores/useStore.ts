@@ -117,6 +117,7 @@ export const useStore = create<AppState>()(
         orderNumberFormat: 'NO.{SEQ:6}',
         orderNumberPrefix: '',
         orderNumberResetDaily: true,
+        orderNumberResetPeriod: 'daily',
         orderNumberDigits: 6,
         retainDays: 0,
         autoBackup: true,
@@ -410,6 +411,7 @@ export const useStore = create<AppState>()(
               id: 'default_template_v1',
               name: '默认模板',
               templateBase64: DEFAULT_TEMPLATE_BASE64,
+              hasFile: true,
               fileName: 'template1.xlsx',
               filenamePattern: '{date}_{customerName}_{orderNumber}',
               isDefault: true,
@@ -12,6 +12,15 @@ export interface Product {
   trackStock?: boolean  // 是否跟踪库存
   createdAt: string
   updatedAt: string
+  barcode?: string      // 商品条码（扫码录入）
+}
+
+// low-stock 事件的内容：保存订单等扣减库存后降到最低库存及以下的商品（每个商品每次保存只发一次）
+export interface LowStockAlert {
+  productId: string
+  name: string
+  stock: number
+  minStock: number
 }
 
 export interface Category {
@@ -25,6 +34,13 @@ export interface Category {
   updatedAt: string
 }
 
+// delete_category_subtree 的返回值
+export interface CategorySubtreeDeletion {
+  deletedCategories: number
+  reassignedProducts: number
+  reassignedTo?: string
+}
+
 export interface Customer {
   id: string
   name: string
@@ -47,6 +63,8 @@ export interface OrderItem {
   discountPrice?: number
   remark?: string
   sortValue: number
+  lineTotal?: number // 行金额，保存订单时由后端计算
+  lineId?: string    // 订单项行 ID（id 为商品 ID），新加的行为空，保存时由后端生成
 }
 
 export interface Order {
@@ -59,11 +77,178 @@ export interface Order {
   totalAmount: number
   remark?: string
   templateId?: string
-  status: 'completed' | 'draft'
+  status: 'draft' | 'confirmed' | 'completed' | 'cancelled'  // 已确认与已完成的订单占用库存
+  onAccount?: boolean   // 挂账（记入客户往来账）
+  roundingAdjustment?: number  // 抹零调整额（抹零后总额 - 明细合计）
+  currency?: string       // 外币订单的币种（为空表示本币）
+  exchangeRate?: number   // 外币兑本币汇率，本币订单为 1
+  orderDiscount?: number  // 整单优惠金额（计税与抹零之前扣减）
+  taxRate?: number            // 保存时使用的税率（计税快照，由后端写入）
+  pricesIncludeTax?: boolean  // 保存时价格是否含税（计税快照）
   createdAt: string
   updatedAt: string
 }
 
+// get_all_orders_paged 的返回值：当前页订单与订单总数
+export interface OrderPage {
+  items: Order[]
+  total: number
+}
+
+// save_order 的返回值：订单号与超出上限等提示
+export interface SaveOrderResult {
+  orderNumber: string
+  warnings: string[]
+}
+
+// 订单附件（文件已复制到数据目录下的 attachments 文件夹）
+export interface OrderAttachment {
+  id: string
+  orderId: string
+  filePath: string
+  fileName: string
+  kind: 'image' | 'document'
+  sizeBytes: number
+  createdAt: string
+}
+
+// get_order_export_info 的返回值：导出/打印次数与最后导出时间
+export interface OrderExportInfo {
+  orderId: string
+  orderNumber: string
+  exportCount: number
+  lastExportedAt?: string
+}
+
+// 报表统计（金额均已按汇率折算为本币）
+export interface SalesTotal {
+  orderCount: number
+  totalAmount: number
+}
+
+export interface DailySales {
+  date: string
+  orderCount: number
+  totalAmount: number
+}
+
+export interface ProductSales {
+  productId?: string  // 手动录入的订单项按名称汇总，此项为空
+  name: string
+  quantity: number
+  revenue: number
+}
+
+export interface CustomerSales {
+  customerId: string
+  name: string
+  orderCount: number
+  totalAmount: number
+}
+
+export interface CategorySales {
+  categoryId: string  // “未分类”为空字符串
+  name: string
+  parentId?: string
+  level: number
+  ownRevenue: number
+  subtreeRevenue: number
+}
+
+// get_report_bundle 的返回值：报表页需要的全部统计
+export interface ReportBundle {
+  from: string
+  to: string
+  salesTotal: SalesTotal
+  dailySales: DailySales[]
+  topProducts: ProductSales[]
+  topCustomers: CustomerSales[]
+  categorySales: CategorySales[]
+}
+
+// 批量导出文件名预览：collision 为 true 的订单会互相覆盖，collisionGroup 相同的为一组
+export interface ExportFilenamePreview {
+  orderId: string
+  orderNumber: string
+  filename: string
+  collision: boolean
+  collisionGroup?: number
+}
+
+// split_order 的返回值：原订单与拆分出的新订单
+export interface SplitOrderResult {
+  originalOrderId: string
+  newOrderId: string
+  newOrderNumber: string
+}
+
+// restore_default_data 的返回值：重新插入的默认数据与被恢复为默认配置的已有项数量
+export interface RestoredDefaults {
+  categoriesAdded: string[]
+  unitsAdded: string[]
+  templateAdded: boolean
+  overwritten: number
+}
+
+// 重复订单号：orderIds 按创建时间排列，修复时保留第一个订单的原单号
+export interface DuplicateOrderNumber {
+  orderNumber: string
+  orderIds: string[]
+}
+
+// CSV 导入的逐行结果（预览与实际导入格式相同）
+export interface CsvImportRow {
+  line: number
+  name: string
+  action: 'insert' | 'update' | 'skip'
+  reason?: string
+}
+
+export interface CsvImportReport {
+  rows: CsvImportRow[]
+  inserted: number
+  updated: number
+  skipped: number
+  dryRun: boolean
+}
+
+// 客户连同订单迁移（import_customers_with_orders）的 ID 对照报告
+export interface CustomerIdMapping {
+  sourceId: string
+  targetId: string
+  name: string
+  action: 'matched' | 'created'
+}
+
+export interface OrderIdMapping {
+  sourceId: string
+  targetId: string
+  sourceNumber: string
+  targetNumber: string
+  customerId: string
+  action: 'imported' | 'skipped'
+  reason?: string
+}
+
+export interface CustomerTransferReport {
+  customers: CustomerIdMapping[]
+  orders: OrderIdMapping[]
+  customersMatched: number
+  customersCreated: number
+  ordersImported: number
+  ordersSkipped: number
+}
+
+// inspect_template_file 的返回值：valid 为 false 时 error 说明原因
+export interface TemplateFileInfo {
+  valid: boolean
+  sheetNames: string[]
+  maxRow: number
+  maxCol: number
+  sizeBytes: number
+  error?: string
+}
+
 export interface TemplateConfig {
   id: string
   name: string
@@ -79,9 +264,12 @@ export interface TemplateConfig {
     orderNumber: string
     orderRemark: string
     totalAmount: string
+    subtotal?: string   // 税前小计单元格（可选）
+    taxAmount?: string  // 税额单元格（可选）
     itemStartRow: number
     itemEndRow: number
     columns: {
+      index?: string   // 序号列（可选，按 1、2、3… 填写）
       name: string
       unit: string
       quantity: string
@@ -89,6 +277,15 @@ export interface TemplateConfig {
       total: string
       remark: string
     }
+    // 数值单元格的数字格式（如 "#,##0.00"），为空时保留模板单元格原有格式
+    numberFormats?: {
+      quantity?: string
+      price?: string
+      total?: string
+      totalAmount?: string
+      subtotal?: string
+      taxAmount?: string
+    }
   }
   // 模板级别的必填字段设置（用于Excel导出验证）
   requiredFields: {
@@ -105,7 +302,10 @@ export interface TemplateConfig {
     requireItemPrice: boolean
     requireItemTotal: boolean
     requireItemRemark: boolean
+    minItems?: number  // 最少商品行数，0 表示不限制
   }
+  numberFormat?: string  // 模板独立的单号格式，为空时使用全局设置
+  hasFile?: boolean  // 是否已上传模板文件（模板列表不含文件内容，导出时按需加载）
   createdAt: string
   updatedAt: string
 }
@@ -141,7 +341,8 @@ export interface AppSettings {
   excelDateFormat: string
   orderNumberFormat: string
   orderNumberPrefix: string
-  orderNumberResetDaily: boolean
+  orderNumberResetDaily: boolean  // 已弃用，由 orderNumberResetPeriod 取代（daily 对应 true）
+  orderNumberResetPeriod?: 'daily' | 'monthly' | 'yearly' | 'never'  // 序号重新从 1 开始的周期
   orderNumberDigits: number
   retainDays: number
   autoBackup: boolean
@@ -172,6 +373,42 @@ export interface AppSettings {
     requireItemRemark: boolean
   }
 
+  // 日志级别（error / warn / info / debug / trace）
+  logLevel?: string
+
+  // 订单总额抹零方式（none 不处理 / round 四舍五入到元 / floor 舍去角分）
+  totalRounding?: 'none' | 'round' | 'floor'
+
+  // 税率（百分比，如 13 表示 13%；0 表示不计税）
+  taxRate?: number
+
+  // 商品价格是否含税（含税时从总额倒算税额，不含税时在小计上加税）
+  pricesIncludeTax?: boolean
+
+  // 单行商品数量上限（超出时保存提示，0 表示不限制）
+  maxItemQuantity?: number
+
+  // 订单总额上限（超出时保存提示，0 表示不限制）
+  maxOrderTotal?: number
+
+  // 超出数量/总额上限时禁止保存（否则仅提示）
+  blockOnOrderLimits?: boolean
+
+  // 导出时订单项的排列方式：录入顺序 / 按分类分组 / 按金额降序
+  exportItemSort?: 'entry' | 'category' | 'amount_desc'
+
+  // 新建商品（未指定时）默认开启库存跟踪
+  defaultTrackStock?: boolean
+
+  // 新建跟踪库存的商品（未指定时）使用的最低库存，0 表示不设置
+  defaultMinStock?: number
+
+  // 允许商品零售价为 0（关闭后保存/导入商品时拒绝价格小于等于 0 的商品）
+  allowZeroPrice?: boolean
+
+  // 严格库存：跟踪库存的商品库存不足时禁止保存订单（关闭时扣减到 0 为止）
+  strictStock?: boolean
+
   updatedAt: string
 }
 